@@ -0,0 +1,13 @@
+//! Points the linker at the legacy location of the VideoCore libraries for `bcm-host`
+
+use std::env;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let legacy = Path::new("/opt/vc/lib");
+    if env::var_os("CARGO_FEATURE_BCM_HOST").is_some() && legacy.is_dir() {
+        println!("cargo:rustc-link-search=native={}", legacy.display());
+    }
+}
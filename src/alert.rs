@@ -0,0 +1,39 @@
+//! Alerts raised by detectors watching the monitor's samples
+
+use std::fmt;
+use std::time::SystemTime;
+
+/// How urgent an alert is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        };
+        f.pad(name)
+    }
+}
+
+/// Something noteworthy a detector found in the readings
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub timestamp: SystemTime,
+    pub severity: Severity,
+    /// Short, stable identifier of what raised the alert, e.g. `clock_drop`
+    pub source: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.severity, self.source, self.message)
+    }
+}
@@ -0,0 +1,232 @@
+//! Detection of abrupt clock drops
+//!
+//! When the supply sags, the firmware lowers the clocks before the throttled bits as read by
+//! a poller reflect it, so a sudden drop relative to the recent baseline is often the first
+//! visible symptom of PSU trouble.
+//!
+//! With a dynamic cpufreq governor such as `ondemand`, the ARM clock legitimately falls to
+//! its idle frequency whenever the load goes away. Set a `floor` at the idle frequency, e.g.
+//! from a calibrated `Baseline`, so that only drops below it are flagged, or pin the
+//! governor while watching for brownouts.
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use crate::alert::{Alert, Severity};
+use crate::calibrate::Baseline;
+use crate::monitor::{Reading, Sample};
+use crate::ClockSrc;
+
+/// Default number of readings the baseline is computed from
+const DEFAULT_WINDOW: usize = 10;
+/// Default relative drop below the baseline that raises an alert
+const DEFAULT_DROP_RATIO: f64 = 0.3;
+
+/// Flags clock readings that fall abruptly below the median of the recent ones.
+///
+/// An alert is raised once per drop. The detector re-arms when the clock recovers above the
+/// threshold again, so a sustained drop doesn't raise an alert on every sample.
+#[derive(Debug, Clone)]
+pub struct ClockDropDetector {
+    src: ClockSrc,
+    window: usize,
+    drop_ratio: f64,
+    floor: Option<isize>,
+    history: VecDeque<isize>,
+    dropped: bool,
+}
+
+impl ClockDropDetector {
+    /// Watch the clock of `src`, e.g. `ClockSrc::Arm` or `ClockSrc::Core`
+    pub fn new(src: ClockSrc) -> ClockDropDetector {
+        ClockDropDetector {
+            src,
+            window: DEFAULT_WINDOW,
+            drop_ratio: DEFAULT_DROP_RATIO,
+            floor: None,
+            history: VecDeque::with_capacity(DEFAULT_WINDOW),
+            dropped: false,
+        }
+    }
+
+    /// Number of recent readings the baseline is the median of, 10 by default
+    pub fn window(mut self, window: usize) -> ClockDropDetector {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Fraction of the baseline a reading has to fall by to count as a drop, 0.3 by default
+    pub fn drop_ratio(mut self, drop_ratio: f64) -> ClockDropDetector {
+        self.drop_ratio = drop_ratio;
+        self
+    }
+
+    /// Never flag readings at or above `floor` Hz, even if they are far below the baseline
+    pub fn floor(mut self, floor: isize) -> ClockDropDetector {
+        self.floor = Some(floor);
+        self
+    }
+
+    /// Use the lowest idle clock of `baseline` as floor, if it was calibrated for `src`
+    pub fn with_baseline(self, baseline: &Baseline) -> ClockDropDetector {
+        let stats = match self.src {
+            ClockSrc::Arm => baseline.arm_clock,
+            ClockSrc::Core => baseline.core_clock,
+            _ => None,
+        };
+
+        match stats {
+            Some(stats) => self.floor(stats.min as isize),
+            None => self,
+        }
+    }
+
+    /// The median of the recent readings, `None` until the window is filled
+    pub fn baseline(&self) -> Option<isize> {
+        if self.history.len() < self.window {
+            return None;
+        }
+
+        let mut sorted: Vec<_> = self.history.iter().copied().collect();
+        sorted.sort_unstable();
+        sorted.get(sorted.len() / 2).copied()
+    }
+
+    /// Feed a frequency reading in Hz, returns an alert if it is an abrupt drop
+    pub fn observe(&mut self, frequency: isize, at: SystemTime) -> Option<Alert> {
+        let alert = self.baseline().and_then(|baseline| {
+            let threshold = baseline as f64 * (1.0 - self.drop_ratio);
+
+            let above_floor = self.floor.is_some_and(|floor| frequency >= floor);
+            if (frequency as f64) >= threshold || above_floor {
+                self.dropped = false;
+                return None;
+            }
+            if self.dropped {
+                return None;
+            }
+            self.dropped = true;
+
+            Some(Alert {
+                timestamp: at,
+                severity: Severity::Warning,
+                source: "clock_drop",
+                message: format!(
+                    "{:?} clock dropped to {} MHz from a baseline of {} MHz",
+                    self.src,
+                    frequency / 1_000_000,
+                    baseline / 1_000_000
+                ),
+            })
+        });
+
+        if self.history.len() >= self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back(frequency);
+
+        alert
+    }
+
+    /// Look for a reading of the watched clock in `sample`
+    pub fn observe_sample(&mut self, sample: &Sample) -> Option<Alert> {
+        sample.readings.iter().find_map(|reading| match *reading {
+            Reading::Clock(src, frequency) if src == self.src => {
+                self.observe(frequency, sample.timestamp)
+            }
+            _ => None,
+        })
+    }
+
+    /// Turn the detector into a monitor sink, passing alerts to `on_alert`
+    pub fn into_sink<F>(mut self, mut on_alert: F) -> impl FnMut(&Sample) + Send + 'static
+    where
+        F: FnMut(&Alert) + Send + 'static,
+    {
+        move |sample| {
+            if let Some(alert) = self.observe_sample(sample) {
+                on_alert(&alert);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibrate::Stats;
+
+    const MHZ: isize = 1_000_000;
+
+    #[test]
+    fn test_detects_drop_once() {
+        let now = SystemTime::now();
+        let mut detector = ClockDropDetector::new(ClockSrc::Arm).window(3);
+
+        for _ in 0..3 {
+            assert!(detector.observe(1500 * MHZ, now).is_none());
+        }
+        assert_eq!(Some(1500 * MHZ), detector.baseline());
+
+        // small dips are fine
+        assert!(detector.observe(1400 * MHZ, now).is_none());
+
+        let alert = detector.observe(600 * MHZ, now).unwrap();
+        assert_eq!("clock_drop", alert.source);
+        assert!(alert.message.contains("600 MHz"));
+
+        // still down, no repeated alert
+        assert!(detector.observe(600 * MHZ, now).is_none());
+    }
+
+    #[test]
+    fn test_rearms_after_recovery() {
+        let now = SystemTime::now();
+        let mut detector = ClockDropDetector::new(ClockSrc::Arm).window(3);
+        for _ in 0..3 {
+            detector.observe(1500 * MHZ, now);
+        }
+
+        assert!(detector.observe(500 * MHZ, now).is_some());
+        assert!(detector.observe(1500 * MHZ, now).is_none());
+        assert!(detector.observe(1500 * MHZ, now).is_none());
+        assert!(detector.observe(500 * MHZ, now).is_some());
+    }
+
+    #[test]
+    fn test_floor_from_baseline() {
+        let now = SystemTime::now();
+        let baseline = Baseline {
+            arm_clock: Stats::from_values(vec![600.0 * MHZ as f64, 1500.0 * MHZ as f64]),
+            ..Baseline::default()
+        };
+        let mut detector = ClockDropDetector::new(ClockSrc::Arm)
+            .window(3)
+            .with_baseline(&baseline);
+        for _ in 0..3 {
+            detector.observe(1500 * MHZ, now);
+        }
+
+        // back to idle is no drop
+        assert!(detector.observe(600 * MHZ, now).is_none());
+        assert!(detector.observe(400 * MHZ, now).is_some());
+    }
+
+    #[test]
+    fn test_ignores_other_clocks() {
+        let mut detector = ClockDropDetector::new(ClockSrc::Arm).window(1);
+        let sample = |reading| Sample {
+            timestamp: SystemTime::now(),
+            readings: vec![reading],
+            errors: Vec::new(),
+        };
+
+        detector.observe_sample(&sample(Reading::Clock(ClockSrc::Arm, 1500 * MHZ)));
+        assert!(detector
+            .observe_sample(&sample(Reading::Clock(ClockSrc::Core, 100 * MHZ)))
+            .is_none());
+        assert!(detector
+            .observe_sample(&sample(Reading::Clock(ClockSrc::Arm, 100 * MHZ)))
+            .is_some());
+    }
+}
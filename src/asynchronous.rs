@@ -0,0 +1,564 @@
+//! The command wrappers as async functions, on Tokio or async-std
+//!
+//! Named so because `async` is a keyword. The functions spawn vcgencmd asynchronously, as
+//! configured by `set_invocation` just like their blocking counterparts, so an async service
+//! can await several readings at once, e.g. with `tokio::join!` or `Future::join`, without
+//! moving blocking calls to threads of its own:
+//!
+//! ```rust,no_run
+//! # async fn example() -> vcgencmd::Result<()> {
+//! use vcgencmd::asynchronous::{get_throttled, measure_temp};
+//!
+//! let temp = measure_temp().await?;
+//! let throttled = get_throttled().await?;
+//! println!("{} °C, throttled: {:#x}", temp, throttled);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! An `AsyncVcgencmd` runs vcgencmd as its own `Invocation` says instead, like a blocking
+//! `Vcgencmd` client, e.g. to read several Pis over ssh:
+//!
+//! ```rust,no_run
+//! # async fn example() -> vcgencmd::Result<()> {
+//! use vcgencmd::asynchronous::AsyncVcgencmd;
+//! use vcgencmd::Vcgencmd;
+//!
+//! let pi4 = AsyncVcgencmd::from(&Vcgencmd::builder().host("pi@pi4.local").build());
+//! let pi5 = AsyncVcgencmd::from(&Vcgencmd::builder().host("pi@pi5.local").build());
+//! println!("{} °C, {} °C", pi4.measure_temp().await?, pi5.measure_temp().await?);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `tokio` feature vcgencmd is run with `tokio::process`, so the functions have to
+//! be awaited on a Tokio runtime with the time driver enabled, as `Runtime::new` and
+//! `#[tokio::main]` do. With the `async-std` feature it is run with `async_std::process`,
+//! which brings its own reactor and works on any executor, so it's used if both features
+//! are enabled. A call that misses the `Invocation::timeout`, or whose future is dropped,
+//! kills the process right away, rather than asking it to exit first like the blocking
+//! functions.
+
+use std::collections::HashMap;
+use std::io;
+use std::process::{self, Output};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_util::future::join_all;
+use subprocess::PopenError;
+
+use crate::display::{HdmiTimings, LcdInfo};
+use crate::error::ParseError;
+use crate::monitor::{Metric, Reading, Sample};
+use crate::snapshot::{Snapshot, SNAPSHOT_METRICS};
+use crate::thermal::TempLimits;
+use crate::{
+    build_command, each_measured, interpret, interpret_bit_pattern, invocation, known_unsupported,
+    parsers, AdcChannel, ClockSrc, Cmd, ConfigSrc, GpuMemoryPressure, Invocation, MemSplit, MemSrc,
+    RelocStats, Result, Src, ThrottledStatus, Vcgencmd, VoltRails, VoltSrc,
+};
+
+fn timed_out(timeout: Duration) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("no answer within {:?}", timeout),
+    )
+}
+
+/// Run `exec` capturing stdout and stderr, killing it once `timeout` passed
+#[cfg(feature = "async-std")]
+async fn output(exec: process::Command, timeout: Option<Duration>) -> io::Result<Output> {
+    let mut exec = async_std::process::Command::from(exec);
+    exec.kill_on_drop(true);
+
+    let output = exec.output();
+    match timeout {
+        Some(timeout) => async_std::future::timeout(timeout, output)
+            .await
+            .unwrap_or_else(|_| Err(timed_out(timeout))),
+        None => output.await,
+    }
+}
+
+/// Run `exec` capturing stdout and stderr, killing it once `timeout` passed
+#[cfg(all(feature = "tokio", not(feature = "async-std")))]
+async fn output(exec: process::Command, timeout: Option<Duration>) -> io::Result<Output> {
+    let mut exec = tokio::process::Command::from(exec);
+    exec.kill_on_drop(true);
+
+    let output = exec.output();
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, output)
+            .await
+            .unwrap_or_else(|_| Err(timed_out(timeout))),
+        None => output.await,
+    }
+}
+
+/// Wait until `at`, on the runtime's timers
+#[cfg(feature = "async-std")]
+pub(crate) async fn sleep_until(at: Instant) {
+    use async_std::prelude::FutureExt;
+
+    // `task::sleep` comes with the default features, `delay` runs on the same timer
+    let delay = at.saturating_duration_since(Instant::now());
+    async_std::future::ready(()).delay(delay).await
+}
+
+/// Wait until `at`, on the runtime's timers
+#[cfg(all(feature = "tokio", not(feature = "async-std")))]
+pub(crate) async fn sleep_until(at: Instant) {
+    tokio::time::sleep_until(tokio::time::Instant::from_std(at)).await
+}
+
+/// Runs vcgencmd asynchronously as configured by its own `Invocation`, the async counterpart
+/// of a `Vcgencmd` spawning processes. The free functions of this module use one with the
+/// global `Invocation`.
+///
+/// Unlike the free functions, a client doesn't skip commands `session::global` found to be
+/// unsupported, as those capabilities are of the global invocation.
+#[derive(Debug, Clone, Default)]
+pub struct AsyncVcgencmd {
+    invocation: Invocation,
+    /// The temperature limits of the firmware this client talks to, once read
+    limits: OnceLock<TempLimits>,
+    /// Whether to skip the commands `session::global` found to be unsupported, only for
+    /// the client of the free functions
+    skip_unsupported: bool,
+}
+
+/// A client with the `Invocation` of `client`, without its `RetryPolicy`
+impl From<&Vcgencmd> for AsyncVcgencmd {
+    fn from(client: &Vcgencmd) -> AsyncVcgencmd {
+        AsyncVcgencmd::new(client.invocation().clone())
+    }
+}
+
+impl AsyncVcgencmd {
+    pub fn new(invocation: Invocation) -> AsyncVcgencmd {
+        AsyncVcgencmd {
+            invocation,
+            limits: OnceLock::new(),
+            skip_unsupported: false,
+        }
+    }
+
+    /// A client running vcgencmd like the free functions do right now
+    pub fn from_global() -> AsyncVcgencmd {
+        AsyncVcgencmd::new(invocation())
+    }
+
+    /// The client of the free functions, sharing the limits cached by `TempLimits::cached`
+    /// and the capabilities of `session::global`
+    fn global() -> AsyncVcgencmd {
+        let client = AsyncVcgencmd {
+            skip_unsupported: true,
+            ..AsyncVcgencmd::from_global()
+        };
+        if let Some(limits) = TempLimits::cached_if_read() {
+            let _ = client.limits.set(limits);
+        }
+        client
+    }
+
+    /// Cache the limits this client read for `TempLimits::cached`
+    fn cache_limits(&self) {
+        if let Some(limits) = self.limits.get() {
+            TempLimits::cache(*limits);
+        }
+    }
+
+    pub fn invocation(&self) -> &Invocation {
+        &self.invocation
+    }
+
+    /// Run `command` and parse its output, see `crate::call`
+    async fn call<T, E: ParseError>(
+        &self,
+        command: Cmd,
+        src: Option<Src>,
+        parse: fn(&str) -> std::result::Result<T, E>,
+    ) -> Result<T> {
+        self.call_with_raw(command, src, parse)
+            .await
+            .map(|(value, _)| value)
+    }
+
+    /// `call`, also returning the output of `vcgencmd` the value was parsed from
+    async fn call_with_raw<T, E: ParseError>(
+        &self,
+        command: Cmd,
+        src: Option<Src>,
+        parse: fn(&str) -> std::result::Result<T, E>,
+    ) -> Result<(T, String)> {
+        if self.skip_unsupported {
+            if let Some(error) = known_unsupported(command, src) {
+                return Err(error);
+            }
+        }
+
+        let (exec, timeout) = build_command(&self.invocation, command, src);
+        let output = output(exec, timeout).await.map_err(PopenError::IoError);
+        interpret(&self.invocation, command, src, output, parse)
+    }
+
+    /// `Vcgencmd::measure_clock` without blocking
+    pub async fn measure_clock(&self, src: Src) -> Result<isize> {
+        self.call(Cmd::MeasureClock, Some(src), parsers::frequency)
+            .await
+    }
+
+    /// `Vcgencmd::measure_clock_with_raw` without blocking
+    pub async fn measure_clock_with_raw(&self, src: Src) -> Result<(isize, String)> {
+        self.call_with_raw(Cmd::MeasureClock, Some(src), parsers::frequency)
+            .await
+    }
+
+    /// `Vcgencmd::measure_clock_all` without blocking, measuring one clock after the other
+    pub async fn measure_clock_all(&self) -> Result<HashMap<ClockSrc, isize>> {
+        let mut clocks = Vec::new();
+        for &src in &ClockSrc::ALL {
+            clocks.push((src, self.measure_clock(Src::Clock(src)).await));
+        }
+        each_measured(clocks)
+    }
+
+    /// `Vcgencmd::measure_volts` without blocking
+    pub async fn measure_volts(&self, src: Src) -> Result<f64> {
+        self.call(Cmd::MeasureVolts, Some(src), parsers::volts)
+            .await
+    }
+
+    /// `Vcgencmd::measure_volts_with_raw` without blocking
+    pub async fn measure_volts_with_raw(&self, src: Src) -> Result<(f64, String)> {
+        self.call_with_raw(Cmd::MeasureVolts, Some(src), parsers::volts)
+            .await
+    }
+
+    /// `Vcgencmd::measure_volts_all` without blocking
+    pub async fn measure_volts_all(&self) -> Result<VoltRails> {
+        let mut rails = Vec::new();
+        for &src in &VoltSrc::ALL {
+            rails.push((src, self.measure_volts(Src::Volt(src)).await));
+        }
+        let mut volts = each_measured(rails)?;
+
+        Ok(VoltRails {
+            core: volts.remove(&VoltSrc::Core),
+            sdram_c: volts.remove(&VoltSrc::SdramC),
+            sdram_i: volts.remove(&VoltSrc::SdramI),
+            sdram_p: volts.remove(&VoltSrc::SdramP),
+        })
+    }
+
+    /// `Vcgencmd::measure_temp` without blocking
+    pub async fn measure_temp(&self) -> Result<f64> {
+        self.call(Cmd::MeasureTemp, None, parsers::temp).await
+    }
+
+    /// `Vcgencmd::measure_temp_with_raw` without blocking
+    pub async fn measure_temp_with_raw(&self) -> Result<(f64, String)> {
+        self.call_with_raw(Cmd::MeasureTemp, None, parsers::temp)
+            .await
+    }
+
+    /// `Vcgencmd::get_mem` without blocking
+    pub async fn get_mem(&self, src: Src) -> Result<isize> {
+        self.call(Cmd::GetMem, Some(src), parsers::mem).await
+    }
+
+    /// `Vcgencmd::get_mem_with_raw` without blocking
+    pub async fn get_mem_with_raw(&self, src: Src) -> Result<(isize, String)> {
+        self.call_with_raw(Cmd::GetMem, Some(src), parsers::mem)
+            .await
+    }
+
+    /// `Vcgencmd::get_mem_split` without blocking
+    pub async fn get_mem_split(&self) -> Result<MemSplit> {
+        let arm = self.get_mem(Src::Mem(MemSrc::Arm)).await?;
+        let gpu = self.get_mem(Src::Mem(MemSrc::Gpu)).await?;
+
+        Ok(MemSplit::new(arm, gpu))
+    }
+
+    /// `Vcgencmd::get_gpu_memory_pressure` without blocking
+    pub async fn get_gpu_memory_pressure(&self) -> Result<GpuMemoryPressure> {
+        Ok(GpuMemoryPressure {
+            malloc_total: self.get_mem(Src::Mem(MemSrc::MallocTotal)).await?,
+            malloc_free: self.get_mem(Src::Mem(MemSrc::Malloc)).await?,
+            reloc_total: self.get_mem(Src::Mem(MemSrc::RelocTotal)).await?,
+            reloc_free: self.get_mem(Src::Mem(MemSrc::Reloc)).await?,
+            reloc_stats: self.mem_reloc_stats().await?,
+        })
+    }
+
+    /// `Vcgencmd::mem_reloc_stats` without blocking
+    pub async fn mem_reloc_stats(&self) -> Result<RelocStats> {
+        self.call(Cmd::MemRelocStats, None, parsers::reloc_stats)
+            .await
+    }
+
+    /// `Vcgencmd::get_config` without blocking
+    pub async fn get_config(&self, src: Src) -> Result<isize> {
+        self.call(Cmd::GetConfig, Some(src), parsers::config).await
+    }
+
+    /// `Vcgencmd::get_config_with_raw` without blocking
+    pub async fn get_config_with_raw(&self, src: Src) -> Result<(isize, String)> {
+        self.call_with_raw(Cmd::GetConfig, Some(src), parsers::config)
+            .await
+    }
+
+    /// `Vcgencmd::get_throttled` without blocking
+    pub async fn get_throttled(&self) -> Result<isize> {
+        self.call(Cmd::GetThrottled, None, parsers::throttled).await
+    }
+
+    /// `Vcgencmd::get_throttled_with_raw` without blocking
+    pub async fn get_throttled_with_raw(&self) -> Result<(isize, String)> {
+        self.call_with_raw(Cmd::GetThrottled, None, parsers::throttled)
+            .await
+    }
+
+    /// `Vcgencmd::get_throttled_status` without blocking
+    pub async fn get_throttled_status(&self) -> Result<ThrottledStatus> {
+        self.get_throttled().await.map(interpret_bit_pattern)
+    }
+
+    /// `Vcgencmd::is_undervolted` without blocking
+    pub async fn is_undervolted(&self) -> Result<bool> {
+        Ok(self.get_throttled_status().await?.under_voltage)
+    }
+
+    /// `Vcgencmd::is_throttled` without blocking
+    pub async fn is_throttled(&self) -> Result<bool> {
+        Ok(self.get_throttled_status().await?.currently_throttled)
+    }
+
+    /// `Vcgencmd::pmic_read_adc` without blocking
+    pub async fn pmic_read_adc(&self) -> Result<Vec<AdcChannel>> {
+        self.call(Cmd::PmicReadAdc, None, parsers::pmic_adc).await
+    }
+
+    /// `Vcgencmd::display_power` without blocking
+    pub async fn display_power(&self, id: u8) -> Result<Option<bool>> {
+        self.call(
+            Cmd::DisplayPower,
+            Some(Src::Display(id)),
+            parsers::display_power,
+        )
+        .await
+    }
+
+    /// `Vcgencmd::get_lcd_info` without blocking
+    pub async fn get_lcd_info(&self) -> Result<LcdInfo> {
+        self.call(Cmd::GetLcdInfo, None, parsers::lcd_info).await
+    }
+
+    /// `Vcgencmd::hdmi_timings` without blocking
+    pub async fn hdmi_timings(&self) -> Result<Option<HdmiTimings>> {
+        self.call(Cmd::HdmiTimings, None, parsers::hdmi_timings)
+            .await
+    }
+
+    /// `Vcgencmd::temp_limits` without blocking
+    pub async fn temp_limits(&self) -> Result<TempLimits> {
+        if let Some(limits) = self.limits.get() {
+            return Ok(*limits);
+        }
+
+        let soft = self
+            .get_config(Src::Config(ConfigSrc::TempSoftLimit))
+            .await?;
+        let hard = self.get_config(Src::Config(ConfigSrc::TempLimit)).await?;
+        Ok(*self
+            .limits
+            .get_or_init(|| TempLimits::from_config(soft, hard)))
+    }
+
+    /// `Vcgencmd::read` without blocking
+    pub async fn read(&self, metric: Metric) -> Result<Reading> {
+        let reading = match metric {
+            Metric::Temp => Reading::Temp(self.measure_temp().await?),
+            Metric::TempHeadroom => {
+                let limits = self.temp_limits().await?;
+                Reading::TempHeadroom(limits.headroom(self.measure_temp().await?))
+            }
+            Metric::Throttled => Reading::Throttled(self.get_throttled().await?),
+            Metric::Clock(src) => Reading::Clock(src, self.measure_clock(Src::Clock(src)).await?),
+            Metric::Volts(src) => Reading::Volts(src, self.measure_volts(Src::Volt(src)).await?),
+            Metric::Mem(src) => Reading::Mem(src, self.get_mem(Src::Mem(src)).await?),
+            Metric::Derived(_) => return metric.read(),
+        };
+
+        Ok(reading)
+    }
+
+    /// A sample of `metrics` read concurrently, the async counterpart of
+    /// `SnapshotSpec::capture`
+    pub(crate) async fn sample(&self, metrics: &[Metric]) -> Sample {
+        let timestamp = SystemTime::now();
+        let results = join_all(metrics.iter().map(|&metric| self.read(metric))).await;
+
+        let mut sample = Sample {
+            timestamp,
+            readings: Vec::new(),
+            errors: Vec::new(),
+        };
+        for (&metric, result) in metrics.iter().zip(results) {
+            match result {
+                Ok(reading) => sample.readings.push(reading),
+                Err(error) => sample.errors.push((metric, error)),
+            }
+        }
+        sample
+    }
+
+    /// `Snapshot::capture` through this client without blocking, reading the metrics
+    /// concurrently like `Snapshot::capture`
+    pub async fn snapshot(&self) -> Snapshot {
+        let sample = self.sample(&SNAPSHOT_METRICS).await;
+
+        let limits = self.temp_limits().await.unwrap_or_default();
+        let mut snapshot = Snapshot::from_sample(&sample, limits);
+        snapshot.errors = sample.errors;
+        snapshot
+    }
+}
+
+/// `crate::measure_clock` without blocking
+pub async fn measure_clock(src: Src) -> Result<isize> {
+    AsyncVcgencmd::global().measure_clock(src).await
+}
+
+/// `crate::measure_clock_with_raw` without blocking
+pub async fn measure_clock_with_raw(src: Src) -> Result<(isize, String)> {
+    AsyncVcgencmd::global().measure_clock_with_raw(src).await
+}
+
+/// `crate::measure_clock_all` without blocking
+pub async fn measure_clock_all() -> Result<HashMap<ClockSrc, isize>> {
+    AsyncVcgencmd::global().measure_clock_all().await
+}
+
+/// `crate::measure_volts` without blocking
+pub async fn measure_volts(src: Src) -> Result<f64> {
+    AsyncVcgencmd::global().measure_volts(src).await
+}
+
+/// `crate::measure_volts_with_raw` without blocking
+pub async fn measure_volts_with_raw(src: Src) -> Result<(f64, String)> {
+    AsyncVcgencmd::global().measure_volts_with_raw(src).await
+}
+
+/// `crate::measure_volts_all` without blocking
+pub async fn measure_volts_all() -> Result<VoltRails> {
+    AsyncVcgencmd::global().measure_volts_all().await
+}
+
+/// `crate::measure_temp` without blocking
+pub async fn measure_temp() -> Result<f64> {
+    AsyncVcgencmd::global().measure_temp().await
+}
+
+/// `crate::measure_temp_with_raw` without blocking
+pub async fn measure_temp_with_raw() -> Result<(f64, String)> {
+    AsyncVcgencmd::global().measure_temp_with_raw().await
+}
+
+/// `crate::get_mem` without blocking
+pub async fn get_mem(src: Src) -> Result<isize> {
+    AsyncVcgencmd::global().get_mem(src).await
+}
+
+/// `crate::get_mem_with_raw` without blocking
+pub async fn get_mem_with_raw(src: Src) -> Result<(isize, String)> {
+    AsyncVcgencmd::global().get_mem_with_raw(src).await
+}
+
+/// `crate::get_mem_split` without blocking
+pub async fn get_mem_split() -> Result<MemSplit> {
+    AsyncVcgencmd::global().get_mem_split().await
+}
+
+/// `crate::get_gpu_memory_pressure` without blocking
+pub async fn get_gpu_memory_pressure() -> Result<GpuMemoryPressure> {
+    AsyncVcgencmd::global().get_gpu_memory_pressure().await
+}
+
+/// `crate::mem_reloc_stats` without blocking
+pub async fn mem_reloc_stats() -> Result<RelocStats> {
+    AsyncVcgencmd::global().mem_reloc_stats().await
+}
+
+/// `crate::get_config` without blocking
+pub async fn get_config(src: Src) -> Result<isize> {
+    AsyncVcgencmd::global().get_config(src).await
+}
+
+/// `crate::get_config_with_raw` without blocking
+pub async fn get_config_with_raw(src: Src) -> Result<(isize, String)> {
+    AsyncVcgencmd::global().get_config_with_raw(src).await
+}
+
+/// `crate::get_throttled` without blocking
+pub async fn get_throttled() -> Result<isize> {
+    AsyncVcgencmd::global().get_throttled().await
+}
+
+/// `crate::get_throttled_with_raw` without blocking
+pub async fn get_throttled_with_raw() -> Result<(isize, String)> {
+    AsyncVcgencmd::global().get_throttled_with_raw().await
+}
+
+/// `crate::get_throttled_status` without blocking
+pub async fn get_throttled_status() -> Result<ThrottledStatus> {
+    AsyncVcgencmd::global().get_throttled_status().await
+}
+
+/// `crate::is_undervolted` without blocking
+pub async fn is_undervolted() -> Result<bool> {
+    AsyncVcgencmd::global().is_undervolted().await
+}
+
+/// `crate::is_throttled` without blocking
+pub async fn is_throttled() -> Result<bool> {
+    AsyncVcgencmd::global().is_throttled().await
+}
+
+/// `crate::pmic_read_adc` without blocking
+pub async fn pmic_read_adc() -> Result<Vec<AdcChannel>> {
+    AsyncVcgencmd::global().pmic_read_adc().await
+}
+
+/// `crate::display_power` without blocking
+pub async fn display_power(id: u8) -> Result<Option<bool>> {
+    AsyncVcgencmd::global().display_power(id).await
+}
+
+/// `crate::get_lcd_info` without blocking
+pub async fn get_lcd_info() -> Result<LcdInfo> {
+    AsyncVcgencmd::global().get_lcd_info().await
+}
+
+/// `crate::hdmi_timings` without blocking
+pub async fn hdmi_timings() -> Result<Option<HdmiTimings>> {
+    AsyncVcgencmd::global().hdmi_timings().await
+}
+
+/// `Metric::read` without blocking
+pub async fn read(metric: Metric) -> Result<Reading> {
+    let client = AsyncVcgencmd::global();
+    let reading = client.read(metric).await;
+    client.cache_limits();
+    reading
+}
+
+/// `Snapshot::capture` without blocking, reading the metrics one after the other
+pub async fn snapshot() -> Snapshot {
+    let client = AsyncVcgencmd::global();
+    let snapshot = client.snapshot().await;
+    client.cache_limits();
+    snapshot
+}
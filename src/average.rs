@@ -0,0 +1,218 @@
+//! Averaged readings of a single metric
+//!
+//! A single reading of the core voltage or a clock is noisy: the firmware reports the
+//! value of the moment, and the call to vcgencmd itself causes load. `measure_avg` takes
+//! a number of readings spaced apart, drops the outliers among them and summarizes the
+//! rest.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use vcgencmd::average::measure_avg;
+//! use vcgencmd::monitor::Metric;
+//! use vcgencmd::VoltSrc;
+//!
+//! let core = Metric::Volts(VoltSrc::Core);
+//! if let Some(volts) = measure_avg(core, 10, Duration::from_millis(100))? {
+//!     println!("{:.4} V ± {:.4}", volts.mean, volts.stddev);
+//! }
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use crate::calibrate::Stats;
+use crate::monitor::{Metric, Reading};
+use crate::Result;
+
+/// Readings further than this many (scaled) median absolute deviations from the median
+/// are outliers, the usual cut-off of the modified z-score
+const OUTLIER_SCORE: f64 = 3.5;
+
+/// Scales the median absolute deviation to the standard deviation of normal data
+const MAD_SCALE: f64 = 1.4826;
+
+/// The summary of the readings kept by `measure_avg`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Average {
+    pub metric: Metric,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Sample standard deviation, 0 for a single reading
+    pub stddev: f64,
+    /// Number of readings the summary is made of
+    pub count: usize,
+    /// Number of readings dropped as outliers
+    pub outliers: usize,
+}
+
+/// e.g. `volts.core: 0.8563 ± 0.0012 (0.8538..0.8588, 9 readings, 1 outlier)`
+impl fmt::Display for Average {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ± {} ({}..{}, {} readings, {} outlier{})",
+            self.metric.name(),
+            self.mean,
+            self.stddev,
+            self.min,
+            self.max,
+            self.count,
+            self.outliers,
+            if self.outliers == 1 { "" } else { "s" }
+        )
+    }
+}
+
+impl Average {
+    /// Summarize `values` of `metric`, dropping outliers first. `None` if there are none.
+    pub fn from_values(metric: Metric, values: &[f64]) -> Option<Average> {
+        let kept = without_outliers(values);
+        let stats = Stats::from_values(kept.iter().copied())?;
+        let variance = match stats.count {
+            1 => 0.0,
+            count => {
+                kept.iter()
+                    .map(|value| (value - stats.mean).powi(2))
+                    .sum::<f64>()
+                    / (count - 1) as f64
+            }
+        };
+
+        Some(Average {
+            metric,
+            mean: stats.mean,
+            min: stats.min,
+            max: stats.max,
+            stddev: variance.sqrt(),
+            count: stats.count,
+            outliers: values.len() - kept.len(),
+        })
+    }
+}
+
+/// Take `samples` readings of `metric`, `spacing` apart, and summarize them.
+///
+/// Fails with the first reading that fails. `None` if no reading was taken, or the metric
+/// has no numeric value, like `Metric::Throttled`.
+pub fn measure_avg(metric: Metric, samples: usize, spacing: Duration) -> Result<Option<Average>> {
+    measure_avg_with(metric, samples, spacing, Metric::read)
+}
+
+/// `measure_avg` with the readings taken by `read`, see `Monitor::sampler`
+pub fn measure_avg_with<F>(
+    metric: Metric,
+    samples: usize,
+    spacing: Duration,
+    mut read: F,
+) -> Result<Option<Average>>
+where
+    F: FnMut(Metric) -> Result<Reading>,
+{
+    let mut values = Vec::with_capacity(samples);
+    for i in 0..samples {
+        if i > 0 {
+            thread::sleep(spacing);
+        }
+        match read(metric)?.value() {
+            Some(value) => values.push(value),
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Average::from_values(metric, &values))
+}
+
+/// `values` without those whose modified z-score exceeds `OUTLIER_SCORE`.
+///
+/// The median absolute deviation is used rather than the standard deviation, as a single
+/// large outlier inflates the latter enough to hide itself. If most readings are equal,
+/// as with clocks, the deviation is 0 and nothing is dropped.
+fn without_outliers(values: &[f64]) -> Vec<f64> {
+    let center = match median(values) {
+        Some(center) => center,
+        None => return Vec::new(),
+    };
+    let deviations: Vec<_> = values.iter().map(|value| (value - center).abs()).collect();
+    let mad = median(&deviations).unwrap_or_default() * MAD_SCALE;
+    if mad == 0.0 {
+        return values.to_vec();
+    }
+
+    values
+        .iter()
+        .copied()
+        .filter(|value| (value - center).abs() / mad <= OUTLIER_SCORE)
+        .collect()
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    match sorted.len() % 2 {
+        0 => Some((sorted.get(middle.checked_sub(1)?)? + sorted.get(middle)?) / 2.0),
+        _ => sorted.get(middle).copied(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClockSrc, VoltSrc};
+
+    const CORE: Metric = Metric::Volts(VoltSrc::Core);
+
+    #[test]
+    fn test_median() {
+        assert_eq!(Some(2.0), median(&[3.0, 1.0, 2.0]));
+        assert_eq!(Some(2.5), median(&[4.0, 1.0, 3.0, 2.0]));
+        assert_eq!(None, median(&[]));
+    }
+
+    #[test]
+    fn test_outliers_are_dropped() {
+        let values = [0.85, 0.86, 0.85, 0.86, 0.85, 1.2];
+        let average = Average::from_values(CORE, &values).unwrap();
+        assert_eq!(5, average.count);
+        assert_eq!(1, average.outliers);
+        assert_eq!(0.86, average.max);
+        assert!((average.mean - 0.854).abs() < 1e-9);
+        assert!(average.stddev > 0.0);
+    }
+
+    #[test]
+    fn test_equal_readings_are_kept() {
+        let values = [600e6, 600e6, 600e6, 1500e6];
+        let average = Average::from_values(Metric::Clock(ClockSrc::Arm), &values).unwrap();
+        assert_eq!(4, average.count);
+        assert_eq!(0, average.outliers);
+
+        let single = Average::from_values(Metric::Temp, &[42.0]).unwrap();
+        assert_eq!(0.0, single.stddev);
+        assert_eq!(None, Average::from_values(Metric::Temp, &[]));
+    }
+
+    #[test]
+    fn test_measure_avg_with() {
+        let mut volts = 0.84;
+        let average = measure_avg_with(CORE, 3, Duration::ZERO, |metric| {
+            volts += 0.01;
+            match metric {
+                Metric::Volts(src) => Ok(Reading::Volts(src, volts)),
+                _ => unreachable!(),
+            }
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(3, average.count);
+        assert!((average.mean - 0.86).abs() < 1e-9);
+
+        let throttled = measure_avg_with(Metric::Throttled, 3, Duration::ZERO, |_| {
+            Ok(Reading::Throttled(0))
+        });
+        assert_eq!(None, throttled.unwrap());
+    }
+}
@@ -0,0 +1,122 @@
+//! Sending gencmd commands in-process through `libbcm_host`, with the `bcm-host` feature
+//!
+//! vcgencmd is a thin wrapper around `vc_gencmd()` of the VideoCore host library. Linking
+//! that library and calling it directly saves a fork and exec per reading, and works where
+//! the firmware libraries are installed but the `vcgencmd` binary isn't, e.g. in a slim
+//! container with `/dev/vchiq` mapped into it. A `BcmHostExecutor` is an `Executor`, so a
+//! `Vcgencmd` client built on it offers every reading, and `gencmd` sends any command line:
+//!
+//! ```rust,no_run
+//! use vcgencmd::bcm_host::BcmHostExecutor;
+//! use vcgencmd::Vcgencmd;
+//!
+//! let client = Vcgencmd::with_executor(BcmHostExecutor::new());
+//! println!("{} °C", client.measure_temp()?);
+//! println!("{}", client.executor().gencmd("get_rsts")?);
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+//!
+//! The library is `libbcm_host.so` from the `libraspberrypi-dev` package, or the one in
+//! `/opt/vc/lib` on older releases, which the build script adds to the linker path. It
+//! isn't available on 64 bit releases of Raspberry Pi OS before Bookworm.
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::sync::{Mutex, Once};
+
+use crate::executor::Executor;
+use crate::{describe, Cmd, Error, ExecutionError, Result, Src};
+
+/// Large enough for the longest reply, that of `commands`
+const RESPONSE_SIZE: usize = 4096;
+
+#[link(name = "bcm_host")]
+extern "C" {
+    fn bcm_host_init();
+    fn vc_gencmd(response: *mut c_char, maxlen: c_int, format: *const c_char, ...) -> c_int;
+}
+
+static INIT: Once = Once::new();
+/// Serializes the calls, the library isn't documented to be thread safe
+static GENCMD: Mutex<()> = Mutex::new(());
+
+/// The text up to the terminating NUL of a reply
+fn reply_text(response: &[u8]) -> String {
+    let end = response
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(response.len());
+    String::from_utf8_lossy(response.get(..end).unwrap_or_default()).into_owned()
+}
+
+/// Sends commands through `vc_gencmd()`, see the module documentation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BcmHostExecutor {
+    _private: (),
+}
+
+impl BcmHostExecutor {
+    /// Connect to the firmware, once per process, which `bcm_host_init()` does on the first
+    /// use of the library
+    pub fn new() -> BcmHostExecutor {
+        // SAFETY: only ever called once, before any call to `vc_gencmd`
+        INIT.call_once(|| unsafe { bcm_host_init() });
+        BcmHostExecutor { _private: () }
+    }
+
+    /// The reply of the firmware to the command line `command`, e.g. `measure_volts core`,
+    /// unparsed and including error replies like `error=2 error_msg="Invalid arguments"`
+    pub fn gencmd(&self, command: &str) -> Result<String> {
+        let failed = |message: String| Error::Vchi {
+            command: command.to_owned(),
+            message,
+        };
+        let line =
+            CString::new(command).map_err(|_| failed("the command contains a NUL".to_owned()))?;
+
+        let mut response = vec![0u8; RESPONSE_SIZE];
+        let _serialized = GENCMD.lock().unwrap_or_else(|e| e.into_inner());
+        // SAFETY: the library writes at most `maxlen` bytes including the NUL, and the
+        // format consumes the one string passed
+        let result = unsafe {
+            vc_gencmd(
+                response.as_mut_ptr().cast::<c_char>(),
+                RESPONSE_SIZE as c_int,
+                b"%s\0".as_ptr().cast::<c_char>(),
+                line.as_ptr(),
+            )
+        };
+        if result != 0 {
+            return Err(failed(format!("vc_gencmd() failed with {}", result)));
+        }
+
+        Ok(reply_text(&response))
+    }
+}
+
+impl Executor for BcmHostExecutor {
+    fn run(&self, cmd: Cmd, src: Option<Src>) -> std::result::Result<String, ExecutionError> {
+        self.gencmd(&describe(cmd, src))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_text() {
+        assert_eq!("temp=48.3'C", reply_text(b"temp=48.3'C\0\0garbage"));
+        assert_eq!("throttled=0x0", reply_text(b"throttled=0x0"));
+        assert_eq!("", reply_text(&[0; 8]));
+    }
+
+    #[cfg(target_arch = "arm")]
+    #[test]
+    fn test_measure_temp() {
+        let client = crate::Vcgencmd::with_executor(BcmHostExecutor::new());
+        let temp = client.measure_temp();
+        dbg!(&temp);
+        assert!(temp.is_ok());
+    }
+}
@@ -0,0 +1,1048 @@
+//! Command line parsing, declared with clap so help and completions follow from it
+
+use std::ffi::OsString;
+use std::iter;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use vcgencmd::alert::Severity;
+use vcgencmd::config::{MonitorConfig, SinkConfig, Threshold};
+use vcgencmd::events::Condition;
+use vcgencmd::monitor::Metric;
+use vcgencmd::snapshot::{SnapshotSpec, SNAPSHOT_METRICS};
+use vcgencmd::{ClockSrc, Escalation, Invocation, MemSrc, PrivilegeMode, VoltSrc};
+
+use crate::config::Config;
+
+pub const BIN: &str = "vcgencmd-rs";
+
+/// The command line as declared to clap, turned into `Args` by `parse`
+#[derive(Debug, Parser)]
+#[command(name = BIN, version, about = "The typed API of the vcgencmd crate on the command line")]
+pub struct Cli {
+    #[command(subcommand)]
+    command: CliCommand,
+    /// Print results as JSON, short for --format json
+    #[arg(long, global = true)]
+    json: bool,
+    /// text, json, or for snapshot, watch and --hosts csv and tsv
+    #[arg(long, global = true, value_name = "FORMAT", value_parser = table_parser(&FORMATS))]
+    format: Option<Format>,
+    /// Start csv and tsv output with a row of column names
+    #[arg(long, global = true)]
+    header: bool,
+    /// TOML file with defaults for the other options and the monitor to run
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Path to vcgencmd, defaults to looking it up in PATH
+    #[arg(long, global = true, value_name = "PATH")]
+    binary: Option<PathBuf>,
+    /// Run vcgencmd without sudo
+    #[arg(long, global = true)]
+    no_sudo: bool,
+    /// Run vcgencmd through doas, pkexec or the program CMD instead of sudo
+    #[arg(long, global = true, value_name = "CMD", value_parser = parse_escalation)]
+    escalate: Option<Escalation>,
+    /// Run vcgencmd on HOST over ssh, e.g. pi@pi4.local
+    #[arg(long, global = true, value_name = "HOST", value_parser = parse_host)]
+    host: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum CliCommand {
+    /// SoC temperature in °C
+    Temp(Hosts),
+    /// Clock frequency in Hz
+    Clock {
+        #[arg(default_value = "arm", value_parser = table_parser(&CLOCK_SRCS))]
+        src: ClockSrc,
+        #[command(flatten)]
+        hosts: Hosts,
+    },
+    /// Voltage in V
+    Volts {
+        #[arg(default_value = "core", value_parser = table_parser(&VOLT_SRCS))]
+        src: VoltSrc,
+        #[command(flatten)]
+        hosts: Hosts,
+    },
+    /// Memory split in MB
+    Mem {
+        #[arg(value_parser = table_parser(&MEM_SRCS))]
+        src: MemSrc,
+        #[command(flatten)]
+        hosts: Hosts,
+    },
+    /// Decoded get_throttled flags
+    Throttled {
+        #[command(flatten)]
+        hosts: Hosts,
+        #[command(flatten)]
+        checks: Checks,
+    },
+    /// Meaning and severity of every set get_throttled bit, of HEX (e.g. 0x50005) or of
+    /// the current value
+    ExplainThrottled {
+        #[arg(value_parser = parse_hex)]
+        hex: Option<isize>,
+        #[command(flatten)]
+        checks: Checks,
+    },
+    /// Temperature, throttling, clocks and voltage with a health summary, or the metrics
+    /// chosen with --fields
+    Snapshot {
+        #[command(flatten)]
+        fields: Fields,
+        #[command(flatten)]
+        hosts: Hosts,
+        #[command(flatten)]
+        checks: Checks,
+    },
+    /// Print the snapshot metrics, or those of --fields, every --interval
+    Watch {
+        #[command(flatten)]
+        fields: Fields,
+        #[command(flatten)]
+        interval: Interval,
+    },
+    /// Live terminal dashboard of temperature, clocks and throttling, quit with q, needs
+    /// the tui feature
+    Dashboard {
+        #[command(flatten)]
+        interval: Interval,
+    },
+    /// Nagios/Icinga plugin: temperature against -w/-c and throttling, exits with 0, 1, 2
+    /// or 3 for OK, WARNING, CRITICAL and UNKNOWN
+    Check {
+        /// Warn from TEMP °C, defaults to the soft limit
+        #[arg(short, long, value_name = "TEMP", value_parser = parse_temp)]
+        warning: Option<f64>,
+        /// Critical from TEMP °C, defaults to 5 °C below the hard limit
+        #[arg(short, long, value_name = "TEMP", value_parser = parse_temp)]
+        critical: Option<f64>,
+    },
+    /// Serve the snapshot metrics, or those of the monitor of --config, for Prometheus at
+    /// /metrics
+    Serve {
+        /// Address to listen on, defaults to 0.0.0.0:9110
+        #[arg(long, value_name = "ADDR")]
+        listen: Option<String>,
+        #[command(flatten)]
+        interval: Interval,
+    },
+    /// Log or publish the snapshot metrics, or those of the monitor of --config,
+    /// continuously
+    Export {
+        #[command(subcommand)]
+        format: ExportFormat,
+    },
+    /// Run the monitor of --config with its thresholds and sinks
+    Monitor {
+        #[command(flatten)]
+        interval: Interval,
+    },
+    /// Print the completion script for SHELL
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ExportFormat {
+    /// Log the snapshot metrics as JSON Lines
+    Jsonl {
+        /// File to append JSON Lines to, defaults to stdout
+        #[arg(long, value_name = "PATH")]
+        output: Option<String>,
+        #[command(flatten)]
+        interval: Interval,
+    },
+    /// Publish the snapshot metrics as JSON to an MQTT broker
+    Mqtt {
+        /// MQTT broker to publish to
+        #[arg(long, value_name = "HOST:PORT")]
+        broker: String,
+        /// MQTT topic, defaults to vcgencmd
+        #[arg(long, value_name = "TOPIC")]
+        topic: Option<String>,
+        /// MQTT client identifier, defaults to vcgencmd-<pid>
+        #[arg(long, value_name = "ID")]
+        client_id: Option<String>,
+        /// Have the broker retain the last sample
+        #[arg(long)]
+        retain: bool,
+        #[command(flatten)]
+        interval: Interval,
+    },
+}
+
+/// `--hosts`, for the commands whose readings fit in a table
+#[derive(Debug, clap::Args)]
+struct Hosts {
+    /// Read from each of the comma separated hosts over ssh and print a table with a row
+    /// per host
+    #[arg(long = "hosts", value_name = "LIST", value_delimiter = ',', value_parser = parse_host)]
+    list: Vec<String>,
+}
+
+/// `--fail-on`, for the commands reading the throttled flags
+#[derive(Debug, clap::Args)]
+struct Checks {
+    /// Exit with 2 if one of the comma separated checks fails. Checks are the conditions
+    /// under-voltage, arm-frequency-capped, throttled and soft-temp-limit, each also with
+    /// an -occurred suffix, and the health states degraded and critical. A check also
+    /// fails if the value it needs couldn't be read
+    #[arg(long, value_name = "LIST", value_delimiter = ',', value_parser = parse_check)]
+    fail_on: Vec<FailOn>,
+}
+
+#[derive(Debug, clap::Args)]
+struct Fields {
+    /// Comma separated metrics to read instead, e.g. temp,clock.arm,volts.core,throttled
+    #[arg(long = "fields", value_name = "LIST", value_parser = SnapshotSpec::parse)]
+    spec: Option<SnapshotSpec>,
+}
+
+#[derive(Debug, clap::Args)]
+struct Interval {
+    /// Seconds between samples, defaults to the interval of the monitor of --config or 5,
+    /// and to 1 for dashboard
+    #[arg(long = "interval", value_name = "SECS", value_parser = parse_interval)]
+    secs: Option<Duration>,
+}
+
+/// Everything given on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Args {
+    pub command: Command,
+    pub format: Format,
+    /// Print a header row before csv or tsv output
+    pub header: bool,
+    pub fail_on: Vec<FailOn>,
+    pub invocation: Invocation,
+}
+
+/// A check of `--fail-on`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailOn {
+    Active(Condition),
+    Occurred(Condition),
+    /// The health is at least this bad
+    Severity(Severity),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Temp,
+    Clock(ClockSrc),
+    Volts(VoltSrc),
+    Mem(MemSrc),
+    Throttled,
+    /// Explain the given bit pattern, or query the current one
+    ExplainThrottled(Option<isize>),
+    /// The full snapshot, or just the metrics of a spec
+    Snapshot(Option<SnapshotSpec>),
+    /// Print the metrics of a spec every interval until stopped
+    Watch {
+        spec: SnapshotSpec,
+        interval: Duration,
+    },
+    /// A live terminal dashboard, updated every interval until quit
+    Dashboard {
+        interval: Duration,
+    },
+    /// The metrics of a spec read from each host over ssh, as a table with a row per host
+    Hosts {
+        hosts: Vec<String>,
+        spec: SnapshotSpec,
+    },
+    /// A check plugin run, thresholds left out default to the temperature limits
+    Check {
+        warning: Option<f64>,
+        critical: Option<f64>,
+    },
+    /// A long running `serve`, `export` or `monitor`, sampling until stopped
+    Service(MonitorConfig),
+    Completions(Shell),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl Format {
+    /// The column separator of csv and tsv, `None` for other formats
+    pub fn delimiter(self) -> Option<char> {
+        match self {
+            Format::Csv => Some(','),
+            Format::Tsv => Some('\t'),
+            Format::Text | Format::Json => None,
+        }
+    }
+}
+
+pub const FORMATS: [(&str, Format); 4] = [
+    ("text", Format::Text),
+    ("json", Format::Json),
+    ("csv", Format::Csv),
+    ("tsv", Format::Tsv),
+];
+
+pub use clap_complete::Shell;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+const DASHBOARD_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_LISTEN: &str = "0.0.0.0:9110";
+
+pub const CLOCK_SRCS: [(&str, ClockSrc); 12] = [
+    ("arm", ClockSrc::Arm),
+    ("core", ClockSrc::Core),
+    ("dpi", ClockSrc::Dpi),
+    ("emmc", ClockSrc::Emmc),
+    ("h264", ClockSrc::H264),
+    ("hdmi", ClockSrc::Hdmi),
+    ("isp", ClockSrc::Isp),
+    ("pixel", ClockSrc::Pixel),
+    ("pwm", ClockSrc::Pwm),
+    ("uart", ClockSrc::Uart),
+    ("v3d", ClockSrc::V3d),
+    ("vec", ClockSrc::Vec),
+];
+
+pub const VOLT_SRCS: [(&str, VoltSrc); 4] = [
+    ("core", VoltSrc::Core),
+    ("sdram_c", VoltSrc::SdramC),
+    ("sdram_i", VoltSrc::SdramI),
+    ("sdram_p", VoltSrc::SdramP),
+];
+
+pub const MEM_SRCS: [(&str, MemSrc); 6] = [
+    ("arm", MemSrc::Arm),
+    ("gpu", MemSrc::Gpu),
+    ("malloc", MemSrc::Malloc),
+    ("malloc_total", MemSrc::MallocTotal),
+    ("reloc", MemSrc::Reloc),
+    ("reloc_total", MemSrc::RelocTotal),
+];
+
+/// The command line name of `src` in one of the source tables
+pub fn name_of<T: Copy + PartialEq>(table: &[(&'static str, T)], src: T) -> &'static str {
+    table
+        .iter()
+        .find(|&&(_, candidate)| candidate == src)
+        .map_or("", |&(name, _)| name)
+}
+
+/// Parses the names in `table`, which are offered as completions and listed on errors
+fn table_parser<T>(table: &'static [(&'static str, T)]) -> impl TypedValueParser<Value = T>
+where
+    T: Copy + Send + Sync + 'static,
+{
+    PossibleValuesParser::new(table.iter().map(|&(name, _)| name)).try_map(move |name| {
+        table
+            .iter()
+            .find(|(candidate, _)| *candidate == name)
+            .map(|&(_, value)| value)
+            .ok_or_else(|| format!("unknown value '{}'", name))
+    })
+}
+
+/// Parse a bit pattern as printed by `vcgencmd get_throttled`, the `0x` prefix is optional
+fn parse_hex(value: &str) -> Result<isize, String> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+
+    isize::from_str_radix(digits, 16).map_err(|_| format!("invalid hex value '{}'", value))
+}
+
+fn parse_temp(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(temp) if temp.is_finite() => Ok(temp),
+        _ => Err(format!("invalid temperature '{}'", value)),
+    }
+}
+
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(format!("invalid interval '{}'", value)),
+    }
+}
+
+/// Parse one of the checks of `--fail-on`, `-` and `_` are interchangeable
+fn parse_check(check: &str) -> Result<FailOn, String> {
+    let name = check.trim().replace('-', "_");
+    let condition = |name: &str| Condition::from_name(name);
+
+    match name.as_str() {
+        "degraded" => Some(FailOn::Severity(Severity::Warning)),
+        "critical" => Some(FailOn::Severity(Severity::Critical)),
+        name => match name.strip_suffix("_occurred") {
+            Some(name) => condition(name).map(FailOn::Occurred),
+            None => condition(name).map(FailOn::Active),
+        },
+    }
+    .ok_or_else(|| format!("unknown check '{}'", check.trim()))
+}
+
+/// `given`, or the setting of the config file parsed like the option
+fn or_config<T>(
+    given: Option<T>,
+    setting: Option<&str>,
+    parse: fn(&str) -> Result<T, String>,
+) -> Result<Option<T>, String> {
+    match given {
+        Some(value) => Ok(Some(value)),
+        None => setting.map(parse).transpose(),
+    }
+}
+
+impl Config {
+    fn checks(&self, given: Checks) -> Result<Vec<FailOn>, String> {
+        match given.fail_on.is_empty() {
+            true => self
+                .fail_on
+                .iter()
+                .map(|check| parse_check(check))
+                .collect(),
+            false => Ok(given.fail_on),
+        }
+    }
+
+    /// The limit of the temperature threshold of the monitor, `limit` picking which
+    fn temp_limit(&self, limit: fn(&Threshold) -> Option<f64>) -> Option<f64> {
+        self.monitor
+            .iter()
+            .flat_map(|monitor| &monitor.thresholds)
+            .find(|threshold| threshold.metric == Metric::Temp)
+            .and_then(limit)
+    }
+
+    /// The monitor of `serve` and `export`: the one of the config file without its sinks,
+    /// or one sampling the snapshot metrics, and `sink`
+    fn service(&self, interval: Interval, sink: SinkConfig) -> MonitorConfig {
+        let mut monitor = match self.monitor {
+            Some(ref monitor) => MonitorConfig {
+                sinks: Vec::new(),
+                ..monitor.clone()
+            },
+            None => MonitorConfig::new(DEFAULT_INTERVAL, &SNAPSHOT_METRICS),
+        };
+        if let Some(secs) = interval.secs {
+            monitor.interval = secs;
+        }
+        monitor.sinks.push(sink);
+        monitor
+    }
+}
+
+/// Parse the arguments following the program name.
+///
+/// Fails with clap's error, which is also how `--help` and `--version` are printed.
+pub fn parse<I, S>(args: I) -> Result<Args, clap::Error>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<OsString> + Clone,
+{
+    let cli = Cli::try_parse_from(
+        iter::once(OsString::from(BIN)).chain(args.into_iter().map(Into::into)),
+    )?;
+    cli.into_args()
+        .map_err(|message| Cli::command().error(ErrorKind::ArgumentConflict, message))
+}
+
+impl Cli {
+    fn into_args(self) -> Result<Args, String> {
+        let config = match self.config {
+            Some(ref path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        let mut invocation = vcgencmd::Config::cached().invocation();
+        if let Some(binary) = self.binary.or_else(|| config.binary.clone()) {
+            invocation.binary = binary;
+        }
+        if self.no_sudo {
+            invocation.privilege = PrivilegeMode::None;
+        } else if let Some(sudo) = config.sudo {
+            invocation.privilege = if sudo {
+                PrivilegeMode::Sudo
+            } else {
+                PrivilegeMode::None
+            };
+        }
+        let escalation = or_config(self.escalate, config.escalate.as_deref(), parse_escalation)?;
+        if let Some(escalation) = escalation {
+            invocation.escalation = escalation;
+        }
+        invocation.host = or_config(self.host, config.host.as_deref(), parse_host)?;
+
+        let mut hosts = Vec::new();
+        let mut fail_on = Vec::new();
+        let command = match self.command {
+            CliCommand::Temp(given) => {
+                hosts = given.list;
+                Command::Temp
+            }
+            CliCommand::Clock { src, hosts: given } => {
+                hosts = given.list;
+                Command::Clock(src)
+            }
+            CliCommand::Volts { src, hosts: given } => {
+                hosts = given.list;
+                Command::Volts(src)
+            }
+            CliCommand::Mem { src, hosts: given } => {
+                hosts = given.list;
+                Command::Mem(src)
+            }
+            CliCommand::Throttled {
+                hosts: given,
+                checks,
+            } => {
+                hosts = given.list;
+                fail_on = config.checks(checks)?;
+                Command::Throttled
+            }
+            CliCommand::ExplainThrottled { hex, checks } => {
+                fail_on = config.checks(checks)?;
+                Command::ExplainThrottled(hex)
+            }
+            CliCommand::Snapshot {
+                fields,
+                hosts: given,
+                checks,
+            } => {
+                hosts = given.list;
+                fail_on = config.checks(checks)?;
+                Command::Snapshot(fields.spec)
+            }
+            CliCommand::Watch { fields, interval } => {
+                let monitor = config.monitor.as_ref();
+                let monitor_spec = |monitor: &MonitorConfig| {
+                    let metrics = monitor.metrics.iter();
+                    metrics.fold(SnapshotSpec::new(), |spec, scheduled| {
+                        spec.metric(scheduled.metric)
+                    })
+                };
+                Command::Watch {
+                    spec: fields
+                        .spec
+                        .or_else(|| monitor.map(monitor_spec))
+                        .unwrap_or_default(),
+                    interval: interval
+                        .secs
+                        .or_else(|| monitor.map(|monitor| monitor.interval))
+                        .unwrap_or(DEFAULT_INTERVAL),
+                }
+            }
+            CliCommand::Dashboard { interval } => Command::Dashboard {
+                interval: interval.secs.unwrap_or(DASHBOARD_INTERVAL),
+            },
+            CliCommand::Check { warning, critical } => {
+                let warning = warning.or_else(|| config.temp_limit(|t| t.warning));
+                let critical = critical.or_else(|| config.temp_limit(|t| t.critical));
+                if let (Some(warning), Some(critical)) = (warning, critical) {
+                    if warning > critical {
+                        return Err("the warning threshold is above the critical one".to_owned());
+                    }
+                }
+                Command::Check { warning, critical }
+            }
+            CliCommand::Serve { listen, interval } => {
+                let listen = listen.unwrap_or_else(|| DEFAULT_LISTEN.to_owned());
+                Command::Service(config.service(interval, SinkConfig::Prometheus { listen }))
+            }
+            CliCommand::Export {
+                format: ExportFormat::Jsonl { output, interval },
+            } => Command::Service(config.service(
+                interval,
+                SinkConfig::Jsonl {
+                    path: output.map(PathBuf::from),
+                },
+            )),
+            CliCommand::Export {
+                format:
+                    ExportFormat::Mqtt {
+                        broker,
+                        topic,
+                        client_id,
+                        retain,
+                        interval,
+                    },
+            } => Command::Service(config.service(
+                interval,
+                SinkConfig::Mqtt {
+                    broker,
+                    topic,
+                    client_id,
+                    retain,
+                },
+            )),
+            CliCommand::Monitor { interval } => {
+                let mut monitor = config
+                    .monitor
+                    .clone()
+                    .ok_or("monitor needs a [monitor] table in the --config file")?;
+                if let Some(secs) = interval.secs {
+                    monitor.interval = secs;
+                }
+                Command::Service(monitor)
+            }
+            CliCommand::Completions { shell } => Command::Completions(shell),
+        };
+
+        let command = match hosts.is_empty() {
+            true => command,
+            false if invocation.host.is_some() => {
+                return Err("--host and --hosts can't be combined".to_owned())
+            }
+            false => Command::Hosts {
+                spec: host_spec(&command).ok_or(
+                    "--hosts only applies to temp, clock, volts, mem, throttled and snapshot",
+                )?,
+                hosts,
+            },
+        };
+
+        let format = match self.format {
+            Some(format) => format,
+            None if self.json => Format::Json,
+            None => Format::Text,
+        };
+
+        let command = match command {
+            // csv and tsv have fixed columns, which the full snapshot with its health hasn't
+            Command::Snapshot(None) if format.delimiter().is_some() => {
+                Command::Snapshot(Some(SnapshotSpec::default()))
+            }
+            Command::Snapshot(_) | Command::Watch { .. } | Command::Hosts { .. } => command,
+            _ if format.delimiter().is_some() => {
+                return Err("csv and tsv only apply to snapshot, watch and --hosts".to_owned())
+            }
+            _ if self.header => {
+                return Err("--header only applies to snapshot, watch and --hosts".to_owned())
+            }
+            _ => command,
+        };
+
+        Ok(Args {
+            command,
+            format,
+            header: self.header,
+            fail_on,
+            invocation,
+        })
+    }
+}
+
+/// A host for `ssh`, which mustn't look like an option or need quoting
+fn parse_host(host: &str) -> Result<String, String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || "@.-_:[]%".contains(c);
+    if host.is_empty() || host.starts_with('-') || !host.chars().all(valid) {
+        return Err(format!("invalid host '{}'", host));
+    }
+    Ok(host.to_owned())
+}
+
+/// `sudo`, `doas`, `pkexec` or the path of another program, which like a host mustn't
+/// need quoting for `ssh`
+fn parse_escalation(escalation: &str) -> Result<Escalation, String> {
+    match escalation {
+        "sudo" => return Ok(Escalation::Sudo),
+        "doas" => return Ok(Escalation::Doas),
+        "pkexec" => return Ok(Escalation::Pkexec),
+        _ => {}
+    }
+    let valid = |c: char| c.is_ascii_alphanumeric() || "/.-_+".contains(c);
+    if escalation.is_empty() || escalation.starts_with('-') || !escalation.chars().all(valid) {
+        return Err(format!("invalid escalation command '{}'", escalation));
+    }
+    Ok(Escalation::Custom(escalation.into()))
+}
+
+/// The metrics a command reads, for the table of `--hosts`
+fn host_spec(command: &Command) -> Option<SnapshotSpec> {
+    let metric = match *command {
+        Command::Temp => Metric::Temp,
+        Command::Clock(src) => Metric::Clock(src),
+        Command::Volts(src) => Metric::Volts(src),
+        Command::Mem(src) => Metric::Mem(src),
+        Command::Throttled => Metric::Throttled,
+        Command::Snapshot(Some(ref spec)) => return Some(spec.clone()),
+        Command::Snapshot(None) => return Some(SnapshotSpec::default()),
+        _ => return None,
+    };
+    Some(SnapshotSpec::new().metric(metric))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command<const N: usize>(args: [&str; N]) -> Result<Command, String> {
+        parse(args)
+            .map(|args| args.command)
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(Command::Temp), command(["temp"]));
+        assert_eq!(Ok(Command::Clock(ClockSrc::Arm)), command(["clock"]));
+        assert_eq!(Ok(Command::Clock(ClockSrc::V3d)), command(["clock", "v3d"]));
+        assert_eq!(
+            Ok(Command::Volts(VoltSrc::SdramP)),
+            command(["volts", "sdram_p"])
+        );
+        assert_eq!(Ok(Command::Mem(MemSrc::Gpu)), command(["mem", "gpu"]));
+        assert_eq!(
+            ErrorKind::DisplayHelp,
+            parse(["--help"]).unwrap_err().kind()
+        );
+        assert_eq!(
+            Ok(Command::ExplainThrottled(Some(0x50005))),
+            command(["explain-throttled", "0x50005"])
+        );
+        assert_eq!(
+            Ok(Command::ExplainThrottled(Some(0x50005))),
+            command(["explain-throttled", "50005"])
+        );
+        assert_eq!(
+            Ok(Command::ExplainThrottled(None)),
+            command(["explain-throttled"])
+        );
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        assert_eq!(Ok(Command::Snapshot(None)), command(["snapshot"]));
+        assert_eq!(
+            Ok(Command::Snapshot(Some(
+                SnapshotSpec::parse("temp,clock.arm").unwrap()
+            ))),
+            command(["snapshot", "--fields", "temp,clock.arm"])
+        );
+        assert!(command(["snapshot", "--fields", "temp,clock.gpu"])
+            .unwrap_err()
+            .contains("unknown field"));
+        assert!(command(["temp", "--fields", "temp"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_fail_on() {
+        let args = parse([
+            "throttled",
+            "--fail-on",
+            "under-voltage,throttled_occurred,critical",
+        ])
+        .unwrap();
+        assert_eq!(
+            vec![
+                FailOn::Active(Condition::UnderVoltage),
+                FailOn::Occurred(Condition::Throttled),
+                FailOn::Severity(Severity::Critical),
+            ],
+            args.fail_on
+        );
+        assert!(parse(["snapshot"]).unwrap().fail_on.is_empty());
+
+        assert!(command(["throttled", "--fail-on", "overheated"])
+            .unwrap_err()
+            .contains("unknown check 'overheated'"));
+        assert!(command(["temp", "--fail-on", "critical"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_check() {
+        assert_eq!(
+            Ok(Command::Check {
+                warning: Some(65.0),
+                critical: Some(75.5),
+            }),
+            command(["check", "-w", "65", "--critical", "75.5"])
+        );
+        assert_eq!(
+            Ok(Command::Check {
+                warning: None,
+                critical: None,
+            }),
+            command(["check"])
+        );
+        assert!(command(["check", "-w", "80", "-c", "70"]).is_err());
+        assert!(command(["check", "-w", "hot"]).is_err());
+        assert!(command(["snapshot", "-w", "60"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let path = std::env::temp_dir().join(format!("vcgencmd-rs-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "binary = \"/opt/vc/bin/vcgencmd\"\n\
+             fail_on = [\"throttled\"]\n\
+             [monitor]\n\
+             interval = 10\n\
+             metrics = [\"temp\", \"throttled\"]\n\
+             [[monitor.thresholds]]\n\
+             metric = \"temp\"\n\
+             warning = 50\n\
+             [[monitor.sinks]]\n\
+             type = \"mqtt\"\n\
+             broker = \"broker:1883\"\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        // flags win over the config, settings other commands use are ignored
+        let args = parse(["--config", path, "check", "-c", "70"]).unwrap();
+        assert_eq!(
+            Command::Check {
+                warning: Some(50.0),
+                critical: Some(70.0),
+            },
+            args.command
+        );
+        assert_eq!(
+            std::path::Path::new("/opt/vc/bin/vcgencmd"),
+            args.invocation.binary
+        );
+
+        // services sample the monitor's metrics, with only their own sink
+        let args = parse(["--config", path, "serve", "--binary", "vcgencmd"]).unwrap();
+        assert_eq!(std::path::Path::new("vcgencmd"), args.invocation.binary);
+        assert!(matches!(
+            args.command,
+            Command::Service(ref monitor) if monitor.interval == Duration::from_secs(10)
+                && monitor.metrics.len() == 2
+                && monitor.thresholds.len() == 1
+                && matches!(monitor.sinks[..], [SinkConfig::Prometheus { .. }])
+        ));
+        assert!(matches!(
+            command(["--config", path, "monitor", "--interval", "2"]),
+            Ok(Command::Service(ref monitor)) if monitor.interval == Duration::from_secs(2)
+                && matches!(monitor.sinks[..], [SinkConfig::Mqtt { .. }])
+        ));
+        assert_eq!(
+            Ok(Command::Watch {
+                spec: SnapshotSpec::new()
+                    .metric(Metric::Temp)
+                    .metric(Metric::Throttled),
+                interval: Duration::from_secs(10),
+            }),
+            command(["watch", "--config", path])
+        );
+
+        assert_eq!(
+            vec![FailOn::Active(Condition::Throttled)],
+            parse(["throttled", "--config", path]).unwrap().fail_on
+        );
+        assert!(parse(["--no-sudo", "temp"])
+            .map(|args| args.invocation.privilege == PrivilegeMode::None)
+            .unwrap());
+        assert!(command(["monitor"]).unwrap_err().contains("[monitor]"));
+
+        std::fs::remove_file(path).unwrap();
+        assert!(command(["--config", path, "temp"])
+            .unwrap_err()
+            .contains("can't read config file"));
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let args = parse(["--json", "clock", "core"]).unwrap();
+        assert_eq!(Command::Clock(ClockSrc::Core), args.command);
+        assert_eq!(Format::Json, args.format);
+
+        assert_eq!(Format::Json, parse(["temp", "--json"]).unwrap().format);
+        assert_eq!(Format::Text, parse(["temp"]).unwrap().format);
+        assert!(parse(["temp", "--jsn"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let args = parse(["snapshot", "--format", "csv", "--header"]).unwrap();
+        assert_eq!(Format::Csv, args.format);
+        assert!(args.header);
+        assert_eq!(
+            Command::Snapshot(Some(SnapshotSpec::default())),
+            args.command
+        );
+
+        let args = parse([
+            "watch",
+            "--fields",
+            "temp",
+            "--format",
+            "tsv",
+            "--interval",
+            "2",
+        ]);
+        assert_eq!(
+            Command::Watch {
+                spec: SnapshotSpec::parse("temp").unwrap(),
+                interval: Duration::from_secs(2),
+            },
+            args.unwrap().command
+        );
+
+        assert_eq!(
+            Ok(Command::Dashboard {
+                interval: Duration::from_secs(1)
+            }),
+            command(["dashboard"])
+        );
+        assert!(command(["dashboard", "--format", "csv"]).is_err());
+
+        assert!(command(["temp", "--format", "csv"]).is_err());
+        assert!(command(["temp", "--header"]).is_err());
+        assert!(command(["snapshot", "--format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_hosts() {
+        let args = parse(["--host", "pi@pi4.local", "temp"]).unwrap();
+        assert_eq!(Some("pi@pi4.local".to_owned()), args.invocation.host);
+
+        assert_eq!(
+            Ok(Command::Hosts {
+                hosts: vec!["pi4".to_owned(), "pi@10.0.0.3".to_owned()],
+                spec: SnapshotSpec::new().metric(Metric::Clock(ClockSrc::Core)),
+            }),
+            command(["clock", "core", "--hosts", "pi4,pi@10.0.0.3"])
+        );
+        assert_eq!(
+            Ok(Command::Hosts {
+                hosts: vec!["pi4".to_owned()],
+                spec: SnapshotSpec::default(),
+            }),
+            command(["snapshot", "--hosts", "pi4"])
+        );
+
+        assert!(command(["--host", "-oProxyCommand=x", "temp"]).is_err());
+        assert!(command(["--hosts", "pi4,,pi5", "temp"]).is_err());
+        assert!(command(["--hosts", "pi4", "check"]).is_err());
+        assert!(command(["--host", "pi3", "--hosts", "pi4", "temp"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_escalate() {
+        let escalation = |arg: &str| {
+            parse(["--escalate", arg, "temp"])
+                .map(|args| args.invocation.escalation)
+                .map_err(|error| error.to_string())
+        };
+        assert_eq!(
+            Escalation::Sudo,
+            parse(["temp"]).unwrap().invocation.escalation
+        );
+        assert_eq!(Ok(Escalation::Doas), escalation("doas"));
+        assert_eq!(Ok(Escalation::Pkexec), escalation("pkexec"));
+        assert_eq!(
+            Ok(Escalation::Custom("/usr/local/bin/vc-wrapper".into())),
+            escalation("/usr/local/bin/vc-wrapper")
+        );
+        assert!(escalation("doas -n").is_err());
+        assert!(escalation("-oProxyCommand=x").is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(Vec::<String>::new()).is_err());
+        assert!(command(["mem"]).is_err());
+        assert!(command(["explain-throttled", "0xzz"]).is_err());
+        assert!(command(["temp", "arm"]).is_err());
+        assert!(command(["clock", "gpu"])
+            .unwrap_err()
+            .contains("[possible values: arm, core"));
+    }
+
+    #[test]
+    fn test_parse_service() {
+        let service = |interval, sink| {
+            let mut monitor = MonitorConfig::new(interval, &SNAPSHOT_METRICS);
+            monitor.sinks.push(sink);
+            Ok(Command::Service(monitor))
+        };
+        assert_eq!(
+            service(
+                DEFAULT_INTERVAL,
+                SinkConfig::Prometheus {
+                    listen: DEFAULT_LISTEN.to_owned()
+                }
+            ),
+            command(["serve"])
+        );
+        assert_eq!(
+            service(
+                Duration::from_millis(500),
+                SinkConfig::Jsonl {
+                    path: Some(PathBuf::from("pi.jsonl"))
+                }
+            ),
+            command([
+                "export",
+                "jsonl",
+                "--output",
+                "pi.jsonl",
+                "--interval",
+                "0.5"
+            ])
+        );
+        assert_eq!(
+            service(
+                DEFAULT_INTERVAL,
+                SinkConfig::Mqtt {
+                    broker: "broker:1883".to_owned(),
+                    topic: Some("pi/soc".to_owned()),
+                    client_id: None,
+                    retain: true,
+                }
+            ),
+            command([
+                "export",
+                "mqtt",
+                "--retain",
+                "--broker",
+                "broker:1883",
+                "--topic",
+                "pi/soc"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_service_errors() {
+        assert!(command(["export"]).is_err());
+        assert!(command(["export", "csv"]).is_err());
+        assert!(command(["export", "mqtt"])
+            .unwrap_err()
+            .contains("--broker"));
+        assert!(command(["serve", "--interval", "0"]).is_err());
+        assert!(command(["serve", "--listen"]).is_err());
+        assert!(command(["temp", "--interval", "1"])
+            .unwrap_err()
+            .contains("unexpected argument '--interval'"));
+        assert!(command(["export", "jsonl", "--listen", ":9110"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_completions() {
+        assert_eq!(
+            Ok(Command::Completions(Shell::Zsh)),
+            command(["completions", "zsh"])
+        );
+        assert!(command(["completions"]).is_err());
+        assert!(command(["completions", "tcsh"]).is_err());
+    }
+}
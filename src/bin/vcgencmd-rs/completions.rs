@@ -0,0 +1,39 @@
+//! Shell completion scripts, generated by clap_complete from the command line definition
+
+use clap::CommandFactory;
+
+use crate::args::{Cli, Shell, BIN};
+
+pub fn script(shell: Shell) -> String {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), BIN, &mut script);
+    String::from_utf8_lossy(&script).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bash() {
+        let script = script(Shell::Bash);
+        assert!(script.contains(" vcgencmd-rs\n"));
+        assert!(script.contains("explain-throttled"));
+        assert!(script.contains("core sdram_c sdram_i sdram_p"));
+    }
+
+    #[test]
+    fn test_zsh() {
+        let script = script(Shell::Zsh);
+        assert!(script.starts_with("#compdef vcgencmd-rs\n"));
+        assert!(script.contains("arm gpu malloc malloc_total reloc reloc_total"));
+        assert!(script.contains("--output"));
+    }
+
+    #[test]
+    fn test_fish() {
+        let script = script(Shell::Fish);
+        assert!(script.contains("complete -c vcgencmd-rs"));
+        assert!(script.contains("-l broker"));
+    }
+}
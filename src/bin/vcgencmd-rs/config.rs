@@ -0,0 +1,106 @@
+//! The `--config` file, TOML with how to invoke `vcgencmd` and the monitor to run
+//!
+//! ```toml
+//! binary = "/usr/bin/vcgencmd"
+//! sudo = false
+//! fail_on = ["under-voltage", "throttled"]
+//!
+//! [monitor]
+//! interval = 10
+//! metrics = ["temp", "clock.arm", "volts.core", "throttled"]
+//!
+//! [[monitor.thresholds]]
+//! metric = "temp"
+//! warning = 65
+//! critical = 75
+//!
+//! [[monitor.sinks]]
+//! type = "mqtt"
+//! broker = "localhost:1883"
+//! topic = "pi/soc"
+//! ```
+//!
+//! `[monitor]` is the library's `MonitorConfig`. `monitor` runs it with its sinks, `serve`,
+//! `export` and `watch` sample its metrics at its interval, and `check` takes its
+//! thresholds from the one of `temp`. Flags given on the command line take precedence.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use vcgencmd::config::MonitorConfig;
+
+/// The settings of a config file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub binary: Option<PathBuf>,
+    pub sudo: Option<bool>,
+    /// Like `--escalate`
+    pub escalate: Option<String>,
+    /// Like `--host`
+    pub host: Option<String>,
+    /// The checks of `--fail-on`, for the commands reading the throttled flags
+    #[serde(default)]
+    pub fail_on: Vec<String>,
+    pub monitor: Option<MonitorConfig>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("can't read config file '{}': {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use vcgencmd::config::SinkConfig;
+    use vcgencmd::monitor::Metric;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(
+            r#"
+            # a comment
+            binary = "/opt/vc/bin/vcgencmd"
+            sudo = false
+            fail_on = ["under-voltage", 'throttled']
+
+            [monitor]
+            interval = 2.5
+            metrics = ["temp", { metric = "throttled", interval = 1 }]
+
+            [[monitor.sinks]]
+            type = "jsonl"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(Some(PathBuf::from("/opt/vc/bin/vcgencmd")), config.binary);
+        assert_eq!(Some(false), config.sudo);
+        assert_eq!(vec!["under-voltage", "throttled"], config.fail_on);
+
+        let monitor = config.monitor.unwrap();
+        assert_eq!(Duration::from_millis(2500), monitor.interval);
+        assert_eq!(Metric::Throttled, monitor.metrics[1].metric);
+        assert_eq!(Some(Duration::from_secs(1)), monitor.metrics[1].interval);
+        assert_eq!(vec![SinkConfig::Jsonl { path: None }], monitor.sinks);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |text| toml::from_str::<Config>(text).map_err(|e| e.to_string());
+        assert!(parse("interval = 5").unwrap_err().contains("unknown field"));
+        assert!(parse("sudo = \"yes\"").is_err());
+        assert!(parse("[monitor]\ninterval = 5").is_err());
+        assert!(
+            parse("[monitor]\ninterval = 5\nmetrics = [\"temp\"]\nport = 1")
+                .unwrap_err()
+                .contains("unknown field")
+        );
+    }
+}
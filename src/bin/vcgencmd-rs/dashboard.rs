@@ -0,0 +1,262 @@
+//! `dashboard`, a live view of the snapshot metrics in the terminal
+//!
+//! Built with the `tui` feature. The temperature history is drawn as a sparkline, the
+//! clocks as gauges against the configured frequencies and the throttling conditions as
+//! a panel of flags, active ones in red and those that occurred since boot in yellow.
+
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+
+use vcgencmd::events::Condition;
+use vcgencmd::snapshot::Snapshot;
+use vcgencmd::{get_config, interpret_bit_pattern, ConfigSrc, Src};
+
+/// Temperatures kept for the sparkline, more than fit the width of most terminals
+const HISTORY: usize = 240;
+
+/// What is shown, updated with every snapshot
+struct Dashboard {
+    /// Temperatures in tenths of °C, the sparkline only takes integers
+    temps: VecDeque<u64>,
+    snapshot: Option<Snapshot>,
+    /// The full ARM and core clock in Hz, from `config.txt` or the highest seen
+    arm_max: isize,
+    core_max: isize,
+}
+
+impl Dashboard {
+    fn new(arm_max: Option<isize>, core_max: Option<isize>) -> Dashboard {
+        Dashboard {
+            temps: VecDeque::with_capacity(HISTORY),
+            snapshot: None,
+            arm_max: arm_max.unwrap_or_default(),
+            core_max: core_max.unwrap_or_default(),
+        }
+    }
+
+    fn update(&mut self, snapshot: Snapshot) {
+        if let Some(temp) = snapshot.temp {
+            if self.temps.len() == HISTORY {
+                self.temps.pop_front();
+            }
+            self.temps.push_back((temp.max(0.0) * 10.0).round() as u64);
+        }
+        self.arm_max = self.arm_max.max(snapshot.arm_clock.unwrap_or_default());
+        self.core_max = self.core_max.max(snapshot.core_clock.unwrap_or_default());
+        self.snapshot = Some(snapshot);
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let [temp, arm, core, flags] = Layout::vertical([
+            Constraint::Length(8),
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Min(6),
+        ])
+        .areas(frame.area());
+
+        self.render_temp(frame, temp);
+        let snapshot = self.snapshot.as_ref();
+        let clock = |max, read: fn(&Snapshot) -> Option<isize>, name| {
+            gauge(name, snapshot.and_then(read), max)
+        };
+        frame.render_widget(clock(self.arm_max, |s| s.arm_clock, "ARM clock"), arm);
+        frame.render_widget(clock(self.core_max, |s| s.core_clock, "Core clock"), core);
+        self.render_flags(frame, flags);
+    }
+
+    fn render_temp(&self, frame: &mut Frame, area: Rect) {
+        let headroom = self.snapshot.as_ref().and_then(Snapshot::temp_headroom);
+        let (title, color) = match headroom {
+            Some(headroom) => (
+                format!(
+                    " Temperature {:.1} °C, {:.1} °C to the soft limit ",
+                    headroom.temp,
+                    headroom.to_soft_limit()
+                ),
+                match headroom.to_soft_limit() {
+                    left if left <= 0.0 => Color::Red,
+                    left if left <= 5.0 => Color::Yellow,
+                    _ => Color::Green,
+                },
+            ),
+            None => (" Temperature n/a ".to_owned(), Color::DarkGray),
+        };
+        let hard = headroom.map_or(85.0, |headroom| headroom.hard_limit);
+        // the newest readings on the right, as many as fit
+        let width = usize::from(area.width.saturating_sub(2));
+        let temps: Vec<u64> = self.temps.iter().copied().collect();
+        let shown = temps
+            .get(temps.len().saturating_sub(width)..)
+            .unwrap_or(&[]);
+
+        let sparkline = Sparkline::default()
+            .block(Block::bordered().title(title))
+            .data(shown)
+            .max((hard * 10.0) as u64)
+            .style(Style::new().fg(color));
+        frame.render_widget(sparkline, area);
+    }
+
+    fn render_flags(&self, frame: &mut Frame, area: Rect) {
+        let snapshot = self.snapshot.as_ref();
+        let bit_pattern = snapshot.and_then(|snapshot| snapshot.throttled);
+        let volts = snapshot
+            .and_then(|snapshot| snapshot.core_volts)
+            .map_or_else(|| "n/a".to_owned(), |volts| format!("{:.4} V", volts));
+        let title = match bit_pattern {
+            Some(bit_pattern) => format!(" Throttling 0x{:x}, core {} ", bit_pattern, volts),
+            None => format!(" Throttling n/a, core {} ", volts),
+        };
+
+        let mut lines: Vec<Line> = match bit_pattern.map(interpret_bit_pattern) {
+            Some(status) => Condition::ALL
+                .iter()
+                .map(|&condition| {
+                    let (state, color) = if condition.is_active(&status) {
+                        ("active", Color::Red)
+                    } else if condition.has_occurred(&status) {
+                        ("occurred", Color::Yellow)
+                    } else {
+                        ("ok", Color::Green)
+                    };
+                    Line::from(vec![
+                        Span::raw(format!("{:<22}", condition.name())),
+                        Span::styled(state, Style::new().fg(color)),
+                    ])
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        for (_, error) in snapshot.map_or(&[][..], |snapshot| snapshot.errors.as_slice()) {
+            lines.push(Line::styled(error.to_string(), Style::new().fg(Color::Red)));
+        }
+        lines.push(Line::styled("q to quit", Style::new().fg(Color::DarkGray)));
+
+        let flags = Paragraph::new(lines).block(Block::bordered().title(title));
+        frame.render_widget(flags, area);
+    }
+}
+
+/// A gauge of `clock` relative to `max`, both in Hz
+fn gauge(name: &str, clock: Option<isize>, max: isize) -> Gauge<'static> {
+    let mhz = |hz: isize| hz / 1_000_000;
+    let (ratio, label) = match clock {
+        Some(clock) if max > 0 => (
+            (clock as f64 / max as f64).clamp(0.0, 1.0),
+            format!("{} / {} MHz", mhz(clock), mhz(max)),
+        ),
+        Some(clock) => (0.0, format!("{} MHz", mhz(clock))),
+        None => (0.0, "n/a".to_owned()),
+    };
+
+    Gauge::default()
+        .block(Block::bordered().title(format!(" {} ", name)))
+        .gauge_style(Style::new().fg(Color::Cyan))
+        .ratio(ratio)
+        .label(label)
+}
+
+/// The frequency `src` is configured to in `config.txt`, in Hz
+fn configured(src: ConfigSrc) -> Option<isize> {
+    get_config(Src::Config(src))
+        .ok()
+        .filter(|&mhz| mhz > 0)
+        .map(|mhz| mhz * 1_000_000)
+}
+
+/// Whether a key asks to quit, the terminal is in raw mode so Ctrl-C arrives as a key
+fn quits(code: KeyCode, modifiers: KeyModifiers) -> bool {
+    match code {
+        KeyCode::Char('q') | KeyCode::Esc => true,
+        KeyCode::Char('c') => modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+/// Show the dashboard until quit, taking a snapshot every `interval`
+pub fn run(interval: Duration) -> io::Result<()> {
+    let mut dashboard = Dashboard::new(
+        configured(ConfigSrc::ArmFreq),
+        configured(ConfigSrc::CoreFreq),
+    );
+
+    let mut terminal = ratatui::try_init()?;
+    let result = show(&mut terminal, &mut dashboard, interval);
+    ratatui::try_restore()?;
+    result
+}
+
+fn show(
+    terminal: &mut DefaultTerminal,
+    dashboard: &mut Dashboard,
+    interval: Duration,
+) -> io::Result<()> {
+    let mut next = Instant::now();
+    loop {
+        let now = Instant::now();
+        if now >= next {
+            dashboard.update(Snapshot::capture());
+            next = (next + interval).max(now);
+        }
+        terminal.draw(|frame| dashboard.render(frame))?;
+
+        if event::poll(next.saturating_duration_since(Instant::now()))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && quits(key.code, key.modifiers) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+    use vcgencmd::thermal::TempLimits;
+
+    fn snapshot(temp: f64, arm_clock: isize) -> Snapshot {
+        Snapshot {
+            timestamp: UNIX_EPOCH,
+            temp: Some(temp),
+            temp_limits: TempLimits::default(),
+            throttled: Some(0x50000),
+            arm_clock: Some(arm_clock),
+            core_clock: None,
+            core_volts: Some(0.85),
+            errors: Vec::new(),
+            lagging: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let mut dashboard = Dashboard::new(Some(1_500_000_000), None);
+        for _ in 0..HISTORY + 10 {
+            dashboard.update(snapshot(48.25, 1_800_000_000));
+        }
+
+        assert_eq!(HISTORY, dashboard.temps.len());
+        assert_eq!(Some(&483), dashboard.temps.back());
+        // overclocked beyond config.txt, the gauge scales with it
+        assert_eq!(1_800_000_000, dashboard.arm_max);
+        assert_eq!(0, dashboard.core_max);
+    }
+
+    #[test]
+    fn test_quits() {
+        assert!(quits(KeyCode::Char('q'), KeyModifiers::NONE));
+        assert!(quits(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(!quits(KeyCode::Char('c'), KeyModifiers::NONE));
+    }
+}
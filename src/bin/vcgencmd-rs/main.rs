@@ -0,0 +1,442 @@
+//! `vcgencmd-rs`, the typed API of the crate on the command line
+//!
+//! Built with the `cli` feature. Like the library, it invokes `vcgencmd` through `sudo`
+//! unless told otherwise with `--no-sudo`, or built with the `no-sudo` feature.
+
+use std::error::Error as _;
+use std::io;
+use std::iter;
+use std::process;
+
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use vcgencmd::alert::Severity;
+use vcgencmd::csv;
+use vcgencmd::monitor::{Metric, Reading, Sample};
+use vcgencmd::nagios::{self, Thresholds};
+use vcgencmd::snapshot::{Snapshot, SnapshotSpec};
+use vcgencmd::thermal::TempLimits;
+use vcgencmd::throttled::explain;
+use vcgencmd::{
+    get_mem, get_throttled, interpret_bit_pattern, measure_clock, measure_temp, measure_volts,
+    Error, Invocation, Src,
+};
+
+mod args;
+mod completions;
+mod config;
+#[cfg(feature = "tui")]
+mod dashboard;
+mod service;
+
+use args::{name_of, Args, Command, FailOn, Format, CLOCK_SRCS, MEM_SRCS, VOLT_SRCS};
+
+/// Exit code for a failed `--fail-on` check
+const EXIT_CHECK_FAILED: i32 = 2;
+/// Exit code for invalid arguments, sysexits' `EX_USAGE` so it can't be mistaken for a
+/// failed check
+const EXIT_USAGE: i32 = 64;
+
+/// What the `--fail-on` checks look at, `None` where it is unknown
+#[derive(Default)]
+struct Checked {
+    bit_pattern: Option<isize>,
+    /// The severity of the health, `Some(None)` if healthy
+    health: Option<Option<Severity>>,
+}
+
+impl Checked {
+    fn throttled(bit_pattern: isize) -> Checked {
+        Checked {
+            bit_pattern: Some(bit_pattern),
+            health: Some(explain(bit_pattern).severity()),
+        }
+    }
+
+    /// Whether `check` fails, which it also does if its value is unknown
+    fn fails(&self, check: FailOn) -> bool {
+        let status = self.bit_pattern.map(interpret_bit_pattern);
+        match check {
+            FailOn::Active(condition) => status.is_none_or(|s| condition.is_active(&s)),
+            FailOn::Occurred(condition) => status.is_none_or(|s| condition.has_occurred(&s)),
+            FailOn::Severity(severity) => self.health.is_none_or(|s| s >= Some(severity)),
+        }
+    }
+}
+
+/// Run a single command, returning the exit code
+fn run(args: &Args) -> Result<i32, Error> {
+    let mut checked = Checked::default();
+
+    // every command yields its result both human readable and as JSON
+    let (human, json) = match args.command {
+        // handled by `service` and `dashboard`, they don't return a single result
+        Command::Service(_) | Command::Watch { .. } | Command::Dashboard { .. } => return Ok(0),
+        Command::Completions(shell) => {
+            print!("{}", completions::script(shell));
+            return Ok(0);
+        }
+        Command::Temp => {
+            let temp = measure_temp()?;
+            (temp.to_string(), json!({ "temp": temp }).to_string())
+        }
+        Command::Clock(src) => {
+            let frequency = measure_clock(Src::Clock(src))?;
+            let json = json!({ "clock": name_of(&CLOCK_SRCS, src), "frequency": frequency });
+            (frequency.to_string(), json.to_string())
+        }
+        Command::Volts(src) => {
+            let volts = measure_volts(Src::Volt(src))?;
+            let json = json!({ "volts": name_of(&VOLT_SRCS, src), "value": volts });
+            (volts.to_string(), json.to_string())
+        }
+        Command::Mem(src) => {
+            let mem = get_mem(Src::Mem(src))?;
+            let json = json!({ "mem": name_of(&MEM_SRCS, src), "value": mem });
+            (mem.to_string(), json.to_string())
+        }
+        Command::Throttled => {
+            let bit_pattern = get_throttled()?;
+            checked = Checked::throttled(bit_pattern);
+            let json = json!({
+                "bit_pattern": bit_pattern,
+                "status": interpret_bit_pattern(bit_pattern),
+            });
+            (format_throttled(bit_pattern), json.to_string())
+        }
+        Command::ExplainThrottled(bit_pattern) => {
+            let explanation = explain(match bit_pattern {
+                Some(bit_pattern) => bit_pattern,
+                None => get_throttled()?,
+            });
+            checked = Checked::throttled(explanation.bit_pattern);
+            (explanation.to_string(), to_json(&explanation)?)
+        }
+        Command::Snapshot(Some(ref spec)) => {
+            let sample = spec.capture();
+            for (_, error) in &sample.errors {
+                eprintln!("vcgencmd-rs: {}", describe_error(error));
+            }
+            let limits = TempLimits::cached().unwrap_or_default();
+            checked = Checked {
+                bit_pattern: sample.get(Metric::Throttled).and_then(|r| match *r {
+                    Reading::Throttled(bit_pattern) => Some(bit_pattern),
+                    _ => None,
+                }),
+                health: Some(Snapshot::from_sample(&sample, limits).health().severity()),
+            };
+            let text = match args.format.delimiter() {
+                Some(delimiter) => format_delimited(spec, &sample, delimiter, args.header),
+                None => format_record(spec, &sample, "\n"),
+            };
+            (text, to_json(&sample)?)
+        }
+        Command::Hosts {
+            ref hosts,
+            ref spec,
+        } => {
+            let samples: Vec<_> = hosts
+                .iter()
+                .map(|host| {
+                    vcgencmd::set_invocation(Invocation {
+                        host: Some(host.clone()),
+                        ..args.invocation.clone()
+                    });
+                    let sample = spec.capture();
+                    for (_, error) in &sample.errors {
+                        eprintln!("vcgencmd-rs: {}: {}", host, describe_error(error));
+                    }
+                    (host.as_str(), sample)
+                })
+                .collect();
+
+            let text = match args.format.delimiter() {
+                Some(delimiter) => format_hosts_delimited(spec, &samples, delimiter, args.header),
+                None => format_table(spec, &samples),
+            };
+            // keyed by host in the order they were given
+            let mut json = Vec::new();
+            serde_json::Serializer::new(&mut json)
+                .collect_map(samples.iter().map(|(host, sample)| (host, sample)))
+                .map_err(io::Error::from)?;
+            (text, String::from_utf8_lossy(&json).into_owned())
+        }
+        Command::Check { warning, critical } => {
+            let defaults = Thresholds::from_limits(&TempLimits::cached().unwrap_or_default());
+            let thresholds = Thresholds {
+                warning: warning.unwrap_or(defaults.warning),
+                critical: critical.unwrap_or(defaults.critical),
+            };
+            let result = nagios::check(&Snapshot::capture(), &thresholds);
+
+            if args.format == Format::Json {
+                println!("{}", to_json(&result)?);
+            } else {
+                println!("{}", result);
+            }
+            return Ok(result.exit_code());
+        }
+        Command::Snapshot(None) => {
+            let snapshot = Snapshot::capture();
+            for (_, error) in &snapshot.errors {
+                eprintln!("vcgencmd-rs: {}", describe_error(error));
+            }
+            checked = Checked {
+                bit_pattern: snapshot.throttled,
+                health: Some(snapshot.health().severity()),
+            };
+            (format_snapshot(&snapshot), to_json(&snapshot)?)
+        }
+    };
+
+    if args.format == Format::Json {
+        println!("{}", json);
+    } else {
+        println!("{}", human);
+    }
+
+    match args.fail_on.iter().find(|&&check| checked.fails(check)) {
+        Some(check) => {
+            eprintln!("vcgencmd-rs: check {:?} failed", check);
+            Ok(EXIT_CHECK_FAILED)
+        }
+        None => Ok(0),
+    }
+}
+
+/// `value` as a line of JSON
+fn to_json<T: Serialize>(value: &T) -> Result<String, Error> {
+    Ok(serde_json::to_string(value).map_err(io::Error::from)?)
+}
+
+/// The error followed by its cause
+fn describe_error(error: &Error) -> String {
+    let description = match error.source() {
+        Some(source) => format!("{}: {}", error, source),
+        None => error.to_string(),
+    };
+    match error.hint() {
+        Some(hint) => format!("{} ({})", description, hint),
+        None => description,
+    }
+}
+
+fn format_throttled(bit_pattern: isize) -> String {
+    let status = interpret_bit_pattern(bit_pattern);
+
+    [
+        format!("throttled=0x{:x}", bit_pattern),
+        format!("under_voltage={}", status.under_voltage),
+        format!("arm_frequency_capped={}", status.arm_frequency_capped),
+        format!("currently_throttled={}", status.currently_throttled),
+        format!("soft_temp_limit_active={}", status.soft_temp_limit_active),
+        format!("under_voltage_occurred={}", status.under_voltage_occurred),
+        format!(
+            "arm_frequency_cap_occurred={}",
+            status.arm_frequency_cap_occurred
+        ),
+        format!("throttling_occurred={}", status.throttling_occurred),
+        format!(
+            "soft_temp_limit_occurred={}",
+            status.soft_temp_limit_occurred
+        ),
+    ]
+    .join("\n")
+}
+
+fn format_snapshot(snapshot: &Snapshot) -> String {
+    fn field<T: ToString>(name: &str, value: Option<T>) -> String {
+        let value = value.map_or_else(|| "n/a".to_owned(), |v| v.to_string());
+        format!("{}={}", name, value)
+    }
+
+    [
+        field("temp", snapshot.temp),
+        field(
+            "throttled",
+            snapshot.throttled.map(|b| format!("0x{:x}", b)),
+        ),
+        field("arm_clock", snapshot.arm_clock),
+        field("core_clock", snapshot.core_clock),
+        field("core_volts", snapshot.core_volts),
+        format!("health={}", snapshot.health()),
+    ]
+    .join("\n")
+}
+
+/// A `name=value` pair per field of `spec` in the order they were asked for, separated by
+/// `separator`
+fn format_record(spec: &SnapshotSpec, sample: &Sample, separator: &str) -> String {
+    spec.metrics()
+        .iter()
+        .map(|&metric| format!("{}={}", metric.name(), format_value(sample, metric)))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn format_value(sample: &Sample, metric: Metric) -> String {
+    match sample.get(metric) {
+        Some(&Reading::Temp(temp)) => temp.to_string(),
+        Some(&Reading::TempHeadroom(headroom)) => headroom.to_soft_limit().to_string(),
+        Some(&Reading::Throttled(bit_pattern)) => format!("0x{:x}", bit_pattern),
+        Some(&Reading::Clock(_, frequency)) => frequency.to_string(),
+        Some(&Reading::Volts(_, volts)) | Some(&Reading::Derived(_, volts)) => volts.to_string(),
+        Some(&Reading::Mem(_, megabytes)) => megabytes.to_string(),
+        None => "n/a".to_owned(),
+    }
+}
+
+/// A row per host with a column per field of `spec`, aligned with spaces
+fn format_table(spec: &SnapshotSpec, samples: &[(&str, Sample)]) -> String {
+    let header = iter::once("host".to_owned()).chain(spec.metrics().iter().map(Metric::name));
+    let mut rows = vec![header.collect::<Vec<_>>()];
+    for (host, sample) in samples {
+        let values = spec
+            .metrics()
+            .iter()
+            .map(|&metric| format_value(sample, metric));
+        rows.push(iter::once(host.to_string()).chain(values).collect());
+    }
+
+    let widths: Vec<_> = (0..rows[0].len())
+        .map(|column| {
+            let width = rows.iter().map(|row| row[column].chars().count()).max();
+            width.unwrap_or_default()
+        })
+        .collect();
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<_> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `csv` rows with the host in front
+fn format_hosts_delimited(
+    spec: &SnapshotSpec,
+    samples: &[(&str, Sample)],
+    delimiter: char,
+    header: bool,
+) -> String {
+    let mut lines = Vec::new();
+    if header {
+        lines.push(format!(
+            "host{}{}",
+            delimiter,
+            csv::header(spec.metrics(), delimiter)
+        ));
+    }
+    for (host, sample) in samples {
+        let row = csv::row(sample, spec.metrics(), delimiter);
+        lines.push(format!("{}{}{}", host, delimiter, row));
+    }
+    lines.join("\n")
+}
+
+fn format_delimited(spec: &SnapshotSpec, sample: &Sample, delimiter: char, header: bool) -> String {
+    let row = csv::row(sample, spec.metrics(), delimiter);
+    if header {
+        format!("{}\n{}", csv::header(spec.metrics(), delimiter), row)
+    } else {
+        row
+    }
+}
+
+fn main() {
+    let args = match args::parse(std::env::args_os().skip(1)) {
+        Ok(args) => args,
+        // help and the version are printed as errors as well, but to stdout
+        Err(error) => {
+            let _ = error.print();
+            process::exit(if error.use_stderr() { EXIT_USAGE } else { 0 });
+        }
+    };
+
+    vcgencmd::set_invocation(args.invocation.clone());
+
+    let result = match args.command {
+        Command::Service(ref config) => service::run(config).map(|_| 0).map_err(|e| e.to_string()),
+        Command::Watch { ref spec, interval } => {
+            service::watch(spec, interval, args.format, args.header)
+                .map(|_| 0)
+                .map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "tui")]
+        Command::Dashboard { interval } => dashboard::run(interval)
+            .map(|_| 0)
+            .map_err(|e| e.to_string()),
+        #[cfg(not(feature = "tui"))]
+        Command::Dashboard { .. } => {
+            Err("dashboard needs vcgencmd-rs built with the tui feature".to_owned())
+        }
+        _ => run(&args).map_err(|e| describe_error(&e)),
+    };
+
+    match result {
+        Ok(0) => {}
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            eprintln!("vcgencmd-rs: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+    use vcgencmd::events::Condition;
+
+    #[test]
+    fn test_checks() {
+        let checked = Checked::throttled(0x50000);
+        assert!(!checked.fails(FailOn::Active(Condition::UnderVoltage)));
+        assert!(checked.fails(FailOn::Occurred(Condition::UnderVoltage)));
+        assert!(checked.fails(FailOn::Severity(Severity::Warning)));
+        assert!(!checked.fails(FailOn::Severity(Severity::Critical)));
+
+        let unknown = Checked::default();
+        assert!(unknown.fails(FailOn::Active(Condition::Throttled)));
+        assert!(unknown.fails(FailOn::Severity(Severity::Warning)));
+
+        let healthy = Checked::throttled(0);
+        assert!(!healthy.fails(FailOn::Severity(Severity::Warning)));
+    }
+
+    #[test]
+    fn test_format_table() {
+        let spec = SnapshotSpec::new()
+            .metric(Metric::Temp)
+            .metric(Metric::Throttled);
+        let sample = |readings| Sample {
+            timestamp: UNIX_EPOCH,
+            readings,
+            errors: Vec::new(),
+        };
+        let samples = [
+            (
+                "pi@pi4.local",
+                sample(vec![Reading::Temp(47.2), Reading::Throttled(0x50005)]),
+            ),
+            ("pi3", sample(vec![Reading::Throttled(0)])),
+        ];
+
+        assert_eq!(
+            "host          temp  throttled\n\
+             pi@pi4.local  47.2  0x50005\n\
+             pi3           n/a   0x0",
+            format_table(&spec, &samples)
+        );
+        assert_eq!(
+            "pi3,1970-01-01T00:00:00.000Z,,0",
+            format_hosts_delimited(&spec, &samples[1..], ',', false)
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! `serve`, `export`, `monitor` and `watch`, sampling metrics until the process is stopped
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use vcgencmd::config::{MonitorConfig, SinkConfig};
+use vcgencmd::csv;
+use vcgencmd::monitor::Monitor;
+use vcgencmd::snapshot::SnapshotSpec;
+
+use crate::args::Format;
+
+pub fn run(config: &MonitorConfig) -> io::Result<()> {
+    let monitor = Monitor::from_config(config)?;
+    for sink in &config.sinks {
+        if let SinkConfig::Prometheus { listen } = sink {
+            eprintln!("vcgencmd-rs: serving metrics on http://{}/metrics", listen);
+        }
+    }
+    run_until_stopped(monitor)
+}
+
+/// Print a line with the metrics of `spec` every `interval`
+pub fn watch(
+    spec: &SnapshotSpec,
+    interval: Duration,
+    format: Format,
+    header: bool,
+) -> io::Result<()> {
+    let monitor = spec
+        .metrics()
+        .iter()
+        .fold(Monitor::new(interval), |monitor, &metric| {
+            monitor.metric(metric)
+        });
+
+    if let (true, Some(delimiter)) = (header, format.delimiter()) {
+        println!("{}", csv::header(spec.metrics(), delimiter));
+    }
+
+    let spec = spec.clone();
+    let monitor = monitor.sink(move |sample| {
+        let line = match (format, format.delimiter()) {
+            (_, Some(delimiter)) => csv::row(sample, spec.metrics(), delimiter),
+            (Format::Json, None) => match crate::to_json(sample) {
+                Ok(json) => json,
+                Err(error) => return eprintln!("vcgencmd-rs: {}", crate::describe_error(&error)),
+            },
+            _ => crate::format_record(&spec, sample, " "),
+        };
+        // a closed pipe, e.g. to `head`, is not worth a panic
+        let _ = writeln!(io::stdout(), "{}", line);
+    });
+
+    run_until_stopped(monitor)
+}
+
+/// Run under `Daemon`, which stops cleanly on `SIGTERM` and talks to systemd, and prints
+/// a snapshot as JSON to stderr on `SIGUSR1`
+#[cfg(unix)]
+fn run_until_stopped(monitor: Monitor) -> io::Result<()> {
+    vcgencmd::daemon::Daemon::new(monitor)
+        .on_dump(|snapshot| match crate::to_json(snapshot) {
+            Ok(json) => eprintln!("{}", json),
+            Err(error) => eprintln!("vcgencmd-rs: {}", crate::describe_error(&error)),
+        })
+        .run()
+        .map(drop)
+}
+
+#[cfg(not(unix))]
+fn run_until_stopped(mut monitor: Monitor) -> io::Result<()> {
+    use std::sync::atomic::AtomicBool;
+
+    monitor.run(&AtomicBool::new(false));
+    Ok(())
+}
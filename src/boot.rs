@@ -0,0 +1,168 @@
+//! Relating the sticky `*_occurred` throttle flags to the current boot
+//!
+//! The firmware resets the `*_occurred` bits on reboot, so "has occurred" always means
+//! "has occurred since the system started at `boot_time`". Readings stored across
+//! reboots need this context, otherwise a flag that is set in one sample and cleared in the
+//! next looks like the condition was fixed, when in fact the system just restarted.
+
+use std::fs;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{interpret_bit_pattern, ThrottledStatus};
+
+const PROC_STAT: &str = "/proc/stat";
+const BOOT_ID: &str = "/proc/sys/kernel/random/boot_id";
+
+/// Identifies the current boot of the system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Boot {
+    /// Random id the kernel generates on every boot
+    pub id: String,
+    /// Wall-clock time the system booted at
+    pub time: SystemTime,
+}
+
+impl Boot {
+    /// Read boot id and boot time of the running system from `/proc`
+    pub fn current() -> io::Result<Boot> {
+        let id = fs::read_to_string(BOOT_ID)?.trim().to_owned();
+        let time = parse_btime(&fs::read_to_string(PROC_STAT)?).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "no btime line in /proc/stat")
+        })?;
+
+        Ok(Boot { id, time })
+    }
+}
+
+/// Boot time of the running system
+pub fn boot_time() -> io::Result<SystemTime> {
+    Boot::current().map(|boot| boot.time)
+}
+
+fn parse_btime(proc_stat: &str) -> Option<SystemTime> {
+    let secs = proc_stat
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// A decoded `get_throttled` status together with the boot its sticky flags refer to
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootStatus {
+    pub status: ThrottledStatus,
+    pub boot: Boot,
+    /// When the status was read
+    pub observed_at: SystemTime,
+}
+
+impl BootStatus {
+    /// Decode `bit_pattern`, read at `observed_at` during `boot`
+    pub fn new(bit_pattern: isize, boot: Boot, observed_at: SystemTime) -> BootStatus {
+        BootStatus {
+            status: interpret_bit_pattern(bit_pattern),
+            boot,
+            observed_at,
+        }
+    }
+
+    /// The window any `*_occurred` flag that is set must have happened in
+    pub fn occurred_window(&self) -> (SystemTime, SystemTime) {
+        (self.boot.time, self.observed_at)
+    }
+
+    /// How long the window of the sticky flags is
+    pub fn uptime(&self) -> Duration {
+        self.observed_at
+            .duration_since(self.boot.time)
+            .unwrap_or_default()
+    }
+}
+
+/// Notices reboots between consecutive samples
+#[derive(Debug, Clone, Default)]
+pub struct RebootDetector {
+    last: Option<Boot>,
+}
+
+impl RebootDetector {
+    pub fn new() -> RebootDetector {
+        RebootDetector::default()
+    }
+
+    /// Feed the boot a sample was taken in, returns true if it differs from the previous one
+    pub fn check(&mut self, boot: &Boot) -> bool {
+        let rebooted = match &self.last {
+            Some(last) => last.id != boot.id,
+            None => false,
+        };
+
+        self.last = Some(boot.clone());
+        rebooted
+    }
+
+    /// Whether something that happened at `time` lies before the last known boot
+    pub fn is_before_boot(&self, time: SystemTime) -> bool {
+        match &self.last {
+            Some(boot) => time < boot.time,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boot(id: &str, secs: u64) -> Boot {
+        Boot {
+            id: id.to_owned(),
+            time: UNIX_EPOCH + Duration::from_secs(secs),
+        }
+    }
+
+    #[test]
+    fn test_parse_btime() {
+        let proc_stat = "cpu  10 0 20 300\nintr 1234\nbtime 1566741789\nprocesses 42\n";
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(1_566_741_789)),
+            parse_btime(proc_stat)
+        );
+        assert_eq!(None, parse_btime("cpu 1 2 3\n"));
+    }
+
+    #[test]
+    fn test_occurred_window() {
+        let observed_at = UNIX_EPOCH + Duration::from_secs(1600);
+        let status = BootStatus::new(0x50000, boot("a", 1000), observed_at);
+
+        assert_eq!(
+            (UNIX_EPOCH + Duration::from_secs(1000), observed_at),
+            status.occurred_window()
+        );
+        assert_eq!(Duration::from_secs(600), status.uptime());
+    }
+
+    #[test]
+    fn test_reboot_detector() {
+        let mut detector = RebootDetector::new();
+        assert!(!detector.check(&boot("a", 1000)));
+        assert!(!detector.check(&boot("a", 1000)));
+        assert!(detector.check(&boot("b", 2000)));
+
+        assert!(detector.is_before_boot(UNIX_EPOCH + Duration::from_secs(1500)));
+        assert!(!detector.is_before_boot(UNIX_EPOCH + Duration::from_secs(2500)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_current_boot() {
+        let boot = Boot::current().unwrap();
+        assert!(!boot.id.is_empty());
+        assert!(boot.time < SystemTime::now());
+    }
+}
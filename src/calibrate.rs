@@ -0,0 +1,287 @@
+//! Baseline statistics of an idle system
+//!
+//! What counts as normal differs a lot between a Pi Zero in a closed case and a Pi 5 with a
+//! fan. `calibrate` samples the system while it is idle and records the ranges of
+//! temperature, clocks and core voltage, so thresholds can be set relative to them instead
+//! of being hard-coded.
+//!
+//! A `Baseline` can be saved to and loaded from a simple `key=value` file, so calibration
+//! only has to run once per device.
+
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::monitor::{Metric, Monitor, Reading};
+use crate::profile::sample_for;
+use crate::{ClockSrc, Result, VoltSrc};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Range and mean of a quantity over the calibration run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub count: usize,
+}
+
+impl Stats {
+    /// Statistics of `values`, `None` if there are none
+    pub fn from_values<I: IntoIterator<Item = f64>>(values: I) -> Option<Stats> {
+        let mut stats: Option<Stats> = None;
+        let mut sum = 0.0;
+
+        for value in values {
+            sum += value;
+            stats = Some(match stats {
+                Some(stats) => Stats {
+                    min: stats.min.min(value),
+                    max: stats.max.max(value),
+                    mean: 0.0,
+                    count: stats.count + 1,
+                },
+                None => Stats {
+                    min: value,
+                    max: value,
+                    mean: 0.0,
+                    count: 1,
+                },
+            });
+        }
+
+        stats.map(|stats| Stats {
+            mean: sum / stats.count as f64,
+            ..stats
+        })
+    }
+}
+
+/// What the system looks like when idle
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Baseline {
+    /// Temperature in °C
+    pub temp: Option<Stats>,
+    /// ARM clock in Hz
+    pub arm_clock: Option<Stats>,
+    /// Core clock in Hz
+    pub core_clock: Option<Stats>,
+    /// Core voltage in V
+    pub core_volts: Option<Stats>,
+}
+
+impl Baseline {
+    /// Read a baseline written by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Baseline> {
+        Baseline::parse(&fs::read_to_string(path)?)
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    fn fields(&mut self) -> [(&'static str, &mut Option<Stats>); 4] {
+        [
+            ("temp", &mut self.temp),
+            ("arm_clock", &mut self.arm_clock),
+            ("core_clock", &mut self.core_clock),
+            ("core_volts", &mut self.core_volts),
+        ]
+    }
+
+    fn parse(input: &str) -> Result<Baseline, String> {
+        let mut baseline = Baseline::default();
+
+        for line in input.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got {:?}", line))?;
+            let (name, stat) = key
+                .split_once('.')
+                .ok_or_else(|| format!("unknown key {:?}", key))?;
+
+            let mut fields = baseline.fields();
+            let field = fields
+                .iter_mut()
+                .find(|(field, _)| *field == name)
+                .ok_or_else(|| format!("unknown key {:?}", key))?;
+            let stats = field.1.get_or_insert(Stats {
+                min: 0.0,
+                max: 0.0,
+                mean: 0.0,
+                count: 0,
+            });
+
+            let invalid = || format!("invalid value for {}: {:?}", key, value);
+            match stat {
+                "min" => stats.min = value.parse().map_err(|_| invalid())?,
+                "max" => stats.max = value.parse().map_err(|_| invalid())?,
+                "mean" => stats.mean = value.parse().map_err(|_| invalid())?,
+                "count" => stats.count = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown key {:?}", key)),
+            }
+        }
+
+        Ok(baseline)
+    }
+}
+
+/// The `key=value` representation written by `save`
+impl fmt::Display for Baseline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut copy = *self;
+        let mut output = String::new();
+
+        for (name, stats) in copy.fields().iter() {
+            if let Some(stats) = stats {
+                let _ = writeln!(output, "{}.min={}", name, stats.min);
+                let _ = writeln!(output, "{}.max={}", name, stats.max);
+                let _ = writeln!(output, "{}.mean={}", name, stats.mean);
+                let _ = writeln!(output, "{}.count={}", name, stats.count);
+            }
+        }
+
+        f.write_str(&output)
+    }
+}
+
+/// Samples an idle system to establish a `Baseline`
+pub struct Calibration {
+    duration: Duration,
+    interval: Duration,
+    monitor: Monitor,
+}
+
+impl Calibration {
+    /// Calibrate for `duration`, sampling once per second
+    pub fn new(duration: Duration) -> Calibration {
+        let monitor = Monitor::new(DEFAULT_INTERVAL)
+            .metric(Metric::Temp)
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .metric(Metric::Clock(ClockSrc::Core))
+            .metric(Metric::Volts(VoltSrc::Core));
+
+        Calibration {
+            duration,
+            interval: DEFAULT_INTERVAL,
+            monitor,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Calibration {
+        self.interval = interval;
+        self.monitor.set_interval(interval);
+        self
+    }
+
+    /// Replace the function used to read a metric, see `Monitor::sampler`
+    pub fn sampler<F>(mut self, sampler: F) -> Calibration
+    where
+        F: FnMut(Metric) -> Result<Reading> + Send + 'static,
+    {
+        self.monitor = self.monitor.sampler(sampler);
+        self
+    }
+
+    /// Sample for the configured duration, blocking until done
+    pub fn run(mut self) -> Baseline {
+        let mut readings = Vec::new();
+        sample_for(
+            &mut self.monitor,
+            self.duration,
+            self.interval,
+            |_, sample| readings.extend(sample.readings.iter().copied()),
+        );
+
+        let stats_of = |metric: Metric| {
+            Stats::from_values(readings.iter().filter_map(|reading| {
+                if reading.metric() != metric {
+                    return None;
+                }
+                match *reading {
+                    Reading::Temp(temp) => Some(temp),
+                    Reading::Clock(_, frequency) => Some(frequency as f64),
+                    Reading::Volts(_, volts) => Some(volts),
+                    _ => None,
+                }
+            }))
+        };
+
+        Baseline {
+            temp: stats_of(Metric::Temp),
+            arm_clock: stats_of(Metric::Clock(ClockSrc::Arm)),
+            core_clock: stats_of(Metric::Clock(ClockSrc::Core)),
+            core_volts: stats_of(Metric::Volts(VoltSrc::Core)),
+        }
+    }
+}
+
+/// Sample the idle system for `duration` and return its baseline
+pub fn calibrate(duration: Duration) -> Baseline {
+    Calibration::new(duration).run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let stats = Stats::from_values(vec![40.0, 44.0, 42.0]).unwrap();
+        assert_eq!(40.0, stats.min);
+        assert_eq!(44.0, stats.max);
+        assert_eq!(42.0, stats.mean);
+        assert_eq!(3, stats.count);
+
+        assert_eq!(None, Stats::from_values(Vec::new()));
+    }
+
+    #[test]
+    fn test_calibration() {
+        let mut temp = 40.0;
+        let baseline = Calibration::new(Duration::from_millis(30))
+            .interval(Duration::from_millis(10))
+            .sampler(move |metric| match metric {
+                Metric::Temp => {
+                    temp += 1.0;
+                    Ok(Reading::Temp(temp))
+                }
+                Metric::Clock(src) => Ok(Reading::Clock(src, 600_000_000)),
+                Metric::Volts(src) => Ok(Reading::Volts(src, 0.85)),
+                _ => unreachable!(),
+            })
+            .run();
+
+        let temp = baseline.temp.unwrap();
+        assert_eq!(41.0, temp.min);
+        assert!(temp.count >= 3);
+        assert_eq!(600_000_000.0, baseline.arm_clock.unwrap().max);
+        assert_eq!(0.85, baseline.core_volts.unwrap().mean);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let baseline = Baseline {
+            temp: Stats::from_values(vec![41.5, 43.0]),
+            arm_clock: Stats::from_values(vec![600_000_000.0, 1_500_000_000.0]),
+            core_clock: None,
+            core_volts: Stats::from_values(vec![0.8563]),
+        };
+
+        let path = std::env::temp_dir().join(format!("vcgencmd-baseline-{}", std::process::id()));
+        baseline.save(&path).unwrap();
+        assert_eq!(baseline, Baseline::load(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert!(Baseline::parse("temp.median=4").is_err());
+        assert!(Baseline::parse("temp.min=warm").is_err());
+    }
+}
@@ -0,0 +1,421 @@
+//! Posting alerts to a Slack or Discord channel through an incoming webhook
+//!
+//! `ChatWebhook` formats every alert as a message of the chat service, colored by severity
+//! and with the decoded throttled flags attached, so the channel says "Under-voltage
+//! detected" instead of `0x50005`. As a monitor sink it posts whenever the flags active
+//! right now change, once when a condition starts and once when all of them cleared again.
+//! Alerts of other detectors can be passed in with `on_alert`.
+//!
+//! The service is told by the URL: Discord webhooks get Discord's embeds, everything else
+//! Slack's attachments, which Mattermost and Rocket.Chat understand as well.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use vcgencmd::chat::ChatWebhook;
+//! use vcgencmd::monitor::{Metric, Monitor};
+//!
+//! let webhook = ChatWebhook::new("https://hooks.slack.com/services/T000/B000/XXXX")?
+//!     .username("pi4");
+//! let monitor = Monitor::new(Duration::from_secs(1))
+//!     .metric(Metric::Throttled)
+//!     .queued_metric_sink(webhook, 16, vcgencmd::sink::Backpressure::DropOldest);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::alert::{Alert, Severity};
+use crate::monitor::{Reading, Sample};
+use crate::sink::{self, MetricSink};
+use crate::throttled::{self, Explanation, ThrottledFlag};
+use crate::{json, timefmt};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The chat service a webhook belongs to, which decides the format of the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatService {
+    /// Slack and services accepting its payloads, like Mattermost
+    Slack,
+    Discord,
+}
+
+impl ChatService {
+    /// The service of the webhook at `url`
+    pub fn from_url(url: &str) -> ChatService {
+        let host = url
+            .split_once("://")
+            .map_or(url, |(_, rest)| rest)
+            .split(['/', ':'])
+            .next()
+            .unwrap_or_default();
+
+        match host {
+            "discord.com" | "discordapp.com" => ChatService::Discord,
+            host if host.ends_with(".discord.com") => ChatService::Discord,
+            _ => ChatService::Slack,
+        }
+    }
+}
+
+/// The color of messages of `severity`, as RGB
+fn color(severity: Severity) -> u32 {
+    match severity {
+        Severity::Info => 0x2e_b8_86,
+        Severity::Warning => 0xf2_c7_44,
+        Severity::Critical => 0xd5_02_00,
+    }
+}
+
+/// Posts alerts to a chat webhook, see the module documentation
+#[derive(Debug, Clone)]
+pub struct ChatWebhook {
+    url: String,
+    service: ChatService,
+    username: Option<String>,
+    severity: Severity,
+    agent: ureq::Agent,
+    /// The last throttled bit pattern seen, shared with `on_alert`
+    throttled: Arc<Mutex<Option<isize>>>,
+    /// Whether a started condition was posted that hasn't been posted as cleared yet
+    posted: bool,
+}
+
+impl ChatWebhook {
+    /// Post to the incoming webhook at `url`, over `https://` or `http://`
+    pub fn new(url: &str) -> io::Result<ChatWebhook> {
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not an HTTP(S) webhook URL: '{}'", url),
+            ));
+        }
+
+        Ok(ChatWebhook {
+            url: url.to_owned(),
+            service: ChatService::from_url(url),
+            username: None,
+            severity: Severity::Warning,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            throttled: Arc::new(Mutex::new(None)),
+            posted: false,
+        })
+    }
+
+    /// Format the messages for `service` instead of the one told by the URL
+    pub fn service(mut self, service: ChatService) -> ChatWebhook {
+        self.service = service;
+        self
+    }
+
+    /// Post as `username` instead of the name the webhook was set up with
+    pub fn username(mut self, username: &str) -> ChatWebhook {
+        self.username = Some(username.to_owned());
+        self
+    }
+
+    /// Only post alerts of `severity` or worse, warnings by default. Cleared conditions are
+    /// posted whenever their start was.
+    pub fn severity(mut self, severity: Severity) -> ChatWebhook {
+        self.severity = severity;
+        self
+    }
+
+    /// A callback for detectors like `ThresholdDetector::into_sink`, posting their alerts
+    /// along with the last throttled flags seen.
+    ///
+    /// The callbacks of detectors return nothing, so failed posts are dropped, use `post`
+    /// to handle them.
+    pub fn on_alert(&self) -> impl FnMut(&Alert) + Send + 'static {
+        let webhook = self.clone();
+        move |alert| {
+            if alert.severity >= webhook.severity {
+                let _ = webhook.post(alert);
+            }
+        }
+    }
+
+    /// Post `alert`, with the last throttled flags seen
+    pub fn post(&self, alert: &Alert) -> io::Result<()> {
+        let throttled = *self.throttled.lock().unwrap_or_else(|e| e.into_inner());
+        let payload = self.payload(alert, throttled.map(throttled::explain).as_ref());
+
+        match self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&payload)
+        {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => Err(io::Error::other(format!(
+                "webhook answered {} {}",
+                status,
+                response.status_text()
+            ))),
+            Err(error) => Err(io::Error::other(error)),
+        }
+    }
+
+    /// The alert for a change of the active flags with `bit_pattern`, if any
+    fn observe(&mut self, bit_pattern: isize, at: SystemTime) -> Option<Alert> {
+        let previous = self
+            .throttled
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(bit_pattern);
+        let explanation = throttled::explain(bit_pattern);
+        let unchanged = previous
+            .is_some_and(|previous| throttled::explain(previous).active == explanation.active);
+        if unchanged {
+            return None;
+        }
+
+        if explanation.active.is_empty() {
+            if !self.posted {
+                return None;
+            }
+            self.posted = false;
+            return Some(Alert {
+                timestamp: at,
+                severity: Severity::Info,
+                source: "throttled",
+                message: "All conditions cleared".to_owned(),
+            });
+        }
+
+        let severity = explanation
+            .active
+            .iter()
+            .map(ThrottledFlag::severity)
+            .max()
+            .unwrap_or(Severity::Info);
+        if severity < self.severity {
+            return None;
+        }
+        self.posted = true;
+        let meanings: Vec<_> = explanation.active.iter().map(|flag| flag.meaning).collect();
+        Some(Alert {
+            timestamp: at,
+            severity,
+            source: "throttled",
+            message: meanings.join(", "),
+        })
+    }
+
+    /// The JSON body posting `alert` with the flags of `explanation`
+    fn payload(&self, alert: &Alert, explanation: Option<&Explanation>) -> String {
+        let title = format!("[{}] {}", alert.severity, alert.source);
+        let flags = explanation.map(|explanation| {
+            let names = |flags: &[ThrottledFlag]| match flags {
+                [] => "none".to_owned(),
+                flags => {
+                    let meanings: Vec<_> = flags.iter().map(|flag| flag.meaning).collect();
+                    meanings.join(", ")
+                }
+            };
+            format!(
+                "0x{:x}\nActive now: {}\nOccurred since boot: {}",
+                explanation.bit_pattern,
+                names(&explanation.active),
+                names(&explanation.historic)
+            )
+        });
+        // a null username isn't accepted everywhere, so it's left out unless set
+        let mut message = Vec::new();
+        if let Some(username) = &self.username {
+            message.push(("username", json::string(username)));
+        }
+
+        match self.service {
+            ChatService::Slack => {
+                let fields = flags.iter().map(|flags| {
+                    json::object(&[
+                        ("title", json::string("Throttled")),
+                        ("value", json::string(flags)),
+                        ("short", "false".to_owned()),
+                    ])
+                });
+                let since_epoch = alert
+                    .timestamp
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                let attachment = json::object(&[
+                    (
+                        "color",
+                        json::string(&format!("#{:06x}", color(alert.severity))),
+                    ),
+                    ("fallback", json::string(&alert.to_string())),
+                    ("title", json::string(&title)),
+                    ("text", json::string(&alert.message)),
+                    ("fields", json::array(fields)),
+                    ("ts", json::number(since_epoch.as_secs() as f64)),
+                ]);
+                message.push(("text", json::string(&alert.to_string())));
+                message.push(("attachments", json::array(vec![attachment])));
+            }
+            ChatService::Discord => {
+                let fields = flags.iter().map(|flags| {
+                    json::object(&[
+                        ("name", json::string("Throttled")),
+                        ("value", json::string(flags)),
+                    ])
+                });
+                let embed = json::object(&[
+                    ("title", json::string(&title)),
+                    ("description", json::string(&alert.message)),
+                    ("color", json::number(f64::from(color(alert.severity)))),
+                    ("fields", json::array(fields)),
+                    (
+                        "timestamp",
+                        json::string(&timefmt::rfc3339(alert.timestamp)),
+                    ),
+                ]);
+                message.push(("embeds", json::array(vec![embed])));
+            }
+        }
+
+        json::object(&message)
+    }
+
+    /// Turn this into a plain monitor sink posting changes of the throttled flags. Failed
+    /// posts are dropped, `Monitor::metric_sink` counts them in the sink's `SinkStats`.
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for ChatWebhook {
+    /// Post the change of the active flags with the `Throttled` reading of `sample`, if any
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        for reading in &sample.readings {
+            if let Reading::Throttled(bit_pattern) = *reading {
+                if let Some(alert) = self.observe(bit_pattern, sample.timestamp) {
+                    self.post(&alert)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_service() {
+        let service = ChatService::from_url;
+        assert_eq!(
+            ChatService::Discord,
+            service("https://discord.com/api/webhooks/1/x")
+        );
+        assert_eq!(
+            ChatService::Discord,
+            service("https://ptb.discord.com/api/webhooks/1/x")
+        );
+        assert_eq!(
+            ChatService::Slack,
+            service("https://hooks.slack.com/services/T/B/X")
+        );
+        assert_eq!(
+            ChatService::Slack,
+            service("http://mattermost:8065/hooks/x")
+        );
+        assert!(ChatWebhook::new("hooks.slack.com/services").is_err());
+    }
+
+    #[test]
+    fn test_observe() {
+        let mut webhook = ChatWebhook::new("https://hooks.slack.com/services/T/B/X").unwrap();
+        assert_eq!(None, webhook.observe(0x50000, at(0)));
+
+        let alert = webhook.observe(0x50005, at(1)).unwrap();
+        assert_eq!(Severity::Critical, alert.severity);
+        assert_eq!("Under-voltage detected, Currently throttled", alert.message);
+        assert_eq!(None, webhook.observe(0x50005, at(2)));
+
+        let alert = webhook.observe(0x50000, at(3)).unwrap();
+        assert_eq!(Severity::Info, alert.severity);
+        assert_eq!(None, webhook.observe(0x50000, at(4)));
+    }
+
+    #[test]
+    fn test_payload() {
+        let alert = Alert {
+            timestamp: at(60),
+            severity: Severity::Critical,
+            source: "throttled",
+            message: "Under-voltage detected".to_owned(),
+        };
+        let explanation = throttled::explain(0x50001);
+
+        let slack = ChatWebhook::new("https://hooks.slack.com/services/T/B/X").unwrap();
+        let payload = slack.payload(&alert, Some(&explanation));
+        assert!(payload.starts_with(
+            r##"{"text":"[critical] throttled: Under-voltage detected","attachments":[{"color":"#d50200","##
+        ));
+        assert!(payload.contains(
+            r#""value":"0x50001\nActive now: Under-voltage detected\nOccurred since boot: "#
+        ));
+        assert!(payload.ends_with(r#""ts":60}]}"#));
+
+        let discord = ChatWebhook::new("https://discord.com/api/webhooks/1/x")
+            .unwrap()
+            .username("pi4");
+        let payload = discord.payload(&alert, None);
+        assert_eq!(
+            r#"{"username":"pi4","embeds":[{"title":"[critical] throttled","description":"Under-voltage detected","color":13959680,"fields":[],"timestamp":"1970-01-01T00:01:00.000Z"}]}"#,
+            payload
+        );
+    }
+
+    #[test]
+    fn test_post() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/x", listener.local_addr().unwrap());
+
+        let received = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .unwrap();
+            String::from_utf8(body).unwrap()
+        });
+
+        let mut webhook = ChatWebhook::new(&url).unwrap();
+        let sample = Sample {
+            timestamp: at(60),
+            readings: vec![Reading::Throttled(0x50005)],
+            errors: Vec::new(),
+        };
+        MetricSink::write(&mut webhook, &sample).unwrap();
+        assert!(received
+            .join()
+            .unwrap()
+            .contains("Under-voltage detected, Currently throttled"));
+    }
+}
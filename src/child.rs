@@ -0,0 +1,352 @@
+//! Running a child process to completion without leaving anything behind
+//!
+//! `Command::output` returns early without waiting for the child if reading one of its
+//! pipes fails, and has no deadline, so a `vcgencmd` stuck on the firmware or a `sudo`
+//! waiting for a password blocks the caller for good. Here every child is reaped on every
+//! path, including timeouts, failing reads and a failure to spawn the reader thread, so a
+//! long-running monitor accumulates neither zombies nor pipe descriptors.
+//!
+//! A child that misses its deadline gets `SIGTERM` first, which `sudo` and `ssh` relay to
+//! the command they run, unlike `SIGKILL`, and is killed only after a grace period.
+//! The same happens to the children of a thread whose cancellation flag, see `cancel_on`,
+//! is set, so a stopping monitor doesn't have to wait for a stuck `vcgencmd`.
+
+use std::cell::RefCell;
+#[cfg(unix)]
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use std::process::{Child, ChildStderr, ChildStdout, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a child gets to exit after `SIGTERM` before it is killed
+#[cfg(unix)]
+const GRACE_PERIOD: Duration = Duration::from_millis(500);
+/// `try_wait` is polled at an interval doubling from the first to the second, most
+/// commands finish within a few milliseconds
+const POLL_INTERVALS: (Duration, Duration) = (Duration::from_millis(1), Duration::from_millis(50));
+
+thread_local! {
+    /// The flag set to cut the children of this thread short, see `cancel_on`
+    static CANCEL: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Terminate the children run on this thread from now on as soon as `flag` is set, as if
+/// they had timed out
+pub(crate) fn cancel_on(flag: Arc<AtomicBool>) {
+    CANCEL.with(|cancel| *cancel.borrow_mut() = Some(flag));
+}
+
+/// Whether children run on this thread may be cut short at all
+fn cancellable() -> bool {
+    CANCEL.with(|cancel| cancel.borrow().is_some())
+}
+
+fn cancelled() -> bool {
+    CANCEL.with(|cancel| {
+        cancel
+            .borrow()
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::SeqCst))
+    })
+}
+
+/// A child that is terminated and reaped when dropped, unless it was reaped already
+struct Reaper {
+    child: Child,
+    reaped: bool,
+}
+
+impl Reaper {
+    /// Wait for the child to exit, for good without a deadline. Returns `None` if it is
+    /// still running at the deadline or the thread was cancelled.
+    fn wait_until(&mut self, deadline: Option<Instant>) -> io::Result<Option<ExitStatus>> {
+        if deadline.is_none() && !cancellable() {
+            let status = self.child.wait()?;
+            self.reaped = true;
+            return Ok(Some(status));
+        }
+
+        let (mut interval, max_interval) = POLL_INTERVALS;
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                self.reaped = true;
+                return Ok(Some(status));
+            }
+
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) || cancelled() {
+                return Ok(None);
+            }
+            let left = deadline.map_or(max_interval, |deadline| deadline - now);
+            thread::sleep(interval.min(left));
+            interval = (interval * 2).min(max_interval);
+        }
+    }
+
+    /// Ask the child to exit, kill it if it doesn't within the grace period, and reap it
+    fn terminate(&mut self) {
+        #[cfg(unix)]
+        {
+            if let Ok(pid) = libc::pid_t::try_from(self.child.id()) {
+                // SAFETY: the child isn't reaped yet, so its pid can't have been reused
+                unsafe { libc::kill(pid, libc::SIGTERM) };
+                if let Ok(Some(_)) = self.wait_until(Some(Instant::now() + GRACE_PERIOD)) {
+                    return;
+                }
+            }
+        }
+
+        // fails only if the child exited in the meantime, which `wait` reaps just as well
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        self.reaped = true;
+    }
+}
+
+impl Drop for Reaper {
+    fn drop(&mut self) {
+        if !self.reaped {
+            self.terminate();
+        }
+    }
+}
+
+/// Read once from `pipe` into `data`, dropping the pipe at its end
+#[cfg(unix)]
+fn read_some<R: Read>(
+    pipe: &mut Option<R>,
+    data: &mut Vec<u8>,
+    buffer: &mut [u8],
+) -> io::Result<()> {
+    if let Some(reader) = pipe {
+        match reader.read(buffer) {
+            Ok(0) => *pipe = None,
+            Ok(read) => data.extend_from_slice(buffer.get(..read).unwrap_or_default()),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+/// Read stdout and stderr to their ends, whichever has data, so the child never blocks on
+/// a full pipe
+#[cfg(unix)]
+fn read_both(
+    mut stdout: Option<ChildStdout>,
+    mut stderr: Option<ChildStderr>,
+) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    use std::os::unix::io::{AsRawFd, RawFd};
+
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let mut buffer = [0; 4096];
+    while stdout.is_some() || stderr.is_some() {
+        // `poll` skips the negative descriptors of closed pipes
+        let poll_fd = |fd: Option<RawFd>| libc::pollfd {
+            fd: fd.unwrap_or(-1),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let mut fds = [
+            poll_fd(stdout.as_ref().map(AsRawFd::as_raw_fd)),
+            poll_fd(stderr.as_ref().map(AsRawFd::as_raw_fd)),
+        ];
+        // SAFETY: `fds` holds the two entries `poll` is told about
+        if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+
+        let [out_fd, err_fd] = fds;
+        if out_fd.revents != 0 {
+            read_some(&mut stdout, &mut out, &mut buffer)?;
+        }
+        if err_fd.revents != 0 {
+            read_some(&mut stderr, &mut err, &mut buffer)?;
+        }
+    }
+
+    Ok((out, err))
+}
+
+/// Read stdout and then stderr to their ends. Without `poll` a child filling stderr before
+/// closing stdout would block, vcgencmd writes far less than a pipe holds.
+#[cfg(not(unix))]
+fn read_both(
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    if let Some(mut stdout) = stdout {
+        stdout.read_to_end(&mut out)?;
+    }
+    if let Some(mut stderr) = stderr {
+        stderr.read_to_end(&mut err)?;
+    }
+    Ok((out, err))
+}
+
+fn interrupted() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "cancelled")
+}
+
+fn timed_out(timeout: Duration) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("no answer within {:?}", timeout),
+    )
+}
+
+/// Run `command` capturing stdout and stderr, like `Command::output`, but reaping the child
+/// on every path and terminating it once `timeout` passed or the thread was cancelled.
+///
+/// Both pipes are read on one thread of its own, which ends with the last process holding
+/// them open, while the calling thread waits for the child. After a timeout the reader is
+/// left to finish on its own, as a grandchild that ignores `SIGTERM` may hold the pipes
+/// beyond the child's death.
+pub(crate) fn output(command: &mut Command, timeout: Option<Duration>) -> io::Result<Output> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut child = Reaper {
+        child: command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?,
+        reaped: false,
+    };
+
+    let (sender, received) = mpsc::channel();
+    let (stdout, stderr) = (child.child.stdout.take(), child.child.stderr.take());
+    thread::Builder::new()
+        .name("vcgencmd-pipe".to_owned())
+        .spawn(move || {
+            let _ = sender.send(read_both(stdout, stderr));
+        })?;
+
+    let status = match child.wait_until(deadline)? {
+        Some(status) => status,
+        None if cancelled() => return Err(interrupted()),
+        None => return Err(timed_out(timeout.unwrap_or_default())),
+    };
+
+    let (stdout, stderr) = match deadline {
+        Some(deadline) => received
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .map_err(|_| timed_out(timeout.unwrap_or_default()))?,
+        None => received
+            .recv()
+            .map_err(|_| io::Error::other("lost the pipe reader"))?,
+    }?;
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn open_fds() -> usize {
+        fs::read_dir("/proc/self/fd").unwrap().count()
+    }
+
+    /// Processes whose parent is this one, including zombies
+    fn children() -> usize {
+        let pid = std::process::id().to_string();
+        fs::read_dir("/proc")
+            .unwrap()
+            .filter_map(|entry| fs::read_to_string(entry.ok()?.path().join("stat")).ok())
+            .filter(|stat| {
+                // the parent is the second field after the command name in parentheses
+                let fields = stat.rsplit(')').next().unwrap_or_default();
+                fields.split_whitespace().nth(1) == Some(pid.as_str())
+            })
+            .count()
+    }
+
+    fn sh(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    #[test]
+    fn test_output() {
+        let output = output(&mut sh("echo out; echo err >&2; exit 3"), None).unwrap();
+        assert_eq!(b"out\n", output.stdout.as_slice());
+        assert_eq!(b"err\n", output.stderr.as_slice());
+        assert_eq!(Some(3), output.status.code());
+    }
+
+    #[test]
+    fn test_output_of_full_pipes() {
+        // more than a pipe holds on both, written in turns
+        let script = "for i in $(seq 2000); do echo $i; echo $i >&2; done; \
+                      head -c 100000 /dev/zero >&2";
+        let output = output(&mut sh(script), Some(Duration::from_secs(10))).unwrap();
+        assert!(output.stdout.ends_with(b"\n2000\n"));
+        assert_eq!(100_000 + 8893, output.stderr.len());
+    }
+
+    #[test]
+    fn test_timeout_reaps() {
+        let started = Instant::now();
+        let error = output(&mut sh("exec sleep 10"), Some(Duration::from_millis(50))).unwrap_err();
+
+        assert_eq!(io::ErrorKind::TimedOut, error.kind());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let cancel = Arc::clone(&flag);
+        let started = Instant::now();
+        let worker = thread::spawn(move || {
+            cancel_on(cancel);
+            output(&mut sh("exec sleep 10"), None)
+        });
+
+        thread::sleep(Duration::from_millis(100));
+        flag.store(true, Ordering::SeqCst);
+        let error = worker.join().unwrap().unwrap_err();
+
+        assert_eq!(io::ErrorKind::Interrupted, error.kind());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    /// Tens of thousands of commands, succeeding, failing and timing out, must leave the
+    /// number of descriptors and children as it was. Counts are per process, so run it
+    /// alone: `cargo test soak -- --ignored --test-threads=1`
+    #[test]
+    #[ignore]
+    fn test_soak() {
+        let (fds, children_before) = (open_fds(), children());
+
+        for run in 0..20_000 {
+            let result = match run % 100 {
+                0 => output(&mut sh("exec sleep 10"), Some(Duration::from_millis(1))),
+                1 => output(&mut Command::new("/nonexistent/vcgencmd"), None),
+                _ => output(&mut sh("echo frequency=1; exit $(($$ % 2))"), None),
+            };
+            if run % 100 > 1 {
+                assert!(result.is_ok());
+            }
+        }
+        // give the readers of timed out children a moment to see their pipes close
+        thread::sleep(Duration::from_millis(200));
+
+        assert_eq!(fds, open_fds());
+        assert_eq!(children_before, children());
+    }
+}
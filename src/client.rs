@@ -0,0 +1,509 @@
+//! A client running vcgencmd its own way
+//!
+//! The free functions of this crate all run vcgencmd as configured by `set_invocation`,
+//! once for the whole process. A `Vcgencmd` carries an `Invocation` of its own instead, so
+//! a library can read the firmware without touching the global one, and a program can
+//! talk to several binaries or hosts side by side:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use vcgencmd::{Src, Vcgencmd, VoltSrc};
+//!
+//! let local = Vcgencmd::builder()
+//!     .binary_path("/opt/vc/bin/vcgencmd")
+//!     .use_sudo(false)
+//!     .timeout(Duration::from_secs(2))
+//!     .build();
+//! let remote = Vcgencmd::builder().host("pi@pi4.local").build();
+//!
+//! println!("{} °C here", local.measure_temp()?);
+//! println!("{} V there", remote.measure_volts(Src::Volt(VoltSrc::Core))?);
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+//!
+//! Unlike the free functions, a client doesn't skip commands `session::global` found to be
+//! unsupported, as those capabilities are of the global invocation.
+//!
+//! With a `RetryPolicy`, a client repeats calls failing with a transient error, see `retry`.
+//!
+//! A client doesn't have to spawn vcgencmd at all, `Vcgencmd::with_executor` gets the output
+//! from any `Executor` instead, see `executor`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::devices::DeviceNodes;
+use crate::display::{HdmiTimings, LcdInfo};
+use crate::error::ParseError;
+use crate::executor::{Executor, ProcessExecutor};
+use crate::monitor::{Metric, Reading};
+use crate::retry::RetryPolicy;
+use crate::thermal::TempLimits;
+use crate::{
+    interpret_bit_pattern, measure_each, parse_reply, parsers, AdcChannel, ClockSrc, Cmd, Config,
+    ConfigSrc, Escalation, GpuMemoryPressure, Invocation, MemSplit, MemSrc, PrivilegeMode,
+    RelocStats, Result, Src, ThrottledStatus, VoltRails, VoltSrc,
+};
+
+/// Runs vcgencmd as configured by its own `Invocation`, or through another `Executor`, see
+/// the module documentation.
+///
+/// Every measurement function of the crate is available as a method of the same name.
+#[derive(Debug, Clone)]
+pub struct Vcgencmd<X = ProcessExecutor> {
+    executor: X,
+    /// The temperature limits of the firmware this client talks to, once read
+    limits: OnceLock<TempLimits>,
+    retry: Option<RetryPolicy>,
+}
+
+impl Default for Vcgencmd {
+    /// `Vcgencmd::new`, with the `Invocation` of the environment
+    fn default() -> Vcgencmd {
+        Vcgencmd::new()
+    }
+}
+
+/// Builds a `Vcgencmd`, see `Vcgencmd::builder`
+#[derive(Debug, Clone)]
+pub struct VcgencmdBuilder {
+    invocation: Invocation,
+    retry: Option<RetryPolicy>,
+}
+
+impl Default for VcgencmdBuilder {
+    /// `Vcgencmd::builder`, starting from the `Invocation` of the environment
+    fn default() -> VcgencmdBuilder {
+        Vcgencmd::builder()
+    }
+}
+
+impl VcgencmdBuilder {
+    /// Run the binary at `path`, a plain name is looked up in `PATH` and then in
+    /// `FALLBACK_DIRS`
+    pub fn binary_path<P: Into<PathBuf>>(mut self, path: P) -> VcgencmdBuilder {
+        self.invocation.binary = path.into();
+        self
+    }
+
+    /// Whether to run it through `sudo`
+    pub fn privilege(mut self, privilege: PrivilegeMode) -> VcgencmdBuilder {
+        self.invocation.privilege = privilege;
+        self
+    }
+
+    /// Run it through `escalation` instead of `sudo`, e.g. `doas` on Alpine
+    pub fn escalation(mut self, escalation: Escalation) -> VcgencmdBuilder {
+        self.invocation.escalation = escalation;
+        self
+    }
+
+    /// `privilege(PrivilegeMode::Sudo)` or `privilege(PrivilegeMode::None)`
+    pub fn use_sudo(self, sudo: bool) -> VcgencmdBuilder {
+        self.privilege(if sudo {
+            PrivilegeMode::Sudo
+        } else {
+            PrivilegeMode::None
+        })
+    }
+
+    /// Run it on `host` over `ssh`, see `Invocation::host`
+    pub fn host(mut self, host: &str) -> VcgencmdBuilder {
+        self.invocation.host = Some(host.to_owned());
+        self
+    }
+
+    /// The device nodes to check when a local call fails inside a container
+    pub fn devices(mut self, devices: DeviceNodes) -> VcgencmdBuilder {
+        self.invocation.devices = devices;
+        self
+    }
+
+    /// Terminate a call taking longer than `timeout`, see `Invocation::timeout`
+    pub fn timeout(mut self, timeout: Duration) -> VcgencmdBuilder {
+        self.invocation.timeout = Some(timeout);
+        self
+    }
+
+    /// Let calls take as long as they take
+    pub fn no_timeout(mut self) -> VcgencmdBuilder {
+        self.invocation.timeout = None;
+        self
+    }
+
+    /// Whether to answer what sysfs can when the binary is missing, see `sysfs`
+    pub fn sysfs_fallback(mut self, fallback: bool) -> VcgencmdBuilder {
+        self.invocation.sysfs_fallback = fallback;
+        self
+    }
+
+    /// Repeat calls failing with a transient error as `policy` says
+    pub fn retry(mut self, policy: RetryPolicy) -> VcgencmdBuilder {
+        self.retry = Some(policy);
+        self
+    }
+
+    pub fn build(self) -> Vcgencmd {
+        let client = Vcgencmd::with_invocation(self.invocation);
+        match self.retry {
+            Some(policy) => client.retry(policy),
+            None => client,
+        }
+    }
+}
+
+impl Vcgencmd {
+    /// A client with the `Invocation` of the environment, see `Config`, regardless of
+    /// `set_invocation`
+    pub fn new() -> Vcgencmd {
+        Vcgencmd::with_invocation(Config::cached().invocation())
+    }
+
+    /// A builder starting from the `Invocation` of the environment, like `new`
+    pub fn builder() -> VcgencmdBuilder {
+        VcgencmdBuilder {
+            invocation: Config::cached().invocation(),
+            retry: None,
+        }
+    }
+
+    /// A client running vcgencmd as `invocation` says
+    pub fn with_invocation(invocation: Invocation) -> Vcgencmd {
+        Vcgencmd::with_executor(ProcessExecutor::new(invocation))
+    }
+
+    /// A client running vcgencmd like the free functions do right now
+    pub fn from_global() -> Vcgencmd {
+        Vcgencmd::with_invocation(crate::invocation())
+    }
+
+    pub fn invocation(&self) -> &Invocation {
+        self.executor.invocation()
+    }
+}
+
+impl<X: Executor> Vcgencmd<X> {
+    /// A client getting the output of vcgencmd from `executor`
+    pub fn with_executor(executor: X) -> Vcgencmd<X> {
+        Vcgencmd {
+            executor,
+            limits: OnceLock::new(),
+            retry: None,
+        }
+    }
+
+    /// Repeat calls failing with a transient error as `policy` says, see `retry`
+    pub fn retry(mut self, policy: RetryPolicy) -> Vcgencmd<X> {
+        self.retry = Some(policy);
+        self
+    }
+
+    pub fn executor(&self) -> &X {
+        &self.executor
+    }
+
+    /// Run `command` and parse its output, see `crate::call`
+    fn call<T, E: ParseError>(
+        &self,
+        command: Cmd,
+        src: Option<Src>,
+        parse: fn(&str) -> std::result::Result<T, E>,
+    ) -> Result<T> {
+        self.call_with_raw(command, src, parse)
+            .map(|(value, _)| value)
+    }
+
+    /// `call`, also returning the output of `vcgencmd` the value was parsed from
+    fn call_with_raw<T, E: ParseError>(
+        &self,
+        command: Cmd,
+        src: Option<Src>,
+        parse: fn(&str) -> std::result::Result<T, E>,
+    ) -> Result<(T, String)> {
+        let call = || {
+            let stdout = self.executor.run(command, src)?;
+            parse_reply(command, src, stdout, parse)
+        };
+        match &self.retry {
+            Some(policy) => policy.run(call),
+            None => call(),
+        }
+    }
+
+    /// See `crate::measure_clock`
+    pub fn measure_clock(&self, src: Src) -> Result<isize> {
+        self.call(Cmd::MeasureClock, Some(src), parsers::frequency)
+    }
+
+    /// See `crate::measure_clock_with_raw`
+    pub fn measure_clock_with_raw(&self, src: Src) -> Result<(isize, String)> {
+        self.call_with_raw(Cmd::MeasureClock, Some(src), parsers::frequency)
+    }
+
+    /// See `crate::measure_clock_all`
+    pub fn measure_clock_all(&self) -> Result<HashMap<ClockSrc, isize>> {
+        measure_each(&ClockSrc::ALL, |src| self.measure_clock(Src::Clock(src)))
+    }
+
+    /// See `crate::measure_volts`
+    pub fn measure_volts(&self, src: Src) -> Result<f64> {
+        self.call(Cmd::MeasureVolts, Some(src), parsers::volts)
+    }
+
+    /// See `crate::measure_volts_with_raw`
+    pub fn measure_volts_with_raw(&self, src: Src) -> Result<(f64, String)> {
+        self.call_with_raw(Cmd::MeasureVolts, Some(src), parsers::volts)
+    }
+
+    /// See `crate::measure_volts_all`
+    pub fn measure_volts_all(&self) -> Result<VoltRails> {
+        let mut volts = measure_each(&VoltSrc::ALL, |src| self.measure_volts(Src::Volt(src)))?;
+
+        Ok(VoltRails {
+            core: volts.remove(&VoltSrc::Core),
+            sdram_c: volts.remove(&VoltSrc::SdramC),
+            sdram_i: volts.remove(&VoltSrc::SdramI),
+            sdram_p: volts.remove(&VoltSrc::SdramP),
+        })
+    }
+
+    /// See `crate::measure_temp`
+    pub fn measure_temp(&self) -> Result<f64> {
+        self.call(Cmd::MeasureTemp, None, parsers::temp)
+    }
+
+    /// See `crate::measure_temp_with_raw`
+    pub fn measure_temp_with_raw(&self) -> Result<(f64, String)> {
+        self.call_with_raw(Cmd::MeasureTemp, None, parsers::temp)
+    }
+
+    /// See `crate::get_mem`
+    pub fn get_mem(&self, src: Src) -> Result<isize> {
+        self.call(Cmd::GetMem, Some(src), parsers::mem)
+    }
+
+    /// See `crate::get_mem_with_raw`
+    pub fn get_mem_with_raw(&self, src: Src) -> Result<(isize, String)> {
+        self.call_with_raw(Cmd::GetMem, Some(src), parsers::mem)
+    }
+
+    /// See `crate::get_mem_split`
+    pub fn get_mem_split(&self) -> Result<MemSplit> {
+        let arm = self.get_mem(Src::Mem(MemSrc::Arm))?;
+        let gpu = self.get_mem(Src::Mem(MemSrc::Gpu))?;
+
+        Ok(MemSplit::new(arm, gpu))
+    }
+
+    /// See `crate::get_gpu_memory_pressure`
+    pub fn get_gpu_memory_pressure(&self) -> Result<GpuMemoryPressure> {
+        Ok(GpuMemoryPressure {
+            malloc_total: self.get_mem(Src::Mem(MemSrc::MallocTotal))?,
+            malloc_free: self.get_mem(Src::Mem(MemSrc::Malloc))?,
+            reloc_total: self.get_mem(Src::Mem(MemSrc::RelocTotal))?,
+            reloc_free: self.get_mem(Src::Mem(MemSrc::Reloc))?,
+            reloc_stats: self.mem_reloc_stats()?,
+        })
+    }
+
+    /// See `crate::mem_reloc_stats`
+    pub fn mem_reloc_stats(&self) -> Result<RelocStats> {
+        self.call(Cmd::MemRelocStats, None, parsers::reloc_stats)
+    }
+
+    /// See `crate::get_config`
+    pub fn get_config(&self, src: Src) -> Result<isize> {
+        self.call(Cmd::GetConfig, Some(src), parsers::config)
+    }
+
+    /// See `crate::get_config_with_raw`
+    pub fn get_config_with_raw(&self, src: Src) -> Result<(isize, String)> {
+        self.call_with_raw(Cmd::GetConfig, Some(src), parsers::config)
+    }
+
+    /// See `crate::get_throttled`
+    pub fn get_throttled(&self) -> Result<isize> {
+        self.call(Cmd::GetThrottled, None, parsers::throttled)
+    }
+
+    /// See `crate::get_throttled_with_raw`
+    pub fn get_throttled_with_raw(&self) -> Result<(isize, String)> {
+        self.call_with_raw(Cmd::GetThrottled, None, parsers::throttled)
+    }
+
+    /// See `crate::get_throttled_status`
+    pub fn get_throttled_status(&self) -> Result<ThrottledStatus> {
+        self.get_throttled().map(interpret_bit_pattern)
+    }
+
+    /// See `crate::is_undervolted`
+    pub fn is_undervolted(&self) -> Result<bool> {
+        Ok(self.get_throttled_status()?.under_voltage)
+    }
+
+    /// See `crate::is_throttled`
+    pub fn is_throttled(&self) -> Result<bool> {
+        Ok(self.get_throttled_status()?.currently_throttled)
+    }
+
+    /// See `crate::pmic_read_adc`
+    pub fn pmic_read_adc(&self) -> Result<Vec<AdcChannel>> {
+        self.call(Cmd::PmicReadAdc, None, parsers::pmic_adc)
+    }
+
+    /// See `crate::display_power`
+    pub fn display_power(&self, id: u8) -> Result<Option<bool>> {
+        self.call(
+            Cmd::DisplayPower,
+            Some(Src::Display(id)),
+            parsers::display_power,
+        )
+    }
+
+    /// See `crate::get_lcd_info`
+    pub fn get_lcd_info(&self) -> Result<LcdInfo> {
+        self.call(Cmd::GetLcdInfo, None, parsers::lcd_info)
+    }
+
+    /// See `crate::hdmi_timings`
+    pub fn hdmi_timings(&self) -> Result<Option<HdmiTimings>> {
+        self.call(Cmd::HdmiTimings, None, parsers::hdmi_timings)
+    }
+
+    /// The temperature limits of the firmware, read once per client like `TempLimits::cached`
+    pub fn temp_limits(&self) -> Result<TempLimits> {
+        if let Some(limits) = self.limits.get() {
+            return Ok(*limits);
+        }
+
+        let soft = self.get_config(Src::Config(ConfigSrc::TempSoftLimit))?;
+        let hard = self.get_config(Src::Config(ConfigSrc::TempLimit))?;
+        Ok(*self
+            .limits
+            .get_or_init(|| TempLimits::from_config(soft, hard)))
+    }
+
+    /// `Metric::read` through this client, e.g. as the sampler of a `Monitor`:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use vcgencmd::monitor::{Metric, Monitor};
+    /// use vcgencmd::Vcgencmd;
+    ///
+    /// let client = Vcgencmd::builder().host("pi@pi4.local").build();
+    /// let monitor = Monitor::new(Duration::from_secs(5))
+    ///     .metric(Metric::Temp)
+    ///     .sampler(move |metric| client.read(metric));
+    /// ```
+    pub fn read(&self, metric: Metric) -> Result<Reading> {
+        let reading = match metric {
+            Metric::Temp => Reading::Temp(self.measure_temp()?),
+            Metric::TempHeadroom => {
+                let limits = self.temp_limits()?;
+                Reading::TempHeadroom(limits.headroom(self.measure_temp()?))
+            }
+            Metric::Throttled => Reading::Throttled(self.get_throttled()?),
+            Metric::Clock(src) => Reading::Clock(src, self.measure_clock(Src::Clock(src))?),
+            Metric::Volts(src) => Reading::Volts(src, self.measure_volts(Src::Volt(src))?),
+            Metric::Mem(src) => Reading::Mem(src, self.get_mem(Src::Mem(src))?),
+            Metric::Derived(_) => return metric.read(),
+        };
+
+        Ok(reading)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn test_builder() {
+        let client = Vcgencmd::builder()
+            .binary_path("/opt/vc/bin/vcgencmd")
+            .use_sudo(false)
+            .escalation(Escalation::Doas)
+            .host("pi@pi4.local")
+            .timeout(Duration::from_secs(2))
+            .build();
+
+        let invocation = client.invocation();
+        assert_eq!(PathBuf::from("/opt/vc/bin/vcgencmd"), invocation.binary);
+        assert_eq!(PrivilegeMode::None, invocation.privilege);
+        assert_eq!(Escalation::Doas, invocation.escalation);
+        assert_eq!(Some("pi@pi4.local"), invocation.host.as_deref());
+        assert_eq!(Some(Duration::from_secs(2)), invocation.timeout);
+
+        let client = Vcgencmd::builder().no_timeout().build();
+        assert_eq!(None, client.invocation().timeout);
+        assert_eq!(
+            Config::cached().invocation().binary,
+            client.invocation().binary
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_runs_own_binary() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("vcgencmd-client-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("vcgencmd");
+        fs::write(
+            &binary,
+            "#!/bin/sh\ncase \"$1\" in\n\
+             measure_temp) echo \"temp=47.2'C\" ;;\n\
+             get_config) echo \"$2=85\" ;;\n\
+             get_mem) exec sleep 5 ;;\n\
+             pmic_read_adc) echo 'mailbox request failed' >&2; exit 3 ;;\n\
+             *) echo 'error=1 error_msg=\"Command not registered\"' ;;\n\
+             esac\n",
+        )
+        .unwrap();
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let client = Vcgencmd::builder()
+            .binary_path(&binary)
+            .use_sudo(false)
+            .build();
+        assert_eq!(47.2, client.measure_temp().unwrap());
+        let (temp, raw) = client.measure_temp_with_raw().unwrap();
+        assert_eq!((47.2, "temp=47.2'C\n"), (temp, raw.as_str()));
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client.get_throttled().unwrap_err().kind()
+        );
+
+        match client.read(Metric::TempHeadroom).unwrap() {
+            Reading::TempHeadroom(headroom) => {
+                assert_eq!(85.0, headroom.hard_limit);
+                assert_eq!(47.2, headroom.temp);
+            }
+            reading => panic!("unexpected {:?}", reading),
+        }
+
+        // not a confusing parse error of the empty stdout
+        let error = client.pmic_read_adc().unwrap_err();
+        assert!(
+            matches!(&error, crate::Error::Exit { code: Some(3), stderr, .. } if stderr == "mailbox request failed"),
+            "{:?}",
+            error
+        );
+
+        let hanging = Vcgencmd::builder()
+            .binary_path(&binary)
+            .use_sudo(false)
+            .timeout(Duration::from_millis(100))
+            .build();
+        let error = hanging.get_mem(Src::Mem(MemSrc::Arm)).unwrap_err();
+        assert!(matches!(error, crate::Error::Timeout { .. }), "{:?}", error);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
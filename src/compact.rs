@@ -0,0 +1,378 @@
+//! Compact binary encodings of samples and snapshots, for links where a JSON object per
+//! sample is too heavy, like LoRa or metered cellular gateways
+//!
+//! Two encodings are available, each behind the feature of the same name: `postcard`, the
+//! smallest, and `cbor`, which any CBOR library can take apart without this crate. Both
+//! encode the same layout, an array of the time in milliseconds since the unix epoch and
+//! the readings, each an array of the code of its metric and its values as integers:
+//!
+//! | Code          | Metric                | Values                               |
+//! |---------------|-----------------------|--------------------------------------|
+//! | 0             | `temp`                | m°C                                  |
+//! | 1             | `temp_headroom`       | temperature, soft and hard limit, m°C|
+//! | 2             | `throttled`           | bit pattern                          |
+//! | 16 + n        | `clock.*`             | Hz                                   |
+//! | 32 + n        | `volts.*`             | µV                                   |
+//! | 48 + n        | `mem.*`               | MB                                   |
+//!
+//! where `n` is the index of the source in `ClockSrc::ALL`, `VoltSrc::ALL` or
+//! `MemSrc::ALL`. A sample of temperature, throttled bit pattern and ARM clock takes 24
+//! bytes as postcard and 33 as CBOR, its `Sample::to_json` 119. Errors and derived metrics
+//! aren't encoded.
+//!
+//! ```no_run
+//! use vcgencmd::compact::Encoding;
+//! use vcgencmd::snapshot::SnapshotSpec;
+//!
+//! let sample = SnapshotSpec::parse("temp,throttled,clock.arm")?.capture();
+//! let bytes = Encoding::Postcard.encode(&sample);
+//! let decoded = Encoding::Postcard.decode(&bytes)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::monitor::{Metric, Reading, Sample};
+use crate::sink::MetricSink;
+use crate::snapshot::Snapshot;
+use crate::thermal::{TempHeadroom, TempLimits};
+use crate::{timefmt, ClockSrc, MemSrc, VoltSrc};
+
+const TEMP: u8 = 0;
+const TEMP_HEADROOM: u8 = 1;
+const THROTTLED: u8 = 2;
+const CLOCK: u8 = 16;
+const VOLTS: u8 = 32;
+const MEM: u8 = 48;
+
+/// Frames longer than this aren't read, a sample is far shorter
+const MAX_FRAME: usize = 64 * 1024;
+
+/// A sample as it is encoded, see the module documentation
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Wire(u64, Vec<(u8, Vec<i64>)>);
+
+/// A binary encoding of samples and snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[cfg(feature = "postcard")]
+    Postcard,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Encoding {
+    /// The readings of `sample` with its timestamp
+    pub fn encode(self, sample: &Sample) -> Vec<u8> {
+        let wire = Wire(
+            timefmt::unix_millis(sample.timestamp),
+            sample.readings.iter().filter_map(encode_reading).collect(),
+        );
+
+        // neither encoding fails for plain integers in memory
+        match self {
+            #[cfg(feature = "postcard")]
+            Encoding::Postcard => postcard::to_allocvec(&wire).unwrap_or_default(),
+            #[cfg(feature = "cbor")]
+            Encoding::Cbor => serde_cbor::to_vec(&wire).unwrap_or_default(),
+        }
+    }
+
+    /// The sample encoded in `bytes`, without errors.
+    ///
+    /// Readings of a metric this version doesn't know are skipped. Fails with
+    /// `ErrorKind::InvalidData` on anything that isn't an encoded sample.
+    pub fn decode(self, bytes: &[u8]) -> io::Result<Sample> {
+        let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidData, error);
+        let Wire(millis, readings) = match self {
+            #[cfg(feature = "postcard")]
+            Encoding::Postcard => {
+                postcard::from_bytes(bytes).map_err(|e| invalid(e.to_string()))?
+            }
+            #[cfg(feature = "cbor")]
+            Encoding::Cbor => serde_cbor::from_slice(bytes).map_err(|e| invalid(e.to_string()))?,
+        };
+
+        Ok(Sample {
+            timestamp: timefmt::from_unix_millis(millis),
+            readings: readings
+                .iter()
+                .filter_map(|(code, values)| decode_reading(*code, values))
+                .collect(),
+            errors: Vec::new(),
+        })
+    }
+
+    /// The readings of `snapshot`, the temperature together with its limits
+    pub fn encode_snapshot(self, snapshot: &Snapshot) -> Vec<u8> {
+        let readings = [
+            snapshot.temp_headroom().map(Reading::TempHeadroom),
+            snapshot.throttled.map(Reading::Throttled),
+            snapshot
+                .arm_clock
+                .map(|hz| Reading::Clock(ClockSrc::Arm, hz)),
+            snapshot
+                .core_clock
+                .map(|hz| Reading::Clock(ClockSrc::Core, hz)),
+            snapshot
+                .core_volts
+                .map(|volts| Reading::Volts(VoltSrc::Core, volts)),
+        ];
+
+        self.encode(&Sample {
+            timestamp: snapshot.timestamp,
+            readings: readings.iter().flatten().copied().collect(),
+            errors: Vec::new(),
+        })
+    }
+
+    /// The snapshot encoded in `bytes` by `encode_snapshot`, or the snapshot metrics of an
+    /// encoded sample with the default temperature limits if it has none
+    pub fn decode_snapshot(self, bytes: &[u8]) -> io::Result<Snapshot> {
+        let sample = self.decode(bytes)?;
+        let limits = match sample.get(Metric::TempHeadroom) {
+            Some(Reading::TempHeadroom(headroom)) => TempLimits {
+                soft: headroom.soft_limit,
+                hard: headroom.hard_limit,
+            },
+            _ => TempLimits::default(),
+        };
+        Ok(Snapshot::from_sample(&sample, limits))
+    }
+}
+
+/// The code and values of `reading`, `None` for derived metrics, which have no code
+fn encode_reading(reading: &Reading) -> Option<(u8, Vec<i64>)> {
+    let milli = |value: f64| (value * 1e3).round() as i64;
+    let micro = |value: f64| (value * 1e6).round() as i64;
+    let index = |position: Option<usize>| position.unwrap_or_default() as u8;
+
+    let encoded = match *reading {
+        Reading::Temp(temp) => (TEMP, vec![milli(temp)]),
+        Reading::TempHeadroom(headroom) => (
+            TEMP_HEADROOM,
+            vec![
+                milli(headroom.temp),
+                milli(headroom.soft_limit),
+                milli(headroom.hard_limit),
+            ],
+        ),
+        Reading::Throttled(bit_pattern) => (THROTTLED, vec![bit_pattern as i64]),
+        Reading::Clock(src, hz) => (
+            CLOCK + index(ClockSrc::ALL.iter().position(|&known| known == src)),
+            vec![hz as i64],
+        ),
+        Reading::Volts(src, volts) => (
+            VOLTS + index(VoltSrc::ALL.iter().position(|&known| known == src)),
+            vec![micro(volts)],
+        ),
+        Reading::Mem(src, mb) => (
+            MEM + index(MemSrc::ALL.iter().position(|&known| known == src)),
+            vec![mb as i64],
+        ),
+        Reading::Derived(..) => return None,
+    };
+
+    Some(encoded)
+}
+
+fn decode_reading(code: u8, values: &[i64]) -> Option<Reading> {
+    let milli = |value: i64| value as f64 / 1e3;
+    let source = |first: u8| usize::from(code - first);
+
+    let reading = match (code, values) {
+        (TEMP, &[temp]) => Reading::Temp(milli(temp)),
+        (TEMP_HEADROOM, &[temp, soft, hard]) => Reading::TempHeadroom(TempHeadroom {
+            temp: milli(temp),
+            soft_limit: milli(soft),
+            hard_limit: milli(hard),
+        }),
+        (THROTTLED, &[bit_pattern]) => Reading::Throttled(bit_pattern as isize),
+        (CLOCK..=31, &[hz]) => Reading::Clock(*ClockSrc::ALL.get(source(CLOCK))?, hz as isize),
+        (VOLTS..=47, &[uv]) => Reading::Volts(*VoltSrc::ALL.get(source(VOLTS))?, uv as f64 / 1e6),
+        (MEM..=63, &[mb]) => Reading::Mem(*MemSrc::ALL.get(source(MEM))?, mb as isize),
+        _ => return None,
+    };
+    Some(reading)
+}
+
+/// Writes every sample as a frame: its length as a LEB128 varint, then the encoded sample.
+///
+/// Frames can be written to anything byte oriented, like a serial port to a radio modem,
+/// and are read back with `read_frame`.
+pub struct CompactSink<W: Write> {
+    writer: W,
+    encoding: Encoding,
+}
+
+impl<W: Write> CompactSink<W> {
+    pub fn new(writer: W, encoding: Encoding) -> CompactSink<W> {
+        CompactSink { writer, encoding }
+    }
+
+    /// Write `sample` as a frame and flush
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let bytes = self.encoding.encode(sample);
+        let mut length = bytes.len();
+        let mut frame = Vec::with_capacity(bytes.len() + 2);
+        loop {
+            let byte = (length & 0x7f) as u8;
+            length >>= 7;
+            if length == 0 {
+                frame.push(byte);
+                break;
+            }
+            frame.push(byte | 0x80);
+        }
+        frame.extend_from_slice(&bytes);
+
+        self.writer.write_all(&frame)?;
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send> MetricSink for CompactSink<W> {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        CompactSink::write(self, sample)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Read the next frame written by `CompactSink`, `None` at the end of `reader`
+pub fn read_frame<R: Read>(reader: &mut R, encoding: Encoding) -> io::Result<Option<Sample>> {
+    let (mut length, mut shift) = (0, 0);
+    loop {
+        let mut byte = [0];
+        if reader.read(&mut byte)? == 0 {
+            if shift == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let [byte] = byte;
+        length |= usize::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 21 {
+            break;
+        }
+    }
+    if shift > 21 || length > MAX_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long"));
+    }
+
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    encoding.decode(&bytes).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn sample() -> Sample {
+        Sample {
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_566_741_789_120),
+            readings: vec![
+                Reading::Temp(48.3),
+                Reading::Throttled(0x50005),
+                Reading::Clock(ClockSrc::Arm, 1_500_000_000),
+                Reading::Volts(VoltSrc::SdramP, 1.225),
+                Reading::Mem(MemSrc::Gpu, 76),
+                Reading::TempHeadroom(TempHeadroom {
+                    temp: 48.3,
+                    soft_limit: 60.0,
+                    hard_limit: 85.0,
+                }),
+            ],
+            errors: Vec::new(),
+        }
+    }
+
+    fn encodings() -> Vec<Encoding> {
+        vec![
+            #[cfg(feature = "postcard")]
+            Encoding::Postcard,
+            #[cfg(feature = "cbor")]
+            Encoding::Cbor,
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sample = sample();
+        for encoding in encodings() {
+            let decoded = encoding.decode(&encoding.encode(&sample)).unwrap();
+            assert_eq!(sample.timestamp, decoded.timestamp);
+            assert_eq!(sample.readings, decoded.readings);
+
+            assert!(encoding.decode(b"\xff\xff").is_err());
+        }
+    }
+
+    #[test]
+    fn test_size() {
+        let sample = Sample {
+            readings: sample().readings[..3].to_vec(),
+            ..sample()
+        };
+        let sizes: Vec<_> = encodings()
+            .iter()
+            .map(|encoding| encoding.encode(&sample).len())
+            .collect();
+        let expected = vec![
+            #[cfg(feature = "postcard")]
+            24,
+            #[cfg(feature = "cbor")]
+            33,
+        ];
+        assert_eq!(expected, sizes);
+        assert_eq!(119, sample.to_json().len());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let limits = TempLimits {
+            soft: 70.0,
+            hard: 80.0,
+        };
+        let snapshot = Snapshot::from_sample(&sample(), limits);
+        for encoding in encodings() {
+            let decoded = encoding
+                .decode_snapshot(&encoding.encode_snapshot(&snapshot))
+                .unwrap();
+            assert_eq!(snapshot.temp_headroom(), decoded.temp_headroom());
+            assert_eq!(snapshot.throttled, decoded.throttled);
+            assert_eq!(snapshot.arm_clock, decoded.arm_clock);
+        }
+    }
+
+    #[test]
+    fn test_frames() {
+        for encoding in encodings() {
+            let mut sink = CompactSink::new(Vec::new(), encoding);
+            sink.write(&sample()).unwrap();
+            sink.write(&sample()).unwrap();
+            let frames = sink.into_inner();
+
+            let mut reader = frames.as_slice();
+            for _ in 0..2 {
+                let decoded = read_frame(&mut reader, encoding).unwrap().unwrap();
+                assert_eq!(sample().readings, decoded.readings);
+            }
+            assert!(read_frame(&mut reader, encoding).unwrap().is_none());
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! The SoC temperature in the shape of a `sysinfo::Component`
+//!
+//! `hwmon` coverage on the Raspberry Pi is spotty, so system-info crates often miss the SoC
+//! or report it without limits. `SocComponent` has the accessors of `sysinfo::Component`
+//! (`label`, `temperature`, `max`, `critical` and `refresh`), so code that walks the
+//! components of `sysinfo` can treat it the same way:
+//!
+//! ```no_run
+//! use vcgencmd::component::SocComponent;
+//!
+//! let mut soc = SocComponent::new();
+//! soc.refresh()?;
+//! println!("{}: {:?} °C (max {:?}, critical {:?})", soc.label(), soc.temperature(),
+//!          soc.max(), soc.critical());
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+//!
+//! With the `sysinfo` feature, `Sensor` is implemented by both, and `sensors` lists the
+//! components of `sysinfo` with the SoC in place of its `hwmon` entry, `cpu_thermal`, which
+//! lacks the firmware's limits:
+//!
+//! ```no_run
+//! # #[cfg(feature = "sysinfo")]
+//! # fn main() -> vcgencmd::Result<()> {
+//! use sysinfo::Components;
+//! use vcgencmd::component::{sensors, SocComponent};
+//!
+//! let components = Components::new_with_refreshed_list();
+//! let mut soc = SocComponent::new();
+//! soc.refresh()?;
+//! for sensor in sensors(&components, &soc) {
+//!     println!("{}: {:?} °C", sensor.label(), sensor.temperature());
+//! }
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "sysinfo"))]
+//! # fn main() {}
+//! ```
+
+use crate::measure_temp;
+use crate::thermal::TempLimits;
+use crate::Result;
+
+/// The label `SocComponent` reports
+pub const SOC_LABEL: &str = "VideoCore SoC";
+
+/// The SoC temperature as measured by the firmware, with its throttling limits
+#[derive(Debug, Clone, PartialEq)]
+pub struct SocComponent {
+    temperature: Option<f32>,
+    max: Option<f32>,
+    limits: TempLimits,
+}
+
+impl Default for SocComponent {
+    fn default() -> SocComponent {
+        SocComponent::new()
+    }
+}
+
+impl SocComponent {
+    /// A component without a temperature until `refresh` is called, with the configured
+    /// limits or the firmware defaults if they can't be read
+    pub fn new() -> SocComponent {
+        SocComponent::with_limits(TempLimits::cached().unwrap_or_default())
+    }
+
+    pub fn with_limits(limits: TempLimits) -> SocComponent {
+        SocComponent {
+            temperature: None,
+            max: None,
+            limits,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        SOC_LABEL
+    }
+
+    /// The temperature in °C of the last `refresh`
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    /// The highest temperature in °C seen since the component was created
+    pub fn max(&self) -> Option<f32> {
+        self.max
+    }
+
+    /// The hard limit in °C, from which the firmware throttles hard
+    pub fn critical(&self) -> Option<f32> {
+        Some(self.limits.hard as f32)
+    }
+
+    /// Measure the temperature again
+    pub fn refresh(&mut self) -> Result<()> {
+        self.update(measure_temp()?);
+        Ok(())
+    }
+
+    /// Record a temperature measured elsewhere, e.g. by a `Monitor`
+    pub fn update(&mut self, temp: f64) {
+        let temp = temp as f32;
+        self.temperature = Some(temp);
+        self.max = Some(self.max.map_or(temp, |max| max.max(temp)));
+    }
+}
+
+/// A temperature sensor, read through the accessors of `sysinfo::Component`. Missing
+/// values are `None` instead of `NaN`.
+#[cfg(feature = "sysinfo")]
+pub trait Sensor {
+    fn label(&self) -> &str;
+    /// The temperature in °C
+    fn temperature(&self) -> Option<f32>;
+    /// The highest temperature in °C seen so far
+    fn max(&self) -> Option<f32>;
+    /// The temperature in °C at which the hardware protects itself
+    fn critical(&self) -> Option<f32>;
+}
+
+#[cfg(feature = "sysinfo")]
+impl Sensor for SocComponent {
+    fn label(&self) -> &str {
+        SocComponent::label(self)
+    }
+
+    fn temperature(&self) -> Option<f32> {
+        SocComponent::temperature(self)
+    }
+
+    fn max(&self) -> Option<f32> {
+        SocComponent::max(self)
+    }
+
+    fn critical(&self) -> Option<f32> {
+        SocComponent::critical(self)
+    }
+}
+
+#[cfg(feature = "sysinfo")]
+impl Sensor for sysinfo::Component {
+    fn label(&self) -> &str {
+        sysinfo::Component::label(self)
+    }
+
+    fn temperature(&self) -> Option<f32> {
+        Some(sysinfo::Component::temperature(self)).filter(|t| !t.is_nan())
+    }
+
+    fn max(&self) -> Option<f32> {
+        Some(sysinfo::Component::max(self)).filter(|t| !t.is_nan())
+    }
+
+    fn critical(&self) -> Option<f32> {
+        sysinfo::Component::critical(self)
+    }
+}
+
+/// The `hwmon` name of the SoC sensor, which `sysinfo` starts its label with
+#[cfg(feature = "sysinfo")]
+const HWMON_SOC: &str = "cpu_thermal";
+
+/// `soc` followed by the `components` of `sysinfo`, without their entry of the SoC
+#[cfg(feature = "sysinfo")]
+pub fn sensors<'a>(
+    components: &'a sysinfo::Components,
+    soc: &'a SocComponent,
+) -> Vec<&'a dyn Sensor> {
+    let others = components
+        .iter()
+        .filter(|component| !component.label().starts_with(HWMON_SOC))
+        .map(|component| component as &dyn Sensor);
+
+    std::iter::once(soc as &dyn Sensor).chain(others).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let mut soc = SocComponent::with_limits(TempLimits::default());
+        assert_eq!(None, soc.temperature());
+        assert_eq!(Some(85.0), soc.critical());
+
+        soc.update(52.5);
+        soc.update(48.0);
+        assert_eq!(Some(48.0), soc.temperature());
+        assert_eq!(Some(52.5), soc.max());
+        assert_eq!("VideoCore SoC", soc.label());
+    }
+
+    #[cfg(feature = "sysinfo")]
+    #[test]
+    fn test_sensors() {
+        let mut soc = SocComponent::with_limits(TempLimits::default());
+        soc.update(61.0);
+
+        let components = sysinfo::Components::new();
+        let sensors = sensors(&components, &soc);
+        assert_eq!(1, sensors.len());
+        assert_eq!("VideoCore SoC", sensors[0].label());
+        assert_eq!(Some(61.0), sensors[0].temperature());
+        assert_eq!(Some(85.0), sensors[0].critical());
+    }
+}
@@ -0,0 +1,189 @@
+//! Telling apart a broken installation from a container that lacks the device mappings
+//!
+//! Inside a container vcgencmd needs both the binary with its libraries and a device
+//! node it talks to the firmware through, `/dev/vchiq` or `/dev/vcio`, mapped from the
+//! host. Without
+//! them it fails with errors that don't hint at the container, so when a local call fails
+//! that way inside one, the error is turned into `Error::Container`, naming what is
+//! missing and how to map it.
+//!
+//! ```no_run
+//! use vcgencmd::container;
+//!
+//! if let Some(runtime) = container::detect() {
+//!     println!("running in {}", runtime);
+//! }
+//! ```
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::{resolve_binary, Error, Invocation};
+
+const DOCKERENV: &str = "/.dockerenv";
+const CONTAINERENV: &str = "/run/.containerenv";
+const INIT_CGROUP: &str = "/proc/1/cgroup";
+
+/// The container runtime the process runs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Docker,
+    Podman,
+    Kubernetes,
+    Lxc,
+    /// A container, but not one of the above, e.g. systemd-nspawn
+    Other,
+}
+
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Runtime::Docker => "docker",
+            Runtime::Podman => "podman",
+            Runtime::Kubernetes => "kubernetes",
+            Runtime::Lxc => "lxc",
+            Runtime::Other => "a container",
+        })
+    }
+}
+
+impl Runtime {
+    /// How to map `device` into a container of this runtime
+    pub fn device_mapping(&self, device: &Path) -> String {
+        match self {
+            Runtime::Docker | Runtime::Podman => format!("--device {}", device.display()),
+            Runtime::Kubernetes => format!(
+                "a hostPath volume for {} in a privileged container",
+                device.display()
+            ),
+            Runtime::Lxc => format!(
+                "lxc.mount.entry = {} {} none bind,optional,create=file",
+                device.display(),
+                device.display().to_string().trim_start_matches('/')
+            ),
+            Runtime::Other => format!("a bind mount of {}", device.display()),
+        }
+    }
+}
+
+/// The container runtime the process runs in, `None` outside of containers
+pub fn detect() -> Option<Runtime> {
+    runtime_from(
+        env::var("container").ok().as_deref(),
+        env::var_os("KUBERNETES_SERVICE_HOST").is_some(),
+        Path::new(DOCKERENV).exists(),
+        Path::new(CONTAINERENV).exists(),
+        &fs::read_to_string(INIT_CGROUP).unwrap_or_default(),
+    )
+}
+
+fn runtime_from(
+    container_env: Option<&str>,
+    kubernetes_env: bool,
+    dockerenv: bool,
+    containerenv: bool,
+    init_cgroup: &str,
+) -> Option<Runtime> {
+    if kubernetes_env || init_cgroup.contains("kubepods") {
+        return Some(Runtime::Kubernetes);
+    }
+    if containerenv || container_env == Some("podman") || init_cgroup.contains("libpod") {
+        return Some(Runtime::Podman);
+    }
+    if dockerenv || container_env == Some("docker") || init_cgroup.contains("docker") {
+        return Some(Runtime::Docker);
+    }
+    if container_env == Some("lxc") || init_cgroup.contains("/lxc") {
+        return Some(Runtime::Lxc);
+    }
+
+    container_env
+        .filter(|name| !name.is_empty())
+        .map(|_| Runtime::Other)
+}
+
+/// `error` of a call made as configured by `invocation` as `Error::Container` if it comes
+/// from a missing binary or device node inside a container, otherwise unchanged
+pub(crate) fn diagnose(invocation: &Invocation, error: Error) -> Error {
+    if invocation.host.is_some() {
+        return error;
+    }
+    let binary_missing = match &error {
+        Error::MissingBinary { .. } => true,
+        Error::Vchi { .. } | Error::Permission { .. } => false,
+        _ => return error,
+    };
+    let runtime = match detect() {
+        Some(runtime) => runtime,
+        None => return error,
+    };
+
+    let probe = invocation.devices.probe();
+    let devices: Vec<_> = if probe.all_missing() {
+        probe.tried.into_iter().map(|node| node.path).collect()
+    } else {
+        Vec::new()
+    };
+    let binary = Some(resolve_binary(&invocation.binary))
+        .filter(|binary| binary_missing && !binary.is_file());
+    if devices.is_empty() && binary.is_none() {
+        return error;
+    }
+
+    Error::Container {
+        command: error.command().to_owned(),
+        runtime,
+        devices,
+        binary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_from() {
+        let docker_cgroup = "0::/docker/3f4e5d\n";
+        assert_eq!(
+            Some(Runtime::Docker),
+            runtime_from(None, false, false, false, docker_cgroup)
+        );
+        assert_eq!(
+            Some(Runtime::Docker),
+            runtime_from(None, false, true, false, "0::/\n")
+        );
+        assert_eq!(
+            Some(Runtime::Podman),
+            runtime_from(Some("podman"), false, false, true, "0::/\n")
+        );
+        assert_eq!(
+            Some(Runtime::Kubernetes),
+            runtime_from(None, true, true, false, docker_cgroup)
+        );
+        assert_eq!(
+            Some(Runtime::Lxc),
+            runtime_from(Some("lxc"), false, false, false, "0::/\n")
+        );
+        assert_eq!(
+            Some(Runtime::Other),
+            runtime_from(Some("systemd-nspawn"), false, false, false, "")
+        );
+        assert_eq!(
+            None,
+            runtime_from(None, false, false, false, "0::/init.scope\n")
+        );
+    }
+
+    #[test]
+    fn test_device_mapping() {
+        let vchiq = Path::new("/dev/vchiq");
+        assert_eq!("--device /dev/vchiq", Runtime::Docker.device_mapping(vchiq));
+        assert_eq!(
+            "lxc.mount.entry = /dev/vchiq dev/vchiq none bind,optional,create=file",
+            Runtime::Lxc.device_mapping(vchiq)
+        );
+    }
+}
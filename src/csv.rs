@@ -0,0 +1,75 @@
+//! Samples as rows of comma or tab separated values, e.g. for gnuplot or a spreadsheet
+//!
+//! The first column is the timestamp, followed by one column per metric in the order they
+//! are given, named by `Metric::name`. A metric without a reading leaves its cell empty, so
+//! the columns stay aligned across rows.
+
+use crate::monitor::{Metric, Reading, Sample};
+use crate::timefmt;
+
+/// The header row naming every column
+pub fn header(metrics: &[Metric], delimiter: char) -> String {
+    let mut columns = vec!["timestamp".to_owned()];
+    columns.extend(metrics.iter().map(Metric::name));
+
+    columns.join(&delimiter.to_string())
+}
+
+/// A row with the readings of `metrics` in `sample`
+pub fn row(sample: &Sample, metrics: &[Metric], delimiter: char) -> String {
+    let mut cells = vec![timefmt::rfc3339(sample.timestamp)];
+    cells.extend(
+        metrics
+            .iter()
+            .map(|&metric| sample.get(metric).map(cell).unwrap_or_default()),
+    );
+
+    cells.join(&delimiter.to_string())
+}
+
+/// A reading as a plain number, the bit pattern of `get_throttled` in decimal
+fn cell(reading: &Reading) -> String {
+    match *reading {
+        Reading::Temp(temp) => temp.to_string(),
+        Reading::TempHeadroom(headroom) => headroom.to_soft_limit().to_string(),
+        Reading::Throttled(value) | Reading::Clock(_, value) | Reading::Mem(_, value) => {
+            value.to_string()
+        }
+        Reading::Volts(_, volts) | Reading::Derived(_, volts) => volts.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClockSrc, VoltSrc};
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_rows() {
+        let metrics = [
+            Metric::Temp,
+            Metric::Volts(VoltSrc::Core),
+            Metric::Clock(ClockSrc::Arm),
+            Metric::Throttled,
+        ];
+        let sample = Sample {
+            timestamp: UNIX_EPOCH,
+            readings: vec![
+                Reading::Throttled(0x50005),
+                Reading::Clock(ClockSrc::Arm, 600_000_000),
+                Reading::Temp(47.2),
+            ],
+            errors: Vec::new(),
+        };
+
+        assert_eq!(
+            "timestamp,temp,volts.core,clock.arm,throttled",
+            header(&metrics, ',')
+        );
+        assert_eq!(
+            "1970-01-01T00:00:00.000Z\t47.2\t\t600000000\t327685",
+            row(&sample, &metrics, '\t')
+        );
+    }
+}
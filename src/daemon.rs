@@ -0,0 +1,293 @@
+//! A ready-made main loop for running a `Monitor` as a service
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use vcgencmd::daemon::Daemon;
+//! use vcgencmd::monitor::{Metric, Monitor};
+//!
+//! let monitor = Monitor::new(Duration::from_secs(5))
+//!     .metric(Metric::Temp)
+//!     .metric(Metric::Throttled)
+//!     .sink(|sample| println!("{:?}", sample.readings));
+//!
+//! Daemon::new(monitor)
+//!     .pidfile("/run/vcgencmd.pid")
+//!     .dump_to("/run/vcgencmd/snapshot.json")
+//!     .run()
+//!     .unwrap();
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::monitor::{wait_until, Monitor};
+use crate::snapshot::Snapshot;
+
+/// How long a dump, and stopping, waits for queued sinks to catch up
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+static DUMP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    // only async-signal-safe work in here, the main loop picks the flags up
+    match signal {
+        libc::SIGHUP => RELOAD.store(true, Ordering::SeqCst),
+        libc::SIGUSR1 => DUMP.store(true, Ordering::SeqCst),
+        _ => TERMINATE.store(true, Ordering::SeqCst),
+    }
+}
+
+fn install_signal_handlers(signals: &[libc::c_int]) -> io::Result<()> {
+    for &signal in signals {
+        // SAFETY: the handler only touches atomics, and the sigaction struct is
+        // fully initialized before being passed to the kernel
+        let result = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut())
+        };
+
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+fn is_running(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 performs only the existence and permission check
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Write our pid to `path`, refusing to do so if it names another running process
+fn write_pidfile(path: &Path) -> io::Result<()> {
+    if let Ok(contents) = fs::read_to_string(path) {
+        if let Ok(pid) = contents.trim().parse::<libc::pid_t>() {
+            if pid != process::id() as libc::pid_t && is_running(pid) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} belongs to running process {}", path.display(), pid),
+                ));
+            }
+        }
+    }
+
+    fs::write(path, format!("{}\n", process::id()))
+}
+
+type ReloadHook = Box<dyn FnMut(&mut Monitor) + Send>;
+type DumpHook = Box<dyn FnMut(&Snapshot) + Send>;
+
+/// Write `snapshot` to `path` as JSON, through a temporary file so readers never see a
+/// partial dump
+fn write_dump(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+
+    fs::write(&partial, format!("{}\n", snapshot.to_json()))?;
+    fs::rename(&partial, path)
+}
+
+/// Runs a `Monitor` until `SIGTERM` or `SIGINT` arrives.
+///
+/// `SIGHUP` calls the reload hook between two samples, which may reconfigure the monitor
+/// in place. With a dump hook, `SIGUSR1` captures a full snapshot for it and flushes the
+/// queued sinks, without it the signal keeps its default action. When started by systemd, readiness and watchdog notifications are sent
+/// automatically with the `systemd` feature, see `systemd::Watchdog`.
+pub struct Daemon {
+    monitor: Monitor,
+    pidfile: Option<PathBuf>,
+    on_reload: Option<ReloadHook>,
+    on_dump: Option<DumpHook>,
+}
+
+impl Daemon {
+    pub fn new(monitor: Monitor) -> Daemon {
+        Daemon {
+            monitor,
+            pidfile: None,
+            on_reload: None,
+            on_dump: None,
+        }
+    }
+
+    /// Write the process id to `path` while running, and remove it on shutdown
+    pub fn pidfile<P: AsRef<Path>>(mut self, path: P) -> Daemon {
+        self.pidfile = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Called on `SIGHUP`, typically to re-read a configuration file
+    pub fn on_reload<F>(mut self, hook: F) -> Daemon
+    where
+        F: FnMut(&mut Monitor) + Send + 'static,
+    {
+        self.on_reload = Some(Box::new(hook));
+        self
+    }
+
+    /// Called with a freshly captured snapshot on `SIGUSR1`, after which the queued sinks
+    /// are flushed
+    pub fn on_dump<F>(mut self, hook: F) -> Daemon
+    where
+        F: FnMut(&Snapshot) + Send + 'static,
+    {
+        self.on_dump = Some(Box::new(hook));
+        self
+    }
+
+    /// On `SIGUSR1`, write a snapshot as JSON to `path`, replacing the previous dump.
+    ///
+    /// Failing writes are reported on stderr, they don't stop the daemon.
+    pub fn dump_to<P: AsRef<Path>>(self, path: P) -> Daemon {
+        let path = path.as_ref().to_owned();
+        self.on_dump(move |snapshot| {
+            if let Err(e) = write_dump(&path, snapshot) {
+                eprintln!(
+                    "vcgencmd: can't write snapshot to {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        })
+    }
+
+    /// Run the main loop, returning the monitor after a shutdown signal was received
+    pub fn run(mut self) -> io::Result<Monitor> {
+        let mut signals = vec![libc::SIGTERM, libc::SIGINT, libc::SIGHUP];
+        if self.on_dump.is_some() {
+            signals.push(libc::SIGUSR1);
+        }
+        install_signal_handlers(&signals)?;
+        TERMINATE.store(false, Ordering::SeqCst);
+        RELOAD.store(false, Ordering::SeqCst);
+        DUMP.store(false, Ordering::SeqCst);
+
+        if let Some(path) = &self.pidfile {
+            write_pidfile(path)?;
+        }
+
+        let result = self.main_loop();
+        self.monitor.flush_sinks(FLUSH_TIMEOUT);
+
+        if let Some(path) = &self.pidfile {
+            let _ = fs::remove_file(path);
+        }
+
+        result.map(|_| self.monitor)
+    }
+
+    #[cfg(all(target_os = "linux", feature = "systemd"))]
+    fn main_loop(&mut self) -> io::Result<()> {
+        let mut watchdog = crate::systemd::Watchdog::from_env()?;
+
+        self.sample_until_terminated(|sample| match &mut watchdog {
+            Some(watchdog) if sample.is_ok() => watchdog.sample_succeeded(),
+            _ => Ok(()),
+        })?;
+
+        if let Some(watchdog) = &watchdog {
+            watchdog.notifier().stopping()?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "systemd")))]
+    fn main_loop(&mut self) -> io::Result<()> {
+        self.sample_until_terminated(|_| Ok(()))
+    }
+
+    fn sample_until_terminated<F>(&mut self, mut after_sample: F) -> io::Result<()>
+    where
+        F: FnMut(&crate::monitor::Sample) -> io::Result<()>,
+    {
+        while !TERMINATE.load(Ordering::SeqCst) {
+            if RELOAD.swap(false, Ordering::SeqCst) {
+                if let Some(hook) = &mut self.on_reload {
+                    hook(&mut self.monitor);
+                }
+            }
+
+            if DUMP.swap(false, Ordering::SeqCst) {
+                self.dump();
+            }
+
+            if let Some(sample) = self.monitor.sample_due(Instant::now()) {
+                after_sample(&sample)?;
+            }
+
+            wait_until(self.monitor.wake_at(), || {
+                TERMINATE.load(Ordering::SeqCst)
+                    || RELOAD.load(Ordering::SeqCst)
+                    || DUMP.load(Ordering::SeqCst)
+            });
+        }
+
+        Ok(())
+    }
+
+    fn dump(&mut self) {
+        if let Some(hook) = &mut self.on_dump {
+            hook(&Snapshot::capture());
+            self.monitor.flush_sinks(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_pidfile() {
+        let path = std::env::temp_dir().join(format!("vcgencmd-pid-{}", process::id()));
+
+        write_pidfile(&path).unwrap();
+        assert_eq!(
+            format!("{}\n", process::id()),
+            fs::read_to_string(&path).unwrap()
+        );
+
+        // pid 1 is always running, so its pidfile must not be taken over
+        fs::write(&path, "1\n").unwrap();
+        assert_eq!(
+            io::ErrorKind::AlreadyExists,
+            write_pidfile(&path).unwrap_err().kind()
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_dump() {
+        let path = std::env::temp_dir().join(format!("vcgencmd-dump-{}.json", process::id()));
+        let snapshot = Snapshot {
+            timestamp: std::time::UNIX_EPOCH,
+            temp: Some(47.2),
+            temp_limits: crate::thermal::TempLimits::default(),
+            throttled: Some(0),
+            arm_clock: None,
+            core_clock: None,
+            core_volts: None,
+            errors: Vec::new(),
+            lagging: Vec::new(),
+        };
+
+        fs::write(&path, "previous dump").unwrap();
+        write_dump(&path, &snapshot).unwrap();
+        let dump = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(format!("{}\n", snapshot.to_json()), dump);
+    }
+}
@@ -0,0 +1,206 @@
+//! The device nodes the firmware is reached through
+//!
+//! vcgencmd from the legacy userland talks to the firmware through `/dev/vchiq`, the one
+//! from `raspi-utils` through the mailbox at `/dev/vcio`. Containers and non-standard udev
+//! setups may expose them under other paths, which can be set with the `DeviceNodes`
+//! builder or the `VCGENCMD_VCHIQ` and `VCGENCMD_VCIO` environment variables.
+//!
+//! ```no_run
+//! use vcgencmd::devices::DeviceNodes;
+//! use vcgencmd::Invocation;
+//!
+//! let devices = DeviceNodes::from_env().vchiq("/dev/vc/vchiq");
+//! for node in &devices.probe().tried {
+//!     println!("{}: {}", node.path.display(), node.state);
+//! }
+//! vcgencmd::set_invocation(Invocation {
+//!     devices,
+//!     ..vcgencmd::invocation()
+//! });
+//! ```
+
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const VCHIQ: &str = "/dev/vchiq";
+pub const VCIO: &str = "/dev/vcio";
+/// Overrides the path of `/dev/vchiq`
+pub const VCHIQ_ENV: &str = "VCGENCMD_VCHIQ";
+/// Overrides the path of `/dev/vcio`
+pub const VCIO_ENV: &str = "VCGENCMD_VCIO";
+
+/// The paths of the device nodes, see the module documentation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceNodes {
+    pub vchiq: PathBuf,
+    pub vcio: PathBuf,
+}
+
+/// `DeviceNodes::new`
+impl Default for DeviceNodes {
+    fn default() -> DeviceNodes {
+        DeviceNodes::new()
+    }
+}
+
+impl DeviceNodes {
+    /// The standard paths, `/dev/vchiq` and `/dev/vcio`
+    pub fn new() -> DeviceNodes {
+        DeviceNodes {
+            vchiq: PathBuf::from(VCHIQ),
+            vcio: PathBuf::from(VCIO),
+        }
+    }
+
+    /// The standard paths, unless overridden by `VCGENCMD_VCHIQ` or `VCGENCMD_VCIO`
+    pub fn from_env() -> DeviceNodes {
+        DeviceNodes::from_vars(env::var_os(VCHIQ_ENV), env::var_os(VCIO_ENV))
+    }
+
+    fn from_vars(vchiq: Option<OsString>, vcio: Option<OsString>) -> DeviceNodes {
+        let set = |value: Option<OsString>| value.filter(|value| !value.is_empty());
+        let nodes = DeviceNodes::new();
+
+        DeviceNodes {
+            vchiq: set(vchiq).map_or(nodes.vchiq, PathBuf::from),
+            vcio: set(vcio).map_or(nodes.vcio, PathBuf::from),
+        }
+    }
+
+    pub fn vchiq<P: Into<PathBuf>>(mut self, path: P) -> DeviceNodes {
+        self.vchiq = path.into();
+        self
+    }
+
+    pub fn vcio<P: Into<PathBuf>>(mut self, path: P) -> DeviceNodes {
+        self.vcio = path.into();
+        self
+    }
+
+    /// Try to open the nodes, falling back to the standard path of a node that is missing
+    /// at the configured one
+    pub fn probe(&self) -> DeviceProbe {
+        let mut tried = Vec::new();
+        for &(path, standard) in [(&self.vchiq, VCHIQ), (&self.vcio, VCIO)].iter() {
+            let node = NodeProbe::open(path);
+            let fall_back =
+                node.state == NodeState::Missing && path.as_path() != Path::new(standard);
+            tried.push(node);
+            if fall_back {
+                tried.push(NodeProbe::open(Path::new(standard)));
+            }
+        }
+
+        DeviceProbe { tried }
+    }
+}
+
+/// Whether a device node could be opened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeState {
+    Accessible,
+    /// The node exists, but the user may not open it, e.g. outside the `video` group
+    PermissionDenied,
+    Missing,
+    /// Opening it failed for another reason, with the message of the error
+    Failed(String),
+}
+
+impl fmt::Display for NodeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeState::Accessible => f.pad("accessible"),
+            NodeState::PermissionDenied => f.pad("permission denied"),
+            NodeState::Missing => f.pad("missing"),
+            NodeState::Failed(message) => write!(f, "failed: {}", message),
+        }
+    }
+}
+
+/// A node tried by `DeviceNodes::probe`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeProbe {
+    pub path: PathBuf,
+    pub state: NodeState,
+}
+
+impl NodeProbe {
+    fn open(path: &Path) -> NodeProbe {
+        let state = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(_) => NodeState::Accessible,
+            Err(error) => match error.kind() {
+                io::ErrorKind::NotFound => NodeState::Missing,
+                io::ErrorKind::PermissionDenied => NodeState::PermissionDenied,
+                _ => NodeState::Failed(error.to_string()),
+            },
+        };
+
+        NodeProbe {
+            path: path.to_owned(),
+            state,
+        }
+    }
+}
+
+/// The outcome of `DeviceNodes::probe`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceProbe {
+    /// Every node tried, in order
+    pub tried: Vec<NodeProbe>,
+}
+
+impl DeviceProbe {
+    /// The first node that could be opened
+    pub fn accessible(&self) -> Option<&Path> {
+        self.tried
+            .iter()
+            .find(|node| node.state == NodeState::Accessible)
+            .map(|node| node.path.as_path())
+    }
+
+    /// Whether none of the nodes exists, as opposed to existing but not being accessible
+    pub fn all_missing(&self) -> bool {
+        self.tried
+            .iter()
+            .all(|node| node.state == NodeState::Missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_from_vars() {
+        assert_eq!(DeviceNodes::new(), DeviceNodes::from_vars(None, None));
+
+        let nodes = DeviceNodes::from_vars(Some("/dev/vc/vchiq".into()), Some("".into()));
+        assert_eq!(Path::new("/dev/vc/vchiq"), nodes.vchiq);
+        assert_eq!(Path::new(VCIO), nodes.vcio);
+    }
+
+    #[test]
+    fn test_probe() {
+        let dir = std::env::temp_dir().join(format!("vcgencmd-devices-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (vchiq, vcio) = (dir.join("vchiq"), dir.join("vcio"));
+        fs::write(&vcio, "").unwrap();
+
+        let probe = DeviceNodes::new().vchiq(&vchiq).vcio(&vcio).probe();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let tried: Vec<_> = probe.tried.iter().map(|node| node.path.as_path()).collect();
+        assert_eq!(
+            vec![vchiq.as_path(), Path::new(VCHIQ), vcio.as_path()],
+            tried
+        );
+        assert_eq!(NodeState::Missing, probe.tried[0].state);
+        assert_eq!(Some(vcio.as_path()), probe.accessible());
+        assert!(!probe.all_missing());
+    }
+}
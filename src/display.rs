@@ -0,0 +1,159 @@
+//! The state of the attached displays in one call
+//!
+//! `display_power` is answered per display, `get_lcd_info` and `hdmi_timings` only for the
+//! display the firmware drives by default. Under the KMS graphics driver the firmware
+//! doesn't drive any display, so the latter two are mostly useful with the legacy stack,
+//! and left out where the firmware can't answer them.
+
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{display_power, get_lcd_info, hdmi_timings, ErrorKind, Result};
+
+/// The display ids the firmware uses, as taken by `display_power`
+pub const MAIN_LCD: u8 = 0;
+pub const AUX_LCD: u8 = 1;
+pub const HDMI0: u8 = 2;
+pub const COMPOSITE: u8 = 3;
+pub const HDMI1: u8 = 7;
+
+/// The ids queried by `DisplayInfo::query_all`
+pub const ALL: [u8; 5] = [MAIN_LCD, AUX_LCD, HDMI0, COMPOSITE, HDMI1];
+
+/// Resolution and color depth as reported by `get_lcd_info`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct LcdInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per pixel
+    pub depth: u32,
+}
+
+/// e.g. `1920x1080, 24 bit`
+impl fmt::Display for LcdInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}, {} bit", self.width, self.height, self.depth)
+    }
+}
+
+/// Custom HDMI timings configured with `hdmi_timings` in `config.txt`, in the order of
+/// that option
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct HdmiTimings {
+    pub h_active_pixels: u32,
+    /// `true` for positive polarity
+    pub h_sync_polarity: bool,
+    pub h_front_porch: u32,
+    pub h_sync_pulse: u32,
+    pub h_back_porch: u32,
+    pub v_active_lines: u32,
+    pub v_sync_polarity: bool,
+    pub v_front_porch: u32,
+    pub v_sync_pulse: u32,
+    pub v_back_porch: u32,
+    pub v_sync_offset_a: u32,
+    pub v_sync_offset_b: u32,
+    pub pixel_rep: u32,
+    /// In Hz
+    pub frame_rate: u32,
+    pub interlaced: bool,
+    /// In Hz
+    pub pixel_freq: u32,
+    /// The `HDMI_ASPECT_*` value, e.g. 3 for 16:9
+    pub aspect_ratio: u32,
+}
+
+/// Power state, resolution and timings of a single display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct DisplayInfo {
+    pub id: u8,
+    /// `None` if the firmware doesn't know a display with this id, i.e. none is attached
+    pub powered: Option<bool>,
+    /// `None` if the firmware doesn't report a resolution, e.g. under KMS
+    pub resolution: Option<LcdInfo>,
+    /// `None` unless custom timings are configured
+    pub timings: Option<HdmiTimings>,
+}
+
+impl DisplayInfo {
+    /// Query display `id`, one of the ids of this module.
+    ///
+    /// Only fails if the power state can't be read, resolution and timings the firmware
+    /// doesn't support are left out.
+    pub fn query(id: u8) -> Result<DisplayInfo> {
+        let powered = display_power(id)?;
+        let resolution = unless_unsupported(get_lcd_info())?.filter(|lcd| lcd.width > 0);
+        let timings = unless_unsupported(hdmi_timings())?.flatten();
+
+        Ok(DisplayInfo {
+            id,
+            powered,
+            resolution,
+            timings,
+        })
+    }
+
+    /// Query every id of `ALL`, keeping the displays that are attached
+    pub fn query_all() -> Result<Vec<DisplayInfo>> {
+        let mut displays = Vec::new();
+        for &id in ALL.iter() {
+            let display = DisplayInfo::query(id)?;
+            if display.is_attached() {
+                displays.push(display);
+            }
+        }
+
+        Ok(displays)
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.powered.is_some()
+    }
+}
+
+/// `None` instead of an error for commands the firmware doesn't know
+fn unless_unsupported<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(error) if error.kind() == ErrorKind::Unsupported => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_unless_unsupported() {
+        let unsupported = Error::firmware(
+            "get_lcd_info".to_owned(),
+            1,
+            "Command not registered".to_owned(),
+        );
+        assert!(matches!(
+            unless_unsupported::<()>(Err(unsupported)),
+            Ok(None)
+        ));
+        assert!(matches!(unless_unsupported(Ok(1)), Ok(Some(1))));
+
+        let failed = Error::firmware("get_lcd_info".to_owned(), -1, String::new());
+        assert!(unless_unsupported::<()>(Err(failed)).is_err());
+    }
+
+    #[test]
+    fn test_lcd_info_display() {
+        let lcd = LcdInfo {
+            width: 1920,
+            height: 1080,
+            depth: 24,
+        };
+        assert_eq!("1920x1080, 24 bit", lcd.to_string());
+    }
+}
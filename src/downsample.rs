@@ -0,0 +1,255 @@
+//! Reducing long captures to a number of points that can be plotted
+//!
+//! A day of readings at 1 Hz is 86 400 points, far more than an embedded UI can draw or a
+//! screen can show. `lttb` picks the points that keep the shape of a series
+//! (Largest-Triangle-Three-Buckets), `bucket` summarizes equal spans of it, and `samples`
+//! reduces whole samples, keeping the worst of every bucket so that a short throttling
+//! episode or temperature spike isn't averaged away.
+//!
+//! ```no_run
+//! use vcgencmd::downsample;
+//! use vcgencmd::monitor::Metric;
+//! use vcgencmd::replay::Replay;
+//!
+//! let history: Vec<_> = Replay::open("/var/log/vcgencmd.jsonl")?.collect();
+//! let temps = downsample::lttb(&downsample::series(&history, Metric::Temp), 500);
+//! let overview = downsample::samples(&history, 500);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::borrow::Borrow;
+use std::time::SystemTime;
+
+use crate::monitor::{Metric, Reading, Sample};
+
+/// How `bucket` summarizes the points of a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    /// The mean of the values, at the time of the first point
+    Mean,
+    /// The lowest point
+    Min,
+    /// The highest point
+    Max,
+}
+
+/// The values of `metric` in `samples`, skipping samples without a reading of it or
+/// holding the throttled bit pattern
+pub fn series<S: Borrow<Sample>>(samples: &[S], metric: Metric) -> Vec<(SystemTime, f64)> {
+    samples
+        .iter()
+        .filter_map(|sample| {
+            let sample = sample.borrow();
+            let value = sample.get(metric)?.value()?;
+            Some((sample.timestamp, value))
+        })
+        .collect()
+}
+
+/// At most `target` points of `points` picked by Largest-Triangle-Three-Buckets.
+///
+/// The first and last point are always kept. Of every bucket in between the point is kept
+/// that spans the largest triangle with the point kept before and the mean of the next
+/// bucket, which preserves peaks and troughs. `points` must be ordered by time.
+pub fn lttb(points: &[(SystemTime, f64)], target: usize) -> Vec<(SystemTime, f64)> {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(&first), Some(&last)) if target < points.len() => (first, last),
+        _ => return points.to_vec(),
+    };
+    if target < 3 {
+        return [first, last].iter().copied().take(target).collect();
+    }
+
+    let x = |at: SystemTime| {
+        at.duration_since(first.0)
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+    };
+    // the points between first and last are split into target - 2 buckets
+    let width = (points.len() - 2) as f64 / (target - 2) as f64;
+    let bound = |bucket: usize| ((bucket as f64 * width) as usize + 1).min(points.len() - 1);
+
+    let mut kept = Vec::with_capacity(target);
+    kept.push(first);
+    let mut previous = first;
+    for bucket in 0..target - 2 {
+        let next = points
+            .get(bound(bucket + 1)..bound(bucket + 2))
+            .unwrap_or(&[]);
+        let (next_x, next_y) = match next.len() {
+            0 => (x(last.0), last.1),
+            len => (
+                next.iter().map(|&(at, _)| x(at)).sum::<f64>() / len as f64,
+                next.iter().map(|&(_, value)| value).sum::<f64>() / len as f64,
+            ),
+        };
+
+        let (previous_x, previous_y) = (x(previous.0), previous.1);
+        let area = |&&(at, value): &&(SystemTime, f64)| {
+            ((previous_x - next_x) * (value - previous_y)
+                - (previous_x - x(at)) * (next_y - previous_y))
+                .abs()
+        };
+        let candidates = points.get(bound(bucket)..bound(bucket + 1)).unwrap_or(&[]);
+        if let Some(&point) = candidates.iter().max_by(|a, b| area(a).total_cmp(&area(b))) {
+            kept.push(point);
+            previous = point;
+        }
+    }
+    kept.push(last);
+
+    kept
+}
+
+/// `points` split into at most `target` buckets of as many points, each summarized as
+/// one point by `aggregate`
+pub fn bucket(
+    points: &[(SystemTime, f64)],
+    target: usize,
+    aggregate: Aggregate,
+) -> Vec<(SystemTime, f64)> {
+    buckets(points, target)
+        .filter_map(|bucket| {
+            let &(first, _) = bucket.first()?;
+            let by_value = |a: &&(SystemTime, f64), b: &&(SystemTime, f64)| a.1.total_cmp(&b.1);
+            match aggregate {
+                Aggregate::Mean => {
+                    let sum: f64 = bucket.iter().map(|&(_, value)| value).sum();
+                    Some((first, sum / bucket.len() as f64))
+                }
+                Aggregate::Min => bucket.iter().min_by(by_value).copied(),
+                Aggregate::Max => bucket.iter().max_by(by_value).copied(),
+            }
+        })
+        .collect()
+}
+
+/// `samples` reduced to at most `target`, each standing for a bucket of as many samples.
+///
+/// A reduced sample has the timestamp of the first in its bucket and, per metric, the
+/// worst reading of the bucket: the throttled bit patterns ORed together, the highest
+/// temperature and the lowest clocks and voltages. Memory splits keep the last reading.
+/// Errors aren't carried over.
+pub fn samples<S: Borrow<Sample>>(samples: &[S], target: usize) -> Vec<Sample> {
+    buckets(samples, target)
+        .filter_map(|bucket| {
+            let timestamp = bucket.first()?.borrow().timestamp;
+            let mut readings: Vec<Reading> = Vec::new();
+            for reading in bucket.iter().flat_map(|sample| &sample.borrow().readings) {
+                match readings.iter_mut().find(|r| r.metric() == reading.metric()) {
+                    Some(worst) => *worst = worse(*worst, *reading),
+                    None => readings.push(*reading),
+                }
+            }
+
+            Some(Sample {
+                timestamp,
+                readings,
+                errors: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// The worse of two readings of the same metric, see `samples`
+fn worse(kept: Reading, reading: Reading) -> Reading {
+    match (kept, reading) {
+        (Reading::Throttled(a), Reading::Throttled(b)) => Reading::Throttled(a | b),
+        (Reading::Temp(a), Reading::Temp(b)) => Reading::Temp(a.max(b)),
+        (Reading::TempHeadroom(a), Reading::TempHeadroom(b)) if b.temp > a.temp => reading,
+        (Reading::Clock(src, a), Reading::Clock(_, b)) => Reading::Clock(src, a.min(b)),
+        (Reading::Volts(src, a), Reading::Volts(_, b)) => Reading::Volts(src, a.min(b)),
+        (Reading::Mem(..), Reading::Mem(..)) => reading,
+        _ => kept,
+    }
+}
+
+/// `items` split into at most `target` consecutive runs of as many items
+fn buckets<T>(items: &[T], target: usize) -> impl Iterator<Item = &[T]> {
+    let count = target.min(items.len());
+    (0..count).filter_map(move |bucket| {
+        let start = bucket * items.len() / count;
+        let end = (bucket + 1) * items.len() / count;
+        items.get(start..end)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClockSrc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn points(values: &[f64]) -> Vec<(SystemTime, f64)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(secs, &value)| (at(secs as u64), value))
+            .collect()
+    }
+
+    #[test]
+    fn test_lttb() {
+        let mut values = vec![50.0; 100];
+        values[37] = 80.0;
+        values[71] = 20.0;
+        let kept = lttb(&points(&values), 10);
+
+        assert_eq!(10, kept.len());
+        assert_eq!(Some(&(at(0), 50.0)), kept.first());
+        assert_eq!(Some(&(at(99), 50.0)), kept.last());
+        assert!(kept.contains(&(at(37), 80.0)));
+        assert!(kept.contains(&(at(71), 20.0)));
+
+        assert_eq!(3, lttb(&points(&[1.0, 2.0, 3.0]), 10).len());
+        assert_eq!(vec![(at(0), 1.0)], lttb(&points(&[1.0, 2.0, 3.0]), 1));
+    }
+
+    #[test]
+    fn test_bucket() {
+        let points = points(&[1.0, 5.0, 3.0, 2.0, 4.0, 6.0, 0.0]);
+        assert_eq!(
+            vec![(at(0), 3.0), (at(2), 2.5), (at(4), 10.0 / 3.0)],
+            bucket(&points, 3, Aggregate::Mean)
+        );
+        assert_eq!(
+            vec![(at(1), 5.0), (at(2), 3.0), (at(5), 6.0)],
+            bucket(&points, 3, Aggregate::Max)
+        );
+        assert_eq!(7, bucket(&points, 100, Aggregate::Min).len());
+    }
+
+    #[test]
+    fn test_samples() {
+        let history: Vec<Sample> = (0..6)
+            .map(|secs| Sample {
+                timestamp: at(secs),
+                readings: vec![
+                    Reading::Temp(50.0 + secs as f64),
+                    Reading::Throttled(if secs == 4 { 0x4 } else { 0 }),
+                    Reading::Clock(ClockSrc::Arm, 1_500_000_000 - secs as isize),
+                ],
+                errors: Vec::new(),
+            })
+            .collect();
+
+        let reduced = samples(&history, 2);
+        assert_eq!(2, reduced.len());
+        assert_eq!(at(3), reduced[1].timestamp);
+        assert_eq!(
+            vec![
+                Reading::Temp(55.0),
+                Reading::Throttled(0x4),
+                Reading::Clock(ClockSrc::Arm, 1_499_999_995),
+            ],
+            reduced[1].readings
+        );
+        assert_eq!(
+            Some(&Reading::Throttled(0)),
+            reduced[0].get(Metric::Throttled)
+        );
+    }
+}
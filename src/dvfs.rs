@@ -0,0 +1,463 @@
+//! Observing the frequency steps of the ARM clock
+//!
+//! Dynamic voltage and frequency scaling moves the ARM clock between a few steps: the
+//! idle frequency (`arm_freq_min`), the full one (`arm_freq`), and with some governors
+//! the steps in between. A `DvfsObserver` polls the clock at a high rate for a bounded
+//! window and reports which steps were seen and how long the clock stayed at each, so
+//! `force_turbo`, the cpufreq governor or a frequency cap can be verified.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use vcgencmd::dvfs::DvfsObserver;
+//!
+//! let report = DvfsObserver::new()
+//!     .duration(Duration::from_secs(30))
+//!     .run();
+//! println!("{}", report);
+//! if report.is_pinned() {
+//!     println!("the clock doesn't scale, force_turbo or the performance governor?");
+//! }
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::json;
+use crate::monitor::{Metric, Monitor, Reading};
+use crate::profile::sample_for;
+use crate::timefmt::rfc3339;
+use crate::{interpret_bit_pattern, ClockSrc, Result};
+
+const DEFAULT_DURATION: Duration = Duration::from_secs(10);
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(20);
+/// Readings this close to a step belong to it, the measured clock jitters a little
+const DEFAULT_TOLERANCE: isize = 5_000_000;
+
+/// The readings taken at one point of the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DvfsPoint {
+    /// Time since the start of the window
+    pub elapsed: Duration,
+    /// ARM clock in Hz
+    pub arm_clock: Option<isize>,
+    /// Bit pattern as returned by `get_throttled`
+    pub throttled: Option<isize>,
+}
+
+impl DvfsPoint {
+    /// Whether the firmware capped the ARM clock at this point, `None` if it wasn't read
+    pub fn is_capped(&self) -> Option<bool> {
+        self.throttled.map(|bit_pattern| {
+            let status = interpret_bit_pattern(bit_pattern);
+            status.arm_frequency_capped || status.currently_throttled
+        })
+    }
+}
+
+/// What a frequency step most likely is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    /// The lowest of several steps
+    Idle,
+    /// Between the idle and the turbo step
+    Intermediate,
+    /// The highest of several steps
+    Turbo,
+    /// Held there by the firmware, because of under-voltage or the temperature
+    Capped,
+    /// The only step seen, the clock didn't scale
+    Fixed,
+}
+
+impl fmt::Display for StepKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            StepKind::Idle => "idle",
+            StepKind::Intermediate => "intermediate",
+            StepKind::Turbo => "turbo",
+            StepKind::Capped => "capped",
+            StepKind::Fixed => "fixed",
+        })
+    }
+}
+
+/// A frequency the clock stayed at during the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrequencyStep {
+    /// Mean of the readings at this step, in Hz
+    pub frequency: isize,
+    pub kind: StepKind,
+    /// Total time spent at this step
+    pub dwell: Duration,
+    /// Longest uninterrupted stay at this step
+    pub longest: Duration,
+    /// Number of times the clock moved to this step, the first reading included
+    pub visits: usize,
+}
+
+impl FrequencyStep {
+    /// Average time of a single stay at this step
+    pub fn mean_dwell(&self) -> Duration {
+        match u32::try_from(self.visits) {
+            Ok(visits) if visits > 0 => self.dwell / visits,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Polls the ARM clock for a `DvfsReport`, see the module documentation
+pub struct DvfsObserver {
+    duration: Duration,
+    interval: Duration,
+    tolerance: isize,
+    monitor: Monitor,
+}
+
+impl Default for DvfsObserver {
+    fn default() -> DvfsObserver {
+        DvfsObserver::new()
+    }
+}
+
+impl DvfsObserver {
+    /// Poll for 10 s, every 20 ms
+    pub fn new() -> DvfsObserver {
+        let monitor = Monitor::new(DEFAULT_INTERVAL)
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .metric(Metric::Throttled);
+
+        DvfsObserver {
+            duration: DEFAULT_DURATION,
+            interval: DEFAULT_INTERVAL,
+            tolerance: DEFAULT_TOLERANCE,
+            monitor,
+        }
+    }
+
+    pub fn duration(mut self, duration: Duration) -> DvfsObserver {
+        self.duration = duration;
+        self
+    }
+
+    /// Time between samples, each sample invokes vcgencmd twice
+    pub fn interval(mut self, interval: Duration) -> DvfsObserver {
+        self.interval = interval;
+        self.monitor.set_interval(interval);
+        self
+    }
+
+    /// How far in Hz a reading may be off a step and still belong to it, 5 MHz by default
+    pub fn tolerance(mut self, tolerance: isize) -> DvfsObserver {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Replace the function used to read a metric, see `Monitor::sampler`
+    pub fn sampler<F>(mut self, sampler: F) -> DvfsObserver
+    where
+        F: FnMut(Metric) -> Result<Reading> + Send + 'static,
+    {
+        self.monitor = self.monitor.sampler(sampler);
+        self
+    }
+
+    /// Poll for the window, blocking for its duration
+    pub fn run(mut self) -> DvfsReport {
+        let started_at = SystemTime::now();
+        let mut points = Vec::new();
+        sample_for(
+            &mut self.monitor,
+            self.duration,
+            self.interval,
+            |elapsed, sample| {
+                points.push(DvfsPoint {
+                    elapsed,
+                    arm_clock: match sample.get(Metric::Clock(ClockSrc::Arm)) {
+                        Some(&Reading::Clock(_, frequency)) => Some(frequency),
+                        _ => None,
+                    },
+                    throttled: match sample.get(Metric::Throttled) {
+                        Some(&Reading::Throttled(bit_pattern)) => Some(bit_pattern),
+                        _ => None,
+                    },
+                })
+            },
+        );
+
+        DvfsReport {
+            started_at,
+            tolerance: self.tolerance,
+            points,
+        }
+    }
+}
+
+/// The readings of a window and the frequency steps derived from them
+#[derive(Debug, Clone, PartialEq)]
+pub struct DvfsReport {
+    pub started_at: SystemTime,
+    /// See `DvfsObserver::tolerance`
+    pub tolerance: isize,
+    pub points: Vec<DvfsPoint>,
+}
+
+/// A step while it is being collected
+struct Collected {
+    sum: f64,
+    readings: usize,
+    capped: usize,
+    dwell: Duration,
+    longest: Duration,
+    visits: usize,
+}
+
+impl Collected {
+    fn mean(&self) -> f64 {
+        self.sum / self.readings as f64
+    }
+}
+
+impl DvfsReport {
+    /// The steps seen, from the lowest to the highest frequency.
+    ///
+    /// A reading stands for the time until the next one, so the last reading adds no
+    /// dwell time.
+    pub fn steps(&self) -> Vec<FrequencyStep> {
+        let mut collected: Vec<Collected> = Vec::new();
+        let mut current: Option<(usize, Duration)> = None;
+
+        let mut points = self.points.iter().peekable();
+        while let Some(point) = points.next() {
+            let frequency = match point.arm_clock {
+                Some(frequency) => frequency as f64,
+                None => continue,
+            };
+            let index = match collected
+                .iter()
+                .position(|step| (step.mean() - frequency).abs() <= self.tolerance as f64)
+            {
+                Some(index) => index,
+                None => {
+                    collected.push(Collected {
+                        sum: 0.0,
+                        readings: 0,
+                        capped: 0,
+                        dwell: Duration::ZERO,
+                        longest: Duration::ZERO,
+                        visits: 0,
+                    });
+                    collected.len() - 1
+                }
+            };
+            let until = points.peek().map_or(point.elapsed, |next| next.elapsed);
+            let dwell = until.saturating_sub(point.elapsed);
+
+            let stay = match current {
+                Some((previous, stay)) if previous == index => stay + dwell,
+                _ => {
+                    if let Some(step) = collected.get_mut(index) {
+                        step.visits += 1;
+                    }
+                    dwell
+                }
+            };
+            current = Some((index, stay));
+
+            if let Some(step) = collected.get_mut(index) {
+                step.sum += frequency;
+                step.readings += 1;
+                step.dwell += dwell;
+                step.longest = step.longest.max(stay);
+                if point.is_capped() == Some(true) {
+                    step.capped += 1;
+                }
+            }
+        }
+
+        collected.sort_by(|a, b| a.mean().total_cmp(&b.mean()));
+        let last = collected.len().saturating_sub(1);
+        collected
+            .iter()
+            .enumerate()
+            .map(|(index, step)| FrequencyStep {
+                frequency: step.mean().round() as isize,
+                kind: match index {
+                    _ if step.capped * 2 > step.readings => StepKind::Capped,
+                    _ if last == 0 => StepKind::Fixed,
+                    0 => StepKind::Idle,
+                    _ if index == last => StepKind::Turbo,
+                    _ => StepKind::Intermediate,
+                },
+                dwell: step.dwell,
+                longest: step.longest,
+                visits: step.visits,
+            })
+            .collect()
+    }
+
+    /// Number of times the clock moved from one step to another
+    pub fn transitions(&self) -> usize {
+        self.steps()
+            .iter()
+            .map(|step| step.visits)
+            .sum::<usize>()
+            .saturating_sub(1)
+    }
+
+    /// Whether the clock stayed at a single frequency the firmware didn't cap it to
+    pub fn is_pinned(&self) -> bool {
+        matches!(self.steps().as_slice(), [step] if step.kind == StepKind::Fixed)
+    }
+
+    /// The report including all points as a JSON object
+    pub fn to_json(&self) -> String {
+        let steps = self.steps().into_iter().map(|step| {
+            json::object(&[
+                ("frequency", json::number(step.frequency as f64)),
+                ("kind", json::string(&step.kind.to_string())),
+                ("dwell", json::number(step.dwell.as_secs_f64())),
+                ("longest", json::number(step.longest.as_secs_f64())),
+                ("visits", json::number(step.visits as f64)),
+            ])
+        });
+        let points = self.points.iter().map(|point| {
+            json::object(&[
+                ("elapsed", json::number(point.elapsed.as_secs_f64())),
+                (
+                    "arm_clock",
+                    json::optional(point.arm_clock, |f| json::number(f as f64)),
+                ),
+                (
+                    "throttled",
+                    json::optional(point.throttled, |b| json::number(b as f64)),
+                ),
+            ])
+        });
+
+        json::object(&[
+            ("started_at", json::string(&rfc3339(self.started_at))),
+            ("transitions", json::number(self.transitions() as f64)),
+            ("steps", json::array(steps)),
+            ("points", json::array(points)),
+        ])
+    }
+}
+
+/// One line per step, e.g. `1500 MHz  turbo         4.20 s in 3 visits, longest 2.10 s`
+impl fmt::Display for DvfsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps = self.steps();
+        if steps.is_empty() {
+            return write!(f, "no clock readings");
+        }
+
+        for step in &steps {
+            writeln!(
+                f,
+                "{:>5} MHz  {:<12} {:.2} s in {} visits, longest {:.2} s",
+                step.frequency / 1_000_000,
+                step.kind,
+                step.dwell.as_secs_f64(),
+                step.visits,
+                step.longest.as_secs_f64()
+            )?;
+        }
+        write!(f, "transitions: {}", self.transitions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    const MHZ: isize = 1_000_000;
+
+    fn report(points: &[(isize, isize)]) -> DvfsReport {
+        let points = points
+            .iter()
+            .enumerate()
+            .map(|(index, &(arm_clock, throttled))| DvfsPoint {
+                elapsed: Duration::from_millis(100 * index as u64),
+                arm_clock: Some(arm_clock),
+                throttled: Some(throttled),
+            })
+            .collect();
+
+        DvfsReport {
+            started_at: UNIX_EPOCH,
+            tolerance: DEFAULT_TOLERANCE,
+            points,
+        }
+    }
+
+    #[test]
+    fn test_steps() {
+        let report = report(&[
+            (600_117_184, 0),
+            (600_169_000, 0),
+            (1_500_398_464, 0),
+            (1_000_000_000, 0),
+            (1_500_345_728, 0),
+            (1_500_345_728, 0),
+            (600_117_184, 0),
+        ]);
+        let steps = report.steps();
+
+        assert_eq!(3, steps.len());
+        assert_eq!(StepKind::Idle, steps[0].kind);
+        assert_eq!(600, steps[0].frequency / MHZ);
+        assert_eq!(2, steps[0].visits);
+        assert_eq!(Duration::from_millis(200), steps[0].dwell);
+        assert_eq!(StepKind::Intermediate, steps[1].kind);
+        assert_eq!(StepKind::Turbo, steps[2].kind);
+        assert_eq!(Duration::from_millis(300), steps[2].dwell);
+        assert_eq!(Duration::from_millis(200), steps[2].longest);
+        assert_eq!(Duration::from_millis(150), steps[2].mean_dwell());
+
+        assert_eq!(4, report.transitions());
+        assert!(!report.is_pinned());
+    }
+
+    #[test]
+    fn test_pinned_and_capped() {
+        let pinned = report(&[(1500 * MHZ, 0); 5]);
+        assert!(pinned.is_pinned());
+        assert_eq!(0, pinned.transitions());
+
+        let capped = report(&[(1500 * MHZ, 0), (1000 * MHZ, 0x6), (1000 * MHZ, 0x6)]);
+        let steps = capped.steps();
+        assert_eq!(StepKind::Capped, steps[0].kind);
+        assert_eq!(StepKind::Turbo, steps[1].kind);
+        assert!(!report(&[(1000 * MHZ, 0x6); 3]).is_pinned());
+    }
+
+    #[test]
+    fn test_report_output() {
+        let report = report(&[(600 * MHZ, 0), (1500 * MHZ, 0)]);
+        let json = report.to_json();
+        assert!(json.starts_with(
+            r#"{"started_at":"1970-01-01T00:00:00.000Z","transitions":1,"steps":[{"frequency":600000000,"kind":"idle","dwell":0.1,"#
+        ));
+        assert!(report.to_string().contains("  600 MHz  idle"));
+        assert_eq!("no clock readings", self::report(&[]).to_string());
+    }
+
+    #[test]
+    fn test_run() {
+        let mut frequencies = vec![600 * MHZ, 1500 * MHZ].into_iter().cycle();
+        let report = DvfsObserver::new()
+            .duration(Duration::from_millis(50))
+            .interval(Duration::from_millis(10))
+            .sampler(move |metric| match metric {
+                Metric::Clock(src) => Ok(Reading::Clock(src, frequencies.next().unwrap())),
+                _ => Ok(Reading::Throttled(0)),
+            })
+            .run();
+
+        assert!(report.points.len() >= 5);
+        assert_eq!(2, report.steps().len());
+        assert!(report.transitions() >= 4);
+    }
+}
@@ -0,0 +1,712 @@
+//! The error of every call that invokes `vcgencmd`, and the categories it falls into
+
+use std::convert::Infallible;
+use std::fmt;
+use std::io;
+use std::num::{ParseFloatError, ParseIntError};
+use std::path::PathBuf;
+
+#[cfg(feature = "serde")]
+use serde::ser::SerializeStruct;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize, Serializer};
+use subprocess::PopenError;
+
+use crate::container::Runtime;
+
+/// Why a call to `vcgencmd` failed.
+///
+/// Every variant but `Io` names the `command` that failed with its source, e.g.
+/// `measure_volts sdram_c`. Errors converted with `From` from the errors of spawning and
+/// parsing, for callers using `?` on their own, have an empty one, which `Display` leaves
+/// out.
+///
+/// With the `serde` feature an error serializes as a flat object of its `kind`, `command`,
+/// `message`, `hint`, the message of its `source` and the `code` of a firmware error, e.g.
+/// `{"kind":"unsupported","command":"measure_volts sdram_c","message":"not supported by
+/// the firmware (error 2): Invalid arguments","hint":null,"source":null,"code":2}`. It
+/// can't be deserialized, as the sources aren't.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// `vcgencmd` (or `sudo`, or `ssh`) couldn't be run, for another reason than below
+    Popen { command: String, source: PopenError },
+    /// `vcgencmd`, `sudo` or `ssh` isn't installed, or not in `PATH`
+    MissingBinary { command: String, message: String },
+    /// Not allowed to run `vcgencmd` or to open `/dev/vchiq`
+    Permission { command: String, message: String },
+    /// `vcgencmd` couldn't connect to the firmware, e.g. in a container without `/dev/vchiq`
+    Vchi { command: String, message: String },
+    /// `vcgencmd` didn't exit within `Invocation::timeout` and was terminated, e.g. on a
+    /// wedged VCHI connection
+    Timeout { command: String, message: String },
+    /// `vcgencmd`, `sudo` or `ssh` exited unsuccessfully for another reason than above and
+    /// without an error reply of the firmware, with its exit code, `None` if it was killed
+    /// by a signal, and what it printed to stderr
+    Exit {
+        command: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    /// Running in a container without a device node or the binary mapped into it,
+    /// see `container`
+    Container {
+        command: String,
+        runtime: Runtime,
+        /// The device nodes tried, if none of them exists
+        devices: Vec<PathBuf>,
+        /// The binary, if it is missing
+        binary: Option<PathBuf>,
+    },
+    ParseInt {
+        command: String,
+        source: ParseIntError,
+    },
+    ParseFloat {
+        command: String,
+        source: ParseFloatError,
+    },
+    /// The firmware answered with an error reply, `error=<code> error_msg="<message>"`
+    Firmware {
+        command: String,
+        code: i32,
+        message: String,
+    },
+    /// The firmware doesn't know the command or source, like the SDRAM rails on the
+    /// Raspberry Pi 5
+    Unsupported {
+        command: String,
+        code: i32,
+        message: String,
+    },
+    /// An I/O error of the caller's, converted with `From` for using `?` on it, unrelated
+    /// to running `vcgencmd`
+    Io(io::Error),
+}
+
+/// The former name of `Error`
+pub type ExecutionError = Error;
+
+/// The result of every call that invokes `vcgencmd`
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The category of an `Error`, to react to failures without inspecting messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Running the process failed for another reason than the ones below
+    Io,
+    /// Not allowed to run `vcgencmd` or access the firmware, e.g. without `sudo`
+    Permission,
+    /// The firmware reported an error
+    Firmware,
+    /// The output couldn't be understood
+    Parse,
+    /// `vcgencmd` didn't answer in time
+    Timeout,
+    /// The command or source isn't available here, including `vcgencmd` not being installed
+    Unsupported,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ErrorKind::Io => "io",
+            ErrorKind::Permission => "permission",
+            ErrorKind::Firmware => "firmware",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Unsupported => "unsupported",
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.command().is_empty() {
+            write!(f, "{}: ", self.command())?;
+        }
+        self.fmt_message(f)
+    }
+}
+
+impl Error {
+    /// What went wrong, the `Display` output without the command in front
+    fn fmt_message(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Popen { .. } => f.write_str("failed to run vcgencmd"),
+            Error::MissingBinary { message, .. } => write!(f, "not installed: {}", message),
+            Error::Permission { message, .. } => write!(f, "permission denied: {}", message),
+            Error::Vchi { message, .. } => {
+                write!(f, "failed to connect to the firmware: {}", message)
+            }
+            Error::Timeout { message, .. } => write!(f, "timed out: {}", message),
+            Error::Exit { code, stderr, .. } => {
+                match code {
+                    Some(code) => write!(f, "exited with status {}", code)?,
+                    None => f.write_str("was killed by a signal")?,
+                }
+                match stderr.as_str() {
+                    "" => Ok(()),
+                    stderr => write!(f, ": {}", stderr),
+                }
+            }
+            Error::Container {
+                runtime,
+                devices,
+                binary,
+                ..
+            } => {
+                write!(f, "running in {} without ", runtime)?;
+                if let Some(device) = devices.first() {
+                    let tried: Vec<_> = devices
+                        .iter()
+                        .map(|device| device.display().to_string())
+                        .collect();
+                    write!(
+                        f,
+                        "{}, map one with e.g. {}",
+                        tried.join(" or "),
+                        runtime.device_mapping(device)
+                    )?;
+                }
+                if let Some(binary) = binary {
+                    if !devices.is_empty() {
+                        f.write_str(", and without ")?;
+                    }
+                    write!(
+                        f,
+                        "{}, install it in the image or mount it from the host",
+                        binary.display()
+                    )?;
+                }
+                Ok(())
+            }
+            Error::ParseInt { .. } | Error::ParseFloat { .. } => {
+                f.write_str("failed to parse the output of vcgencmd")
+            }
+            Error::Firmware { code, message, .. } => {
+                write!(f, "the firmware reported error {}: {}", code, message)
+            }
+            Error::Unsupported { code, message, .. } => write!(
+                f,
+                "not supported by the firmware (error {}): {}",
+                code, message
+            ),
+            Error::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// `Error::fmt_message` as a `Display`
+struct Message<'a>(&'a Error);
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_message(f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use std::error::Error as _;
+
+        let mut error = serializer.serialize_struct("Error", 6)?;
+        error.serialize_field("kind", &self.kind())?;
+        error.serialize_field("command", self.command())?;
+        error.serialize_field("message", &self.message())?;
+        error.serialize_field("hint", &self.hint())?;
+        error.serialize_field("source", &self.source().map(ToString::to_string))?;
+        error.serialize_field("code", &self.code())?;
+        error.end()
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Popen { source, .. } => Some(source),
+            Error::ParseInt { source, .. } => Some(source),
+            Error::ParseFloat { source, .. } => Some(source),
+            // its message is already the one of the error
+            Error::Io(error) => error.source(),
+            Error::MissingBinary { .. }
+            | Error::Permission { .. }
+            | Error::Vchi { .. }
+            | Error::Timeout { .. }
+            | Error::Exit { .. }
+            | Error::Container { .. }
+            | Error::Firmware { .. }
+            | Error::Unsupported { .. } => None,
+        }
+    }
+}
+
+/// The non-empty lines of `stderr` joined into one
+fn one_line(stderr: &str) -> String {
+    stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The codes of `vcgencmd` for a command it doesn't know and for invalid arguments
+const UNSUPPORTED_CODES: [i32; 2] = [1, 2];
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Popen {
+                source: PopenError::IoError(error),
+                ..
+            } => match error.kind() {
+                io::ErrorKind::PermissionDenied => ErrorKind::Permission,
+                io::ErrorKind::NotFound => ErrorKind::Unsupported,
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ErrorKind::Timeout,
+                _ => ErrorKind::Io,
+            },
+            Error::Popen { .. } | Error::Vchi { .. } | Error::Exit { .. } | Error::Io(_) => {
+                ErrorKind::Io
+            }
+            Error::MissingBinary { .. } | Error::Container { .. } => ErrorKind::Unsupported,
+            Error::Permission { .. } => ErrorKind::Permission,
+            Error::Timeout { .. } => ErrorKind::Timeout,
+            Error::ParseInt { .. } | Error::ParseFloat { .. } => ErrorKind::Parse,
+            Error::Firmware { .. } => ErrorKind::Firmware,
+            Error::Unsupported { .. } => ErrorKind::Unsupported,
+        }
+    }
+
+    /// The `vcgencmd` command that failed with its source, e.g. `measure_volts sdram_c`
+    pub fn command(&self) -> &str {
+        match self {
+            Error::Popen { command, .. }
+            | Error::MissingBinary { command, .. }
+            | Error::Permission { command, .. }
+            | Error::Vchi { command, .. }
+            | Error::Timeout { command, .. }
+            | Error::Exit { command, .. }
+            | Error::Container { command, .. }
+            | Error::ParseInt { command, .. }
+            | Error::ParseFloat { command, .. }
+            | Error::Firmware { command, .. }
+            | Error::Unsupported { command, .. } => command,
+            Error::Io(_) => "",
+        }
+    }
+
+    /// Whether the call may succeed when repeated, as after a hiccup of the VCHI connection
+    /// or a timeout, unlike after a missing binary or an unsupported command, see `retry`
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Vchi { .. } | Error::Timeout { .. } => true,
+            Error::Popen { .. } => self.kind() == ErrorKind::Io,
+            // the firmware answers with a negative code when its own VCHI service failed
+            Error::Firmware { code, .. } => *code < 0,
+            _ => false,
+        }
+    }
+
+    /// What went wrong, like the `Display` output but without the command in front
+    pub fn message(&self) -> String {
+        Message(self).to_string()
+    }
+
+    /// The code of a firmware error reply
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            Error::Firmware { code, .. } | Error::Unsupported { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// What to do about the error, for the failures users commonly run into
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::MissingBinary { .. } => Some(
+                "install vcgencmd (the libraspberrypi-bin or raspi-utils package), \
+                 or set Invocation::binary to its path if it isn't in PATH, /usr/bin \
+                 or /opt/vc/bin",
+            ),
+            Error::Permission { .. } => Some(
+                "run as root or through sudo or doas, or add the user to the video group \
+                 to allow access to /dev/vchiq",
+            ),
+            Error::Vchi { .. } => Some(
+                "check that /dev/vchiq exists and is accessible, in a container pass it \
+                 through, e.g. with `--device /dev/vchiq`",
+            ),
+            Error::Timeout { .. } => Some(
+                "the firmware didn't answer, a wedged VCHI connection usually needs a \
+                 reboot, raise Invocation::timeout if vcgencmd is merely slow, e.g. over ssh",
+            ),
+            Error::Container { .. } => Some(
+                "vcgencmd needs both the firmware device node and its binary with the \
+                 libraries inside the container",
+            ),
+            _ => None,
+        }
+    }
+
+    /// The error for a process that couldn't be spawned
+    pub(crate) fn spawn(command: String, source: PopenError) -> Error {
+        let kind = match &source {
+            PopenError::IoError(error) => error.kind(),
+            _ => return Error::Popen { command, source },
+        };
+        match kind {
+            io::ErrorKind::NotFound => Error::MissingBinary {
+                command,
+                message: source.to_string(),
+            },
+            io::ErrorKind::PermissionDenied => Error::Permission {
+                command,
+                message: source.to_string(),
+            },
+            io::ErrorKind::TimedOut => Error::Timeout {
+                command,
+                message: source.to_string(),
+            },
+            _ => Error::Popen { command, source },
+        }
+    }
+
+    /// The error of a process that exited unsuccessfully with `code`, the one described by
+    /// its `stderr` if that is recognized, see `from_stderr`, otherwise `Exit`
+    pub(crate) fn exit(command: String, code: Option<i32>, stderr: &str) -> Error {
+        match Error::from_stderr(command.clone(), stderr) {
+            Some(error) => error,
+            None => Error::Exit {
+                command,
+                code,
+                stderr: one_line(stderr),
+            },
+        }
+    }
+
+    /// The error described by what a failed `vcgencmd`, `sudo`, `doas`, `pkexec` or `ssh`
+    /// printed to stderr, `None` if it isn't one of the failures recognized
+    pub(crate) fn from_stderr(command: String, stderr: &str) -> Option<Error> {
+        let message = one_line(stderr);
+        let lowercase = message.to_lowercase();
+        let has = |patterns: &[&str]| patterns.iter().any(|p| lowercase.contains(p));
+
+        // permission problems also fail the VCHI initialization, so they are checked first,
+        // and a missing /dev/vchiq isn't a missing binary
+        if has(&[
+            "permission denied",
+            "not in the sudoers",
+            "password is required",
+            // doas and pkexec
+            "operation not permitted",
+            "not authorized",
+        ]) {
+            Some(Error::Permission { command, message })
+        } else if has(&["vchi"]) {
+            Some(Error::Vchi { command, message })
+        } else if has(&["command not found", "no such file or directory"]) {
+            Some(Error::MissingBinary { command, message })
+        } else {
+            None
+        }
+    }
+
+    /// The error for a firmware error reply, `Unsupported` for unknown commands and sources
+    pub(crate) fn firmware(command: String, code: i32, message: String) -> Error {
+        if UNSUPPORTED_CODES.contains(&code) {
+            Error::Unsupported {
+                command,
+                code,
+                message,
+            }
+        } else {
+            Error::Firmware {
+                command,
+                code,
+                message,
+            }
+        }
+    }
+}
+
+/// The errors of the parsers, turned into an `Error` once the command is known
+pub(crate) trait ParseError {
+    fn into_error(self, command: String) -> Error;
+}
+
+impl ParseError for ParseIntError {
+    fn into_error(self, command: String) -> Error {
+        Error::ParseInt {
+            command,
+            source: self,
+        }
+    }
+}
+
+impl ParseError for ParseFloatError {
+    fn into_error(self, command: String) -> Error {
+        Error::ParseFloat {
+            command,
+            source: self,
+        }
+    }
+}
+
+/// For parsers that accept any output
+impl ParseError for Infallible {
+    fn into_error(self, _command: String) -> Error {
+        match self {}
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+/// Classified like a failure to spawn `vcgencmd`, e.g. `NotFound` as `MissingBinary`
+impl From<PopenError> for Error {
+    fn from(error: PopenError) -> Error {
+        Error::spawn(String::new(), error)
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(error: ParseIntError) -> Error {
+        error.into_error(String::new())
+    }
+}
+
+impl From<ParseFloatError> for Error {
+    fn from(error: ParseFloatError) -> Error {
+        error.into_error(String::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    fn popen(kind: io::ErrorKind) -> Error {
+        Error::Popen {
+            command: "measure_temp".to_owned(),
+            source: PopenError::IoError(io::Error::from(kind)),
+        }
+    }
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(
+            ErrorKind::Permission,
+            popen(io::ErrorKind::PermissionDenied).kind()
+        );
+        assert_eq!(
+            ErrorKind::Unsupported,
+            popen(io::ErrorKind::NotFound).kind()
+        );
+        assert_eq!(
+            ErrorKind::Parse,
+            "".parse::<f64>()
+                .unwrap_err()
+                .into_error("measure_temp".to_owned())
+                .kind()
+        );
+        assert_eq!(
+            ErrorKind::Firmware,
+            Error::firmware(
+                "measure_temp".to_owned(),
+                -1,
+                "VCHI connection failed".to_owned()
+            )
+            .kind()
+        );
+    }
+
+    #[test]
+    fn test_from_stderr() {
+        let classify = |stderr| Error::from_stderr("measure_temp".to_owned(), stderr);
+
+        let vchi = classify("VCHI initialization failed\n").unwrap();
+        assert!(matches!(vchi, Error::Vchi { .. }));
+        assert_eq!(ErrorKind::Io, vchi.kind());
+        assert!(vchi.hint().unwrap().contains("/dev/vchiq"));
+
+        let permission = classify(
+            "Can't open device file: /dev/vchiq: Permission denied\n\
+             VCHI initialization failed\n",
+        )
+        .unwrap();
+        assert_eq!(ErrorKind::Permission, permission.kind());
+        assert_eq!(
+            "measure_temp: permission denied: Can't open device file: /dev/vchiq: \
+             Permission denied, VCHI initialization failed",
+            permission.to_string()
+        );
+
+        let no_device = classify("Can't open device file: /dev/vchiq: No such file or directory\n");
+        assert!(matches!(no_device.unwrap(), Error::Vchi { .. }));
+
+        let missing = classify("sudo: vcgencmd: command not found\n").unwrap();
+        assert!(matches!(missing, Error::MissingBinary { .. }));
+        assert_eq!(ErrorKind::Unsupported, missing.kind());
+
+        for stderr in &[
+            "doas: Operation not permitted\n",
+            "Error executing command as another user: Not authorized\n",
+        ] {
+            assert_eq!(ErrorKind::Permission, classify(stderr).unwrap().kind());
+        }
+
+        assert!(classify("something else entirely").is_none());
+    }
+
+    #[test]
+    fn test_exit() {
+        let error = Error::exit(
+            "measure_temp".to_owned(),
+            Some(3),
+            "mailbox request failed\n\n  try again\n",
+        );
+        assert!(matches!(error, Error::Exit { code: Some(3), .. }));
+        assert_eq!(ErrorKind::Io, error.kind());
+        assert_eq!(
+            "measure_temp: exited with status 3: mailbox request failed, try again",
+            error.to_string()
+        );
+        assert_eq!(
+            "was killed by a signal",
+            Error::exit("measure_temp".to_owned(), None, "").message()
+        );
+
+        let recognized = Error::exit("measure_temp".to_owned(), Some(1), "VCHI failed\n");
+        assert!(matches!(recognized, Error::Vchi { .. }));
+    }
+
+    #[test]
+    fn test_container() {
+        let error = Error::Container {
+            command: "measure_temp".to_owned(),
+            runtime: Runtime::Docker,
+            devices: vec![PathBuf::from("/dev/vchiq"), PathBuf::from("/dev/vcio")],
+            binary: Some(PathBuf::from("vcgencmd")),
+        };
+        assert_eq!(ErrorKind::Unsupported, error.kind());
+        assert_eq!(
+            "measure_temp: running in docker without /dev/vchiq or /dev/vcio, map one with \
+             e.g. --device /dev/vchiq, and without vcgencmd, install it in the image or \
+             mount it from the host",
+            error.to_string()
+        );
+        assert!(error.hint().is_some());
+    }
+
+    #[test]
+    fn test_spawn() {
+        let spawn = |kind| {
+            Error::spawn(
+                "measure_temp".to_owned(),
+                PopenError::IoError(io::Error::from(kind)),
+            )
+        };
+        assert!(matches!(
+            spawn(io::ErrorKind::NotFound),
+            Error::MissingBinary { .. }
+        ));
+        assert!(matches!(
+            spawn(io::ErrorKind::PermissionDenied),
+            Error::Permission { .. }
+        ));
+        let timeout = spawn(io::ErrorKind::TimedOut);
+        assert!(matches!(timeout, Error::Timeout { .. }));
+        assert_eq!(ErrorKind::Timeout, timeout.kind());
+        assert!(timeout.hint().is_some());
+        assert!(timeout.is_transient());
+        assert!(matches!(spawn(io::ErrorKind::Other), Error::Popen { .. }));
+        assert!(spawn(io::ErrorKind::Other).is_transient());
+        assert!(!spawn(io::ErrorKind::NotFound).is_transient());
+        assert!(!spawn(io::ErrorKind::PermissionDenied).is_transient());
+        assert!(spawn(io::ErrorKind::Other).hint().is_none());
+    }
+
+    #[test]
+    fn test_source() {
+        let error = "x"
+            .parse::<isize>()
+            .unwrap_err()
+            .into_error("measure_clock arm".to_owned());
+        assert_eq!("measure_clock arm", error.command());
+        assert_eq!(
+            "measure_clock arm: failed to parse the output of vcgencmd",
+            error.to_string()
+        );
+        assert_eq!(
+            "invalid digit found in string",
+            error.source().unwrap().to_string()
+        );
+
+        let unsupported = Error::firmware(
+            "measure_volts sdram_c".to_owned(),
+            2,
+            "Invalid arguments".to_owned(),
+        );
+        assert_eq!(ErrorKind::Unsupported, unsupported.kind());
+        assert_eq!(Some(2), unsupported.code());
+        assert_eq!(
+            "not supported by the firmware (error 2): Invalid arguments",
+            unsupported.message()
+        );
+        assert_eq!(
+            "measure_volts sdram_c: not supported by the firmware (error 2): Invalid arguments",
+            unsupported.to_string()
+        );
+        assert!(unsupported.source().is_none());
+    }
+
+    #[test]
+    fn test_from() {
+        fn parse(text: &str) -> Result<f64> {
+            Ok(text.parse::<f64>()?)
+        }
+        let error = parse("hot").unwrap_err();
+        assert_eq!(ErrorKind::Parse, error.kind());
+        assert_eq!("failed to parse the output of vcgencmd", error.to_string());
+        assert!(error.source().is_some());
+
+        let error = Error::from(io::Error::new(io::ErrorKind::NotFound, "no config.toml"));
+        assert!(matches!(error, Error::Io(_)));
+        assert_eq!(ErrorKind::Io, error.kind());
+        assert_eq!("", error.command());
+        assert_eq!("no config.toml", error.to_string());
+        assert!(!error.is_transient());
+        assert!(error.hint().is_none());
+        assert!(matches!(
+            Error::from(PopenError::IoError(io::Error::from(
+                io::ErrorKind::NotFound
+            ))),
+            Error::MissingBinary { .. }
+        ));
+        assert_eq!(
+            ErrorKind::Timeout,
+            Error::from(PopenError::IoError(io::Error::from(
+                io::ErrorKind::TimedOut
+            )))
+            .kind()
+        );
+
+        // usable wherever a boxed or anyhow error is expected
+        fn boxed() -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            Err(Error::from("x".parse::<isize>().unwrap_err()))?
+        }
+        assert_eq!(
+            "failed to parse the output of vcgencmd",
+            boxed().unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,418 @@
+//! A persistent log of throttling and under-voltage episodes
+//!
+//! The `*_occurred` flags of `get_throttled` only say that something happened since boot,
+//! not when. `EventLog` watches the live flags and appends a line to a file whenever a
+//! condition starts or ends, so the history survives restarts of the monitoring process:
+//!
+//! ```txt
+//! 1566741789120 2019-08-25T14:03:09.120Z started under_voltage 0x50005
+//! 1566741791120 2019-08-25T14:03:11.120Z ended under_voltage 0x50000
+//! ```
+//!
+//! The first column is the time in milliseconds since the unix epoch, the second one the
+//! same time in a human readable form.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::boot;
+use crate::monitor::{Reading, Sample};
+use crate::sink::{self, MetricSink};
+use crate::timefmt;
+use crate::{interpret_bit_pattern, ThrottledStatus};
+
+/// A condition reported by `get_throttled`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Condition {
+    UnderVoltage,
+    ArmFrequencyCapped,
+    Throttled,
+    SoftTempLimit,
+}
+
+impl Condition {
+    pub const ALL: [Condition; 4] = [
+        Condition::UnderVoltage,
+        Condition::ArmFrequencyCapped,
+        Condition::Throttled,
+        Condition::SoftTempLimit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Condition::UnderVoltage => "under_voltage",
+            Condition::ArmFrequencyCapped => "arm_frequency_capped",
+            Condition::Throttled => "throttled",
+            Condition::SoftTempLimit => "soft_temp_limit",
+        }
+    }
+
+    /// The condition called `name`, the inverse of `Condition::name`
+    pub fn from_name(name: &str) -> Option<Condition> {
+        Condition::ALL.iter().copied().find(|c| c.name() == name)
+    }
+
+    /// Whether the condition is active right now
+    pub fn is_active(self, status: &ThrottledStatus) -> bool {
+        match self {
+            Condition::UnderVoltage => status.under_voltage,
+            Condition::ArmFrequencyCapped => status.arm_frequency_capped,
+            Condition::Throttled => status.currently_throttled,
+            Condition::SoftTempLimit => status.soft_temp_limit_active,
+        }
+    }
+
+    /// Whether the condition occurred at some point since boot
+    pub fn has_occurred(self, status: &ThrottledStatus) -> bool {
+        match self {
+            Condition::UnderVoltage => status.under_voltage_occurred,
+            Condition::ArmFrequencyCapped => status.arm_frequency_cap_occurred,
+            Condition::Throttled => status.throttling_occurred,
+            Condition::SoftTempLimit => status.soft_temp_limit_occurred,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// The condition became active
+    Started,
+    /// The condition is no longer active
+    Ended,
+    /// The sticky flag says the condition occurred, but it was never seen active, e.g.
+    /// because it happened before monitoring started or between two samples
+    Unobserved,
+}
+
+impl EventKind {
+    fn name(self) -> &'static str {
+        match self {
+            EventKind::Started => "started",
+            EventKind::Ended => "ended",
+            EventKind::Unobserved => "unobserved",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<EventKind> {
+        match name {
+            "started" => Some(EventKind::Started),
+            "ended" => Some(EventKind::Ended),
+            "unobserved" => Some(EventKind::Unobserved),
+            _ => None,
+        }
+    }
+}
+
+/// A single line of the event log
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub timestamp: SystemTime,
+    pub kind: EventKind,
+    pub condition: Condition,
+    /// The `get_throttled` bit pattern the event was derived from
+    pub bit_pattern: isize,
+}
+
+impl Event {
+    fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {} 0x{:x}\n",
+            timefmt::unix_millis(self.timestamp),
+            timefmt::rfc3339(self.timestamp),
+            self.kind.name(),
+            self.condition.name(),
+            self.bit_pattern
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Event> {
+        let mut fields = line.split_whitespace();
+        let millis = fields.next()?.parse::<u64>().ok()?;
+        let _human_readable = fields.next()?;
+        let kind = EventKind::from_name(fields.next()?)?;
+        let condition = Condition::from_name(fields.next()?)?;
+        let bit_pattern =
+            isize::from_str_radix(fields.next()?.trim_start_matches("0x"), 16).ok()?;
+
+        Some(Event {
+            timestamp: timefmt::from_unix_millis(millis),
+            kind,
+            condition,
+            bit_pattern,
+        })
+    }
+}
+
+/// Appends throttling episodes to a file, see the module documentation.
+pub struct EventLog {
+    path: PathBuf,
+    file: File,
+    /// Conditions with a `Started` event but no `Ended` one yet
+    active: Vec<Condition>,
+    /// Conditions whose sticky flag was already accounted for
+    accounted: Vec<Condition>,
+}
+
+impl EventLog {
+    /// Open or create the log at `path`.
+    ///
+    /// Episodes left open by a previous run are picked up again, so a condition that is
+    /// still active isn't logged as started twice. If the system rebooted since the last
+    /// entry, open episodes are closed at the boot time instead, as the firmware forgets
+    /// about them on reboot.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<EventLog> {
+        EventLog::open_with_boot_time(path, boot::boot_time().ok())
+    }
+
+    fn open_with_boot_time<P: AsRef<Path>>(
+        path: P,
+        boot_time: Option<SystemTime>,
+    ) -> io::Result<EventLog> {
+        let path = path.as_ref().to_owned();
+        let mut active = Vec::new();
+        let mut last_event = None;
+
+        if path.exists() {
+            for event in EventLog::read(&path)? {
+                match event.kind {
+                    EventKind::Started if !active.contains(&event.condition) => {
+                        active.push(event.condition)
+                    }
+                    EventKind::Ended => active.retain(|&c| c != event.condition),
+                    _ => {}
+                }
+                last_event = Some(event);
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut log = EventLog {
+            path,
+            file,
+            accounted: active.clone(),
+            active,
+        };
+
+        match (last_event, boot_time) {
+            (Some(last_event), Some(boot_time)) if last_event.timestamp < boot_time => {
+                log.close_for_reboot(boot_time, last_event.bit_pattern)?;
+            }
+            _ => {}
+        }
+
+        Ok(log)
+    }
+
+    /// End all open episodes at `boot_time`, since they can't have outlasted the reboot
+    fn close_for_reboot(&mut self, boot_time: SystemTime, bit_pattern: isize) -> io::Result<()> {
+        let events: Vec<_> = self
+            .active
+            .drain(..)
+            .map(|condition| Event {
+                timestamp: boot_time,
+                kind: EventKind::Ended,
+                condition,
+                bit_pattern,
+            })
+            .collect();
+        self.accounted.clear();
+
+        self.write(&events)
+    }
+
+    /// Read all events from a log file, skipping lines that can't be parsed
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<Event>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut events = Vec::new();
+
+        for line in reader.lines() {
+            if let Some(event) = Event::from_line(&line?) {
+                events.push(event);
+            }
+        }
+
+        Ok(events)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record the state described by `bit_pattern`, returning the events that were logged
+    pub fn observe(&mut self, bit_pattern: isize, at: SystemTime) -> io::Result<Vec<Event>> {
+        let status = interpret_bit_pattern(bit_pattern);
+        let mut events = Vec::new();
+
+        for &condition in &Condition::ALL {
+            let was_active = self.active.contains(&condition);
+            let kind = match (was_active, condition.is_active(&status)) {
+                (false, true) => EventKind::Started,
+                (true, false) => EventKind::Ended,
+                (false, false)
+                    if condition.has_occurred(&status) && !self.accounted.contains(&condition) =>
+                {
+                    EventKind::Unobserved
+                }
+                _ => continue,
+            };
+
+            match kind {
+                EventKind::Started => self.active.push(condition),
+                EventKind::Ended => self.active.retain(|&c| c != condition),
+                EventKind::Unobserved => {}
+            }
+            if !self.accounted.contains(&condition) {
+                self.accounted.push(condition);
+            }
+
+            events.push(Event {
+                timestamp: at,
+                kind,
+                condition,
+                bit_pattern,
+            });
+        }
+
+        self.write(&events)?;
+        Ok(events)
+    }
+
+    fn write(&mut self, events: &[Event]) -> io::Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let lines: String = events.iter().map(Event::to_line).collect();
+        self.file.write_all(lines.as_bytes())?;
+        // under-voltage tends to be followed by losing power, so don't leave it buffered
+        self.file.sync_data()
+    }
+
+    /// Turn the log into a plain monitor sink, recording every `Throttled` reading.
+    ///
+    /// Unlike with `Monitor::metric_sink`, failing writes aren't counted anywhere, the
+    /// events are written again with the next sample that brings a change.
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for EventLog {
+    /// Record the `Throttled` reading of `sample`, if any
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        for reading in &sample.readings {
+            if let Reading::Throttled(bit_pattern) = *reading {
+                self.observe(bit_pattern, sample.timestamp)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("vcgencmd-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_event_line_roundtrip() {
+        let event = Event {
+            timestamp: timefmt::from_unix_millis(1_566_741_789_120),
+            kind: EventKind::Started,
+            condition: Condition::UnderVoltage,
+            bit_pattern: 0x50005,
+        };
+        let line = event.to_line();
+
+        assert_eq!(
+            "1566741789120 2019-08-25T14:03:09.120Z started under_voltage 0x50005\n",
+            line
+        );
+        assert_eq!(Some(event), Event::from_line(&line));
+    }
+
+    #[test]
+    fn test_episodes() {
+        let path = temp_path("events");
+        let start = SystemTime::now();
+        let mut log = EventLog::open(&path).unwrap();
+
+        assert!(log.observe(0x0, start).unwrap().is_empty());
+        let started = log.observe(0x3, start).unwrap();
+        assert_eq!(2, started.len());
+        assert!(started.iter().all(|e| e.kind == EventKind::Started));
+
+        // nothing changes while the conditions persist
+        assert!(log.observe(0x3, start).unwrap().is_empty());
+
+        let ended = log.observe(0x0, start + Duration::from_secs(2)).unwrap();
+        assert_eq!(2, ended.len());
+        assert!(ended.iter().all(|e| e.kind == EventKind::Ended));
+
+        assert_eq!(4, EventLog::read(&path).unwrap().len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_restart_continues_open_episodes() {
+        let path = temp_path("events-restart");
+        let now = SystemTime::now();
+
+        EventLog::open(&path).unwrap().observe(0x1, now).unwrap();
+
+        // the condition is still active after the restart, so it must not start again
+        let mut log = EventLog::open(&path).unwrap();
+        assert!(log.observe(0x1, now).unwrap().is_empty());
+        let ended = log.observe(0x0, now).unwrap();
+        assert_eq!(1, ended.len());
+        assert_eq!(EventKind::Ended, ended[0].kind);
+        assert_eq!(Condition::UnderVoltage, ended[0].condition);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reboot_closes_open_episodes() {
+        let path = temp_path("events-reboot");
+        let before = timefmt::from_unix_millis(1_566_741_789_120);
+        let boot_time = before + Duration::from_secs(60);
+
+        EventLog::open(&path).unwrap().observe(0x1, before).unwrap();
+
+        let mut log = EventLog::open_with_boot_time(&path, Some(boot_time)).unwrap();
+        let events = EventLog::read(&path).unwrap();
+        assert_eq!(EventKind::Ended, events[1].kind);
+        assert_eq!(boot_time, events[1].timestamp);
+
+        // after the reboot, the condition is a new episode
+        let started = log.observe(0x1, SystemTime::now()).unwrap();
+        assert_eq!(EventKind::Started, started[0].kind);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unobserved() {
+        let path = temp_path("events-unobserved");
+        let mut log = EventLog::open(&path).unwrap();
+
+        // every sticky flag set, but nothing active
+        let events = log.observe(0x1f0000, SystemTime::now()).unwrap();
+        assert_eq!(4, events.len());
+        assert!(events.iter().all(|e| e.kind == EventKind::Unobserved));
+
+        // only reported once
+        assert!(log.observe(0x1f0000, SystemTime::now()).unwrap().is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
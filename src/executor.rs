@@ -0,0 +1,119 @@
+//! How a `Vcgencmd` client gets the output of a command
+//!
+//! A client hands every command to its `Executor` and only parses what comes back, so
+//! vcgencmd can be reached by other means than spawning it, e.g. through an agent on the
+//! host or the mailbox interface directly, and tests can answer with canned output. The
+//! default `ProcessExecutor` runs it as configured by an `Invocation`, locally or over
+//! `ssh`. Any `Fn(Cmd, Option<Src>) -> Result<String>` is an executor too:
+//!
+//! ```rust
+//! use vcgencmd::{Cmd, Vcgencmd};
+//!
+//! let client = Vcgencmd::with_executor(|cmd, _src| match cmd {
+//!     Cmd::MeasureTemp => Ok("temp=48.3'C\n".to_owned()),
+//!     _ => Ok("error=1 error_msg=\"Command not registered\"\n".to_owned()),
+//! });
+//! assert_eq!(48.3, client.measure_temp()?);
+//! assert!(client.get_throttled().is_err());
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+
+use crate::{run, stdout_of, Cmd, ExecutionError, Invocation, Src};
+
+/// The reply of the firmware to commands it doesn't know
+pub(crate) const NOT_REGISTERED: &str = "error=1 error_msg=\"Command not registered\"\n";
+/// The reply of the firmware to sources it doesn't know
+pub(crate) const INVALID_ARGUMENTS: &str = "error=2 error_msg=\"Invalid arguments\"\n";
+
+/// Gets the output of vcgencmd commands for a `Vcgencmd` client
+pub trait Executor {
+    /// What `vcgencmd <cmd> <src>` prints to stdout, e.g. `temp=48.3'C\n`.
+    ///
+    /// An error reply of the firmware like `error=2 error_msg="Invalid arguments"` is
+    /// returned as output, the client turns it into an `Error` of the right kind. An error
+    /// is only for failing to get any output.
+    fn run(&self, cmd: Cmd, src: Option<Src>) -> Result<String, ExecutionError>;
+}
+
+impl<F> Executor for F
+where
+    F: Fn(Cmd, Option<Src>) -> Result<String, ExecutionError>,
+{
+    fn run(&self, cmd: Cmd, src: Option<Src>) -> Result<String, ExecutionError> {
+        self(cmd, src)
+    }
+}
+
+/// Spawns vcgencmd as configured by an `Invocation`, like the free functions do with the
+/// global one
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessExecutor {
+    invocation: Invocation,
+}
+
+impl ProcessExecutor {
+    pub fn new(invocation: Invocation) -> ProcessExecutor {
+        ProcessExecutor { invocation }
+    }
+
+    pub fn invocation(&self) -> &Invocation {
+        &self.invocation
+    }
+}
+
+impl Executor for ProcessExecutor {
+    fn run(&self, cmd: Cmd, src: Option<Src>) -> Result<String, ExecutionError> {
+        let output = run(&self.invocation, cmd, src);
+        stdout_of(&self.invocation, cmd, src, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorKind, MemSrc, Vcgencmd};
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_closure_executor() {
+        let calls = RefCell::new(Vec::new());
+        let client = Vcgencmd::with_executor(|cmd, src| {
+            calls.borrow_mut().push((cmd, src));
+            match cmd {
+                Cmd::GetMem => Ok("arm=948M\n".to_owned()),
+                Cmd::GetThrottled => Ok("throttled=0x50005\n".to_owned()),
+                _ => Ok("error=2 error_msg=\"Invalid arguments\"\n".to_owned()),
+            }
+        });
+
+        assert_eq!(948, client.get_mem(Src::Mem(MemSrc::Arm)).unwrap());
+        assert!(client.is_undervolted().unwrap());
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client.measure_temp().unwrap_err().kind()
+        );
+        assert_eq!(
+            vec![
+                (Cmd::GetMem, Some(Src::Mem(MemSrc::Arm))),
+                (Cmd::GetThrottled, None),
+                (Cmd::MeasureTemp, None),
+            ],
+            calls.into_inner()
+        );
+    }
+
+    #[test]
+    fn test_executor_error() {
+        let client = Vcgencmd::with_executor(|_, _| {
+            Err(ExecutionError::MissingBinary {
+                command: "measure_temp".to_owned(),
+                message: "no agent".to_owned(),
+            })
+        });
+
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client.measure_temp().unwrap_err().kind()
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Snapshots of several hosts, labeled by host and assessed together
+//!
+//! A `Fleet` keeps the newest snapshot of every host along with its health, whether it was
+//! captured over ssh or restored from a log written by `JsonlSink` or the CSV sink, and
+//! summarizes the lot: how many hosts are in which state, the ranges of temperature, clock
+//! and core voltage, and the hottest host.
+//!
+//! ```no_run
+//! use vcgencmd::fleet::Fleet;
+//!
+//! let mut fleet = Fleet::capture(&["pi4", "pi@10.0.0.3"]);
+//! fleet.ingest("pi-zero", "/var/log/pi-zero.jsonl")?;
+//! for host in fleet.hosts() {
+//!     println!("{}: {}", host.host, host.health);
+//! }
+//! println!("{}", fleet.summary());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::calibrate::Stats;
+use crate::health::{HealthPolicy, HealthSummary};
+use crate::monitor::{Metric, Reading};
+use crate::replay::Replay;
+use crate::snapshot::{Snapshot, SnapshotSpec};
+use crate::thermal::TempLimits;
+use crate::{interpret_bit_pattern, json, Invocation};
+
+/// The snapshot of one host and its health under the policy of the fleet
+#[derive(Debug)]
+pub struct HostSnapshot {
+    pub host: String,
+    pub snapshot: Snapshot,
+    pub health: HealthSummary,
+}
+
+/// The newest snapshot of every host, ordered by host
+#[derive(Debug, Default)]
+pub struct Fleet {
+    hosts: Vec<HostSnapshot>,
+    policy: HealthPolicy,
+}
+
+impl Fleet {
+    pub fn new() -> Fleet {
+        Fleet::default()
+    }
+
+    /// Assess the hosts under `policy` instead of the default one
+    pub fn policy(mut self, policy: HealthPolicy) -> Fleet {
+        self.policy = policy;
+        for host in &mut self.hosts {
+            host.health = host.snapshot.health_with(&policy);
+        }
+        self
+    }
+
+    /// Capture a snapshot of every host over ssh, one after the other.
+    ///
+    /// Each host is read with the current `Invocation` pointed at it, which is restored
+    /// afterwards. Hosts that can't be reached are kept, with the errors in their snapshot.
+    pub fn capture<S: AsRef<str>>(hosts: &[S]) -> Fleet {
+        let base = crate::invocation();
+        let mut fleet = Fleet::new();
+
+        for host in hosts {
+            let host = host.as_ref();
+            crate::set_invocation(Invocation {
+                host: Some(host.to_owned()),
+                ..base.clone()
+            });
+            let capture = SnapshotSpec::default().capture_timed();
+            let lagging = capture.lagging();
+            let sample = capture.sample;
+            // the limits differ between hosts, so the cached ones don't apply
+            let limits = TempLimits::query().unwrap_or_default();
+            let snapshot = Snapshot::from_sample(&sample, limits);
+            let snapshot = Snapshot {
+                errors: sample.errors,
+                lagging,
+                ..snapshot
+            };
+            fleet.insert(host, snapshot);
+        }
+
+        crate::set_invocation(base);
+        fleet
+    }
+
+    /// Add the last sample of the log at `path` as the snapshot of `host`, see `insert`.
+    ///
+    /// The temperature limits are taken from a logged `temp_headroom`, or are the firmware
+    /// defaults. Fails if the log can't be read or holds no sample.
+    pub fn ingest<P: AsRef<Path>>(&mut self, host: &str, path: P) -> io::Result<bool> {
+        let mut replay = Replay::open(path)?;
+        let last = replay.by_ref().last();
+        if let Some(error) = replay.error() {
+            return Err(io::Error::new(error.kind(), error.to_string()));
+        }
+        let sample = last
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the log holds no sample"))?;
+
+        let limits = match sample.get(Metric::TempHeadroom) {
+            Some(Reading::TempHeadroom(headroom)) => TempLimits {
+                soft: headroom.soft_limit,
+                hard: headroom.hard_limit,
+            },
+            _ => TempLimits::default(),
+        };
+        Ok(self.insert(host, Snapshot::from_sample(&sample, limits)))
+    }
+
+    /// Add `snapshot` as that of `host`, replacing an older one.
+    ///
+    /// Returns whether it was added, `false` if the host already has a newer snapshot.
+    pub fn insert(&mut self, host: &str, snapshot: Snapshot) -> bool {
+        let health = snapshot.health_with(&self.policy);
+        let labeled = HostSnapshot {
+            host: host.to_owned(),
+            snapshot,
+            health,
+        };
+
+        match self.position(host) {
+            Ok(index) => match self.hosts.get_mut(index) {
+                Some(known) if known.snapshot.timestamp <= labeled.snapshot.timestamp => {
+                    *known = labeled;
+                    true
+                }
+                _ => false,
+            },
+            Err(index) => {
+                self.hosts.insert(index, labeled);
+                true
+            }
+        }
+    }
+
+    /// Add the hosts of `other`, keeping the newer snapshot of hosts in both
+    pub fn merge(&mut self, other: Fleet) {
+        for host in other.hosts {
+            self.insert(&host.host, host.snapshot);
+        }
+    }
+
+    pub fn get(&self, host: &str) -> Option<&HostSnapshot> {
+        let index = self.position(host).ok()?;
+        self.hosts.get(index)
+    }
+
+    pub fn hosts(&self) -> &[HostSnapshot] {
+        &self.hosts
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    fn position(&self, host: &str) -> Result<usize, usize> {
+        self.hosts
+            .binary_search_by(|known| known.host.as_str().cmp(host))
+    }
+
+    /// Statistics across all hosts
+    pub fn summary(&self) -> FleetSummary {
+        let snapshots = || self.hosts.iter().map(|host| &host.snapshot);
+        let count = |state: &str| {
+            self.hosts
+                .iter()
+                .filter(|host| host.health.state() == state)
+                .count()
+        };
+
+        let hottest = self
+            .hosts
+            .iter()
+            .filter_map(|host| Some((host.host.as_str(), host.snapshot.temp?)))
+            .fold(
+                None,
+                |hottest: Option<(&str, f64)>, (host, temp)| match hottest {
+                    Some((_, max)) if max >= temp => hottest,
+                    _ => Some((host, temp)),
+                },
+            );
+
+        FleetSummary {
+            hosts: self.hosts.len(),
+            healthy: count("healthy"),
+            degraded: count("degraded"),
+            critical: count("critical"),
+            throttled: snapshots()
+                .filter(|s| {
+                    s.throttled
+                        .is_some_and(|b| interpret_bit_pattern(b).currently_throttled)
+                })
+                .count(),
+            temp: Stats::from_values(snapshots().filter_map(|s| s.temp)),
+            arm_clock: Stats::from_values(
+                snapshots().filter_map(|s| s.arm_clock.map(|hz| hz as f64)),
+            ),
+            core_volts: Stats::from_values(snapshots().filter_map(|s| s.core_volts)),
+            hottest: hottest.map(|(host, temp)| (host.to_owned(), temp)),
+        }
+    }
+
+    /// The snapshots as a JSON object keyed by host, along with the summary
+    pub fn to_json(&self) -> String {
+        let hosts: Vec<_> = self
+            .hosts
+            .iter()
+            .map(|host| (host.host.as_str(), host.snapshot.to_json()))
+            .collect();
+
+        json::object(&[
+            ("hosts", json::object(&hosts)),
+            ("summary", self.summary().to_json()),
+        ])
+    }
+}
+
+/// Statistics across the hosts of a `Fleet`, the ranges only cover hosts with a reading
+#[derive(Debug, Clone, PartialEq)]
+pub struct FleetSummary {
+    pub hosts: usize,
+    pub healthy: usize,
+    pub degraded: usize,
+    pub critical: usize,
+    /// Hosts that are throttled right now
+    pub throttled: usize,
+    /// Temperature in °C
+    pub temp: Option<Stats>,
+    /// ARM clock in Hz
+    pub arm_clock: Option<Stats>,
+    /// Core voltage in V
+    pub core_volts: Option<Stats>,
+    /// The host with the highest temperature and its temperature
+    pub hottest: Option<(String, f64)>,
+}
+
+impl FleetSummary {
+    pub fn to_json(&self) -> String {
+        let count = |value: usize| json::number(value as f64);
+        let stats = |stats: Option<Stats>| {
+            json::optional(stats, |stats| {
+                json::object(&[
+                    ("min", json::number(stats.min)),
+                    ("max", json::number(stats.max)),
+                    ("mean", json::number(stats.mean)),
+                    ("count", count(stats.count)),
+                ])
+            })
+        };
+
+        json::object(&[
+            ("hosts", count(self.hosts)),
+            ("healthy", count(self.healthy)),
+            ("degraded", count(self.degraded)),
+            ("critical", count(self.critical)),
+            ("throttled", count(self.throttled)),
+            ("temp", stats(self.temp)),
+            ("arm_clock", stats(self.arm_clock)),
+            ("core_volts", stats(self.core_volts)),
+            (
+                "hottest",
+                json::optional(self.hottest.as_ref(), |(host, temp)| {
+                    json::object(&[("host", json::string(host)), ("temp", json::number(*temp))])
+                }),
+            ),
+        ])
+    }
+}
+
+impl fmt::Display for FleetSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hosts: {} healthy, {} degraded, {} critical, {} throttled",
+            self.hosts, self.healthy, self.degraded, self.critical, self.throttled
+        )?;
+        if let Some(temp) = self.temp {
+            write!(
+                f,
+                ", temp {:.1}..{:.1} °C (mean {:.1})",
+                temp.min, temp.max, temp.mean
+            )?;
+        }
+        if let Some((host, temp)) = &self.hottest {
+            write!(f, ", hottest {} at {:.1} °C", host, temp)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn snapshot(secs: u64, temp: f64, throttled: isize) -> Snapshot {
+        Snapshot {
+            timestamp: UNIX_EPOCH + Duration::from_secs(secs),
+            temp: Some(temp),
+            temp_limits: TempLimits::default(),
+            throttled: Some(throttled),
+            arm_clock: Some(1_500_000_000),
+            core_clock: None,
+            core_volts: Some(0.85),
+            errors: Vec::new(),
+            lagging: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_insert_and_merge() {
+        let mut fleet = Fleet::new();
+        assert!(fleet.insert("pi5", snapshot(10, 52.0, 0)));
+        assert!(fleet.insert("pi4", snapshot(10, 61.0, 0x50005)));
+        assert!(!fleet.insert("pi5", snapshot(5, 40.0, 0)));
+
+        let mut other = Fleet::new();
+        other.insert("pi5", snapshot(20, 55.0, 0));
+        other.insert("pi-zero", snapshot(20, 45.0, 0));
+        fleet.merge(other);
+
+        let hosts: Vec<_> = fleet.hosts().iter().map(|h| h.host.as_str()).collect();
+        assert_eq!(vec!["pi-zero", "pi4", "pi5"], hosts);
+        assert_eq!(Some(55.0), fleet.get("pi5").and_then(|h| h.snapshot.temp));
+        assert_eq!("critical", fleet.get("pi4").unwrap().health.state());
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut fleet = Fleet::new();
+        fleet.insert("pi4", snapshot(0, 61.0, 0x50005));
+        fleet.insert("pi5", snapshot(0, 52.0, 0));
+        let mut unreachable = snapshot(0, 0.0, 0);
+        unreachable.temp = None;
+        unreachable.throttled = None;
+        fleet.insert("pi3", unreachable);
+
+        let summary = fleet.summary();
+        assert_eq!(
+            (3, 1, 1, 1),
+            (
+                summary.hosts,
+                summary.healthy,
+                summary.degraded,
+                summary.critical
+            )
+        );
+        assert_eq!(1, summary.throttled);
+        assert_eq!(Some((61.0, 56.5)), summary.temp.map(|t| (t.max, t.mean)));
+        assert_eq!(Some(("pi4".to_owned(), 61.0)), summary.hottest);
+        assert_eq!(
+            "3 hosts: 1 healthy, 1 degraded, 1 critical, 1 throttled, \
+             temp 52.0..61.0 °C (mean 56.5), hottest pi4 at 61.0 °C",
+            summary.to_string()
+        );
+        assert!(fleet.to_json().starts_with(r#"{"hosts":{"pi3":{"#));
+    }
+}
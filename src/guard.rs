@@ -0,0 +1,347 @@
+//! Telling whether a benchmark ran on a throttled SoC
+//!
+//! Results taken while the firmware capped the clocks measure the cooling or the power
+//! supply rather than the code. A `ThrottleGuard` reads the throttled flags, temperature
+//! and ARM clock right before a closure, keeps sampling them on a background thread while
+//! it runs, and reads them once more afterwards, so benchmark harnesses and CI device farms
+//! can discard or flag the affected runs.
+//!
+//! ```rust,no_run
+//! use vcgencmd::guard::ThrottleGuard;
+//!
+//! let (sum, report) = ThrottleGuard::new().run(|| (0..1_000_000u64).sum::<u64>())?;
+//! if report.throttled() {
+//!     eprintln!("throttled during the run, discarding it:\n{}", report);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::json;
+use crate::monitor::{wait_until, Metric, Monitor, Reading};
+use crate::profile::ProfilePoint;
+use crate::{interpret_bit_pattern, ClockSrc, Result};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches for throttling while running a closure, see the module documentation
+pub struct ThrottleGuard {
+    monitor: Monitor,
+}
+
+impl Default for ThrottleGuard {
+    fn default() -> ThrottleGuard {
+        ThrottleGuard::new()
+    }
+}
+
+impl ThrottleGuard {
+    /// Sample every 250 ms while the closure runs
+    pub fn new() -> ThrottleGuard {
+        let monitor = Monitor::new(DEFAULT_INTERVAL)
+            .metric(Metric::Temp)
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .metric(Metric::Throttled);
+
+        ThrottleGuard { monitor }
+    }
+
+    /// Sample every `interval` while the closure runs. Every sample invokes vcgencmd, which
+    /// is load on its own, so don't go much below the default for CPU-bound benchmarks.
+    pub fn interval(mut self, interval: Duration) -> ThrottleGuard {
+        self.monitor.set_interval(interval);
+        self
+    }
+
+    /// Replace the function used to read a metric, see `Monitor::sampler`
+    pub fn sampler<F>(mut self, sampler: F) -> ThrottleGuard
+    where
+        F: FnMut(Metric) -> Result<Reading> + Send + 'static,
+    {
+        self.monitor = self.monitor.sampler(sampler);
+        self
+    }
+
+    /// Run `work` on the calling thread, returning its result and what happened meanwhile.
+    ///
+    /// The first sample during the run is taken one interval after the start, so a closure
+    /// shorter than that is only covered by the readings before and after it, and the
+    /// sticky flags telling whether throttling occurred in between.
+    ///
+    /// Fails without running `work` if the sampling thread can't be spawned.
+    pub fn run<T, F: FnOnce() -> T>(mut self, work: F) -> io::Result<(T, GuardReport)> {
+        let before = ProfilePoint::from_sample(Duration::from_secs(0), &self.monitor.sample());
+        let start = Instant::now();
+
+        let stop = AtomicBool::new(false);
+        let monitor = &mut self.monitor;
+        let (result, during) = thread::scope(|scope| {
+            let sampling = thread::Builder::new().spawn_scoped(scope, || {
+                let mut during = Vec::new();
+                while !stop.load(Ordering::SeqCst) {
+                    if let Some(sample) = monitor.sample_due(Instant::now()) {
+                        during.push(ProfilePoint::from_sample(start.elapsed(), &sample));
+                    }
+                    wait_until(monitor.wake_at(), || stop.load(Ordering::SeqCst));
+                }
+                during
+            })?;
+
+            // stops sampling even if `work` panics, the scope would wait forever otherwise
+            let _stop = StopOnDrop(&stop);
+            let result = work();
+            stop.store(true, Ordering::SeqCst);
+
+            let during = sampling
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            Ok::<_, io::Error>((result, during))
+        })?;
+
+        let duration = start.elapsed();
+        let after = ProfilePoint::from_sample(duration, &self.monitor.sample());
+        let report = GuardReport {
+            duration,
+            before,
+            during,
+            after,
+        };
+
+        Ok((result, report))
+    }
+}
+
+struct StopOnDrop<'a>(&'a AtomicBool);
+
+impl Drop for StopOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// The readings around and during a guarded run
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardReport {
+    /// How long the closure ran
+    pub duration: Duration,
+    pub before: ProfilePoint,
+    /// The samples taken while the closure ran
+    pub during: Vec<ProfilePoint>,
+    pub after: ProfilePoint,
+}
+
+impl GuardReport {
+    fn points(&self) -> impl Iterator<Item = &ProfilePoint> {
+        Some(&self.before)
+            .into_iter()
+            .chain(&self.during)
+            .chain(Some(&self.after))
+    }
+
+    /// Whether the firmware held the clocks down at any point of the run, either seen in a
+    /// sample or told by a sticky "occurred" flag that was clear before and set after it,
+    /// under-voltage included
+    pub fn throttled(&self) -> bool {
+        self.during.iter().any(ProfilePoint::is_throttled)
+            || self.after.is_throttled()
+            || self.newly_occurred()
+    }
+
+    /// Whether a flag that only tells throttling or under-voltage occurred since boot was set
+    /// during the run
+    fn newly_occurred(&self) -> bool {
+        match (self.before.throttled, self.after.throttled) {
+            (Some(before), Some(after)) => {
+                let before = interpret_bit_pattern(before);
+                let after = interpret_bit_pattern(after);
+                (after.under_voltage_occurred && !before.under_voltage_occurred)
+                    || (after.throttling_occurred && !before.throttling_occurred)
+                    || (after.arm_frequency_cap_occurred && !before.arm_frequency_cap_occurred)
+                    || (after.soft_temp_limit_occurred && !before.soft_temp_limit_occurred)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the SoC was already throttled when the run started, which skews it from the
+    /// start
+    pub fn throttled_before(&self) -> bool {
+        self.before.is_throttled()
+    }
+
+    /// Highest temperature seen before, during or after the run
+    pub fn max_temp(&self) -> Option<f64> {
+        self.points().filter_map(|point| point.temp).fold(
+            None,
+            |max: Option<f64>, temp| match max {
+                Some(max) => Some(max.max(temp)),
+                None => Some(temp),
+            },
+        )
+    }
+
+    /// How far the temperature rose from the start to its maximum
+    pub fn temp_rise(&self) -> Option<f64> {
+        Some(self.max_temp()? - self.before.temp?)
+    }
+
+    /// Lowest ARM clock seen during or after the run
+    pub fn min_arm_clock(&self) -> Option<isize> {
+        self.during
+            .iter()
+            .chain(Some(&self.after))
+            .filter_map(|point| point.arm_clock)
+            .min()
+    }
+
+    /// Share of the samples during the run in which the firmware was throttling
+    pub fn throttled_fraction(&self) -> f64 {
+        if self.during.is_empty() {
+            return 0.0;
+        }
+
+        let throttled = self.during.iter().filter(|p| p.is_throttled()).count();
+        throttled as f64 / self.during.len() as f64
+    }
+
+    /// The summary as a JSON object, without the samples
+    pub fn to_json(&self) -> String {
+        json::object(&[
+            ("duration", json::number(self.duration.as_secs_f64())),
+            ("throttled", self.throttled().to_string()),
+            ("throttled_before", self.throttled_before().to_string()),
+            ("samples", json::number(self.during.len() as f64)),
+            ("max_temp", json::optional(self.max_temp(), json::number)),
+            ("temp_rise", json::optional(self.temp_rise(), json::number)),
+            (
+                "min_arm_clock",
+                json::optional(self.min_arm_clock(), |f| json::number(f as f64)),
+            ),
+            (
+                "throttled_fraction",
+                json::number(self.throttled_fraction()),
+            ),
+        ])
+    }
+}
+
+/// A human readable summary of the run
+impl fmt::Display for GuardReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "duration:   {:.3} s", self.duration.as_secs_f64())?;
+        writeln!(
+            f,
+            "throttled:  {}{}",
+            if self.throttled() { "yes" } else { "no" },
+            if self.throttled_before() {
+                ", already before the run"
+            } else {
+                ""
+            }
+        )?;
+        match self.max_temp() {
+            Some(temp) => writeln!(f, "max temp:   {:.1} °C", temp)?,
+            None => writeln!(f, "max temp:   n/a")?,
+        }
+        match self.min_arm_clock() {
+            Some(clock) => write!(f, "min clock:  {} MHz", clock / 1_000_000),
+            None => write!(f, "min clock:  n/a"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicIsize;
+    use std::sync::Arc;
+
+    /// A sampler reporting `throttled` as the bit pattern
+    fn sampler(throttled: Arc<AtomicIsize>) -> impl FnMut(Metric) -> Result<Reading> + Send {
+        move |metric| {
+            Ok(match metric {
+                Metric::Temp => Reading::Temp(50.0),
+                Metric::Clock(src) => Reading::Clock(src, 1_500_000_000),
+                _ => Reading::Throttled(throttled.load(Ordering::SeqCst)),
+            })
+        }
+    }
+
+    #[test]
+    fn test_not_throttled() {
+        let throttled = Arc::new(AtomicIsize::new(0));
+        let (value, report) = ThrottleGuard::new()
+            .interval(Duration::from_millis(10))
+            .sampler(sampler(throttled))
+            .run(|| {
+                thread::sleep(Duration::from_millis(50));
+                42
+            })
+            .unwrap();
+
+        assert_eq!(42, value);
+        assert!(!report.throttled());
+        assert!(!report.during.is_empty());
+        assert_eq!(Some(50.0), report.max_temp());
+        assert_eq!(Some(0.0), report.temp_rise());
+        assert_eq!(Some(1_500_000_000), report.min_arm_clock());
+        assert!(report.to_json().contains(r#""throttled":false"#));
+    }
+
+    #[test]
+    fn test_throttled_between_samples() {
+        let throttled = Arc::new(AtomicIsize::new(0));
+        let flag = Arc::clone(&throttled);
+        // a long interval, so only the sticky bit tells
+        let (_, report) = ThrottleGuard::new()
+            .interval(Duration::from_secs(60))
+            .sampler(sampler(throttled))
+            .run(|| flag.store(0x40000, Ordering::SeqCst))
+            .unwrap();
+
+        assert!(report.during.is_empty());
+        assert!(!report.after.is_throttled());
+        assert!(report.throttled());
+        assert!(!report.throttled_before());
+    }
+
+    #[test]
+    fn test_under_voltage_between_samples() {
+        let throttled = Arc::new(AtomicIsize::new(0x20000));
+        let flag = Arc::clone(&throttled);
+        let guard = || {
+            ThrottleGuard::new()
+                .interval(Duration::from_secs(60))
+                .sampler(sampler(Arc::clone(&throttled)))
+        };
+
+        // under-voltage that occurred before the run doesn't count against it
+        let (_, report) = guard().run(|| ()).unwrap();
+        assert!(!report.throttled());
+
+        flag.store(0, Ordering::SeqCst);
+        let (_, report) = guard()
+            .run(|| flag.store(0x10000, Ordering::SeqCst))
+            .unwrap();
+        assert!(report.throttled());
+        assert!(!report.after.is_throttled());
+    }
+
+    #[test]
+    fn test_panicking_work_stops_sampling() {
+        let throttled = Arc::new(AtomicIsize::new(0));
+        let guard = ThrottleGuard::new()
+            .interval(Duration::from_millis(10))
+            .sampler(sampler(throttled));
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            guard.run(|| panic!("benchmark failed"))
+        }));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! A single traffic-light value summarising a `Snapshot`
+
+use std::fmt;
+
+use crate::alert::Severity;
+use crate::events::Condition;
+use crate::interpret_bit_pattern;
+use crate::monitor::Metric;
+use crate::snapshot::Snapshot;
+use crate::thermal::TempHeadroom;
+
+/// Why a snapshot isn't healthy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HealthReason {
+    /// A condition reported by `get_throttled` is active right now
+    Active(Condition),
+    /// A condition was active at some point since boot, but isn't anymore
+    Occurred(Condition),
+    /// The temperature exceeds the soft limit or is close to the hard limit
+    Temp(TempHeadroom),
+    /// The core voltage in V lies outside of `HealthPolicy::core_volts`
+    CoreVolts(f64),
+    /// A metric needed for the assessment couldn't be read
+    Unreadable(Metric),
+}
+
+impl HealthReason {
+    /// How bad this reason is on its own under `policy`
+    pub fn severity(&self, policy: &HealthPolicy) -> Severity {
+        match *self {
+            HealthReason::Active(Condition::UnderVoltage)
+            | HealthReason::Active(Condition::Throttled) => Severity::Critical,
+            HealthReason::Temp(headroom)
+                if headroom.to_hard_limit() <= policy.hard_limit_margin =>
+            {
+                Severity::Critical
+            }
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for HealthReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthReason::Active(condition) => write!(f, "{} active", condition.name()),
+            HealthReason::Occurred(condition) => {
+                write!(f, "{} occurred since boot", condition.name())
+            }
+            HealthReason::Temp(headroom) => write!(
+                f,
+                "temperature {:.1} °C, {:.1} °C below the hard limit",
+                headroom.temp,
+                headroom.to_hard_limit()
+            ),
+            HealthReason::CoreVolts(volts) => write!(f, "core voltage {:.4} V out of range", volts),
+            HealthReason::Unreadable(metric) => write!(f, "{:?} unreadable", metric),
+        }
+    }
+}
+
+/// Thresholds used to assess a snapshot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthPolicy {
+    /// Degrees below the hard temperature limit at which the state becomes critical,
+    /// 5 °C by default
+    pub hard_limit_margin: f64,
+    /// Acceptable range of the core voltage, unchecked by default as it depends on the
+    /// model and on `over_voltage`
+    pub core_volts: Option<(f64, f64)>,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> HealthPolicy {
+        HealthPolicy {
+            hard_limit_margin: 5.0,
+            core_volts: None,
+        }
+    }
+}
+
+/// The overall state of a device, with all reasons attached when it isn't healthy
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthSummary {
+    Healthy,
+    Degraded(Vec<HealthReason>),
+    Critical(Vec<HealthReason>),
+}
+
+impl HealthSummary {
+    /// Assess `snapshot` under `policy`.
+    ///
+    /// The summary is as bad as its worst reason, and lists every reason found.
+    pub fn assess(snapshot: &Snapshot, policy: &HealthPolicy) -> HealthSummary {
+        let mut reasons = Vec::new();
+
+        match snapshot.throttled {
+            Some(bit_pattern) => {
+                let status = interpret_bit_pattern(bit_pattern);
+                for &condition in &Condition::ALL {
+                    if condition.is_active(&status) {
+                        reasons.push(HealthReason::Active(condition));
+                    } else if condition.has_occurred(&status) {
+                        reasons.push(HealthReason::Occurred(condition));
+                    }
+                }
+            }
+            None => reasons.push(HealthReason::Unreadable(Metric::Throttled)),
+        }
+
+        match snapshot.temp_headroom() {
+            Some(headroom) => {
+                if headroom.to_soft_limit() < 0.0
+                    || headroom.to_hard_limit() <= policy.hard_limit_margin
+                {
+                    reasons.push(HealthReason::Temp(headroom));
+                }
+            }
+            None => reasons.push(HealthReason::Unreadable(Metric::Temp)),
+        }
+
+        if let (Some((min, max)), Some(volts)) = (policy.core_volts, snapshot.core_volts) {
+            if volts < min || volts > max {
+                reasons.push(HealthReason::CoreVolts(volts));
+            }
+        }
+
+        let worst = reasons.iter().map(|reason| reason.severity(policy)).max();
+        match worst {
+            None => HealthSummary::Healthy,
+            Some(Severity::Critical) => HealthSummary::Critical(reasons),
+            Some(_) => HealthSummary::Degraded(reasons),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        *self == HealthSummary::Healthy
+    }
+
+    /// The severity matching the state, `None` when healthy
+    pub fn severity(&self) -> Option<Severity> {
+        match self {
+            HealthSummary::Healthy => None,
+            HealthSummary::Degraded(_) => Some(Severity::Warning),
+            HealthSummary::Critical(_) => Some(Severity::Critical),
+        }
+    }
+
+    /// `healthy`, `degraded` or `critical`
+    pub fn state(&self) -> &'static str {
+        match self {
+            HealthSummary::Healthy => "healthy",
+            HealthSummary::Degraded(_) => "degraded",
+            HealthSummary::Critical(_) => "critical",
+        }
+    }
+
+    /// The reasons attached, empty when healthy
+    pub fn reasons(&self) -> &[HealthReason] {
+        match self {
+            HealthSummary::Healthy => &[],
+            HealthSummary::Degraded(reasons) | HealthSummary::Critical(reasons) => reasons,
+        }
+    }
+}
+
+impl fmt::Display for HealthSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_healthy() {
+            return f.write_str(self.state());
+        }
+
+        let reasons: Vec<_> = self.reasons().iter().map(|r| r.to_string()).collect();
+        write!(f, "{}: {}", self.state(), reasons.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thermal::TempLimits;
+    use std::time::SystemTime;
+
+    fn snapshot(temp: f64, throttled: isize) -> Snapshot {
+        Snapshot {
+            timestamp: SystemTime::now(),
+            temp: Some(temp),
+            temp_limits: TempLimits::default(),
+            throttled: Some(throttled),
+            arm_clock: Some(1_500_000_000),
+            core_clock: Some(500_000_000),
+            core_volts: Some(0.86),
+            errors: Vec::new(),
+            lagging: Vec::new(),
+        }
+    }
+
+    /// The bit pattern with only `condition` set, either active or occurred
+    fn pattern(condition: Condition, active: bool) -> isize {
+        (0..21)
+            .map(|bit| 1 << bit)
+            .find(|&bit| {
+                let status = interpret_bit_pattern(bit);
+                if active {
+                    condition.is_active(&status)
+                } else {
+                    condition.has_occurred(&status) && !condition.is_active(&status)
+                }
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_healthy() {
+        assert_eq!(HealthSummary::Healthy, snapshot(45.0, 0).health());
+        assert_eq!("healthy", snapshot(45.0, 0).health().to_string());
+    }
+
+    #[test]
+    fn test_degraded() {
+        let occurred = pattern(Condition::UnderVoltage, false);
+        assert_eq!(
+            HealthSummary::Degraded(vec![HealthReason::Occurred(Condition::UnderVoltage)]),
+            snapshot(45.0, occurred).health()
+        );
+
+        let warm = snapshot(65.0, 0).health();
+        assert!(matches!(warm, HealthSummary::Degraded(_)));
+        assert!(matches!(warm.reasons()[0], HealthReason::Temp(_)));
+    }
+
+    #[test]
+    fn test_critical() {
+        let under_voltage = pattern(Condition::UnderVoltage, true);
+        let health = snapshot(45.0, under_voltage).health();
+        assert!(matches!(health, HealthSummary::Critical(_)));
+        assert!(health
+            .reasons()
+            .contains(&HealthReason::Active(Condition::UnderVoltage)));
+
+        assert!(matches!(
+            snapshot(81.0, 0).health(),
+            HealthSummary::Critical(_)
+        ));
+        assert_eq!(Some(Severity::Critical), health.severity());
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut snapshot = snapshot(45.0, 0);
+        snapshot.throttled = None;
+        let policy = HealthPolicy {
+            core_volts: Some((0.9, 1.0)),
+            ..HealthPolicy::default()
+        };
+
+        assert_eq!(
+            HealthSummary::Degraded(vec![
+                HealthReason::Unreadable(Metric::Throttled),
+                HealthReason::CoreVolts(0.86)
+            ]),
+            snapshot.health_with(&policy)
+        );
+    }
+}
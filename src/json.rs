@@ -0,0 +1,307 @@
+//! Just enough JSON for the reports and for reading logs back, without pulling in serde
+//!
+//! Values are passed around as already encoded strings, so nesting is a matter of handing
+//! the output of one function to another. `parse` reads a document into a `Value`.
+
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Encode a string, escaping quotes, backslashes and control characters
+pub(crate) fn string(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len() + 2);
+    encoded.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            '\t' => encoded.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(encoded, "\\u{:04x}", c as u32);
+            }
+            c => encoded.push(c),
+        }
+    }
+
+    encoded.push('"');
+    encoded
+}
+
+/// Encode a number, JSON has no representation for NaN and infinity so those become `null`
+pub(crate) fn number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        null()
+    }
+}
+
+pub(crate) fn null() -> String {
+    "null".to_owned()
+}
+
+/// Encode `value` or `null`
+pub(crate) fn optional<T, F: FnOnce(T) -> String>(value: Option<T>, encode: F) -> String {
+    value.map_or_else(null, encode)
+}
+
+/// Join already encoded values to an array
+pub(crate) fn array<I: IntoIterator<Item = String>>(values: I) -> String {
+    format!("[{}]", values.into_iter().collect::<Vec<_>>().join(","))
+}
+
+/// Join already encoded values to an object, keeping the order of `fields`
+pub(crate) fn object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<_> = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{}", string(key), value))
+        .collect();
+
+    format!("{{{}}}", fields.join(","))
+}
+
+/// A parsed JSON value, objects keep their fields in order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The field `name` of an object, `None` for other values
+    pub(crate) fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string.as_str()),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a single JSON document, surrounding whitespace is ignored
+pub(crate) fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: input.chars().peekable(),
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+
+    match parser.chars.next() {
+        Some(c) => Err(format!("unexpected '{}' after the value", c)),
+        None => Ok(value),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}', found the end", expected)),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        keyword.chars().try_for_each(|c| self.expect(c))?;
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(format!("unexpected '{}'", c)),
+            None => Err("unexpected end".to_owned()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(Value::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let name = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((name, self.value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(fields)),
+                _ => return Err("expected ',' or '}' in an object".to_owned()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err("expected ',' or ']' in an array".to_owned()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut number = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+        {
+            number.push(c);
+        }
+
+        number
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid number '{}'", number))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(self.escape()?),
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_owned()),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = match self.chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('u') => {
+                let high = self.hex4()?;
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    // the high half of a surrogate pair, the low half follows as `\uXXXX`
+                    self.expect('\\')?;
+                    self.expect('u')?;
+                    let low = self.hex4()?;
+                    0x10000 + ((high - 0xd800) << 10) + low.wrapping_sub(0xdc00)
+                } else {
+                    high
+                };
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            Some(c) if "\"\\/".contains(c) => c,
+            Some(c) => return Err(format!("invalid escape '\\{}'", c)),
+            None => return Err("unterminated string".to_owned()),
+        };
+
+        Ok(c)
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = (0..4).filter_map(|_| self.chars.next()).collect();
+        u32::from_str_radix(&digits, 16).map_err(|_| format!("invalid escape '\\u{}'", digits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(r#""a \"b\"\\\n\u0001""#, string("a \"b\"\\\n\u{1}"));
+        assert_eq!("48.3", number(48.3));
+        assert_eq!("null", number(f64::NAN));
+        assert_eq!("null", optional(None::<f64>, number));
+        assert_eq!(
+            r#"{"temp":48.3,"clocks":[1,2]}"#,
+            object(&[
+                ("temp", number(48.3)),
+                ("clocks", array(vec![number(1.0), number(2.0)])),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let value = parse(
+            r#" {"temp": 48.3, "ok": true, "none": null,
+                "clocks": [1, -2.5e3], "name": "a \"b\"\n\u00e9\ud83d\ude00"} "#,
+        )
+        .unwrap();
+
+        assert_eq!(Some(48.3), value.get("temp").and_then(Value::as_f64));
+        assert_eq!(Some(&Value::Bool(true)), value.get("ok"));
+        assert_eq!(Some(&Value::Null), value.get("none"));
+        assert_eq!(
+            Some(&Value::Array(vec![
+                Value::Number(1.0),
+                Value::Number(-2500.0)
+            ])),
+            value.get("clocks")
+        );
+        assert_eq!(
+            Some("a \"b\"\n\u{e9}\u{1f600}"),
+            value.get("name").and_then(Value::as_str)
+        );
+        assert_eq!(Ok(Value::Object(Vec::new())), parse("{}"));
+
+        assert!(parse(r#"{"temp": 48.3"#).is_err());
+        assert!(parse(r#"{"temp": 48.3} x"#).is_err());
+        assert!(parse("tru").is_err());
+        assert!(parse(r#""\x""#).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! Logging samples as JSON Lines, one `Sample::to_json` object per line
+//!
+//! The format is easy to append to, to `tail -f` and to load into most analysis tools.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::monitor::Sample;
+use crate::sink::{self, MetricSink};
+
+/// Writes every sample as a line of JSON
+pub struct JsonlSink<W: Write> {
+    writer: W,
+}
+
+impl JsonlSink<File> {
+    /// Append to the file at `path`, creating it if necessary
+    pub fn append<P: AsRef<Path>>(path: P) -> io::Result<JsonlSink<File>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonlSink::new(file))
+    }
+}
+
+impl JsonlSink<io::Stdout> {
+    pub fn stdout() -> JsonlSink<io::Stdout> {
+        JsonlSink::new(io::stdout())
+    }
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W) -> JsonlSink<W> {
+        JsonlSink { writer }
+    }
+
+    /// Write `sample` as a single line and flush, so readers never see a partial line
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        writeln!(self.writer, "{}", sample.to_json())?;
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send> MetricSink for JsonlSink<W> {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        JsonlSink::write(self, sample)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Send + 'static> JsonlSink<W> {
+    /// Turn this into a plain monitor sink. A failing write loses its line, add the sink
+    /// with `Monitor::metric_sink` to have failures counted.
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::Reading;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_write() {
+        let sample = Sample {
+            timestamp: UNIX_EPOCH + Duration::from_secs(60),
+            readings: vec![Reading::Temp(51.0), Reading::Throttled(0x50005)],
+            errors: Vec::new(),
+        };
+
+        let mut sink = JsonlSink::new(Vec::new());
+        sink.write(&sample).unwrap();
+        sink.write(&sample).unwrap();
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(
+            r#"{"timestamp":"1970-01-01T00:01:00.000Z","readings":{"temp":51,"throttled":327685},"errors":{}}"#,
+            lines[0]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Sharing the latest readings of a monitor with any number of threads
+//!
+//! Web handlers and UI threads usually want the current value of a metric rather than every
+//! sample. `LatestReadings` is a cheap handle to clone and read from: after every sample the
+//! monitor thread publishes a new, immutable `Readings`, and readers only clone the `Arc`
+//! pointing to it. The lock guarding that pointer is held for nothing but the clone or the
+//! swap, so a reader never waits for a sample to be taken or for another reader.
+//!
+//! ```rust,no_run
+//! use std::thread;
+//! use std::time::Duration;
+//! use vcgencmd::monitor::{Metric, Monitor, Reading};
+//!
+//! let mut monitor = Monitor::new(Duration::from_secs(1)).metric(Metric::Temp);
+//! let latest = monitor.latest_readings();
+//! let handle = monitor.start()?;
+//!
+//! thread::spawn(move || {
+//!     if let Some(Reading::Temp(temp)) = latest.get(Metric::Temp) {
+//!         println!("{} °C", temp);
+//!     }
+//! });
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use crate::monitor::{Metric, Monitor, MonitorHandle, Reading, Sample};
+
+/// The latest reading of every metric, as published after a sample
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Readings {
+    /// Time of the last sample, `None` before the first one
+    pub timestamp: Option<SystemTime>,
+    /// Every metric read so far, with the time of the sample it was last read in. A metric
+    /// failing to read keeps its previous reading and time.
+    pub readings: Vec<(Reading, SystemTime)>,
+}
+
+impl Readings {
+    /// The latest reading of `metric`
+    pub fn get(&self, metric: Metric) -> Option<&Reading> {
+        self.find(metric).map(|(reading, _)| reading)
+    }
+
+    /// When `metric` was last read
+    pub fn updated(&self, metric: Metric) -> Option<SystemTime> {
+        self.find(metric).map(|&(_, at)| at)
+    }
+
+    fn find(&self, metric: Metric) -> Option<&(Reading, SystemTime)> {
+        self.readings.iter().find(|(r, _)| r.metric() == metric)
+    }
+
+    /// These readings updated with the ones of `sample`
+    fn merge(&self, sample: &Sample) -> Readings {
+        let mut merged = self.clone();
+        merged.timestamp = Some(sample.timestamp);
+        for reading in &sample.readings {
+            let latest = (*reading, sample.timestamp);
+            match merged
+                .readings
+                .iter_mut()
+                .find(|(r, _)| r.metric() == reading.metric())
+            {
+                Some(known) => *known = latest,
+                None => merged.readings.push(latest),
+            }
+        }
+
+        merged
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    current: RwLock<Arc<Readings>>,
+    samples: AtomicU64,
+}
+
+/// A handle to the latest readings of a monitor, see the module documentation.
+///
+/// Clones share the same readings.
+#[derive(Debug, Clone, Default)]
+pub struct LatestReadings {
+    shared: Arc<Shared>,
+}
+
+impl LatestReadings {
+    /// The readings as of the last sample
+    pub fn load(&self) -> Arc<Readings> {
+        let current = self
+            .shared
+            .current
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        Arc::clone(&current)
+    }
+
+    /// The latest reading of `metric`
+    pub fn get(&self, metric: Metric) -> Option<Reading> {
+        self.load().get(metric).copied()
+    }
+
+    /// The number of samples published so far.
+    ///
+    /// Reading it takes no lock at all, so pollers can check it to only `load` on changes.
+    pub fn samples(&self) -> u64 {
+        self.shared.samples.load(Ordering::Acquire)
+    }
+
+    /// Publish the readings of `sample`, only ever called from the monitor thread
+    fn publish(&self, sample: &Sample) {
+        let merged = Arc::new(self.load().merge(sample));
+
+        let mut current = self
+            .shared
+            .current
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        let previous = mem::replace(&mut *current, merged);
+        drop(current);
+        // the last reader of the previous readings may be this thread, free them unlocked
+        drop(previous);
+
+        self.shared.samples.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl Monitor {
+    /// A handle to the latest readings, updated after every sample from now on
+    pub fn latest_readings(&mut self) -> LatestReadings {
+        let latest = LatestReadings::default();
+        let publisher = latest.clone();
+        self.add_sink(move |sample| publisher.publish(sample));
+        latest
+    }
+}
+
+impl MonitorHandle {
+    /// A handle to the latest readings of the running monitor, see `Monitor::latest_readings`.
+    ///
+    /// It stays empty until the monitor took its next sample.
+    pub fn latest_readings(&self) -> LatestReadings {
+        let latest = LatestReadings::default();
+        let publisher = latest.clone();
+        self.reconfigure(move |monitor| {
+            monitor.add_sink(move |sample| publisher.publish(sample));
+        });
+        latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClockSrc, Error};
+    use std::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_publish() {
+        let latest = LatestReadings::default();
+        assert_eq!(Readings::default(), *latest.load());
+
+        let first = UNIX_EPOCH + Duration::from_secs(1);
+        latest.publish(&Sample {
+            timestamp: first,
+            readings: vec![Reading::Temp(48.0), Reading::Throttled(0)],
+            errors: Vec::new(),
+        });
+        let second = UNIX_EPOCH + Duration::from_secs(2);
+        latest.publish(&Sample {
+            timestamp: second,
+            readings: vec![Reading::Temp(49.5)],
+            errors: vec![(
+                Metric::Throttled,
+                Error::ParseInt {
+                    command: "get_throttled".to_owned(),
+                    source: "x".parse::<isize>().unwrap_err(),
+                },
+            )],
+        });
+
+        let readings = latest.load();
+        assert_eq!(2, latest.samples());
+        assert_eq!(Some(second), readings.timestamp);
+        assert_eq!(Some(Reading::Temp(49.5)), latest.get(Metric::Temp));
+        assert_eq!(
+            Some(&Reading::Throttled(0)),
+            readings.get(Metric::Throttled)
+        );
+        assert_eq!(Some(first), readings.updated(Metric::Throttled));
+        assert_eq!(None, readings.get(Metric::Clock(ClockSrc::Arm)));
+    }
+
+    #[test]
+    fn test_read_from_other_thread() {
+        let mut monitor = Monitor::new(Duration::from_millis(10))
+            .metric(Metric::Temp)
+            .sampler(|_| Ok(Reading::Temp(51.0)));
+        let latest = monitor.latest_readings();
+        let handle = monitor.start().unwrap();
+
+        let reader = latest.clone();
+        let read = thread::spawn(move || {
+            while reader.samples() == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            reader.get(Metric::Temp)
+        });
+
+        assert_eq!(Some(Reading::Temp(51.0)), read.join().unwrap());
+        handle.join().unwrap();
+    }
+}
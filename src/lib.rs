@@ -1,22 +1,127 @@
 //! # Bindings for the RaspberryPi's vcgencmd cli utility
+//!
+//! The library never panics on its own, whatever vcgencmd or the firmware prints: output
+//! that doesn't parse is returned as an `Error`, and running out of threads as an
+//! `io::Error`. Only a panic in a closure passed in, e.g. a sink, can still unwind. This
+//! is enforced by denying the panicking clippy lints outside of tests.
 
-use std::num::{ParseFloatError, ParseIntError};
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::panic,
+        clippy::indexing_slicing,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::unreachable
+    )
+)]
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::Duration;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use subprocess::{Exec, PopenError, Redirection};
+use subprocess::PopenError;
 
 use bitpat::bitpat;
 
+pub mod alert;
+pub mod anomaly;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub mod asynchronous;
+pub mod average;
+#[cfg(all(target_os = "linux", feature = "bcm-host"))]
+pub mod bcm_host;
+pub mod boot;
+pub mod calibrate;
+#[cfg(feature = "chat")]
+pub mod chat;
+mod child;
+pub mod client;
+#[cfg(any(feature = "postcard", feature = "cbor"))]
+pub mod compact;
+pub mod component;
+pub mod config;
+pub mod container;
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(unix)]
+pub mod daemon;
+pub mod devices;
+pub mod display;
+pub mod downsample;
+pub mod dvfs;
+#[cfg(feature = "email")]
+pub mod email;
+pub mod error;
+pub mod events;
+pub mod executor;
+pub mod fleet;
+pub mod guard;
+pub mod health;
+mod json;
+#[cfg(feature = "jsonl")]
+pub mod jsonl;
+pub mod latest;
+#[cfg(all(target_os = "linux", feature = "mailbox"))]
+pub mod mailbox;
+#[cfg(feature = "test-util")]
+pub mod mock;
+pub mod monitor;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nagios")]
+pub mod nagios;
+pub mod overclock;
 mod parsers;
+pub mod pipeline;
+pub mod power;
+pub mod predict;
+pub mod profile;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod quick;
+pub mod replay;
+pub mod retry;
+pub mod session;
+pub mod sink;
+pub mod snapshot;
+#[cfg(feature = "snmp")]
+pub mod snmp;
+#[cfg(all(unix, feature = "ssh"))]
+pub mod ssh;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub mod stream;
+pub mod sysfs;
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+pub mod systemd;
+pub mod thermal;
+pub mod throttled;
+mod timefmt;
+pub mod undervolt;
+pub mod units;
+pub mod verify;
+#[cfg(feature = "zabbix")]
+pub mod zabbix;
 
-#[derive(Debug)]
-pub enum ExecutionError {
-    Popen(PopenError),
-    ParseInt(ParseIntError),
-    ParseFloat(ParseFloatError),
-}
+pub use client::{Vcgencmd, VcgencmdBuilder};
+use devices::DeviceNodes;
+use display::{HdmiTimings, LcdInfo};
+use error::ParseError;
+pub use error::{Error, ErrorKind, ExecutionError, Result};
+/// Derive a `capture(&client)` constructor for a struct of metrics, see `vcgencmd_derive`
+#[cfg(feature = "derive")]
+pub use vcgencmd_derive::VcSnapshot;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum ClockSrc {
     Arm,
     Core,
@@ -32,6 +137,25 @@ pub enum ClockSrc {
     Vec,
 }
 
+impl ClockSrc {
+    pub const ALL: [ClockSrc; 12] = [
+        ClockSrc::Arm,
+        ClockSrc::Core,
+        ClockSrc::Dpi,
+        ClockSrc::Emmc,
+        ClockSrc::H264,
+        ClockSrc::Hdmi,
+        ClockSrc::Isp,
+        ClockSrc::Pixel,
+        ClockSrc::Pwm,
+        ClockSrc::Uart,
+        ClockSrc::V3d,
+        ClockSrc::Vec,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum VoltSrc {
     Core,
     SdramC,
@@ -39,28 +163,85 @@ pub enum VoltSrc {
     SdramP,
 }
 
+impl VoltSrc {
+    pub const ALL: [VoltSrc; 4] = [
+        VoltSrc::Core,
+        VoltSrc::SdramC,
+        VoltSrc::SdramI,
+        VoltSrc::SdramP,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum MemSrc {
     Arm,
     Gpu,
+    /// Free memory of the GPU's malloc heap
+    Malloc,
+    MallocTotal,
+    /// Free memory of the GPU's relocatable heap
+    Reloc,
+    RelocTotal,
+}
+
+impl MemSrc {
+    pub const ALL: [MemSrc; 6] = [
+        MemSrc::Arm,
+        MemSrc::Gpu,
+        MemSrc::Malloc,
+        MemSrc::MallocTotal,
+        MemSrc::Reloc,
+        MemSrc::RelocTotal,
+    ];
 }
 
+/// Options from `config.txt` that can be read back with `get_config`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConfigSrc {
+    ArmFreq,
+    CoreFreq,
+    GpuFreq,
+    GpuMem,
+    OverVoltage,
+    SdramFreq,
+    TempLimit,
+    TempSoftLimit,
+    TotalMem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Src {
     Clock(ClockSrc),
+    Config(ConfigSrc),
+    /// A display id, see `display`
+    Display(u8),
     Mem(MemSrc),
     Volt(VoltSrc),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum Cmd {
+    Commands,
+    DisplayPower,
+    GetConfig,
+    GetLcdInfo,
     GetMem,
     GetThrottled,
+    HdmiTimings,
     MeasureClock,
     MeasureTemp,
     MeasureVolts,
+    MemRelocStats,
+    PmicReadAdc,
+    Version,
 }
 
 /// This struct represents the possible information in a bit-pattern you would get
 /// from the get_throttled command.
-#[derive(Debug, Default, PartialOrd, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialOrd, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct ThrottledStatus {
     pub arm_frequency_cap_occurred: bool,
@@ -73,94 +254,821 @@ pub struct ThrottledStatus {
     pub under_voltage_occurred: bool,
 }
 
+/// Whether an ADC channel of the PMIC measures a current or a voltage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub enum AdcKind {
+    Current,
+    Voltage,
+}
+
+/// A single line of `pmic_read_adc` output, only available on the Raspberry Pi 5.
+///
+/// `rail` is the name of the supply rail with the `_A`/`_V` suffix stripped, so current
+/// and voltage channels of the same rail share it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct AdcChannel {
+    pub rail: String,
+    pub kind: AdcKind,
+    /// In A or V, depending on `kind`
+    pub value: f64,
+}
+
+/// The voltages of all rails of `VoltSrc` in V, `None` for those that couldn't be measured,
+/// like the SDRAM rails on the Raspberry Pi 5
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct VoltRails {
+    pub core: Option<f64>,
+    pub sdram_c: Option<f64>,
+    pub sdram_i: Option<f64>,
+    pub sdram_p: Option<f64>,
+}
+
+impl VoltRails {
+    pub fn get(&self, src: VoltSrc) -> Option<f64> {
+        match src {
+            VoltSrc::Core => self.core,
+            VoltSrc::SdramC => self.sdram_c,
+            VoltSrc::SdramI => self.sdram_i,
+            VoltSrc::SdramP => self.sdram_p,
+        }
+    }
+}
+
+/// e.g. `core 0.850 V, sdram_c n/a, ...`
+impl fmt::Display for VoltRails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rails = [
+            ("core", self.core),
+            ("sdram_c", self.sdram_c),
+            ("sdram_i", self.sdram_i),
+            ("sdram_p", self.sdram_p),
+        ];
+        for (index, (name, volts)) in rails.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            match volts {
+                Some(volts) => write!(f, "{} {}", name, units::Volts(*volts))?,
+                None => write!(f, "{} n/a", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the memory is split between the ARM and the GPU, in MB
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct MemSplit {
+    pub arm: isize,
+    pub gpu: isize,
+    /// The sum of both
+    pub total: isize,
+}
+
+impl MemSplit {
+    pub fn new(arm: isize, gpu: isize) -> MemSplit {
+        MemSplit {
+            arm,
+            gpu,
+            total: arm + gpu,
+        }
+    }
+
+    /// The share of the ARM in the total, between 0 and 1
+    pub fn arm_ratio(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => self.arm as f64 / total as f64,
+        }
+    }
+
+    /// The share of the GPU in the total, between 0 and 1
+    pub fn gpu_ratio(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => self.gpu as f64 / total as f64,
+        }
+    }
+}
+
+/// e.g. `arm 948 MiB, gpu 76 MiB of 1.0 GiB`
+impl fmt::Display for MemSplit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "arm {}, gpu {} of {}",
+            units::MiB(self.arm),
+            units::MiB(self.gpu),
+            units::MiB(self.total)
+        )
+    }
+}
+
+/// The counters of `mem_reloc_stats`, accumulated since boot
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct RelocStats {
+    pub alloc_failures: isize,
+    pub compactions: isize,
+    pub legacy_block_fails: isize,
+}
+
+/// How much of the GPU's heaps is in use, in MB, together with the `RelocStats`.
+///
+/// Camera and video pipelines allocate from the relocatable heap, once it runs full or
+/// too fragmented they fail to allocate buffers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct GpuMemoryPressure {
+    pub malloc_total: isize,
+    pub malloc_free: isize,
+    pub reloc_total: isize,
+    pub reloc_free: isize,
+    pub reloc_stats: RelocStats,
+}
+
+impl GpuMemoryPressure {
+    /// The share of both heaps in use, in percent
+    pub fn utilization(&self) -> f64 {
+        let total = self.malloc_total + self.reloc_total;
+        let free = self.malloc_free + self.reloc_free;
+        match total {
+            0 => 0.0,
+            total => (total - free) as f64 / total as f64 * 100.0,
+        }
+    }
+
+    /// Whether allocations failed although the relocatable heap still has free memory,
+    /// i.e. the free memory is too scattered for the blocks asked for
+    pub fn is_fragmented(&self) -> bool {
+        let failures = self.reloc_stats.alloc_failures + self.reloc_stats.legacy_block_fails;
+        failures > 0 && self.reloc_free > 0
+    }
+}
+
 impl ThrottledStatus {
     pub fn new(bit_pattern: isize) -> ThrottledStatus {
         interpret_bit_pattern(bit_pattern)
     }
+
+    /// The status as a JSON object, with the same field names as the serde representation
+    pub fn to_json(&self) -> String {
+        let flag = |set: bool| set.to_string();
+
+        json::object(&[
+            (
+                "arm_frequency_cap_occurred",
+                flag(self.arm_frequency_cap_occurred),
+            ),
+            ("arm_frequency_capped", flag(self.arm_frequency_capped)),
+            ("currently_throttled", flag(self.currently_throttled)),
+            ("soft_temp_limit_active", flag(self.soft_temp_limit_active)),
+            (
+                "soft_temp_limit_occurred",
+                flag(self.soft_temp_limit_occurred),
+            ),
+            ("throttling_occurred", flag(self.throttling_occurred)),
+            ("under_voltage", flag(self.under_voltage)),
+            ("under_voltage_occurred", flag(self.under_voltage_occurred)),
+        ])
+    }
 }
 
-/// Execute the given command and capture its std_output without modifying it
-#[cfg(not(feature = "no-sudo"))]
-pub fn exec_command(command: Cmd, src: Option<Src>) -> Result<String, PopenError> {
-    // "vcgencmd" must be in PATH
-    const VCGENCMD_INVOCATION: &str = "vcgencmd";
+/// How `vcgencmd` gets the privileges it needs to talk to the firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PrivilegeMode {
+    /// Run it through `sudo`, or the `Invocation::escalation` command instead, which must
+    /// not ask for a password
+    Sudo,
+    /// Run it as the current user, who then needs access to the device nodes, e.g. as root
+    /// or as a member of the `video` group
+    None,
+}
 
-    let vcgencmd_output = Exec::cmd("sudo")
-        .arg(VCGENCMD_INVOCATION)
-        .arg(resolve_command(command))
-        .arg(resolve_src(src).unwrap_or_default())
-        .stdout(Redirection::Pipe)
-        .capture()?
-        .stdout_str();
+/// `Sudo`, unless the crate is built with the `no-sudo` feature
+impl Default for PrivilegeMode {
+    fn default() -> PrivilegeMode {
+        if cfg!(feature = "no-sudo") {
+            PrivilegeMode::None
+        } else {
+            PrivilegeMode::Sudo
+        }
+    }
+}
+
+/// The command `PrivilegeMode::Sudo` runs `vcgencmd` through, for systems without `sudo`
+/// like Alpine
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Escalation {
+    #[default]
+    Sudo,
+    /// `doas`, with a `permit nopass` rule for the user
+    Doas,
+    /// `pkexec`, with a polkit rule allowing the user to run vcgencmd without
+    /// authentication
+    Pkexec,
+    /// Another program taking the command to run as its arguments, e.g. a setuid wrapper
+    Custom(PathBuf),
+}
+
+impl Escalation {
+    /// The program to run, a plain name is looked up in `PATH`
+    pub fn program(&self) -> &Path {
+        match self {
+            Escalation::Sudo => Path::new("sudo"),
+            Escalation::Doas => Path::new("doas"),
+            Escalation::Pkexec => Path::new("pkexec"),
+            Escalation::Custom(program) => program,
+        }
+    }
+}
 
-    Ok(vcgencmd_output)
+/// How `vcgencmd` is invoked by all functions of this crate, or by a `ProcessExecutor`.
+///
+/// Unless changed with `set_invocation`, the functions start from the default as tuned
+/// through environment variables, see `Config`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    /// Path to the binary, a plain name is looked up in `PATH` and then in `FALLBACK_DIRS`
+    pub binary: PathBuf,
+    /// Whether to run it through `sudo`, see `PrivilegeMode::default`
+    pub privilege: PrivilegeMode,
+    /// What to run it through instead of `sudo`, with `PrivilegeMode::Sudo`
+    pub escalation: Escalation,
+    /// Run it on this host over `ssh`, e.g. `pi@pi4.local`, instead of locally.
+    ///
+    /// `ssh` runs in batch mode, so the host needs key based login, and `sudo` on it must
+    /// not ask for a password.
+    pub host: Option<String>,
+    /// The device nodes vcgencmd talks to the firmware through, checked when a local call
+    /// fails inside a container, see `container`
+    pub devices: DeviceNodes,
+    /// How long a call may take before the process is terminated and the call fails with
+    /// `ErrorKind::Timeout`, `DEFAULT_TIMEOUT` by default. Without one a call hanging on
+    /// the firmware, or on `sudo` asking for a password, blocks for good.
+    pub timeout: Option<Duration>,
+    /// Whether to answer `measure_temp` and `measure_clock(Arm)` from sysfs when the binary
+    /// is missing, true by default, see `sysfs`
+    pub sysfs_fallback: bool,
+}
+
+/// The default `Invocation::timeout`, generous enough for `ssh` to a slow host
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Overrides `Invocation::binary`
+pub const PATH_ENV: &str = "VCGENCMD_PATH";
+/// Overrides `Invocation::privilege`, `1`, `true` or `yes` for `Sudo`, `0`, `false` or `no`
+/// for `None`
+pub const SUDO_ENV: &str = "VCGENCMD_SUDO";
+/// Overrides `Invocation::timeout`, in milliseconds, `0` for none
+pub const TIMEOUT_ENV: &str = "VCGENCMD_TIMEOUT_MS";
+
+impl Default for Invocation {
+    fn default() -> Invocation {
+        Invocation {
+            binary: PathBuf::from("vcgencmd"),
+            privilege: PrivilegeMode::default(),
+            escalation: Escalation::default(),
+            host: None,
+            devices: DeviceNodes::new(),
+            timeout: Some(DEFAULT_TIMEOUT),
+            sysfs_fallback: true,
+        }
+    }
+}
+
+/// The settings of `VCGENCMD_PATH`, `VCGENCMD_SUDO`, `VCGENCMD_TIMEOUT_MS`, `VCGENCMD_VCHIQ`
+/// and `VCGENCMD_VCIO`, e.g. in a systemd unit or a container, so deployments can tune the
+/// `Invocation` without recompiling.
+///
+/// The free functions, `Vcgencmd::new` and `Vcgencmd::builder` start from
+/// `Config::cached().invocation()`, the environment as read at first use.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Overrides `Invocation::binary`
+    pub binary: Option<PathBuf>,
+    /// Overrides `Invocation::privilege`
+    pub privilege: Option<PrivilegeMode>,
+    /// Overrides `Invocation::timeout`, `Some(None)` for no timeout
+    pub timeout: Option<Option<Duration>>,
+    /// `Invocation::devices`
+    pub devices: DeviceNodes,
+}
+
+static ENV_CONFIG: OnceLock<Config> = OnceLock::new();
+
+impl Config {
+    /// Read the environment variables now. Values that can't be parsed are ignored, like
+    /// empty ones.
+    pub fn from_env() -> Config {
+        Config::from_vars(
+            env::var_os(PATH_ENV),
+            env::var(SUDO_ENV).ok().as_deref(),
+            env::var(TIMEOUT_ENV).ok().as_deref(),
+            DeviceNodes::from_env(),
+        )
+    }
+
+    /// The environment variables as read by the first call, later changes of the
+    /// environment are not picked up
+    pub fn cached() -> &'static Config {
+        ENV_CONFIG.get_or_init(Config::from_env)
+    }
+
+    fn from_vars(
+        path: Option<OsString>,
+        sudo: Option<&str>,
+        timeout_ms: Option<&str>,
+        devices: DeviceNodes,
+    ) -> Config {
+        let privilege = match sudo.map(str::trim) {
+            Some("1") | Some("true") | Some("yes") => Some(PrivilegeMode::Sudo),
+            Some("0") | Some("false") | Some("no") => Some(PrivilegeMode::None),
+            _ => None,
+        };
+        let timeout = match timeout_ms.and_then(|ms| ms.trim().parse::<u64>().ok()) {
+            Some(0) => Some(None),
+            Some(ms) => Some(Some(Duration::from_millis(ms))),
+            None => None,
+        };
+
+        Config {
+            binary: path.filter(|path| !path.is_empty()).map(PathBuf::from),
+            privilege,
+            timeout,
+            devices,
+        }
+    }
+
+    /// The default `Invocation` with these settings
+    pub fn invocation(&self) -> Invocation {
+        let default = Invocation::default();
+        Invocation {
+            binary: self.binary.clone().unwrap_or(default.binary),
+            privilege: self.privilege.unwrap_or(default.privilege),
+            timeout: self.timeout.unwrap_or(default.timeout),
+            devices: self.devices.clone(),
+            ..default
+        }
+    }
+}
+
+static INVOCATION: RwLock<Option<Invocation>> = RwLock::new(None);
+
+/// Change how `vcgencmd` is invoked from now on, for the whole process.
+///
+/// The capabilities cached by `session::global` are dropped, as they may not apply anymore.
+pub fn set_invocation(invocation: Invocation) {
+    *INVOCATION.write().unwrap_or_else(|e| e.into_inner()) = Some(invocation);
+    session::global().invalidate();
+}
+
+/// The current `Invocation`, the one of the environment unless changed with
+/// `set_invocation`, see `Config`
+pub fn invocation() -> Invocation {
+    let invocation = INVOCATION.read().unwrap_or_else(|e| e.into_inner());
+    invocation
+        .clone()
+        .unwrap_or_else(|| Config::cached().invocation())
+}
+
+/// Programs found in `PATH` so far, as (name, path)
+static RESOLVED_PROGRAMS: Mutex<Vec<(OsString, PathBuf)>> = Mutex::new(Vec::new());
+
+/// Where a plain binary name is looked for when it isn't in `PATH`, which on older Raspbian
+/// images only includes `/opt/vc/bin` for login shells
+pub const FALLBACK_DIRS: [&str; 2] = ["/usr/bin", "/opt/vc/bin"];
+
+/// The path of `program` in `PATH`, looked up once per process.
+///
+/// Paths and programs that can't be found are returned as they are, the latter are
+/// looked up again next time, in case they were installed in the meantime.
+fn resolve_program(program: &Path) -> PathBuf {
+    find_program(program, &[])
+}
+
+/// The path of the vcgencmd `binary`, like `resolve_program` but also looking in
+/// `FALLBACK_DIRS`
+fn resolve_binary(binary: &Path) -> PathBuf {
+    find_program(binary, &FALLBACK_DIRS)
 }
 
-/// Execute the given command and capture its std_output without modifying it
-#[cfg(feature = "no-sudo")]
-pub fn exec_command(command: Cmd, src: Option<Src>) -> Result<String, PopenError> {
-    // "vcgencmd" must be in PATH
-    const VCGENCMD_INVOCATION: &str = "vcgencmd";
+/// The path of `program` in `PATH`, or else in one of `fallback_dirs`
+fn find_program(program: &Path, fallback_dirs: &[&str]) -> PathBuf {
+    if program.components().count() != 1 || program.is_absolute() {
+        return program.to_owned();
+    }
+
+    let mut resolved = RESOLVED_PROGRAMS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, path)) = resolved.iter().find(|(name, _)| name == program) {
+        return path.clone();
+    }
+
+    let dirs = env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let found = dirs
+        .into_iter()
+        .chain(fallback_dirs.iter().map(PathBuf::from))
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file());
+    match found {
+        Some(path) => {
+            resolved.push((program.as_os_str().to_owned(), path.clone()));
+            path
+        }
+        None => program.to_owned(),
+    }
+}
+
+/// Execute the given command and capture its std_output without modifying it.
+///
+/// An error reply of the firmware is returned as output, but a process exiting
+/// unsuccessfully without one fails, with what it printed to stderr, e.g. as
+/// `Error::Exit`.
+///
+/// Programs are spawned directly, without a shell, and from paths resolved once, as
+/// pollers on a Pi Zero spend a noticeable share of their CPU time on process creation.
+/// `std::process` spawns with `posix_spawn` where it can.
+pub fn exec_command(command: Cmd, src: Option<Src>) -> Result<String> {
+    let invocation = invocation();
+    let output = run(&invocation, command, src);
+    stdout_of(&invocation, command, src, output)
+}
+
+/// `exec_command` with another timeout than that of the global `Invocation`, `None` for
+/// none.
+///
+/// A call still running at the timeout is terminated and fails with `Error::Timeout`. The
+/// measurement functions take theirs from the `Invocation`, a `Vcgencmd` client built
+/// with `VcgencmdBuilder::timeout` gives them one of their own.
+pub fn exec_command_with_timeout(
+    command: Cmd,
+    src: Option<Src>,
+    timeout: Option<Duration>,
+) -> Result<String> {
+    let invocation = Invocation {
+        timeout,
+        ..invocation()
+    };
+    let output = run(&invocation, command, src);
+    stdout_of(&invocation, command, src, output)
+}
+
+/// Run `command` as configured by `invocation`, capturing what it prints to both stdout
+/// and stderr
+fn run(
+    invocation: &Invocation,
+    command: Cmd,
+    src: Option<Src>,
+) -> Result<process::Output, PopenError> {
+    let (mut exec, timeout) = build_command(invocation, command, src);
+    child::output(&mut exec, timeout).map_err(PopenError::IoError)
+}
+
+/// The process running `command` as configured by `invocation`, and how long it may take
+pub(crate) fn build_command(
+    invocation: &Invocation,
+    command: Cmd,
+    src: Option<Src>,
+) -> (process::Command, Option<Duration>) {
+    let mut exec = match &invocation.host {
+        // `--` so a host can't be taken for an option of `ssh`
+        Some(host) => {
+            let mut exec = process::Command::new(resolve_program(Path::new("ssh")));
+            exec.args(["-o", "BatchMode=yes", "--", host]);
+            if invocation.privilege == PrivilegeMode::Sudo {
+                exec.arg(invocation.escalation.program());
+            }
+            exec.arg(&invocation.binary);
+            exec
+        }
+        None if invocation.privilege == PrivilegeMode::Sudo => {
+            let mut exec = process::Command::new(resolve_program(invocation.escalation.program()));
+            exec.arg(resolve_binary(&invocation.binary));
+            exec
+        }
+        None => process::Command::new(resolve_binary(&invocation.binary)),
+    };
 
-    let vcgencmd_output = Exec::cmd(VCGENCMD_INVOCATION)
-        .arg(resolve_command(command))
+    exec.arg(resolve_command(command))
         .arg(resolve_src(src).unwrap_or_default())
-        .stdout(Redirection::Pipe)
-        .capture()?
-        .stdout_str();
+        .stdin(Stdio::inherit());
+    (exec, invocation.timeout)
+}
+
+/// `command` with its source the way it is run, e.g. `measure_volts sdram_c`
+fn describe(command: Cmd, src: Option<Src>) -> String {
+    match resolve_src(src) {
+        Some(src) => format!("{} {}", resolve_command(command), src),
+        None => resolve_command(command),
+    }
+}
+
+/// Run `command` and parse its output, turning an error reply of the firmware into an `Error`
+fn call<T, E: ParseError>(
+    command: Cmd,
+    src: Option<Src>,
+    parse: fn(&str) -> Result<T, E>,
+) -> Result<T> {
+    call_with_raw(command, src, parse).map(|(value, _)| value)
+}
+
+/// `call`, also returning the output of `vcgencmd` the value was parsed from
+fn call_with_raw<T, E: ParseError>(
+    command: Cmd,
+    src: Option<Src>,
+    parse: fn(&str) -> Result<T, E>,
+) -> Result<(T, String)> {
+    if let Some(error) = known_unsupported(command, src) {
+        return Err(error);
+    }
+
+    let invocation = invocation();
+    let output = run(&invocation, command, src);
+    interpret(&invocation, command, src, output, parse)
+}
+
+/// The reply the firmware would give if `command` is known not to be supported, so
+/// vcgencmd isn't spawned for it
+pub(crate) fn known_unsupported(command: Cmd, src: Option<Src>) -> Option<Error> {
+    let known = session::global().cached();
+    if known.is_some_and(|capabilities| !capabilities.supports_cmd(command)) {
+        return Some(Error::firmware(
+            describe(command, src),
+            1,
+            "Command not registered".to_owned(),
+        ));
+    }
+
+    None
+}
+
+/// The value parsed from the `output` of running `command` as configured by `invocation`,
+/// or the error it failed with
+pub(crate) fn interpret<T, E: ParseError>(
+    invocation: &Invocation,
+    command: Cmd,
+    src: Option<Src>,
+    output: Result<process::Output, PopenError>,
+    parse: fn(&str) -> Result<T, E>,
+) -> Result<(T, String)> {
+    let stdout = stdout_of(invocation, command, src, output)?;
+    parse_reply(command, src, stdout, parse)
+}
+
+/// What running `command` as configured by `invocation` printed to stdout, or the error it
+/// failed with. An error reply of the firmware is left to `parse_reply`, and a missing
+/// binary to `sysfs::fallback`.
+pub(crate) fn stdout_of(
+    invocation: &Invocation,
+    command: Cmd,
+    src: Option<Src>,
+    output: Result<process::Output, PopenError>,
+) -> Result<String> {
+    let output = match output {
+        Ok(output) => output,
+        Err(source) => {
+            let error =
+                container::diagnose(invocation, Error::spawn(describe(command, src), source));
+            return sysfs::fallback(invocation, command, src, error);
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() && parsers::firmware_error(&stdout).is_none() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let error = Error::exit(describe(command, src), output.status.code(), &stderr);
+        let error = container::diagnose(invocation, error);
+        return sysfs::fallback(invocation, command, src, error);
+    }
 
-    Ok(vcgencmd_output)
+    Ok(stdout)
+}
+
+/// The value parsed from the `stdout` of `command`, turning an error reply of the firmware
+/// into an `Error`
+pub(crate) fn parse_reply<T, E: ParseError>(
+    command: Cmd,
+    src: Option<Src>,
+    stdout: String,
+    parse: fn(&str) -> Result<T, E>,
+) -> Result<(T, String)> {
+    if let Some((code, message)) = parsers::firmware_error(&stdout) {
+        return Err(Error::firmware(describe(command, src), code, message));
+    }
+
+    match parse(&stdout) {
+        Ok(value) => Ok((value, stdout)),
+        Err(error) => Err(error.into_error(describe(command, src))),
+    }
 }
 
 /// Measure the clock of the selected `ClockSrc`, returning the frequency as an isize
-pub fn measure_clock(src: Src) -> Result<isize, ExecutionError> {
-    let output = exec_command(Cmd::MeasureClock, Some(src)).map_err(ExecutionError::Popen)?;
-    let frequency = parsers::frequency(&output).map_err(ExecutionError::ParseInt)?;
+pub fn measure_clock(src: Src) -> Result<isize> {
+    call(Cmd::MeasureClock, Some(src), parsers::frequency)
+}
+
+/// `measure_clock`, also returning the output of `vcgencmd` unmodified, e.g.
+/// `frequency(48)=1500398464\n`, to log it alongside the value
+pub fn measure_clock_with_raw(src: Src) -> Result<(isize, String)> {
+    call_with_raw(Cmd::MeasureClock, Some(src), parsers::frequency)
+}
+
+/// Measure the clock of every `ClockSrc`, leaving out those the firmware can't measure.
+///
+/// Only fails if none of them could be measured, e.g. when `vcgencmd` is missing, with
+/// the error of the first one.
+pub fn measure_clock_all() -> Result<HashMap<ClockSrc, isize>> {
+    measure_each(&ClockSrc::ALL, |src| measure_clock(Src::Clock(src)))
+}
+
+/// Measure the voltage of every rail, see `VoltRails`.
+///
+/// Like `measure_clock_all`, only fails if no rail could be measured.
+pub fn measure_volts_all() -> Result<VoltRails> {
+    let mut volts = measure_each(&VoltSrc::ALL, |src| measure_volts(Src::Volt(src)))?;
+
+    Ok(VoltRails {
+        core: volts.remove(&VoltSrc::Core),
+        sdram_c: volts.remove(&VoltSrc::SdramC),
+        sdram_i: volts.remove(&VoltSrc::SdramI),
+        sdram_p: volts.remove(&VoltSrc::SdramP),
+    })
+}
+
+/// The values `measure` succeeded for, or its first error if it failed for every source
+fn measure_each<S, T, F>(srcs: &[S], mut measure: F) -> Result<HashMap<S, T>>
+where
+    S: Copy + Eq + Hash,
+    F: FnMut(S) -> Result<T>,
+{
+    each_measured(srcs.iter().map(|&src| (src, measure(src))))
+}
+
+/// The values of the sources that were measured, or the first error if none was
+pub(crate) fn each_measured<S, T, I>(results: I) -> Result<HashMap<S, T>>
+where
+    S: Eq + Hash,
+    I: IntoIterator<Item = (S, Result<T>)>,
+{
+    let mut values = HashMap::new();
+    let mut first_error = None;
+    for (src, result) in results {
+        match result {
+            Ok(value) => {
+                values.insert(src, value);
+            }
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+
+    match first_error {
+        Some(error) if values.is_empty() => Err(error),
+        _ => Ok(values),
+    }
+}
+
+pub fn measure_volts(src: Src) -> Result<f64> {
+    call(Cmd::MeasureVolts, Some(src), parsers::volts)
+}
+
+/// `measure_volts` with the output of `vcgencmd`, see `measure_clock_with_raw`
+pub fn measure_volts_with_raw(src: Src) -> Result<(f64, String)> {
+    call_with_raw(Cmd::MeasureVolts, Some(src), parsers::volts)
+}
+
+pub fn measure_temp() -> Result<f64> {
+    call(Cmd::MeasureTemp, None, parsers::temp)
+}
+
+/// `measure_temp` with the output of `vcgencmd`, see `measure_clock_with_raw`
+pub fn measure_temp_with_raw() -> Result<(f64, String)> {
+    call_with_raw(Cmd::MeasureTemp, None, parsers::temp)
+}
+
+pub fn get_mem(src: Src) -> Result<isize> {
+    call(Cmd::GetMem, Some(src), parsers::mem)
+}
 
-    Ok(frequency)
+/// `get_mem` with the output of `vcgencmd`, see `measure_clock_with_raw`
+pub fn get_mem_with_raw(src: Src) -> Result<(isize, String)> {
+    call_with_raw(Cmd::GetMem, Some(src), parsers::mem)
 }
 
-pub fn measure_volts(src: Src) -> Result<f64, ExecutionError> {
-    let output = exec_command(Cmd::MeasureVolts, Some(src)).map_err(ExecutionError::Popen)?;
-    let volts = parsers::volts(&output).map_err(ExecutionError::ParseFloat)?;
+/// Read the memory of the ARM and the GPU at once
+pub fn get_mem_split() -> Result<MemSplit> {
+    let arm = get_mem(Src::Mem(MemSrc::Arm))?;
+    let gpu = get_mem(Src::Mem(MemSrc::Gpu))?;
+
+    Ok(MemSplit::new(arm, gpu))
+}
+
+/// Read the usage of the GPU's heaps and the relocation statistics at once
+pub fn get_gpu_memory_pressure() -> Result<GpuMemoryPressure> {
+    Ok(GpuMemoryPressure {
+        malloc_total: get_mem(Src::Mem(MemSrc::MallocTotal))?,
+        malloc_free: get_mem(Src::Mem(MemSrc::Malloc))?,
+        reloc_total: get_mem(Src::Mem(MemSrc::RelocTotal))?,
+        reloc_free: get_mem(Src::Mem(MemSrc::Reloc))?,
+        reloc_stats: mem_reloc_stats()?,
+    })
+}
+
+/// Read the counters of the GPU's relocatable heap
+pub fn mem_reloc_stats() -> Result<RelocStats> {
+    call(Cmd::MemRelocStats, None, parsers::reloc_stats)
+}
+
+/// Read an integer option from `config.txt`, as applied by the firmware at boot
+pub fn get_config(src: Src) -> Result<isize> {
+    call(Cmd::GetConfig, Some(src), parsers::config)
+}
+
+/// `get_config` with the output of `vcgencmd`, see `measure_clock_with_raw`
+pub fn get_config_with_raw(src: Src) -> Result<(isize, String)> {
+    call_with_raw(Cmd::GetConfig, Some(src), parsers::config)
+}
+
+pub fn get_throttled() -> Result<isize> {
+    call(Cmd::GetThrottled, None, parsers::throttled)
+}
+
+/// `get_throttled` with the output of `vcgencmd`, see `measure_clock_with_raw`
+pub fn get_throttled_with_raw() -> Result<(isize, String)> {
+    call_with_raw(Cmd::GetThrottled, None, parsers::throttled)
+}
+
+/// Read and decode the `get_throttled` bit pattern, see `interpret_bit_pattern`
+pub fn get_throttled_status() -> Result<ThrottledStatus> {
+    get_throttled().map(interpret_bit_pattern)
+}
 
-    Ok(volts)
+/// Whether the supply voltage is too low right now.
+///
+/// ```no_run
+/// if vcgencmd::is_undervolted()? {
+///     eprintln!("check the power supply");
+/// }
+/// # Ok::<(), vcgencmd::Error>(())
+/// ```
+pub fn is_undervolted() -> Result<bool> {
+    Ok(get_throttled_status()?.under_voltage)
 }
 
-pub fn measure_temp() -> Result<f64, ExecutionError> {
-    let output = exec_command(Cmd::MeasureTemp, None).map_err(ExecutionError::Popen)?;
-    let temperature = parsers::temp(&output).map_err(ExecutionError::ParseFloat)?;
+/// Whether the ARM is throttled right now, be it for under-voltage or temperature
+pub fn is_throttled() -> Result<bool> {
+    Ok(get_throttled_status()?.currently_throttled)
+}
 
-    Ok(temperature)
+/// Read all ADC channels of the PMIC, only available on the Raspberry Pi 5
+pub fn pmic_read_adc() -> Result<Vec<AdcChannel>> {
+    call(Cmd::PmicReadAdc, None, parsers::pmic_adc)
 }
 
-pub fn get_mem(src: Src) -> Result<isize, ExecutionError> {
-    let output = exec_command(Cmd::GetMem, Some(src)).map_err(ExecutionError::Popen)?;
-    let mem = parsers::mem(&output).map_err(ExecutionError::ParseInt)?;
+/// Whether display `id` is powered on, `None` if there is no such display, see `display`
+pub fn display_power(id: u8) -> Result<Option<bool>> {
+    call(
+        Cmd::DisplayPower,
+        Some(Src::Display(id)),
+        parsers::display_power,
+    )
+}
 
-    Ok(mem)
+/// Read resolution and color depth of the display the firmware drives by default
+pub fn get_lcd_info() -> Result<LcdInfo> {
+    call(Cmd::GetLcdInfo, None, parsers::lcd_info)
 }
 
-pub fn get_throttled() -> Result<isize, ExecutionError> {
-    let output = exec_command(Cmd::GetThrottled, None).map_err(ExecutionError::Popen)?;
-    let bit_pattern = parsers::throttled(&output).map_err(ExecutionError::ParseInt)?;
-    Ok(bit_pattern)
+/// Read the custom HDMI timings, `None` if none are configured
+pub fn hdmi_timings() -> Result<Option<HdmiTimings>> {
+    call(Cmd::HdmiTimings, None, parsers::hdmi_timings)
 }
 
 /// Interprets a bit pattern obtained from `get_throttled` in the following way:
 /// ```txt
-/// 111100000000000001010
-/// ||||             ||||_ under-voltage
-/// ||||             |||_ currently throttled
-/// ||||             ||_ arm frequency capped
-/// ||||             |_ soft temperature reached
+/// 11110000000000001111
+/// ||||            ||||_ under-voltage
+/// ||||            |||_ arm frequency capped
+/// ||||            ||_ currently throttled
+/// ||||            |_ soft temperature limit active
 /// ||||_ under-voltage has occurred since last reboot
-/// |||_ throttling has occurred since last reboot
-/// ||_ arm frequency capped has occurred since last reboot
-/// |_ soft temperature reached since last reboot
+/// |||_ arm frequency capped has occurred since last reboot
+/// ||_ throttling has occurred since last reboot
+/// |_ soft temperature limit has occurred since last reboot
 /// ```
 ///
-/// > Note: This interpretation might be false/outdated for different versions of vcgencmd...
+/// This is the layout documented for `vcgencmd get_throttled` by the Raspberry Pi
+/// foundation, see `throttled::FLAGS` for the meaning of every bit.
 ///
 /// # Examples
 ///
@@ -168,31 +1076,31 @@ pub fn get_throttled() -> Result<isize, ExecutionError> {
 ///
 /// ```rust
 /// use vcgencmd::{interpret_bit_pattern, ThrottledStatus};
-/// let throttle_status = interpret_bit_pattern(0b111100000000000001010_isize);
+/// let throttle_status = interpret_bit_pattern(0x50005);
 /// // or bit_pattern = get_throttle().unwrap();
 /// // let throttle status = interpret_bit_pattern(bit_pattern);
 /// assert_eq!(throttle_status,
 ///            ThrottledStatus {
-///               arm_frequency_cap_occurred: true,
+///               arm_frequency_cap_occurred: false,
 ///               arm_frequency_capped: false,
 ///               currently_throttled: true,
-///               soft_temp_limit_active: true,
-///               soft_temp_limit_occurred: true,
+///               soft_temp_limit_active: false,
+///               soft_temp_limit_occurred: false,
 ///               throttling_occurred: true,
-///               under_voltage: false,
+///               under_voltage: true,
 ///               under_voltage_occurred: true,
 /// })
 /// ```
 pub fn interpret_bit_pattern(pattern: isize) -> ThrottledStatus {
-    let soft_temp_limit_occurred = bitpat!(1 _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _)(pattern);
-    let arm_frequency_cap_occurred = bitpat!(_ 1 _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _)(pattern);
-    let throttling_occurred = bitpat!(_ _ 1 _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _)(pattern);
-    let under_voltage_occurred = bitpat!(_ _ _ 1 _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _)(pattern);
+    let soft_temp_limit_occurred = bitpat!(1 _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _)(pattern);
+    let throttling_occurred = bitpat!(_ 1 _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _)(pattern);
+    let arm_frequency_cap_occurred = bitpat!(_ _ 1 _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _)(pattern);
+    let under_voltage_occurred = bitpat!(_ _ _ 1 _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _)(pattern);
 
-    let soft_temp_limit_active = bitpat!(_ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ 1 _ _ _)(pattern);
-    let arm_frequency_capped = bitpat!(_ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ 1 _ _)(pattern);
-    let currently_throttled = bitpat!(_ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ 1 _)(pattern);
-    let under_voltage = bitpat!(_ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ 1)(pattern);
+    let soft_temp_limit_active = bitpat!(_ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ 1 _ _ _)(pattern);
+    let currently_throttled = bitpat!(_ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ 1 _ _)(pattern);
+    let arm_frequency_capped = bitpat!(_ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ 1 _)(pattern);
+    let under_voltage = bitpat!(_ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ _ 1)(pattern);
 
     ThrottledStatus {
         arm_frequency_cap_occurred,
@@ -208,15 +1116,31 @@ pub fn interpret_bit_pattern(pattern: isize) -> ThrottledStatus {
 
 fn resolve_command(cmd: Cmd) -> String {
     match cmd {
+        Cmd::Commands => "commands",
+        Cmd::DisplayPower => "display_power",
+        Cmd::GetConfig => "get_config",
+        Cmd::GetLcdInfo => "get_lcd_info",
         Cmd::GetMem => "get_mem",
         Cmd::GetThrottled => "get_throttled",
+        Cmd::HdmiTimings => "hdmi_timings",
         Cmd::MeasureClock => "measure_clock",
         Cmd::MeasureTemp => "measure_temp",
         Cmd::MeasureVolts => "measure_volts",
+        Cmd::MemRelocStats => "mem_reloc_stats",
+        Cmd::PmicReadAdc => "pmic_read_adc",
+        Cmd::Version => "version",
     }
     .to_owned()
 }
 
+/// The name of this system, the default instance or host of the network exporters
+#[cfg(any(feature = "email", feature = "prometheus", feature = "zabbix"))]
+fn hostname() -> Option<String> {
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    let hostname = hostname.trim();
+    (!hostname.is_empty()).then(|| hostname.to_owned())
+}
+
 fn resolve_src(src: Option<Src>) -> Option<String> {
     // check for None
     let src = src.as_ref()?;
@@ -234,8 +1158,23 @@ fn resolve_src(src: Option<Src>) -> Option<String> {
         Src::Clock(ClockSrc::Uart) => Some("uart".to_owned()),
         Src::Clock(ClockSrc::V3d) => Some("v3d".to_owned()),
         Src::Clock(ClockSrc::Vec) => Some("vec".to_owned()),
+        Src::Config(ConfigSrc::ArmFreq) => Some("arm_freq".to_owned()),
+        Src::Config(ConfigSrc::CoreFreq) => Some("core_freq".to_owned()),
+        Src::Config(ConfigSrc::GpuFreq) => Some("gpu_freq".to_owned()),
+        Src::Config(ConfigSrc::GpuMem) => Some("gpu_mem".to_owned()),
+        Src::Config(ConfigSrc::OverVoltage) => Some("over_voltage".to_owned()),
+        Src::Config(ConfigSrc::SdramFreq) => Some("sdram_freq".to_owned()),
+        Src::Config(ConfigSrc::TempLimit) => Some("temp_limit".to_owned()),
+        Src::Config(ConfigSrc::TempSoftLimit) => Some("temp_soft_limit".to_owned()),
+        Src::Config(ConfigSrc::TotalMem) => Some("total_mem".to_owned()),
+        // -1 queries the power state instead of changing it
+        Src::Display(id) => Some(format!("-1 {}", id)),
         Src::Mem(MemSrc::Arm) => Some("arm".to_owned()),
         Src::Mem(MemSrc::Gpu) => Some("gpu".to_owned()),
+        Src::Mem(MemSrc::Malloc) => Some("malloc".to_owned()),
+        Src::Mem(MemSrc::MallocTotal) => Some("malloc_total".to_owned()),
+        Src::Mem(MemSrc::Reloc) => Some("reloc".to_owned()),
+        Src::Mem(MemSrc::RelocTotal) => Some("reloc_total".to_owned()),
         Src::Volt(VoltSrc::Core) => Some("core".to_owned()),
         Src::Volt(VoltSrc::SdramC) => Some("sdram_c".to_owned()),
         Src::Volt(VoltSrc::SdramI) => Some("sdram_i".to_owned()),
@@ -254,9 +1193,39 @@ mod tests {
             resolve_src(Some(Src::Clock(ClockSrc::Arm)))
         );
 
+        assert_eq!(
+            Some(String::from("temp_soft_limit")),
+            resolve_src(Some(Src::Config(ConfigSrc::TempSoftLimit)))
+        );
+
         assert_eq!(None, resolve_src(None));
     }
 
+    #[test]
+    fn test_src_in_set() {
+        let sources: std::collections::HashSet<_> = ClockSrc::ALL
+            .iter()
+            .map(|&src| Src::Clock(src))
+            .chain(VoltSrc::ALL.iter().map(|&src| Src::Volt(src)))
+            .chain(vec![Src::Clock(ClockSrc::Arm)])
+            .collect();
+        assert_eq!(16, sources.len());
+        assert!(sources.contains(&Src::Volt(VoltSrc::SdramP)));
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            "measure_volts sdram_c",
+            describe(Cmd::MeasureVolts, Some(Src::Volt(VoltSrc::SdramC)))
+        );
+        assert_eq!("get_throttled", describe(Cmd::GetThrottled, None));
+        assert_eq!(
+            "display_power -1 2",
+            describe(Cmd::DisplayPower, Some(Src::Display(display::HDMI0)))
+        );
+    }
+
     #[test]
     fn test_resolve_command() {
         assert_eq!("measure_temp", resolve_command(Cmd::MeasureTemp));
@@ -265,7 +1234,7 @@ mod tests {
 
     #[test]
     fn test_throttled_status_methods() {
-        let throttled_status = ThrottledStatus::new(0b111100000000000001010);
+        let throttled_status = ThrottledStatus::new(0xf000c);
         assert_eq!(
             throttled_status,
             ThrottledStatus {
@@ -283,7 +1252,7 @@ mod tests {
 
     #[test]
     fn test_interpret_bit_pattern() {
-        let throttled_info = interpret_bit_pattern(0b111100000000000001010);
+        let throttled_info = interpret_bit_pattern(0xf000c);
         assert_eq!(
             throttled_info,
             ThrottledStatus {
@@ -298,7 +1267,7 @@ mod tests {
             }
         );
 
-        let throttled_info2 = interpret_bit_pattern(0b111100000000000001111);
+        let throttled_info2 = interpret_bit_pattern(0xf000f);
         assert_eq!(
             throttled_info2,
             ThrottledStatus {
@@ -314,6 +1283,199 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_measure_each() {
+        let clocks = measure_each(&ClockSrc::ALL, |src| match src {
+            ClockSrc::Arm => Ok(1_500_000_000),
+            ClockSrc::Core => Ok(500_000_000),
+            _ => Err("".parse::<isize>().unwrap_err().into_error(String::new())),
+        })
+        .unwrap();
+        assert_eq!(2, clocks.len());
+        assert_eq!(Some(&500_000_000), clocks.get(&ClockSrc::Core));
+
+        let none = measure_each(&ClockSrc::ALL, |_| {
+            Err::<isize, _>("x".parse::<isize>().unwrap_err().into_error(String::new()))
+        });
+        assert!(matches!(none, Err(Error::ParseInt { .. })));
+    }
+
+    #[test]
+    fn test_mem_split() {
+        let split = MemSplit::new(948, 76);
+        assert_eq!(1024, split.total);
+        assert_eq!(0.92578125, split.arm_ratio());
+        assert_eq!(0.07421875, split.gpu_ratio());
+        assert_eq!(0.0, MemSplit::default().gpu_ratio());
+        assert_eq!("arm 948 MiB, gpu 76 MiB of 1.0 GiB", split.to_string());
+    }
+
+    #[test]
+    fn test_resolve_program() {
+        let sh = resolve_program(Path::new("sh"));
+        assert!(sh.is_absolute() && sh.ends_with("sh"), "{:?}", sh);
+        assert_eq!(sh, resolve_program(Path::new("sh")));
+
+        assert_eq!(
+            Path::new("./vcgencmd"),
+            resolve_program(Path::new("./vcgencmd"))
+        );
+        assert_eq!(
+            Path::new("vcgencmd-missing"),
+            resolve_program(Path::new("vcgencmd-missing"))
+        );
+    }
+
+    #[test]
+    fn test_find_program_fallback() {
+        let dir = env::temp_dir().join(format!("vcgencmd-fallback-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let program = Path::new("vcgencmd-fallback-test");
+        let fallback_dirs = ["/nonexistent", dir.to_str().unwrap()];
+
+        assert_eq!(program, find_program(program, &fallback_dirs));
+        std::fs::write(dir.join(program), "").unwrap();
+        assert_eq!(dir.join(program), find_program(program, &fallback_dirs));
+        // found once, it is kept
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(dir.join(program), resolve_program(program));
+    }
+
+    #[test]
+    fn test_config_from_vars() {
+        let devices = DeviceNodes::new().vcio("/dev/vc/vcio");
+        let config = Config::from_vars(
+            Some(OsString::from("/opt/vc/bin/vcgencmd")),
+            Some("false"),
+            Some("2500"),
+            devices.clone(),
+        );
+        let invocation = config.invocation();
+        assert_eq!(PathBuf::from("/opt/vc/bin/vcgencmd"), invocation.binary);
+        assert_eq!(PrivilegeMode::None, invocation.privilege);
+        assert_eq!(Some(Duration::from_millis(2500)), invocation.timeout);
+        assert_eq!(devices, invocation.devices);
+
+        let unset = Config::from_vars(
+            Some(OsString::new()),
+            Some("maybe"),
+            Some("soon"),
+            DeviceNodes::new(),
+        );
+        assert_eq!(Config::default(), unset);
+        assert_eq!(Invocation::default(), unset.invocation());
+
+        let no_timeout = Config::from_vars(None, Some("1"), Some("0"), DeviceNodes::new());
+        assert_eq!(Some(PrivilegeMode::Sudo), no_timeout.privilege);
+        assert_eq!(None, no_timeout.invocation().timeout);
+    }
+
+    #[test]
+    fn test_build_command_escalation() {
+        let args = |invocation: &Invocation| {
+            let (exec, _) = build_command(invocation, Cmd::MeasureTemp, None);
+            let program = exec.get_program().to_string_lossy().into_owned();
+            let args: Vec<_> = exec
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect();
+            (program, args)
+        };
+        let invocation = Invocation {
+            binary: PathBuf::from("/usr/bin/vcgencmd"),
+            privilege: PrivilegeMode::Sudo,
+            escalation: Escalation::Custom(PathBuf::from("/usr/local/bin/vc-wrapper")),
+            ..Invocation::default()
+        };
+        assert_eq!(
+            (
+                "/usr/local/bin/vc-wrapper".to_owned(),
+                vec![
+                    "/usr/bin/vcgencmd".to_owned(),
+                    "measure_temp".to_owned(),
+                    String::new()
+                ]
+            ),
+            args(&invocation)
+        );
+
+        let remote = Invocation {
+            host: Some("pi4".to_owned()),
+            escalation: Escalation::Doas,
+            ..invocation.clone()
+        };
+        assert_eq!(
+            vec![
+                "-o",
+                "BatchMode=yes",
+                "--",
+                "pi4",
+                "doas",
+                "/usr/bin/vcgencmd"
+            ],
+            args(&remote).1.get(..6).unwrap()
+        );
+
+        let unprivileged = Invocation {
+            privilege: PrivilegeMode::None,
+            ..invocation
+        };
+        assert_eq!("/usr/bin/vcgencmd", args(&unprivileged).0);
+    }
+
+    #[test]
+    fn test_gpu_memory_pressure() {
+        let mut pressure = GpuMemoryPressure {
+            malloc_total: 8,
+            malloc_free: 6,
+            reloc_total: 56,
+            reloc_free: 10,
+            reloc_stats: RelocStats::default(),
+        };
+        assert_eq!(75.0, pressure.utilization());
+        assert!(!pressure.is_fragmented());
+
+        pressure.reloc_stats.alloc_failures = 3;
+        assert!(pressure.is_fragmented());
+        assert_eq!(0.0, GpuMemoryPressure::default().utilization());
+    }
+
+    #[test]
+    fn test_volt_rails_display() {
+        let rails = VoltRails {
+            core: Some(0.72),
+            ..VoltRails::default()
+        };
+        assert_eq!(
+            "core 0.720 V, sdram_c n/a, sdram_i n/a, sdram_p n/a",
+            rails.to_string()
+        );
+    }
+
+    #[cfg(target_arch = "arm")]
+    #[test]
+    fn test_is_throttled() {
+        let output = is_throttled().and_then(|_| is_undervolted());
+        dbg!(&output);
+        debug_assert_eq!(output.is_ok(), true)
+    }
+
+    #[cfg(target_arch = "arm")]
+    #[test]
+    fn test_measure_clock_all() {
+        let clocks = measure_clock_all();
+        dbg!(&clocks);
+        assert!(clocks.unwrap().contains_key(&ClockSrc::Arm))
+    }
+
+    #[cfg(target_arch = "arm")]
+    #[test]
+    fn test_measure_volts_all() {
+        let volts = measure_volts_all();
+        dbg!(&volts);
+        assert!(volts.unwrap().core.is_some())
+    }
+
     #[cfg(target_arch = "arm")]
     #[test]
     fn test_exec_command() {
@@ -322,6 +1484,15 @@ mod tests {
         assert!(output.contains("frequency"));
     }
 
+    #[cfg(target_arch = "arm")]
+    #[test]
+    fn test_exec_command_with_timeout() {
+        let output =
+            exec_command_with_timeout(Cmd::MeasureTemp, None, Some(Duration::from_secs(5)))
+                .unwrap();
+        assert!(output.contains("temp"));
+    }
+
     #[cfg(target_arch = "arm")]
     #[test]
     fn test_get_mem() {
@@ -338,6 +1509,15 @@ mod tests {
         debug_assert_eq!(output.is_ok(), true)
     }
 
+    #[cfg(target_arch = "arm")]
+    #[test]
+    fn test_measure_temp_with_raw() {
+        let (temp, raw) = measure_temp_with_raw().unwrap();
+        dbg!(&raw);
+        assert!(raw.starts_with("temp="));
+        assert_eq!(Ok(temp), parsers::temp(&raw));
+    }
+
     #[cfg(target_arch = "arm")]
     #[test]
     fn test_measure_volts() {
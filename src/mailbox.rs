@@ -0,0 +1,274 @@
+//! Reading the firmware through its property mailbox, without spawning vcgencmd
+//!
+//! Spawning `sudo vcgencmd` takes milliseconds, more than a sample may cost when sampling
+//! ten times a second. A `MailboxExecutor` asks the VideoCore directly instead, with an
+//! ioctl on `/dev/vcio`, which takes microseconds and needs no `sudo`, only membership in
+//! the `video` group. It's an `Executor`, so a `Vcgencmd` client built on it reads the same
+//! values, and a `Monitor` can sample through it:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use vcgencmd::mailbox::MailboxExecutor;
+//! use vcgencmd::monitor::{Metric, Monitor};
+//! use vcgencmd::{ClockSrc, Vcgencmd};
+//!
+//! let client = Vcgencmd::with_executor(MailboxExecutor::open()?);
+//! let monitor = Monitor::new(Duration::from_millis(100))
+//!     .metric(Metric::Temp)
+//!     .metric(Metric::Clock(ClockSrc::Arm))
+//!     .metric(Metric::Throttled)
+//!     .sampler(move |metric| client.read(metric));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The mailbox only answers `measure_temp`, `measure_clock`, `measure_volts`,
+//! `get_throttled` and `get_mem` for the ARM and the GPU. Other commands are replied to like
+//! the firmware does to unknown ones, and the DPI, HDMI and VEC clocks, which have no
+//! mailbox id, like it does to unknown sources.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use subprocess::PopenError;
+
+use crate::executor::{Executor, INVALID_ARGUMENTS, NOT_REGISTERED};
+use crate::{describe, ClockSrc, Cmd, Error, ExecutionError, MemSrc, Src, VoltSrc};
+
+/// The device of the mailbox, created by the `bcm2835-vcio` kernel driver
+pub const DEFAULT_PATH: &str = "/dev/vcio";
+
+/// `_IOWR(100, 0, char *)`, the ioctl to send a property message
+const IOCTL_MBOX_PROPERTY: libc::c_ulong =
+    0xc000_6400 | ((mem::size_of::<*mut libc::c_char>() as libc::c_ulong) << 16);
+
+const PROCESS_REQUEST: u32 = 0;
+const REQUEST_SUCCESSFUL: u32 = 0x8000_0000;
+/// Set in the value length of a tag the firmware answered
+const TAG_RESPONSE: u32 = 0x8000_0000;
+/// The value of voltage ids the firmware doesn't know
+const INVALID_ID: u32 = 0x8000_0000;
+
+const GET_ARM_MEMORY: u32 = 0x0001_0005;
+const GET_VC_MEMORY: u32 = 0x0001_0006;
+const GET_VOLTAGE: u32 = 0x0003_0003;
+const GET_TEMPERATURE: u32 = 0x0003_0006;
+const GET_THROTTLED: u32 = 0x0003_0046;
+const GET_CLOCK_RATE_MEASURED: u32 = 0x0003_0047;
+
+/// The mailbox id of a clock, `None` for those it can't measure
+fn clock_id(src: ClockSrc) -> Option<u32> {
+    match src {
+        ClockSrc::Emmc => Some(1),
+        ClockSrc::Uart => Some(2),
+        ClockSrc::Arm => Some(3),
+        ClockSrc::Core => Some(4),
+        ClockSrc::V3d => Some(5),
+        ClockSrc::H264 => Some(6),
+        ClockSrc::Isp => Some(7),
+        ClockSrc::Pixel => Some(9),
+        ClockSrc::Pwm => Some(10),
+        ClockSrc::Dpi | ClockSrc::Hdmi | ClockSrc::Vec => None,
+    }
+}
+
+fn voltage_id(src: VoltSrc) -> u32 {
+    match src {
+        VoltSrc::Core => 1,
+        VoltSrc::SdramC => 2,
+        VoltSrc::SdramP => 3,
+        VoltSrc::SdramI => 4,
+    }
+}
+
+/// A message asking for `tag`, with room for its `values`, which hold the request
+fn message(tag: u32, values: &[u32]) -> Vec<u32> {
+    let value_size = (values.len() * 4) as u32;
+    // the header, the tag and the end tag are six words
+    let size = value_size + 6 * 4;
+    let mut message = vec![size, PROCESS_REQUEST, tag, value_size, 0];
+    message.extend_from_slice(values);
+    message.push(0);
+    message
+}
+
+/// The values the firmware answered `message` with
+fn response(message: &[u32]) -> io::Result<&[u32]> {
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_owned());
+
+    if message.get(1) != Some(&REQUEST_SUCCESSFUL) {
+        return Err(invalid(
+            "the firmware failed to process the mailbox request",
+        ));
+    }
+    match message.get(4) {
+        Some(length) if length & TAG_RESPONSE != 0 => {}
+        _ => return Err(invalid("the firmware didn't answer the mailbox tag")),
+    }
+    let end = message.len().saturating_sub(1);
+    message
+        .get(5..end)
+        .ok_or_else(|| invalid("the mailbox response is truncated"))
+}
+
+/// Answers a `Vcgencmd` client from the property mailbox, see the module documentation
+#[derive(Debug)]
+pub struct MailboxExecutor {
+    device: File,
+    path: PathBuf,
+}
+
+impl MailboxExecutor {
+    /// Open `/dev/vcio`
+    pub fn open() -> io::Result<MailboxExecutor> {
+        MailboxExecutor::open_path(DEFAULT_PATH)
+    }
+
+    /// Open the mailbox device at `path`, e.g. one mapped into a container elsewhere
+    pub fn open_path<P: AsRef<Path>>(path: P) -> io::Result<MailboxExecutor> {
+        let path = path.as_ref();
+        let device = OpenOptions::new().read(true).write(true).open(path)?;
+
+        Ok(MailboxExecutor {
+            device,
+            path: path.to_owned(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Ask the firmware for `tag` with `request`, returning the first `request.len()`
+    /// values of the response
+    fn property(&self, tag: u32, request: &[u32]) -> io::Result<Vec<u32>> {
+        let mut message = message(tag, request);
+
+        // SAFETY: the driver reads the size from the first word and copies that many
+        // bytes in and out of the buffer, which is exactly as large
+        let result = unsafe {
+            libc::ioctl(
+                self.device.as_raw_fd(),
+                IOCTL_MBOX_PROPERTY as _,
+                message.as_mut_ptr(),
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(response(&message)?.to_vec())
+    }
+
+    /// The second value the firmware answers `tag` for `id` with, the first being the id
+    fn value_of(&self, tag: u32, id: u32) -> io::Result<Option<u32>> {
+        let values = self.property(tag, &[id, 0])?;
+        Ok(values.get(1).copied())
+    }
+
+    /// What vcgencmd would print for `cmd` with `src`
+    fn reply(&self, cmd: Cmd, src: Option<Src>) -> io::Result<String> {
+        let reply = match (cmd, src) {
+            (Cmd::MeasureTemp, None) => self
+                .value_of(GET_TEMPERATURE, 0)?
+                .map(|millis| format!("temp={}'C\n", f64::from(millis) / 1000.0)),
+            (Cmd::MeasureClock, Some(Src::Clock(src))) => match clock_id(src) {
+                Some(id) => self
+                    .value_of(GET_CLOCK_RATE_MEASURED, id)?
+                    .map(|rate| format!("frequency({})={}\n", id, rate)),
+                None => None,
+            },
+            (Cmd::MeasureVolts, Some(Src::Volt(src))) => self
+                .value_of(GET_VOLTAGE, voltage_id(src))?
+                .filter(|&micros| micros != INVALID_ID)
+                .map(|micros| format!("volt={:.6}V\n", f64::from(micros) / 1_000_000.0)),
+            (Cmd::GetThrottled, None) => self
+                .property(GET_THROTTLED, &[0])?
+                .first()
+                .map(|bits| format!("throttled={:#x}\n", bits)),
+            (Cmd::GetMem, Some(Src::Mem(MemSrc::Arm))) => self
+                .value_of(GET_ARM_MEMORY, 0)?
+                .map(|size| format!("arm={}M\n", size >> 20)),
+            (Cmd::GetMem, Some(Src::Mem(MemSrc::Gpu))) => self
+                .value_of(GET_VC_MEMORY, 0)?
+                .map(|size| format!("gpu={}M\n", size >> 20)),
+            (Cmd::MeasureTemp, _)
+            | (Cmd::MeasureClock, _)
+            | (Cmd::MeasureVolts, _)
+            | (Cmd::GetThrottled, _)
+            | (Cmd::GetMem, _) => None,
+            _ => return Ok(NOT_REGISTERED.to_owned()),
+        };
+
+        Ok(reply.unwrap_or_else(|| INVALID_ARGUMENTS.to_owned()))
+    }
+}
+
+impl Executor for MailboxExecutor {
+    fn run(&self, cmd: Cmd, src: Option<Src>) -> Result<String, ExecutionError> {
+        self.reply(cmd, src)
+            .map_err(|error| Error::spawn(describe(cmd, src), PopenError::IoError(error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorKind, Vcgencmd};
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            vec![32, 0, GET_TEMPERATURE, 8, 0, 0, 0, 0],
+            message(GET_TEMPERATURE, &[0, 0])
+        );
+    }
+
+    #[test]
+    fn test_response() {
+        let answered = [
+            32,
+            REQUEST_SUCCESSFUL,
+            GET_TEMPERATURE,
+            8,
+            0x8000_0008,
+            0,
+            48312,
+            0,
+        ];
+        assert_eq!(&[0, 48312][..], response(&answered).unwrap());
+
+        let failed = [32, 0x8000_0001, GET_TEMPERATURE, 8, 0, 0, 0, 0];
+        assert!(response(&failed).is_err());
+        let unanswered = [32, REQUEST_SUCCESSFUL, GET_TEMPERATURE, 8, 0, 0, 0, 0];
+        assert!(response(&unanswered).is_err());
+        assert!(response(&[8, REQUEST_SUCCESSFUL]).is_err());
+    }
+
+    #[test]
+    fn test_not_a_mailbox() {
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            MailboxExecutor::open_path("/nonexistent/vcio")
+                .unwrap_err()
+                .kind()
+        );
+
+        // /dev/null doesn't know the ioctl, commands without a mailbox tag never reach it
+        let client = Vcgencmd::with_executor(MailboxExecutor::open_path("/dev/null").unwrap());
+        assert_eq!(ErrorKind::Io, client.measure_temp().unwrap_err().kind());
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client.pmic_read_adc().unwrap_err().kind()
+        );
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client
+                .measure_clock(Src::Clock(ClockSrc::Hdmi))
+                .unwrap_err()
+                .kind()
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! Canned vcgencmd output for tests, with the `test-util` feature
+//!
+//! A `MockExecutor` answers a `Vcgencmd` client from replies keyed by command and source,
+//! so monitoring logic built on the client can be unit tested on CI machines without a Pi.
+//! `MockExecutor::pi4` and `MockExecutor::pi5` start out with the output of an idle board,
+//! which single replies can be replaced in, even while a monitor is sampling:
+//!
+//! ```rust
+//! use vcgencmd::mock::MockExecutor;
+//! use vcgencmd::{Cmd, Vcgencmd};
+//!
+//! let mock = MockExecutor::pi4();
+//! let client = Vcgencmd::with_executor(mock.clone());
+//! assert!(!client.is_throttled()?);
+//!
+//! mock.set_reply(Cmd::GetThrottled, None, "throttled=0x50005\n");
+//! assert!(client.is_throttled()?);
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+//!
+//! Commands without a reply get the one of the firmware for unknown commands,
+//! `error=1 error_msg="Command not registered"`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::executor::{Executor, INVALID_ARGUMENTS, NOT_REGISTERED};
+use crate::{ClockSrc, Cmd, ConfigSrc, ExecutionError, MemSrc, Src, VoltSrc};
+
+const COMMANDS: &str = "commands=\"vcos, ap_output_control, ap_output_post_processing, \
+     commands, set_logging, bootloader_config, bootloader_version, cache_flush, \
+     codec_enabled, get_mem, get_rsts, measure_clock, measure_volts, enable_clock, \
+     scaling_kernel, scaling_sharpness, get_hvs_asserts, get_throttled, measure_temp, \
+     get_config, hdmi_ntsc_freqs, hdmi_adjust_clock, hdmi_status_show, hvs_update_fields, \
+     pwm_speedup, force_audio, hdmi_stream_channels, hdmi_channel_map, display_power, \
+     memtest, dispmanx_list, get_lcd_info, arbiter, otp_dump, test_result, \
+     mem_reloc_stats, hdmi_timings, file, vctest_memmap, vctest_start, vctest_stop, \
+     vctest_set, vctest_get, version\"\n";
+
+/// Replies shared by the Raspberry Pi 4 and 5
+const COMMON: &[(Cmd, Option<Src>, &str)] = &[
+    (Cmd::GetThrottled, None, "throttled=0x0\n"),
+    (
+        Cmd::Version,
+        None,
+        "Mar 17 2023 10:50:39 \nCopyright (c) 2012 Broadcom\n\
+         version 82f3750a65fadae9a38077e3c2e217ad158c8d54 (clean) (release) (start)\n",
+    ),
+    (Cmd::GetLcdInfo, None, "1920 1080 24\n"),
+    (Cmd::HdmiTimings, None, "hdmi_timings=\n"),
+    (
+        Cmd::DisplayPower,
+        Some(Src::Display(2)),
+        "display_power=1\n",
+    ),
+    (
+        Cmd::DisplayPower,
+        Some(Src::Display(7)),
+        "display_power=-1\n",
+    ),
+    (
+        Cmd::MemRelocStats,
+        None,
+        "alloc failures:     0\ncompactions:        0\nlegacy block fails: 0\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::TempLimit)),
+        "temp_limit=85\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::OverVoltage)),
+        "over_voltage=0\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::TotalMem)),
+        "total_mem=4096\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::H264)),
+        "frequency(28)=0\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Isp)),
+        "frequency(45)=0\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Uart)),
+        "frequency(22)=48001464\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Pwm)),
+        "frequency(25)=0\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Emmc)),
+        "frequency(50)=250000496\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Pixel)),
+        "frequency(29)=148500000\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Vec)),
+        "frequency(10)=0\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Hdmi)),
+        "frequency(0)=0\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Dpi)),
+        "frequency(4)=0\n",
+    ),
+];
+
+const PI4: &[(Cmd, Option<Src>, &str)] = &[
+    (Cmd::Commands, None, COMMANDS),
+    (Cmd::MeasureTemp, None, "temp=48.3'C\n"),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Arm)),
+        "frequency(48)=1500345728\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Core)),
+        "frequency(1)=500000992\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::V3d)),
+        "frequency(46)=500000992\n",
+    ),
+    (
+        Cmd::MeasureVolts,
+        Some(Src::Volt(VoltSrc::Core)),
+        "volt=0.8500V\n",
+    ),
+    (
+        Cmd::MeasureVolts,
+        Some(Src::Volt(VoltSrc::SdramC)),
+        "volt=1.1000V\n",
+    ),
+    (
+        Cmd::MeasureVolts,
+        Some(Src::Volt(VoltSrc::SdramI)),
+        "volt=1.1000V\n",
+    ),
+    (
+        Cmd::MeasureVolts,
+        Some(Src::Volt(VoltSrc::SdramP)),
+        "volt=1.1000V\n",
+    ),
+    (Cmd::GetMem, Some(Src::Mem(MemSrc::Arm)), "arm=948M\n"),
+    (Cmd::GetMem, Some(Src::Mem(MemSrc::Gpu)), "gpu=76M\n"),
+    (
+        Cmd::GetMem,
+        Some(Src::Mem(MemSrc::MallocTotal)),
+        "malloc_total=9M\n",
+    ),
+    (Cmd::GetMem, Some(Src::Mem(MemSrc::Malloc)), "malloc=8M\n"),
+    (
+        Cmd::GetMem,
+        Some(Src::Mem(MemSrc::RelocTotal)),
+        "reloc_total=58M\n",
+    ),
+    (Cmd::GetMem, Some(Src::Mem(MemSrc::Reloc)), "reloc=54M\n"),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::TempSoftLimit)),
+        "temp_soft_limit=60\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::ArmFreq)),
+        "arm_freq=1500\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::CoreFreq)),
+        "core_freq=500\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::GpuFreq)),
+        "gpu_freq=500\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::GpuMem)),
+        "gpu_mem=76\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::SdramFreq)),
+        "sdram_freq=3200\n",
+    ),
+];
+
+const PI5: &[(Cmd, Option<Src>, &str)] = &[
+    (Cmd::MeasureTemp, None, "temp=52.7'C\n"),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Arm)),
+        "frequency(0)=2400008704\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::Core)),
+        "frequency(0)=910007424\n",
+    ),
+    (
+        Cmd::MeasureClock,
+        Some(Src::Clock(ClockSrc::V3d)),
+        "frequency(0)=960012032\n",
+    ),
+    (
+        Cmd::MeasureVolts,
+        Some(Src::Volt(VoltSrc::Core)),
+        "volt=0.7200V\n",
+    ),
+    (
+        Cmd::MeasureVolts,
+        Some(Src::Volt(VoltSrc::SdramC)),
+        INVALID_ARGUMENTS,
+    ),
+    (
+        Cmd::MeasureVolts,
+        Some(Src::Volt(VoltSrc::SdramI)),
+        INVALID_ARGUMENTS,
+    ),
+    (
+        Cmd::MeasureVolts,
+        Some(Src::Volt(VoltSrc::SdramP)),
+        INVALID_ARGUMENTS,
+    ),
+    (Cmd::GetMem, Some(Src::Mem(MemSrc::Arm)), "arm=1020M\n"),
+    (Cmd::GetMem, Some(Src::Mem(MemSrc::Gpu)), "gpu=4M\n"),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::TempSoftLimit)),
+        "temp_soft_limit=0\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::ArmFreq)),
+        "arm_freq=2400\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::CoreFreq)),
+        "core_freq=910\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::GpuFreq)),
+        "gpu_freq=960\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::GpuMem)),
+        "gpu_mem=4\n",
+    ),
+    (
+        Cmd::GetConfig,
+        Some(Src::Config(ConfigSrc::SdramFreq)),
+        "sdram_freq=4267\n",
+    ),
+    (
+        Cmd::PmicReadAdc,
+        None,
+        "   3V3_SYS_A current(1)=0.07320000A\n  VDD_CORE_A current(7)=0.91480000A\n \
+         3V3_SYS_V volt(9)=3.31440000V\n  VDD_CORE_V volt(15)=0.72080000V\n \
+         EXT5V_V volt(24)=5.13768000V\n",
+    ),
+];
+
+#[derive(Debug, Default)]
+struct Shared {
+    replies: Mutex<HashMap<(Cmd, Option<Src>), String>>,
+    calls: Mutex<Vec<(Cmd, Option<Src>)>>,
+}
+
+/// An `Executor` answering from canned replies, see the module documentation.
+///
+/// Clones share the same replies and calls, so a test can keep one to change replies in
+/// and check calls on after handing another to a client.
+#[derive(Debug, Clone, Default)]
+pub struct MockExecutor {
+    shared: Arc<Shared>,
+}
+
+impl MockExecutor {
+    /// A mock without any replies, add them with `reply`
+    pub fn new() -> MockExecutor {
+        MockExecutor::default()
+    }
+
+    /// A mock answering like an idle Raspberry Pi 4 with 4 GB
+    pub fn pi4() -> MockExecutor {
+        MockExecutor::with_fixtures(&[COMMON, PI4])
+    }
+
+    /// A mock answering like an idle Raspberry Pi 5, which has no SDRAM rails to measure,
+    /// but a PMIC with ADCs.
+    ///
+    /// Its `commands` isn't mocked, as the firmware of the Pi 5 doesn't list them either.
+    pub fn pi5() -> MockExecutor {
+        MockExecutor::with_fixtures(&[COMMON, PI5])
+    }
+
+    fn with_fixtures(fixtures: &[&[(Cmd, Option<Src>, &str)]]) -> MockExecutor {
+        let mock = MockExecutor::new();
+        for &(cmd, src, output) in fixtures.iter().copied().flatten() {
+            mock.set_reply(cmd, src, output);
+        }
+        mock
+    }
+
+    /// Reply to `cmd` with `src` with `output`, e.g. `temp=48.3'C\n`
+    pub fn reply(self, cmd: Cmd, src: Option<Src>, output: &str) -> MockExecutor {
+        self.set_reply(cmd, src, output);
+        self
+    }
+
+    /// Reply to `cmd` with `src` like the firmware does to sources it doesn't support
+    pub fn unsupported(self, cmd: Cmd, src: Option<Src>) -> MockExecutor {
+        self.reply(cmd, src, INVALID_ARGUMENTS)
+    }
+
+    /// Replace the reply to `cmd` with `src` from now on, also for clients already using
+    /// a clone of this mock
+    pub fn set_reply(&self, cmd: Cmd, src: Option<Src>, output: &str) {
+        let mut replies = self
+            .shared
+            .replies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        replies.insert((cmd, src), output.to_owned());
+    }
+
+    /// Every command run so far, in order
+    pub fn calls(&self) -> Vec<(Cmd, Option<Src>)> {
+        let calls = self.shared.calls.lock().unwrap_or_else(|e| e.into_inner());
+        calls.clone()
+    }
+}
+
+impl Executor for MockExecutor {
+    fn run(&self, cmd: Cmd, src: Option<Src>) -> Result<String, ExecutionError> {
+        let mut calls = self.shared.calls.lock().unwrap_or_else(|e| e.into_inner());
+        calls.push((cmd, src));
+        drop(calls);
+
+        let replies = self
+            .shared
+            .replies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let reply = replies.get(&(cmd, src)).map(String::as_str);
+        Ok(reply.unwrap_or(NOT_REGISTERED).to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{Metric, Reading};
+    use crate::{ErrorKind, Vcgencmd};
+
+    #[test]
+    fn test_pi4() {
+        let client = Vcgencmd::with_executor(MockExecutor::pi4());
+
+        assert_eq!(48.3, client.measure_temp().unwrap());
+        assert_eq!(
+            ClockSrc::ALL.len(),
+            client.measure_clock_all().unwrap().len()
+        );
+        assert_eq!(Some(1.1), client.measure_volts_all().unwrap().sdram_p);
+        assert_eq!(1024, client.get_mem_split().unwrap().total);
+        assert_eq!(60.0, client.temp_limits().unwrap().soft);
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client.pmic_read_adc().unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_pi5() {
+        let client = Vcgencmd::with_executor(MockExecutor::pi5());
+
+        let rails = client.measure_volts_all().unwrap();
+        assert_eq!((Some(0.72), None), (rails.core, rails.sdram_c));
+        assert_eq!(5, client.pmic_read_adc().unwrap().len());
+        // an unset soft limit falls back to the default
+        assert_eq!(60.0, client.temp_limits().unwrap().soft);
+        assert_eq!(None, client.display_power(7).unwrap());
+    }
+
+    #[test]
+    fn test_replies_and_calls() {
+        let mock = MockExecutor::new()
+            .reply(Cmd::MeasureTemp, None, "temp=41.0'C\n")
+            .unsupported(Cmd::MeasureVolts, Some(Src::Volt(VoltSrc::SdramC)));
+        let client = Vcgencmd::with_executor(mock.clone());
+
+        assert_eq!(Reading::Temp(41.0), client.read(Metric::Temp).unwrap());
+        mock.set_reply(Cmd::MeasureTemp, None, "temp=72.5'C\n");
+        assert_eq!(72.5, client.measure_temp().unwrap());
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client
+                .measure_volts(Src::Volt(VoltSrc::SdramC))
+                .unwrap_err()
+                .kind()
+        );
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client.get_throttled().unwrap_err().kind()
+        );
+        assert_eq!(4, mock.calls().len());
+    }
+}
@@ -0,0 +1,1452 @@
+//! Periodic sampling of metrics, handing the results to a set of sinks
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::child;
+use crate::sink::{Backpressure, MetricSink, QueuedSink, SinkStats};
+use crate::snapshot::{temp_limits_of, Snapshot};
+use crate::thermal::{measure_temp_headroom, TempHeadroom};
+use crate::{
+    get_mem, get_throttled, json, measure_clock, measure_temp, measure_volts, resolve_src, timefmt,
+    ClockSrc, Error, MemSrc, Result, Src, VoltSrc,
+};
+
+/// Upper bound for a single sleep while waiting for the next sample, so that stop
+/// requests are noticed in time
+const WAIT_GRANULARITY: Duration = Duration::from_millis(100);
+/// How long a stopping monitor waits for its queued sinks to catch up
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A quantity the monitor can sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Temp,
+    /// Temperature compared with the configured throttling limits
+    TempHeadroom,
+    Throttled,
+    Clock(ClockSrc),
+    Volts(VoltSrc),
+    Mem(MemSrc),
+    /// Computed from the other metrics by a closure registered with `Monitor::derive`
+    Derived(&'static str),
+}
+
+impl Metric {
+    /// A short name like `temp` or `clock.arm`, sources are named as vcgencmd names them
+    pub fn name(&self) -> String {
+        match *self {
+            Metric::Temp => "temp".to_owned(),
+            Metric::TempHeadroom => "temp_headroom".to_owned(),
+            Metric::Throttled => "throttled".to_owned(),
+            Metric::Clock(src) => format!("clock.{}", source_name(Src::Clock(src))),
+            Metric::Volts(src) => format!("volts.{}", source_name(Src::Volt(src))),
+            Metric::Mem(src) => format!("mem.{}", source_name(Src::Mem(src))),
+            Metric::Derived(name) => name.to_owned(),
+        }
+    }
+
+    /// The metric called `name`, the inverse of `Metric::name` except for derived metrics
+    pub fn from_name(name: &str) -> Option<Metric> {
+        let sources = ClockSrc::ALL
+            .iter()
+            .map(|&src| Metric::Clock(src))
+            .chain(VoltSrc::ALL.iter().map(|&src| Metric::Volts(src)))
+            .chain(MemSrc::ALL.iter().map(|&src| Metric::Mem(src)));
+
+        [Metric::Temp, Metric::TempHeadroom, Metric::Throttled]
+            .iter()
+            .copied()
+            .chain(sources)
+            .find(|metric| metric.name() == name)
+    }
+
+    /// Take a single reading of this metric by invoking vcgencmd.
+    ///
+    /// Derived metrics can't be read, they only exist on the monitor computing them.
+    pub fn read(self) -> Result<Reading> {
+        let reading = match self {
+            Metric::Temp => Reading::Temp(measure_temp()?),
+            Metric::TempHeadroom => Reading::TempHeadroom(measure_temp_headroom()?),
+            Metric::Throttled => Reading::Throttled(get_throttled()?),
+            Metric::Clock(src) => Reading::Clock(src, measure_clock(Src::Clock(src))?),
+            Metric::Volts(src) => Reading::Volts(src, measure_volts(Src::Volt(src))?),
+            Metric::Mem(src) => Reading::Mem(src, get_mem(Src::Mem(src))?),
+            Metric::Derived(name) => {
+                return Err(Error::Unsupported {
+                    command: name.to_owned(),
+                    code: 1,
+                    message: "derived metrics are computed by Monitor::derive".to_owned(),
+                })
+            }
+        };
+
+        Ok(reading)
+    }
+}
+
+/// Metrics are (de)serialized by `Metric::name`, e.g. `"clock.arm"`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Metric {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Metric {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Metric, D::Error> {
+        use serde::de::Error as _;
+
+        let name = String::deserialize(deserializer)?;
+        Metric::from_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown metric '{}'", name)))
+    }
+}
+
+fn source_name(src: Src) -> String {
+    resolve_src(Some(src)).unwrap_or_default()
+}
+
+/// The value of a single metric, in the units the free functions return
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reading {
+    /// Temperature in °C
+    Temp(f64),
+    TempHeadroom(TempHeadroom),
+    /// Bit pattern as returned by `get_throttled`
+    Throttled(isize),
+    /// Frequency in Hz
+    Clock(ClockSrc, isize),
+    /// Voltage in V
+    Volts(VoltSrc, f64),
+    /// Memory in MB
+    Mem(MemSrc, isize),
+    /// The value of a derived metric, in whatever unit its closure returns
+    Derived(&'static str, f64),
+}
+
+impl Reading {
+    /// The metric this reading belongs to
+    pub fn metric(&self) -> Metric {
+        match *self {
+            Reading::Temp(_) => Metric::Temp,
+            Reading::TempHeadroom(_) => Metric::TempHeadroom,
+            Reading::Throttled(_) => Metric::Throttled,
+            Reading::Clock(src, _) => Metric::Clock(src),
+            Reading::Volts(src, _) => Metric::Volts(src),
+            Reading::Mem(src, _) => Metric::Mem(src),
+            Reading::Derived(name, _) => Metric::Derived(name),
+        }
+    }
+
+    /// The reading as a number in its unit, `None` for the throttled bit pattern
+    pub fn value(&self) -> Option<f64> {
+        match *self {
+            Reading::Temp(temp) => Some(temp),
+            Reading::TempHeadroom(headroom) => Some(headroom.temp),
+            Reading::Throttled(_) => None,
+            Reading::Clock(_, hz) => Some(hz as f64),
+            Reading::Volts(_, volts) => Some(volts),
+            Reading::Mem(_, mb) => Some(mb as f64),
+            Reading::Derived(_, value) => Some(value),
+        }
+    }
+}
+
+/// The outcome of sampling every configured metric once
+#[derive(Debug)]
+pub struct Sample {
+    /// Wall-clock time the metrics were read at, in JSON as an RFC 3339 UTC timestamp.
+    ///
+    /// With the `chrono` or `time` feature it is also available as a calendar type, see
+    /// `date_time` and `offset_date_time`.
+    pub timestamp: SystemTime,
+    pub readings: Vec<Reading>,
+    pub errors: Vec<(Metric, Error)>,
+}
+
+impl Sample {
+    /// Whether at least one metric could be read
+    pub fn is_ok(&self) -> bool {
+        !self.readings.is_empty()
+    }
+
+    /// Look up the reading of a specific metric
+    pub fn get(&self, metric: Metric) -> Option<&Reading> {
+        self.readings.iter().find(|r| r.metric() == metric)
+    }
+
+    /// `timestamp` as a `chrono` date and time in UTC
+    #[cfg(feature = "chrono")]
+    pub fn date_time(&self) -> chrono::DateTime<chrono::Utc> {
+        self.timestamp.into()
+    }
+
+    /// `timestamp` as a `time` date and time in UTC
+    #[cfg(feature = "time")]
+    pub fn offset_date_time(&self) -> time::OffsetDateTime {
+        self.timestamp.into()
+    }
+
+    /// The sample as a JSON object, readings and errors keyed by `Metric::name`
+    pub fn to_json(&self) -> String {
+        let readings: Vec<_> = self
+            .readings
+            .iter()
+            .map(|reading| {
+                let value = match *reading {
+                    Reading::Temp(temp) => json::number(temp),
+                    Reading::TempHeadroom(headroom) => json::object(&[
+                        ("temp", json::number(headroom.temp)),
+                        ("soft_limit", json::number(headroom.soft_limit)),
+                        ("hard_limit", json::number(headroom.hard_limit)),
+                    ]),
+                    Reading::Throttled(value)
+                    | Reading::Clock(_, value)
+                    | Reading::Mem(_, value) => value.to_string(),
+                    Reading::Volts(_, volts) | Reading::Derived(_, volts) => json::number(volts),
+                };
+                (reading.metric().name(), value)
+            })
+            .collect();
+        let errors: Vec<_> = self
+            .errors
+            .iter()
+            .map(|(metric, error)| (metric.name(), json::string(&error.to_string())))
+            .collect();
+
+        let borrow = |fields: &[(String, String)]| {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.clone()))
+                .collect();
+            json::object(&fields)
+        };
+
+        json::object(&[
+            ("timestamp", json::string(&timefmt::rfc3339(self.timestamp))),
+            ("readings", borrow(&readings)),
+            ("errors", borrow(&errors)),
+        ])
+    }
+}
+
+/// Serialized like `Sample::to_json`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Sample {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        struct Readings<'a>(&'a [Reading]);
+
+        impl serde::Serialize for Readings<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+
+                let mut readings = serializer.serialize_map(Some(self.0.len()))?;
+                for reading in self.0 {
+                    let name = reading.metric().name();
+                    match *reading {
+                        Reading::TempHeadroom(headroom) => {
+                            readings.serialize_entry(&name, &headroom)?
+                        }
+                        Reading::Throttled(value)
+                        | Reading::Clock(_, value)
+                        | Reading::Mem(_, value) => readings.serialize_entry(&name, &value)?,
+                        Reading::Temp(value)
+                        | Reading::Volts(_, value)
+                        | Reading::Derived(_, value) => readings.serialize_entry(&name, &value)?,
+                    }
+                }
+                readings.end()
+            }
+        }
+
+        struct Errors<'a>(&'a [(Metric, Error)]);
+
+        impl serde::Serialize for Errors<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_map(
+                    self.0
+                        .iter()
+                        .map(|(metric, error)| (metric.name(), error.to_string())),
+                )
+            }
+        }
+
+        let mut sample = serializer.serialize_struct("Sample", 3)?;
+        sample.serialize_field("timestamp", &timefmt::rfc3339(self.timestamp))?;
+        sample.serialize_field("readings", &Readings(&self.readings))?;
+        sample.serialize_field("errors", &Errors(&self.errors))?;
+        sample.end()
+    }
+}
+
+type Sampler = Box<dyn FnMut(Metric) -> Result<Reading> + Send>;
+type Deriver = Box<dyn FnMut(&Snapshot) -> Option<f64> + Send>;
+type Sink = Box<dyn FnMut(&Sample) + Send>;
+
+enum SinkKind {
+    /// Called on the monitor thread
+    Direct(Sink),
+    /// Running on a thread of its own
+    Queued(QueuedSink),
+    /// Called on the monitor thread, counting failed writes
+    Metric(Box<dyn MetricSink>, Arc<SinkStats>),
+    /// Called on the monitor thread, keeping the sample beyond the call, and with `None`
+    /// once the monitor thread stops
+    Shared(Box<dyn FnMut(Option<Arc<Sample>>) + Send>),
+}
+
+/// Identifies a sink, so it can be removed from a monitor again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SinkId(usize);
+
+impl SinkId {
+    fn next() -> SinkId {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        SinkId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A metric together with the rate it is sampled at
+struct Scheduled {
+    metric: Metric,
+    /// Overrides the monitor's interval if set
+    interval: Option<Duration>,
+    /// The slot on the drift-free grid of `interval`s this metric is due at next
+    nominal_due: Option<Instant>,
+    /// `nominal_due` with jitter applied
+    next_due: Option<Instant>,
+}
+
+/// Randomizes sampling times, so that many devices started together don't sample in lockstep.
+///
+/// A small xorshift generator is plenty for spreading out samples, there's no need for
+/// anything cryptographically sound here.
+pub(crate) struct Jitter {
+    max: Duration,
+    state: u64,
+}
+
+impl Jitter {
+    pub(crate) fn new(max: Duration) -> Jitter {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        // mix in the pid, devices booting in the same nanosecond are unlikely to share it
+        let seed = nanos ^ (u64::from(std::process::id()) << 32);
+
+        Jitter {
+            max,
+            state: seed | 1,
+        }
+    }
+
+    /// A random offset in `[0, max)`
+    pub(crate) fn offset(&mut self) -> Duration {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+
+        match self.max.as_nanos() as u64 {
+            0 => Duration::from_secs(0),
+            max => Duration::from_nanos(self.state % max),
+        }
+    }
+}
+
+/// The first slot on the grid `previous + n * interval` that lies after `now`.
+///
+/// Scheduling off the previous slot rather than the time a sample actually finished keeps
+/// the error from accumulating over long captures, and slots that were missed entirely,
+/// e.g. because the system was suspended, are skipped instead of being sampled in a burst.
+pub(crate) fn next_slot(previous: Instant, interval: Duration, now: Instant) -> Instant {
+    let next = previous + interval;
+    if next > now {
+        return next;
+    }
+
+    match interval.as_nanos() {
+        0 => now,
+        interval_nanos => {
+            let missed = (now - previous).as_nanos() / interval_nanos;
+            previous + Duration::from_nanos(((missed + 1) * interval_nanos) as u64)
+        }
+    }
+}
+
+/// Samples a set of metrics periodically and passes every `Sample` to its sinks.
+///
+/// Every metric is sampled at the monitor's interval, unless it was added with an interval
+/// of its own. Metrics falling due at the same time end up in the same `Sample`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use vcgencmd::monitor::{Metric, Monitor};
+/// use vcgencmd::ClockSrc;
+///
+/// let mut monitor = Monitor::new(Duration::from_secs(1))
+///     .metric(Metric::Temp)
+///     .metric(Metric::Clock(ClockSrc::Arm))
+///     .metric_every(Metric::Throttled, Duration::from_millis(250))
+///     .sink(|sample| println!("{:?}", sample.readings));
+///
+/// let sample = monitor.sample();
+/// ```
+pub struct Monitor {
+    interval: Duration,
+    metrics: Vec<Scheduled>,
+    sinks: Vec<(SinkId, SinkKind)>,
+    sampler: Sampler,
+    jitter: Option<Jitter>,
+    derived: Vec<(&'static str, Deriver)>,
+    /// The last reading of every metric, so derived metrics also see the ones not due
+    latest: Vec<Reading>,
+}
+
+impl Monitor {
+    /// Create a monitor without any metrics or sinks
+    pub fn new(interval: Duration) -> Monitor {
+        Monitor {
+            interval,
+            metrics: Vec::new(),
+            sinks: Vec::new(),
+            sampler: Box::new(Metric::read),
+            jitter: None,
+            derived: Vec::new(),
+            latest: Vec::new(),
+        }
+    }
+
+    /// Add a metric to sample
+    pub fn metric(mut self, metric: Metric) -> Monitor {
+        self.add_metric(metric);
+        self
+    }
+
+    /// Add a metric sampled at its own `interval` instead of the monitor's
+    pub fn metric_every(mut self, metric: Metric, interval: Duration) -> Monitor {
+        self.add_metric_every(metric, interval);
+        self
+    }
+
+    /// Delay every sample by a random amount of up to `max`, see `set_jitter`
+    pub fn jitter(mut self, max: Duration) -> Monitor {
+        self.set_jitter(Some(max));
+        self
+    }
+
+    /// Add a sink receiving every sample
+    pub fn sink<F>(mut self, sink: F) -> Monitor
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        self.add_sink(sink);
+        self
+    }
+
+    /// Add a sink running on its own thread, see `add_queued_sink`
+    pub fn queued_sink<F>(mut self, sink: F, capacity: usize, policy: Backpressure) -> Monitor
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        self.add_queued_sink(sink, capacity, policy);
+        self
+    }
+
+    /// Add a `MetricSink` receiving every sample, see `add_metric_sink`
+    pub fn metric_sink<S: MetricSink + 'static>(mut self, sink: S) -> Monitor {
+        self.add_metric_sink(sink);
+        self
+    }
+
+    /// Add a `MetricSink` running on its own thread, see `add_queued_metric_sink`
+    pub fn queued_metric_sink<S: MetricSink + 'static>(
+        mut self,
+        sink: S,
+        capacity: usize,
+        policy: Backpressure,
+    ) -> Monitor {
+        self.add_queued_metric_sink(sink, capacity, policy);
+        self
+    }
+
+    /// Replace the way readings are taken, which defaults to `Metric::read`
+    pub fn sampler<F>(mut self, sampler: F) -> Monitor
+    where
+        F: FnMut(Metric) -> Result<Reading> + Send + 'static,
+    {
+        self.sampler = Box::new(sampler);
+        self
+    }
+
+    /// Add a derived metric, see `add_derived`
+    pub fn derive<F>(mut self, name: &'static str, derive: F) -> Monitor
+    where
+        F: FnMut(&Snapshot) -> Option<f64> + Send + 'static,
+    {
+        self.add_derived(name, derive);
+        self
+    }
+
+    pub fn add_metric(&mut self, metric: Metric) {
+        self.schedule(metric, None);
+    }
+
+    /// Add a metric sampled at its own `interval`, or change the interval of a present one
+    pub fn add_metric_every(&mut self, metric: Metric, interval: Duration) {
+        self.schedule(metric, Some(interval));
+    }
+
+    fn schedule(&mut self, metric: Metric, interval: Option<Duration>) {
+        match self.metrics.iter_mut().find(|s| s.metric == metric) {
+            Some(scheduled) => scheduled.interval = interval,
+            None => self.metrics.push(Scheduled {
+                metric,
+                interval,
+                nominal_due: None,
+                next_due: None,
+            }),
+        }
+    }
+
+    /// Compute the metric `name` from every sample, or replace the closure of a present one.
+    ///
+    /// `derive` gets a snapshot of the last reading of every metric, including the ones
+    /// sampled at a slower rate, and its result is added to the sample as a
+    /// `Reading::Derived` before the sinks see it, so exporters and thresholds treat it like
+    /// any other metric. Returning `None`, e.g. because an input wasn't read yet, leaves the
+    /// metric out of that sample. The temperature limits of the snapshot are the ones of the
+    /// last `Metric::TempHeadroom` reading, the firmware defaults without one.
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use vcgencmd::monitor::{Metric, Monitor};
+    ///
+    /// let monitor = Monitor::new(Duration::from_secs(1))
+    ///     .metric(Metric::TempHeadroom)
+    ///     .derive("temp_to_soft_limit", |snapshot| {
+    ///         snapshot.temp_headroom().map(|headroom| headroom.to_soft_limit())
+    ///     });
+    /// ```
+    pub fn add_derived<F>(&mut self, name: &'static str, derive: F)
+    where
+        F: FnMut(&Snapshot) -> Option<f64> + Send + 'static,
+    {
+        match self.derived.iter_mut().find(|(known, _)| *known == name) {
+            Some((_, known)) => *known = Box::new(derive),
+            None => self.derived.push((name, Box::new(derive))),
+        }
+    }
+
+    /// Stop computing the derived metric `name`, returns whether it was computed before
+    pub fn remove_derived(&mut self, name: &str) -> bool {
+        let len = self.derived.len();
+        self.derived.retain(|(known, _)| *known != name);
+        self.derived.len() != len
+    }
+
+    /// Stop sampling `metric`, returns whether it was sampled before
+    pub fn remove_metric(&mut self, metric: Metric) -> bool {
+        let len = self.metrics.len();
+        self.metrics.retain(|s| s.metric != metric);
+        self.metrics.len() != len
+    }
+
+    pub fn add_sink<F>(&mut self, sink: F) -> SinkId
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        let id = SinkId::next();
+        self.sinks.push((id, SinkKind::Direct(Box::new(sink))));
+        id
+    }
+
+    /// Add a sink that runs on a thread of its own, fed through a queue of `capacity` samples.
+    ///
+    /// Plain sinks are called on the monitor thread, so a slow one, e.g. a network exporter
+    /// waiting for a stalled server, holds up sampling. A queued sink only does so with
+    /// `Backpressure::Block`, the other policies drop samples once the queue is full and
+    /// count them in the returned stats. Removing or dropping a queued sink waits until
+    /// the samples already queued were delivered.
+    pub fn add_queued_sink<F>(
+        &mut self,
+        sink: F,
+        capacity: usize,
+        policy: Backpressure,
+    ) -> (SinkId, Arc<SinkStats>)
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        let id = SinkId::next();
+        let queued = QueuedSink::spawn(sink, capacity, policy);
+        let stats = queued.stats();
+        self.sinks.push((id, SinkKind::Queued(queued)));
+        (id, stats)
+    }
+
+    /// Add a `MetricSink` called on the monitor thread like a plain sink.
+    ///
+    /// Failed writes are counted in the returned stats, `flush_sinks` flushes it.
+    pub fn add_metric_sink<S: MetricSink + 'static>(
+        &mut self,
+        sink: S,
+    ) -> (SinkId, Arc<SinkStats>) {
+        let id = SinkId::next();
+        let stats = Arc::new(SinkStats::default());
+        self.sinks
+            .push((id, SinkKind::Metric(Box::new(sink), Arc::clone(&stats))));
+        (id, stats)
+    }
+
+    /// Add a `MetricSink` on a thread of its own, see `add_queued_sink`.
+    ///
+    /// The sink is flushed whenever it caught up with the queue.
+    pub fn add_queued_metric_sink<S: MetricSink + 'static>(
+        &mut self,
+        sink: S,
+        capacity: usize,
+        policy: Backpressure,
+    ) -> (SinkId, Arc<SinkStats>) {
+        let id = SinkId::next();
+        let queued = QueuedSink::spawn_metric(sink, capacity, policy);
+        let stats = queued.stats();
+        self.sinks.push((id, SinkKind::Queued(queued)));
+        (id, stats)
+    }
+
+    /// Add a sink receiving the shared sample, so it can hold on to it without copying
+    pub(crate) fn add_shared_sink<F>(&mut self, sink: F) -> SinkId
+    where
+        F: FnMut(Option<Arc<Sample>>) + Send + 'static,
+    {
+        let id = SinkId::next();
+        self.sinks.push((id, SinkKind::Shared(Box::new(sink))));
+        id
+    }
+
+    /// Delivery statistics of all queued sinks and `MetricSink`s
+    pub fn sink_stats(&self) -> Vec<(SinkId, Arc<SinkStats>)> {
+        self.sinks
+            .iter()
+            .filter_map(|(id, sink)| match sink {
+                SinkKind::Queued(queued) => Some((*id, queued.stats())),
+                SinkKind::Metric(_, stats) => Some((*id, Arc::clone(stats))),
+                SinkKind::Direct(_) | SinkKind::Shared(_) => None,
+            })
+            .collect()
+    }
+
+    /// Flush the `MetricSink`s called on the monitor thread, and wait until the queued
+    /// sinks delivered every sample handed to them so far, for at most `timeout` in total.
+    /// Returns whether all of them caught up in time.
+    ///
+    /// Plain sinks are called on the monitor thread and have nothing to flush.
+    pub fn flush_sinks(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut caught_up = true;
+        for (_, sink) in &mut self.sinks {
+            match sink {
+                SinkKind::Queued(queued) => {
+                    caught_up &= queued.flush(deadline.saturating_duration_since(Instant::now()))
+                }
+                SinkKind::Metric(sink, stats) => stats.record(sink.flush(), false),
+                SinkKind::Direct(_) | SinkKind::Shared(_) => {}
+            }
+        }
+
+        caught_up
+    }
+
+    /// Remove a sink, returns whether it was present
+    pub fn remove_sink(&mut self, id: SinkId) -> bool {
+        let len = self.sinks.len();
+        self.sinks.retain(|(sink_id, _)| *sink_id != id);
+        self.sinks.len() != len
+    }
+
+    pub fn metrics(&self) -> Vec<Metric> {
+        self.metrics.iter().map(|s| s.metric).collect()
+    }
+
+    /// The interval `metric` is sampled at, if it is sampled at all
+    pub fn metric_interval(&self, metric: Metric) -> Option<Duration> {
+        let scheduled = self.metrics.iter().find(|s| s.metric == metric)?;
+        Some(scheduled.interval.unwrap_or(self.interval))
+    }
+
+    /// Delay every sample by a random amount of up to `max`, or disable jitter with `None`.
+    ///
+    /// Samples are still taken on average once per interval, as the jitter is applied
+    /// to each slot of the schedule and doesn't push back the following ones.
+    pub fn set_jitter(&mut self, max: Option<Duration>) {
+        self.jitter = max.map(Jitter::new);
+    }
+
+    /// The default interval, used for all metrics without one of their own
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Sample every metric once, regardless of when it is due, and pass the result to all sinks
+    pub fn sample(&mut self) -> Arc<Sample> {
+        let now = Instant::now();
+        self.sample_where(|_| true, now)
+    }
+
+    /// Sample the metrics that are due at `now`, returns `None` if there were none
+    pub(crate) fn sample_due(&mut self, now: Instant) -> Option<Arc<Sample>> {
+        if !self.metrics.iter().any(|s| is_due(s, now)) {
+            return None;
+        }
+
+        Some(self.sample_where(|s| is_due(s, now), now))
+    }
+
+    /// When the next metric falls due, `None` if there are no metrics
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.metrics.iter().map(|s| s.next_due.unwrap_or(now)).min()
+    }
+
+    fn sample_where<F>(&mut self, selected: F, now: Instant) -> Arc<Sample>
+    where
+        F: Fn(&Scheduled) -> bool,
+    {
+        let mut sample = Sample {
+            timestamp: SystemTime::now(),
+            readings: Vec::with_capacity(self.metrics.len()),
+            errors: Vec::new(),
+        };
+
+        for scheduled in self.metrics.iter_mut().filter(|s| selected(s)) {
+            let interval = scheduled.interval.unwrap_or(self.interval);
+            let nominal_due = match scheduled.nominal_due {
+                Some(previous) => next_slot(previous, interval, now),
+                None => now + interval,
+            };
+            let jitter = self.jitter.as_mut().map(Jitter::offset).unwrap_or_default();
+
+            scheduled.nominal_due = Some(nominal_due);
+            scheduled.next_due = Some(nominal_due + jitter);
+
+            match (self.sampler)(scheduled.metric) {
+                Ok(reading) => sample.readings.push(reading),
+                Err(e) => sample.errors.push((scheduled.metric, e)),
+            }
+        }
+        if !self.derived.is_empty() {
+            self.derive_readings(&mut sample);
+        }
+
+        // shared, so queued sinks can hold on to it without copying
+        let sample = Arc::new(sample);
+        for (_, sink) in &mut self.sinks {
+            match sink {
+                SinkKind::Direct(sink) => sink(&sample),
+                SinkKind::Queued(queued) => queued.push(Arc::clone(&sample)),
+                SinkKind::Metric(sink, stats) => stats.record(sink.write(&sample), true),
+                SinkKind::Shared(sink) => sink(Some(Arc::clone(&sample))),
+            }
+        }
+
+        sample
+    }
+
+    /// Add the readings of the derived metrics to `sample`
+    fn derive_readings(&mut self, sample: &mut Sample) {
+        for reading in &sample.readings {
+            match self
+                .latest
+                .iter_mut()
+                .find(|r| r.metric() == reading.metric())
+            {
+                Some(latest) => *latest = *reading,
+                None => self.latest.push(*reading),
+            }
+        }
+
+        let limits = temp_limits_of(&self.latest).unwrap_or_default();
+        let snapshot = Snapshot::from_readings(sample.timestamp, &self.latest, limits);
+
+        for (name, derive) in &mut self.derived {
+            if let Some(value) = derive(&snapshot) {
+                sample.readings.push(Reading::Derived(name, value));
+            }
+        }
+    }
+
+    /// The point in time to wake up at for the next sample
+    pub(crate) fn wake_at(&self) -> Instant {
+        self.next_due()
+            .unwrap_or_else(|| Instant::now() + self.interval)
+    }
+
+    /// Sample on a background thread until the returned handle is stopped or dropped.
+    ///
+    /// Fails only if the thread can't be spawned, e.g. because of resource limits.
+    pub fn start(self) -> io::Result<MonitorHandle> {
+        let (control, commands) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancel_children = Arc::clone(&cancel);
+        let thread = thread::Builder::new()
+            .name("vcgencmd-monitor".to_owned())
+            .spawn(move || {
+                child::cancel_on(cancel_children);
+                self.run_controlled(commands)
+            })?;
+
+        Ok(MonitorHandle {
+            control,
+            cancel,
+            thread: Some(thread),
+        })
+    }
+
+    fn run_controlled(mut self, commands: Receiver<Control>) -> Monitor {
+        let mut paused = false;
+
+        loop {
+            // waiting on the channel instead of sleeping lets commands interrupt the wait
+            let command = if paused {
+                commands.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                commands.recv_timeout(self.wake_at().saturating_duration_since(Instant::now()))
+            };
+
+            match command {
+                Err(RecvTimeoutError::Timeout) => {
+                    self.sample_due(Instant::now());
+                }
+                Ok(Control::Stop) | Err(RecvTimeoutError::Disconnected) => {
+                    self.flush_sinks(SHUTDOWN_FLUSH_TIMEOUT);
+                    for (_, sink) in &mut self.sinks {
+                        if let SinkKind::Shared(sink) = sink {
+                            sink(None);
+                        }
+                    }
+                    return self;
+                }
+                Ok(Control::Pause) => paused = true,
+                Ok(Control::Resume) => paused = false,
+                Ok(Control::Reconfigure(change)) => change(&mut self),
+            }
+        }
+    }
+
+    /// Sample repeatedly until `stop` is set, then flush the sinks, see `flush_sinks`
+    pub fn run(&mut self, stop: &AtomicBool) {
+        while !stop.load(Ordering::SeqCst) {
+            self.sample_due(Instant::now());
+            wait_until(self.wake_at(), || stop.load(Ordering::SeqCst));
+        }
+        self.flush_sinks(SHUTDOWN_FLUSH_TIMEOUT);
+    }
+}
+
+fn is_due(scheduled: &Scheduled, now: Instant) -> bool {
+    scheduled.next_due.is_none_or(|due| due <= now)
+}
+
+enum Control {
+    Stop,
+    Pause,
+    Resume,
+    Reconfigure(Box<dyn FnOnce(&mut Monitor) + Send>),
+}
+
+/// Controls a monitor running on its own thread, see `Monitor::start`.
+///
+/// Dropping the handle stops the monitor and waits for its thread to finish, so the
+/// thread never outlives the handle.
+pub struct MonitorHandle {
+    control: Sender<Control>,
+    /// Cuts short the `vcgencmd` processes run on the monitor thread
+    cancel: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Monitor>>,
+}
+
+impl MonitorHandle {
+    /// Ask the monitor to stop.
+    ///
+    /// A pending wait for the next sample is cut short. So is a sample that is already
+    /// being taken: the `vcgencmd` processes still running are terminated and reaped, and
+    /// the sample is passed to the sinks with errors for the metrics they didn't read.
+    /// The sinks are flushed before the thread finishes, see `Monitor::flush_sinks`.
+    pub fn stop(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        // the thread is gone if sending fails, which is just as good
+        let _ = self.control.send(Control::Stop);
+    }
+
+    /// Suspend sampling until `resume` is called
+    pub fn pause(&self) {
+        let _ = self.control.send(Control::Pause);
+    }
+
+    /// Continue sampling after `pause`, overdue samples are taken right away
+    pub fn resume(&self) {
+        let _ = self.control.send(Control::Resume);
+    }
+
+    /// Change the monitor while it keeps running.
+    ///
+    /// `change` is run on the monitor thread between two samples, so it sees and may
+    /// modify the same state as before `start` was called.
+    pub fn reconfigure<F>(&self, change: F)
+    where
+        F: FnOnce(&mut Monitor) + Send + 'static,
+    {
+        let _ = self.control.send(Control::Reconfigure(Box::new(change)));
+    }
+
+    /// Change the default sampling interval, metrics are next due one new interval after
+    /// their last sample
+    pub fn set_interval(&self, interval: Duration) {
+        self.reconfigure(move |monitor| monitor.set_interval(interval));
+    }
+
+    pub fn add_metric(&self, metric: Metric) {
+        self.reconfigure(move |monitor| monitor.add_metric(metric));
+    }
+
+    pub fn add_metric_every(&self, metric: Metric, interval: Duration) {
+        self.reconfigure(move |monitor| monitor.add_metric_every(metric, interval));
+    }
+
+    pub fn remove_metric(&self, metric: Metric) {
+        self.reconfigure(move |monitor| {
+            monitor.remove_metric(metric);
+        });
+    }
+
+    /// Add a derived metric to the running monitor, see `Monitor::add_derived`
+    pub fn add_derived<F>(&self, name: &'static str, derive: F)
+    where
+        F: FnMut(&Snapshot) -> Option<f64> + Send + 'static,
+    {
+        self.reconfigure(move |monitor| monitor.add_derived(name, derive));
+    }
+
+    pub fn remove_derived(&self, name: &'static str) {
+        self.reconfigure(move |monitor| {
+            monitor.remove_derived(name);
+        });
+    }
+
+    /// Add a sink to the running monitor, it receives every sample taken from now on
+    pub fn add_sink<F>(&self, sink: F) -> SinkId
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        let id = SinkId::next();
+        self.reconfigure(move |monitor| monitor.sinks.push((id, SinkKind::Direct(Box::new(sink)))));
+        id
+    }
+
+    /// Add a `MetricSink` to the running monitor, see `Monitor::add_metric_sink`
+    pub fn add_metric_sink<S: MetricSink + 'static>(&self, sink: S) -> (SinkId, Arc<SinkStats>) {
+        let id = SinkId::next();
+        let stats = Arc::new(SinkStats::default());
+        let kind = SinkKind::Metric(Box::new(sink), Arc::clone(&stats));
+        self.reconfigure(move |monitor| monitor.sinks.push((id, kind)));
+        (id, stats)
+    }
+
+    /// Add a queued sink to the running monitor, see `Monitor::add_queued_sink`
+    pub fn add_queued_sink<F>(
+        &self,
+        sink: F,
+        capacity: usize,
+        policy: Backpressure,
+    ) -> (SinkId, Arc<SinkStats>)
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        let id = SinkId::next();
+        let queued = QueuedSink::spawn(sink, capacity, policy);
+        let stats = queued.stats();
+        self.reconfigure(move |monitor| monitor.sinks.push((id, SinkKind::Queued(queued))));
+        (id, stats)
+    }
+
+    pub fn remove_sink(&self, id: SinkId) {
+        self.reconfigure(move |monitor| {
+            monitor.remove_sink(id);
+        });
+    }
+
+    /// Stop the monitor and wait for its thread to finish.
+    ///
+    /// Returns the monitor with its sinks flushed, so it can be restarted. If the monitor
+    /// is dropped instead, its sinks are dropped along with it.
+    pub fn join(mut self) -> thread::Result<Monitor> {
+        self.stop();
+        match self.thread.take() {
+            Some(thread) => thread.join(),
+            // the thread is only taken by join or drop, which both consume the handle
+            None => Err(Box::new("the monitor thread was already joined")),
+        }
+    }
+
+    /// Whether the monitor thread has finished, e.g. because a sink panicked
+    pub fn is_finished(&self) -> bool {
+        match &self.thread {
+            Some(thread) => thread.is_finished(),
+            None => true,
+        }
+    }
+}
+
+impl Drop for MonitorHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Sleep until `deadline`, returning early once `interrupted` returns true
+pub(crate) fn wait_until<F: Fn() -> bool>(deadline: Instant, interrupted: F) {
+    loop {
+        if interrupted() {
+            return;
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+
+        thread::sleep((deadline - now).min(WAIT_GRANULARITY));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::ParseIntError;
+    use std::sync::{Arc, Mutex};
+
+    fn parse_error() -> Error {
+        let e: ParseIntError = "".parse::<isize>().unwrap_err();
+        Error::ParseInt {
+            command: "get_throttled".to_owned(),
+            source: e,
+        }
+    }
+
+    fn fake_sampler(metric: Metric) -> Result<Reading> {
+        match metric {
+            Metric::Temp => Ok(Reading::Temp(42.8)),
+            Metric::Clock(src) => Ok(Reading::Clock(src, 700_000_000)),
+            _ => Err(parse_error()),
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink_received = Arc::clone(&received);
+
+        let mut monitor = Monitor::new(Duration::from_secs(1))
+            .metric(Metric::Temp)
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .metric(Metric::Throttled)
+            .sampler(fake_sampler)
+            .sink(move |sample| sink_received.lock().unwrap().push(sample.readings.clone()));
+
+        let sample = monitor.sample();
+        assert!(sample.is_ok());
+        assert_eq!(Some(&Reading::Temp(42.8)), sample.get(Metric::Temp));
+        assert_eq!(1, sample.errors.len());
+        assert_eq!(Metric::Throttled, sample.errors[0].0);
+        assert_eq!(vec![sample.readings.clone()], *received.lock().unwrap());
+    }
+
+    #[test]
+    fn test_derived_metrics() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink_received = Arc::clone(&received);
+
+        let mut monitor = Monitor::new(Duration::from_secs(1))
+            .metric(Metric::Temp)
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .sampler(fake_sampler)
+            .derive("mhz_per_degree", |snapshot| {
+                Some(snapshot.arm_clock? as f64 / 1e6 / snapshot.temp?)
+            })
+            .derive("core_volts", |snapshot| snapshot.core_volts)
+            .sink(move |sample| sink_received.lock().unwrap().push(sample.readings.clone()));
+
+        let sample = monitor.sample();
+        let derived = Metric::Derived("mhz_per_degree");
+        let expected = 700.0 / 42.8;
+        assert_eq!(
+            Some(&Reading::Derived("mhz_per_degree", expected)),
+            sample.get(derived)
+        );
+        assert_eq!(None, sample.get(Metric::Derived("core_volts")));
+        assert_eq!(vec![sample.readings.clone()], *received.lock().unwrap());
+        assert!(sample.to_json().contains(r#""mhz_per_degree":16.355"#));
+
+        // the last temperature is still used while it isn't sampled
+        monitor.remove_metric(Metric::Temp);
+        let sample = monitor.sample();
+        assert_eq!(None, sample.get(Metric::Temp));
+        assert_eq!(
+            Some(Some(expected)),
+            sample.get(derived).map(Reading::value)
+        );
+
+        assert!(monitor.remove_derived("mhz_per_degree"));
+        assert_eq!(None, monitor.sample().get(derived));
+        assert!(derived.read().is_err());
+    }
+
+    #[test]
+    fn test_sample_to_json() {
+        let mut monitor = Monitor::new(Duration::from_secs(1))
+            .metric(Metric::Temp)
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .metric(Metric::Throttled)
+            .sampler(fake_sampler);
+
+        let sample = monitor.sample();
+        let json = sample.to_json();
+        assert!(json.contains(r#""readings":{"temp":42.8,"clock.arm":700000000}"#));
+        assert!(json.contains(r#""errors":{"throttled":"get_throttled: failed to parse"#));
+        #[cfg(feature = "serde")]
+        assert_eq!(json, serde_json::to_string(&*sample).unwrap());
+        assert_eq!("volts.sdram_c", Metric::Volts(VoltSrc::SdramC).name());
+    }
+
+    #[test]
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    fn test_calendar_timestamps() {
+        let sample = Sample {
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1_600_000_000_250),
+            readings: Vec::new(),
+            errors: Vec::new(),
+        };
+        #[cfg(feature = "chrono")]
+        assert_eq!(
+            "2020-09-13T12:26:40.250Z",
+            sample
+                .date_time()
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+        );
+        #[cfg(all(feature = "chrono", feature = "serde"))]
+        assert_eq!(
+            r#""2020-09-13T12:26:40.250Z""#,
+            serde_json::to_string(&sample.date_time()).unwrap()
+        );
+        #[cfg(feature = "time")]
+        assert_eq!(
+            1_600_000_000_250_000_000,
+            sample.offset_date_time().unix_timestamp_nanos()
+        );
+    }
+
+    #[test]
+    fn test_metric_from_name() {
+        assert_eq!(
+            Some(Metric::Clock(ClockSrc::H264)),
+            Metric::from_name("clock.h264")
+        );
+        assert_eq!(Some(Metric::Throttled), Metric::from_name("throttled"));
+        assert_eq!(None, Metric::from_name("clock.gpu"));
+        assert_eq!(None, Metric::from_name("clock"));
+    }
+
+    #[test]
+    fn test_metrics_are_deduplicated() {
+        let monitor = Monitor::new(Duration::from_secs(1))
+            .metric(Metric::Temp)
+            .metric(Metric::Temp);
+        assert_eq!(vec![Metric::Temp], monitor.metrics());
+    }
+
+    #[test]
+    fn test_start_and_join() {
+        let (tx, rx) = mpsc::channel();
+        let handle = Monitor::new(Duration::from_secs(3600))
+            .metric(Metric::Temp)
+            .sampler(fake_sampler)
+            .sink(move |sample| tx.send(sample.readings.clone()).unwrap())
+            .start()
+            .unwrap();
+
+        // the first sample is taken right away
+        assert_eq!(vec![Reading::Temp(42.8)], rx.recv().unwrap());
+
+        // join must not wait for the hour long interval
+        let started = Instant::now();
+        let monitor = handle.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(vec![Metric::Temp], monitor.metrics());
+    }
+
+    #[test]
+    fn test_per_metric_intervals() {
+        let mut monitor = Monitor::new(Duration::from_secs(2))
+            .metric(Metric::Temp)
+            .metric_every(Metric::Clock(ClockSrc::Arm), Duration::from_millis(500))
+            .sampler(fake_sampler);
+        assert_eq!(
+            Some(Duration::from_millis(500)),
+            monitor.metric_interval(Metric::Clock(ClockSrc::Arm))
+        );
+
+        let start = Instant::now();
+        assert_eq!(2, monitor.sample_due(start).unwrap().readings.len());
+        assert_eq!(Some(start + Duration::from_millis(500)), monitor.next_due());
+        assert!(monitor
+            .sample_due(start + Duration::from_millis(100))
+            .is_none());
+
+        let sample = monitor
+            .sample_due(start + Duration::from_millis(500))
+            .unwrap();
+        assert_eq!(
+            vec![Reading::Clock(ClockSrc::Arm, 700_000_000)],
+            sample.readings
+        );
+
+        let sample = monitor.sample_due(start + Duration::from_secs(2)).unwrap();
+        assert_eq!(2, sample.readings.len());
+    }
+
+    #[test]
+    fn test_next_slot() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+
+        // sampling late doesn't shift the grid
+        assert_eq!(
+            start + second,
+            next_slot(start, second, start + Duration::from_millis(300))
+        );
+        // missed slots are skipped
+        assert_eq!(
+            start + 4 * second,
+            next_slot(start, second, start + Duration::from_millis(3500))
+        );
+        assert_eq!(
+            start + 4 * second,
+            next_slot(start, second, start + 3 * second)
+        );
+    }
+
+    #[test]
+    fn test_schedule_does_not_drift() {
+        let mut monitor = Monitor::new(Duration::from_secs(1))
+            .metric(Metric::Temp)
+            .sampler(fake_sampler);
+
+        let start = Instant::now();
+        monitor.sample_due(start);
+        for n in 1..100 {
+            // every sample is taken 10ms after it was due
+            let due = monitor.next_due().unwrap();
+            assert_eq!(start + Duration::from_secs(n), due);
+            monitor.sample_due(due + Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let max = Duration::from_millis(200);
+        let mut jitter = Jitter::new(max);
+        let offsets: Vec<_> = (0..1000).map(|_| jitter.offset()).collect();
+
+        assert!(offsets.iter().all(|&offset| offset < max));
+        assert!(offsets.iter().any(|&offset| offset != offsets[0]));
+        assert_eq!(
+            Duration::from_secs(0),
+            Jitter::new(Duration::from_secs(0)).offset()
+        );
+    }
+
+    #[test]
+    fn test_slow_queued_sink_does_not_stall_sampling() {
+        let (release, released) = mpsc::channel::<()>();
+        let mut monitor = Monitor::new(Duration::from_secs(1))
+            .metric(Metric::Temp)
+            .sampler(fake_sampler);
+        let (_, stats) = monitor.add_queued_sink(
+            move |_| {
+                let _ = released.recv();
+            },
+            1,
+            Backpressure::DropNewest,
+        );
+
+        for _ in 0..10 {
+            monitor.sample();
+        }
+        assert!(stats.dropped() >= 8);
+
+        drop(release);
+        drop(monitor);
+        assert_eq!(10, stats.delivered() + stats.dropped());
+    }
+
+    /// Fails every other write, counting writes and flushes
+    struct FlakySink(Arc<Mutex<(usize, usize)>>);
+
+    impl MetricSink for FlakySink {
+        fn write(&mut self, _: &Sample) -> io::Result<()> {
+            let mut counts = self.0.lock().unwrap();
+            counts.0 += 1;
+            match counts.0 % 2 {
+                0 => Err(io::Error::other("unreachable")),
+                _ => Ok(()),
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.lock().unwrap().1 += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_metric_sink() {
+        let counts = Arc::new(Mutex::new((0, 0)));
+        let mut monitor = Monitor::new(Duration::from_secs(1))
+            .metric(Metric::Temp)
+            .sampler(fake_sampler);
+        let (_, stats) = monitor.add_metric_sink(FlakySink(Arc::clone(&counts)));
+
+        for _ in 0..4 {
+            monitor.sample();
+        }
+        assert!(monitor.flush_sinks(Duration::from_secs(1)));
+        assert_eq!((4, 1), *counts.lock().unwrap());
+        assert_eq!((4, 2), (stats.delivered(), stats.failed()));
+        assert_eq!(1, monitor.sink_stats().len());
+    }
+
+    #[test]
+    fn test_stop_flushes_and_cancels() {
+        let counts = Arc::new(Mutex::new((0, 0)));
+        let (started, running) = mpsc::channel();
+        let mut monitor = Monitor::new(Duration::from_millis(10))
+            .metric(Metric::Temp)
+            .sampler(move |_| {
+                let _ = started.send(());
+                let mut sleep = std::process::Command::new("sleep");
+                crate::child::output(sleep.arg("10"), None)?;
+                fake_sampler(Metric::Temp)
+            });
+        monitor.add_metric_sink(FlakySink(Arc::clone(&counts)));
+
+        let handle = monitor.start().unwrap();
+        running.recv().unwrap();
+        let stopping = Instant::now();
+        handle.join().unwrap();
+
+        assert!(stopping.elapsed() < Duration::from_secs(5));
+        assert_eq!((1, 1), *counts.lock().unwrap());
+    }
+
+    #[test]
+    fn test_remove_sink() {
+        let mut monitor = Monitor::new(Duration::from_secs(1));
+        let id = monitor.add_sink(|_| {});
+        assert!(monitor.remove_sink(id));
+        assert!(!monitor.remove_sink(id));
+    }
+
+    #[test]
+    fn test_reconfigure_while_running() {
+        let (tx, rx) = mpsc::channel();
+        let handle = Monitor::new(Duration::from_millis(10))
+            .metric(Metric::Temp)
+            .sampler(fake_sampler)
+            .start()
+            .unwrap();
+
+        handle.pause();
+        handle.add_metric(Metric::Clock(ClockSrc::Core));
+        handle.remove_metric(Metric::Temp);
+        handle.add_sink(move |sample| {
+            let _ = tx.send(sample.readings.clone());
+        });
+
+        // nothing is sampled while paused, even though the interval is short
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+
+        handle.resume();
+        assert_eq!(
+            vec![Reading::Clock(ClockSrc::Core, 700_000_000)],
+            rx.recv().unwrap()
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_drop_stops_thread() {
+        let (tx, rx) = mpsc::channel();
+        let handle = Monitor::new(Duration::from_secs(3600))
+            .metric(Metric::Temp)
+            .sampler(fake_sampler)
+            .sink(move |sample| tx.send(sample.readings.len()).unwrap())
+            .start()
+            .unwrap();
+        rx.recv().unwrap();
+        drop(handle);
+
+        // the sink, and with it the sender, is gone once the thread has ended
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_run_stops() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let sink_stop = Arc::clone(&stop);
+        let mut count = 0;
+
+        let mut monitor = Monitor::new(Duration::from_millis(1))
+            .metric(Metric::Temp)
+            .sampler(fake_sampler)
+            .sink(move |_| {
+                count += 1;
+                if count == 3 {
+                    sink_stop.store(true, Ordering::SeqCst);
+                }
+            });
+
+        monitor.run(&stop);
+        assert!(stop.load(Ordering::SeqCst));
+    }
+}
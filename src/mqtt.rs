@@ -0,0 +1,247 @@
+//! Publishing samples to an MQTT broker, e.g. for Home Assistant or Node-RED
+//!
+//! Only what a sensor needs of MQTT 3.1.1 is implemented: connecting without credentials
+//! and publishing with QoS 0. Every sample is published as `Sample::to_json`.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::monitor::Sample;
+use crate::sink::{self, MetricSink};
+
+const DEFAULT_TOPIC: &str = "vcgencmd";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const RETAIN: u8 = 0x01;
+const CLEAN_SESSION: u8 = 0x02;
+const PROTOCOL_LEVEL: u8 = 4;
+
+/// Publishes samples to a topic, reconnecting whenever the connection was lost
+#[derive(Debug)]
+pub struct MqttPublisher {
+    broker: String,
+    client_id: String,
+    topic: String,
+    retain: bool,
+    stream: Option<TcpStream>,
+}
+
+impl MqttPublisher {
+    /// Publish to `broker`, given as `host:port`, the connection is opened on first use
+    pub fn new(broker: &str) -> MqttPublisher {
+        MqttPublisher {
+            broker: broker.to_owned(),
+            client_id: format!("vcgencmd-{}", std::process::id()),
+            topic: DEFAULT_TOPIC.to_owned(),
+            retain: false,
+            stream: None,
+        }
+    }
+
+    /// Publish to `topic` instead of `vcgencmd`
+    pub fn topic(mut self, topic: &str) -> MqttPublisher {
+        self.topic = topic.to_owned();
+        self
+    }
+
+    /// The client identifier sent to the broker, unique per process by default
+    pub fn client_id(mut self, client_id: &str) -> MqttPublisher {
+        self.client_id = client_id.to_owned();
+        self
+    }
+
+    /// Have the broker keep the last sample for subscribers that connect later
+    pub fn retain(mut self, retain: bool) -> MqttPublisher {
+        self.retain = retain;
+        self
+    }
+
+    /// Publish `payload` to the topic
+    pub fn publish(&mut self, payload: &[u8]) -> io::Result<()> {
+        let packet = publish_packet(&self.topic, payload, self.retain);
+
+        let result = match self.stream {
+            Some(ref mut stream) => stream.write_all(&packet),
+            None => Err(io::ErrorKind::NotConnected.into()),
+        };
+        if result.is_ok() {
+            return result;
+        }
+
+        // the broker may have restarted or dropped an idle connection, retry once
+        let mut stream = self.connect()?;
+        stream.write_all(&packet)?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        let addr =
+            self.broker.to_socket_addrs()?.next().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "broker address not found")
+            })?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        stream.write_all(&connect_packet(&self.client_id))?;
+        let mut connack = [0; 4];
+        stream.read_exact(&mut connack)?;
+        match connack {
+            [CONNACK, 2, _, 0] => Ok(stream),
+            [CONNACK, 2, _, code] => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("broker refused the connection with code {}", code),
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected CONNACK from broker",
+            )),
+        }
+    }
+
+    /// Turn this into a plain monitor sink. A failed publish is dropped and the broker
+    /// reconnected to with the next sample, through `Monitor::metric_sink` the failure is
+    /// counted as well.
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for MqttPublisher {
+    /// Publish `sample` as `Sample::to_json`, a failed publish is retried with the next one
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        self.publish(sample.to_json().as_bytes())
+    }
+}
+
+/// Append the variable length encoding of a packet's remaining length
+fn encode_length(mut length: usize, packet: &mut Vec<u8>) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_string(value: &str, packet: &mut Vec<u8>) {
+    packet.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    packet.extend_from_slice(value.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    encode_length(body.len(), &mut packet);
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn connect_packet(client_id: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string("MQTT", &mut body);
+    body.push(PROTOCOL_LEVEL);
+    body.push(CLEAN_SESSION);
+    // no keep alive, the broker won't drop the connection between samples
+    body.extend_from_slice(&0u16.to_be_bytes());
+    encode_string(client_id, &mut body);
+
+    packet(CONNECT, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(topic, &mut body);
+    body.extend_from_slice(payload);
+
+    let header = if retain { PUBLISH | RETAIN } else { PUBLISH };
+    packet(header, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_encode_length() {
+        let encoded = |length| {
+            let mut packet = Vec::new();
+            encode_length(length, &mut packet);
+            packet
+        };
+
+        assert_eq!(vec![0], encoded(0));
+        assert_eq!(vec![127], encoded(127));
+        assert_eq!(vec![0x80, 0x01], encoded(128));
+        assert_eq!(vec![0xff, 0x7f], encoded(16_383));
+        assert_eq!(vec![0x80, 0x80, 0x01], encoded(16_384));
+    }
+
+    #[test]
+    fn test_packets() {
+        assert_eq!(
+            b"\x10\x10\x00\x04MQTT\x04\x02\x00\x00\x00\x04pi-1".to_vec(),
+            connect_packet("pi-1")
+        );
+        assert_eq!(
+            b"\x31\x05\x00\x01t{}".to_vec(),
+            publish_packet("t", b"{}", true)
+        );
+    }
+
+    #[test]
+    fn test_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let broker = listener.local_addr().unwrap().to_string();
+
+        let received = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut connect = vec![0; connect_packet("pi").len()];
+            stream.read_exact(&mut connect).unwrap();
+            stream.write_all(&[CONNACK, 2, 0, 0]).unwrap();
+
+            let mut publish = vec![0; publish_packet("pi/soc", b"42", false).len()];
+            stream.read_exact(&mut publish).unwrap();
+            (connect, publish)
+        });
+
+        let mut publisher = MqttPublisher::new(&broker).client_id("pi").topic("pi/soc");
+        publisher.publish(b"42").unwrap();
+
+        let (connect, publish) = received.join().unwrap();
+        assert_eq!(connect_packet("pi"), connect);
+        assert_eq!(publish_packet("pi/soc", b"42", false), publish);
+    }
+
+    #[test]
+    fn test_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let broker = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut connect = vec![0; connect_packet("pi").len()];
+            stream.read_exact(&mut connect).unwrap();
+            // not authorized
+            stream.write_all(&[CONNACK, 2, 0, 5]).unwrap();
+        });
+
+        let error = MqttPublisher::new(&broker)
+            .client_id("pi")
+            .publish(b"42")
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::ConnectionRefused, error.kind());
+    }
+}
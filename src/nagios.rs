@@ -0,0 +1,310 @@
+//! Nagios/Icinga check plugin results, e.g. for NRPE
+//!
+//! A plugin prints a single line of the form
+//!
+//! ```txt
+//! VCGENCMD WARNING - temp 63.5 °C >= 60 | temp=63.5;60;80 throttled=0 arm_clock=1500000000
+//! ```
+//!
+//! and exits with 0, 1, 2 or 3 for OK, WARNING, CRITICAL and UNKNOWN. The part after `|` is
+//! performance data, graphed by most monitoring systems.
+
+use std::fmt;
+
+use crate::alert::Severity;
+use crate::json;
+use crate::snapshot::Snapshot;
+use crate::thermal::TempLimits;
+use crate::throttled::explain;
+
+/// The state of a check, ordered from best to worst.
+///
+/// A known problem outranks a reading that couldn't be taken, so CRITICAL comes last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok,
+    Warning,
+    /// The check couldn't be performed completely
+    Unknown,
+    Critical,
+}
+
+impl Status {
+    /// The exit code a plugin reports this status with
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Warning => 1,
+            Status::Critical => 2,
+            Status::Unknown => 3,
+        }
+    }
+
+    fn from_severity(severity: Severity) -> Status {
+        match severity {
+            Severity::Info => Status::Ok,
+            Severity::Warning => Status::Warning,
+            Severity::Critical => Status::Critical,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+/// Temperatures in °C from which a check warns or is critical
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub warning: f64,
+    pub critical: f64,
+}
+
+impl Default for Thresholds {
+    /// The thresholds for the default `TempLimits`
+    fn default() -> Thresholds {
+        Thresholds::from_limits(&TempLimits::default())
+    }
+}
+
+impl Thresholds {
+    /// Warn at the soft limit and be critical 5 °C below the hard limit, like `HealthPolicy`
+    pub fn from_limits(limits: &TempLimits) -> Thresholds {
+        Thresholds {
+            warning: limits.soft,
+            critical: limits.hard - 5.0,
+        }
+    }
+}
+
+/// The outcome of a check
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub status: Status,
+    /// Why the status isn't OK, empty otherwise
+    pub problems: Vec<String>,
+    /// Performance data as `label=value;warn;crit`
+    pub perfdata: Vec<String>,
+}
+
+impl CheckResult {
+    pub fn exit_code(&self) -> i32 {
+        self.status.exit_code()
+    }
+
+    pub fn to_json(&self) -> String {
+        json::object(&[
+            ("status", json::string(&self.status.to_string())),
+            ("exit_code", self.exit_code().to_string()),
+            (
+                "problems",
+                json::array(self.problems.iter().map(|p| json::string(p))),
+            ),
+            (
+                "perfdata",
+                json::array(self.perfdata.iter().map(|p| json::string(p))),
+            ),
+        ])
+    }
+}
+
+/// Serialized like `CheckResult::to_json`
+#[cfg(feature = "serde")]
+impl serde::Serialize for CheckResult {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut result = serializer.serialize_struct("CheckResult", 4)?;
+        result.serialize_field("status", &self.status.to_string())?;
+        result.serialize_field("exit_code", &self.exit_code())?;
+        result.serialize_field("problems", &self.problems)?;
+        result.serialize_field("perfdata", &self.perfdata)?;
+        result.end()
+    }
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VCGENCMD {} - ", self.status)?;
+        if self.problems.is_empty() {
+            f.write_str("all readings within thresholds")?;
+        } else {
+            f.write_str(&self.problems.join(", "))?;
+        }
+
+        if !self.perfdata.is_empty() {
+            write!(f, " | {}", self.perfdata.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the temperature of `snapshot` against `thresholds`, and its throttling flags.
+///
+/// Every set `get_throttled` flag counts with its severity, see `ThrottledFlag::severity`.
+/// A missing temperature or bit pattern makes the check UNKNOWN.
+pub fn check(snapshot: &Snapshot, thresholds: &Thresholds) -> CheckResult {
+    let mut status = Status::Ok;
+    let mut problems = Vec::new();
+    let mut perfdata = Vec::new();
+
+    match snapshot.temp {
+        Some(temp) => {
+            let exceeded = if temp >= thresholds.critical {
+                Some((Status::Critical, thresholds.critical))
+            } else if temp >= thresholds.warning {
+                Some((Status::Warning, thresholds.warning))
+            } else {
+                None
+            };
+            if let Some((temp_status, threshold)) = exceeded {
+                status = status.max(temp_status);
+                problems.push(format!("temp {:.1} °C >= {}", temp, threshold));
+            }
+
+            perfdata.push(format!(
+                "temp={};{};{}",
+                temp, thresholds.warning, thresholds.critical
+            ));
+        }
+        None => {
+            status = status.max(Status::Unknown);
+            problems.push("temp unreadable".to_owned());
+        }
+    }
+
+    match snapshot.throttled {
+        Some(bit_pattern) => {
+            let explanation = explain(bit_pattern);
+            for flag in explanation.active.iter().chain(&explanation.historic) {
+                status = status.max(Status::from_severity(flag.severity()));
+                problems.push(flag.meaning.to_lowercase());
+            }
+
+            perfdata.push(format!("throttled={}", bit_pattern));
+        }
+        None => {
+            status = status.max(Status::Unknown);
+            problems.push("throttling unreadable".to_owned());
+        }
+    }
+
+    let clocks = [
+        ("arm_clock", snapshot.arm_clock),
+        ("core_clock", snapshot.core_clock),
+    ];
+    for (label, clock) in &clocks {
+        if let Some(clock) = clock {
+            perfdata.push(format!("{}={}", label, clock));
+        }
+    }
+    if let Some(volts) = snapshot.core_volts {
+        perfdata.push(format!("core_volts={}", volts));
+    }
+
+    CheckResult {
+        status,
+        problems,
+        perfdata,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{Reading, Sample};
+    use crate::ClockSrc;
+    use std::time::UNIX_EPOCH;
+
+    fn snapshot(readings: Vec<Reading>) -> Snapshot {
+        let sample = Sample {
+            timestamp: UNIX_EPOCH,
+            readings,
+            errors: Vec::new(),
+        };
+        Snapshot::from_sample(&sample, TempLimits::default())
+    }
+
+    #[test]
+    fn test_ok() {
+        let result = check(
+            &snapshot(vec![
+                Reading::Temp(45.5),
+                Reading::Throttled(0),
+                Reading::Clock(ClockSrc::Arm, 1_500_000_000),
+            ]),
+            &Thresholds::default(),
+        );
+
+        assert_eq!(0, result.exit_code());
+        assert_eq!(
+            "VCGENCMD OK - all readings within thresholds | temp=45.5;60;80 throttled=0 arm_clock=1500000000",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let thresholds = Thresholds {
+            warning: 50.0,
+            critical: 70.0,
+        };
+        let warm = check(
+            &snapshot(vec![Reading::Temp(55.0), Reading::Throttled(0)]),
+            &thresholds,
+        );
+        assert_eq!(Status::Warning, warm.status);
+        assert_eq!(vec!["temp 55.0 °C >= 50"], warm.problems);
+
+        let hot = check(
+            &snapshot(vec![Reading::Temp(70.0), Reading::Throttled(0)]),
+            &thresholds,
+        );
+        assert_eq!(Status::Critical, hot.status);
+    }
+
+    #[test]
+    fn test_throttle_conditions() {
+        let occurred = check(
+            &snapshot(vec![Reading::Temp(45.0), Reading::Throttled(0x10000)]),
+            &Thresholds::default(),
+        );
+        assert_eq!(Status::Warning, occurred.status);
+        assert_eq!(vec!["under-voltage has occurred"], occurred.problems);
+
+        let active = check(
+            &snapshot(vec![Reading::Temp(45.0), Reading::Throttled(0x50005)]),
+            &Thresholds::default(),
+        );
+        assert_eq!(2, active.exit_code());
+        assert!(active
+            .to_string()
+            .starts_with("VCGENCMD CRITICAL - under-voltage detected, currently throttled, "));
+    }
+
+    #[test]
+    fn test_unknown() {
+        let result = check(
+            &snapshot(vec![Reading::Throttled(0)]),
+            &Thresholds::default(),
+        );
+        assert_eq!(3, result.exit_code());
+        assert_eq!(
+            "VCGENCMD UNKNOWN - temp unreadable | throttled=0",
+            result.to_string()
+        );
+
+        let result = check(
+            &snapshot(vec![Reading::Throttled(0x4)]),
+            &Thresholds::default(),
+        );
+        assert_eq!(Status::Critical, result.status);
+    }
+}
@@ -0,0 +1,328 @@
+//! Checking whether an overclock in `config.txt` is actually achieved under load
+//!
+//! The firmware silently lowers the clocks when the supply sags or the SoC gets too hot, so
+//! an `arm_freq=2000` in `config.txt` says little about what the system really runs at.
+//! `OverclockCheck` reads the configured values, loads the system, and compares them with
+//! the clocks and voltage measured meanwhile.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use vcgencmd::overclock::OverclockCheck;
+//!
+//! let report = OverclockCheck::new(Duration::from_secs(120))
+//!     .stress_command(&["stress-ng", "--cpu", "4"])
+//!     .run()
+//!     .unwrap();
+//!
+//! println!("{}", report);
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use subprocess::PopenError;
+
+use crate::monitor::{Metric, Monitor, Reading};
+use crate::profile::{sample_for, Stress};
+use crate::{get_config, interpret_bit_pattern, ClockSrc, ConfigSrc, Error, Result, Src, VoltSrc};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+const MHZ: isize = 1_000_000;
+
+/// The overclocking options from `config.txt`, frequencies in MHz, 0 meaning unset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OverclockConfig {
+    pub arm_freq: isize,
+    pub core_freq: isize,
+    pub gpu_freq: isize,
+    pub over_voltage: isize,
+}
+
+impl OverclockConfig {
+    /// Read the options via `get_config`
+    pub fn query() -> Result<OverclockConfig> {
+        let read = |src| get_config(Src::Config(src));
+
+        Ok(OverclockConfig {
+            arm_freq: read(ConfigSrc::ArmFreq)?,
+            core_freq: read(ConfigSrc::CoreFreq)?,
+            gpu_freq: read(ConfigSrc::GpuFreq)?,
+            over_voltage: read(ConfigSrc::OverVoltage)?,
+        })
+    }
+
+    /// The core clock aimed for, `gpu_freq` sets it too unless `core_freq` is given
+    pub fn target_core_freq(&self) -> isize {
+        match self.core_freq {
+            0 => self.gpu_freq,
+            core_freq => core_freq,
+        }
+    }
+}
+
+/// Why a check couldn't be run
+#[derive(Debug)]
+pub enum OverclockError {
+    Config(Error),
+    Stress(PopenError),
+}
+
+/// Loads the system and compares the achieved clocks with `OverclockConfig`
+pub struct OverclockCheck {
+    duration: Duration,
+    interval: Duration,
+    stress_command: Option<Vec<String>>,
+    config: Option<OverclockConfig>,
+    monitor: Monitor,
+}
+
+impl OverclockCheck {
+    /// Check for `duration`, sampling once per second
+    pub fn new(duration: Duration) -> OverclockCheck {
+        let monitor = Monitor::new(DEFAULT_INTERVAL)
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .metric(Metric::Clock(ClockSrc::Core))
+            .metric(Metric::Volts(VoltSrc::Core))
+            .metric(Metric::Throttled);
+
+        OverclockCheck {
+            duration,
+            interval: DEFAULT_INTERVAL,
+            stress_command: None,
+            config: None,
+            monitor,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> OverclockCheck {
+        self.interval = interval;
+        self.monitor.set_interval(interval);
+        self
+    }
+
+    /// Run `argv` to load the system, it is killed if still running at the end
+    pub fn stress_command<S: AsRef<str>>(mut self, argv: &[S]) -> OverclockCheck {
+        self.stress_command = Some(argv.iter().map(|arg| arg.as_ref().to_owned()).collect());
+        self
+    }
+
+    /// Compare against `config` instead of querying it with `get_config`
+    pub fn config(mut self, config: OverclockConfig) -> OverclockCheck {
+        self.config = Some(config);
+        self
+    }
+
+    /// Replace the function used to read a metric, see `Monitor::sampler`
+    pub fn sampler<F>(mut self, sampler: F) -> OverclockCheck
+    where
+        F: FnMut(Metric) -> Result<Reading> + Send + 'static,
+    {
+        self.monitor = self.monitor.sampler(sampler);
+        self
+    }
+
+    /// Run the check, blocking for its duration
+    pub fn run(mut self) -> Result<OverclockReport, OverclockError> {
+        let config = match self.config {
+            Some(config) => config,
+            None => OverclockConfig::query().map_err(OverclockError::Config)?,
+        };
+        let _stress =
+            Stress::spawn(self.stress_command.as_deref()).map_err(OverclockError::Stress)?;
+
+        let mut report = OverclockReport {
+            config,
+            arm_clock: None,
+            core_clock: None,
+            core_volts: None,
+            capped: false,
+            throttled: None,
+        };
+
+        let max =
+            |current: Option<isize>, value: isize| Some(current.map_or(value, |c| c.max(value)));
+        sample_for(
+            &mut self.monitor,
+            self.duration,
+            self.interval,
+            |_, sample| {
+                for reading in &sample.readings {
+                    match *reading {
+                        Reading::Clock(ClockSrc::Arm, frequency) => {
+                            report.arm_clock = max(report.arm_clock, frequency)
+                        }
+                        Reading::Clock(ClockSrc::Core, frequency) => {
+                            report.core_clock = max(report.core_clock, frequency)
+                        }
+                        Reading::Volts(VoltSrc::Core, volts) => {
+                            report.core_volts =
+                                Some(report.core_volts.map_or(volts, |v: f64| v.max(volts)))
+                        }
+                        Reading::Throttled(bit_pattern) => {
+                            let status = interpret_bit_pattern(bit_pattern);
+                            report.capped |=
+                                status.arm_frequency_capped || status.currently_throttled;
+                            report.throttled = Some(bit_pattern);
+                        }
+                        _ => {}
+                    }
+                }
+            },
+        );
+
+        Ok(report)
+    }
+}
+
+/// Configured vs achieved values of an `OverclockCheck`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverclockReport {
+    pub config: OverclockConfig,
+    /// Highest ARM clock seen in Hz
+    pub arm_clock: Option<isize>,
+    /// Highest core clock seen in Hz
+    pub core_clock: Option<isize>,
+    /// Highest core voltage seen in V
+    pub core_volts: Option<f64>,
+    /// Whether the firmware capped or throttled the clocks at any point
+    pub capped: bool,
+    /// The last `get_throttled` bit pattern, its `*_occurred` flags cover the whole check
+    pub throttled: Option<isize>,
+}
+
+impl OverclockReport {
+    /// Whether the ARM reached `arm_freq`, `None` if either is unknown
+    pub fn arm_achieved(&self) -> Option<bool> {
+        achieved(self.config.arm_freq, self.arm_clock)
+    }
+
+    /// Whether the core reached its configured frequency, `None` if either is unknown
+    pub fn core_achieved(&self) -> Option<bool> {
+        achieved(self.config.target_core_freq(), self.core_clock)
+    }
+
+    /// True if every known target was reached without capping
+    pub fn is_verified(&self) -> bool {
+        !self.capped && self.arm_achieved() != Some(false) && self.core_achieved() != Some(false)
+    }
+}
+
+/// Measured clocks are off by a fraction of a MHz, so only whole MHz are compared
+fn achieved(target_mhz: isize, measured_hz: Option<isize>) -> Option<bool> {
+    match (target_mhz, measured_hz) {
+        (0, _) | (_, None) => None,
+        (target, Some(measured)) => Some((measured + MHZ / 2) / MHZ >= target),
+    }
+}
+
+impl fmt::Display for OverclockReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mhz = |hz: Option<isize>| match hz {
+            Some(hz) => format!("{} MHz", (hz + MHZ / 2) / MHZ),
+            None => "n/a".to_owned(),
+        };
+        let verdict = |achieved: Option<bool>| match achieved {
+            Some(true) => "ok",
+            Some(false) => "NOT REACHED",
+            None => "-",
+        };
+
+        writeln!(f, "{:<14}{:>12}{:>12}", "", "configured", "achieved")?;
+        writeln!(
+            f,
+            "{:<14}{:>8} MHz{:>12}  {}",
+            "arm",
+            self.config.arm_freq,
+            mhz(self.arm_clock),
+            verdict(self.arm_achieved())
+        )?;
+        writeln!(
+            f,
+            "{:<14}{:>8} MHz{:>12}  {}",
+            "core",
+            self.config.target_core_freq(),
+            mhz(self.core_clock),
+            verdict(self.core_achieved())
+        )?;
+        let volts = match self.core_volts {
+            Some(volts) => format!("{:.4} V", volts),
+            None => "n/a".to_owned(),
+        };
+        writeln!(
+            f,
+            "{:<14}{:>12}{:>12}",
+            "over_voltage", self.config.over_voltage, volts
+        )?;
+        write!(
+            f,
+            "capped:       {}",
+            if self.capped { "yes" } else { "no" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> OverclockConfig {
+        OverclockConfig {
+            arm_freq: 2000,
+            core_freq: 0,
+            gpu_freq: 750,
+            over_voltage: 6,
+        }
+    }
+
+    #[test]
+    fn test_achieved() {
+        assert_eq!(Some(true), achieved(2000, Some(1_999_998_000)));
+        assert_eq!(Some(false), achieved(2000, Some(1_800_000_000)));
+        assert_eq!(None, achieved(0, Some(1_800_000_000)));
+        assert_eq!(None, achieved(2000, None));
+        assert_eq!(750, config().target_core_freq());
+    }
+
+    #[test]
+    fn test_check() {
+        let mut arm = 1_800_000_000;
+        let report = OverclockCheck::new(Duration::from_millis(30))
+            .interval(Duration::from_millis(10))
+            .config(config())
+            .sampler(move |metric| match metric {
+                Metric::Clock(ClockSrc::Arm) => {
+                    arm += 100_000_000;
+                    Ok(Reading::Clock(ClockSrc::Arm, arm))
+                }
+                Metric::Clock(src) => Ok(Reading::Clock(src, 750_000_000)),
+                Metric::Volts(src) => Ok(Reading::Volts(src, 1.35)),
+                _ => Ok(Reading::Throttled(0)),
+            })
+            .run()
+            .unwrap();
+
+        assert!(report.arm_clock.unwrap() >= 2_000_000_000);
+        assert_eq!(Some(1.35), report.core_volts);
+        assert!(!report.capped);
+        assert!(report.is_verified());
+        assert!(report.to_string().contains("750 MHz"));
+    }
+
+    #[test]
+    fn test_not_verified() {
+        let report = OverclockReport {
+            config: config(),
+            arm_clock: Some(1_500_000_000),
+            core_clock: Some(750_000_000),
+            core_volts: Some(1.2),
+            capped: true,
+            throttled: Some(0),
+        };
+
+        assert_eq!(Some(false), report.arm_achieved());
+        assert!(!report.is_verified());
+        assert!(report.to_string().contains("NOT REACHED"));
+    }
+}
@@ -1,7 +1,33 @@
+use std::convert::Infallible;
 use std::num::{ParseFloatError, ParseIntError};
 
+use crate::display::{HdmiTimings, LcdInfo};
+use crate::session::FirmwareVersion;
+use crate::{AdcChannel, AdcKind, RelocStats};
+
+/// The part after the first `=`, empty if there is none so parsing it fails instead
 fn trim_before_equals(input: &str) -> String {
-    input.split('=').collect::<Vec<_>>()[1].trim().to_owned()
+    input
+        .split('=')
+        .nth(1)
+        .unwrap_or_default()
+        .trim()
+        .to_owned()
+}
+
+/// Parses an error reply like `error=2 error_msg="Invalid arguments"` into code and message
+pub fn firmware_error(input: &str) -> Option<(i32, String)> {
+    let rest = input.trim().strip_prefix("error=")?;
+    let (code, message) = match rest.split_once(' ') {
+        Some((code, message)) => (code, message),
+        None => (rest, ""),
+    };
+    let message = message
+        .trim()
+        .trim_start_matches("error_msg=")
+        .trim_matches('"');
+
+    Some((code.parse().ok()?, message.to_owned()))
 }
 
 pub fn temp(input: &str) -> Result<f64, ParseFloatError> {
@@ -50,6 +76,148 @@ pub fn mem(input: &str) -> Result<isize, ParseIntError> {
     Ok(value)
 }
 
+pub fn config(input: &str) -> Result<isize, ParseIntError> {
+    let parsable = trim_before_equals(input);
+    let value = parsable.parse::<isize>()?;
+    Ok(value)
+}
+
+/// Parses lines like `alloc failures:     0`, counters that aren't reported are left at 0
+pub fn reloc_stats(input: &str) -> Result<RelocStats, ParseIntError> {
+    let mut stats = RelocStats::default();
+
+    for line in input.lines() {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        let counter = match name {
+            "alloc failures" => &mut stats.alloc_failures,
+            "compactions" => &mut stats.compactions,
+            "legacy block fails" => &mut stats.legacy_block_fails,
+            _ => continue,
+        };
+        *counter = value.parse()?;
+    }
+
+    Ok(stats)
+}
+
+/// Parses lines like `   3V3_SYS_A current(1)=0.07320000A`, skipping anything else
+pub fn pmic_adc(input: &str) -> Result<Vec<AdcChannel>, ParseFloatError> {
+    let mut channels = Vec::new();
+
+    for line in input.lines() {
+        let mut parts = line.split_whitespace();
+        let (name, measurement) = match (parts.next(), parts.next()) {
+            (Some(name), Some(measurement)) => (name, measurement),
+            _ => continue,
+        };
+
+        let kind = if measurement.starts_with("current(") {
+            AdcKind::Current
+        } else if measurement.starts_with("volt(") {
+            AdcKind::Voltage
+        } else {
+            continue;
+        };
+
+        let value = match measurement.split_once('=') {
+            Some((_, value)) => value.trim_end_matches(['A', 'V']).parse::<f64>()?,
+            None => continue,
+        };
+
+        let rail = name
+            .trim_end_matches("_A")
+            .trim_end_matches("_V")
+            .to_owned();
+        channels.push(AdcChannel { rail, kind, value });
+    }
+
+    Ok(channels)
+}
+
+/// Parses `display_power=1`, where -1 means there is no such display
+pub fn display_power(input: &str) -> Result<Option<bool>, ParseIntError> {
+    match trim_before_equals(input).parse::<isize>()? {
+        -1 => Ok(None),
+        state => Ok(Some(state != 0)),
+    }
+}
+
+/// Parses `1920 1080 24`, the values may be prefixed with `get_lcd_info=` or similar
+pub fn lcd_info(input: &str) -> Result<LcdInfo, ParseIntError> {
+    let values = input.split_once('=').map_or(input, |(_, values)| values);
+    let mut values = values.split_whitespace().map(str::parse::<u32>);
+    // a missing value fails to parse like an empty one
+    let mut next = || values.next().unwrap_or_else(|| "".parse());
+
+    Ok(LcdInfo {
+        width: next()?,
+        height: next()?,
+        depth: next()?,
+    })
+}
+
+/// Parses `hdmi_timings=1920 1 88 44 148 1080 1 4 5 36 0 0 0 60 0 148500000 3`, `None`
+/// if the values are missing
+pub fn hdmi_timings(input: &str) -> Result<Option<HdmiTimings>, ParseIntError> {
+    let values = trim_before_equals(input);
+    if values.is_empty() {
+        return Ok(None);
+    }
+
+    let mut values = values.split_whitespace().map(str::parse::<u32>);
+    let mut next = || values.next().unwrap_or_else(|| "".parse());
+
+    Ok(Some(HdmiTimings {
+        h_active_pixels: next()?,
+        h_sync_polarity: next()? != 0,
+        h_front_porch: next()?,
+        h_sync_pulse: next()?,
+        h_back_porch: next()?,
+        v_active_lines: next()?,
+        v_sync_polarity: next()? != 0,
+        v_front_porch: next()?,
+        v_sync_pulse: next()?,
+        v_back_porch: next()?,
+        v_sync_offset_a: next()?,
+        v_sync_offset_b: next()?,
+        pixel_rep: next()?,
+        frame_rate: next()?,
+        interlaced: next()? != 0,
+        pixel_freq: next()?,
+        aspect_ratio: next()?,
+    }))
+}
+
+/// Parses the build date on the first line and the hash on the `version` line
+pub fn version(input: &str) -> Result<FirmwareVersion, Infallible> {
+    let date = input.lines().next().unwrap_or_default().trim().to_owned();
+    let hash = input
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("version "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap_or_default()
+        .to_owned();
+
+    Ok(FirmwareVersion { date, hash })
+}
+
+/// Parses `commands="vcos, ap_output_control, ..."`
+pub fn commands(input: &str) -> Result<Vec<String>, Infallible> {
+    let list = input.split_once('=').map_or(input, |(_, list)| list);
+
+    Ok(list
+        .trim()
+        .trim_matches('"')
+        .split(',')
+        .map(str::trim)
+        .filter(|command| !command.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -60,7 +228,26 @@ mod test {
         assert_eq!(
             "250000000",
             trim_before_equals("core:   frequency(1)=250000000")
-        )
+        );
+        assert_eq!("", trim_before_equals("VCHI initialization failed"));
+    }
+
+    #[test]
+    fn test_unexpected_output_is_an_error() {
+        assert!(temp("").is_err());
+        assert!(temp("VCHI initialization failed").is_err());
+        assert!(throttled("throttled").is_err());
+        assert!(volts("volt").is_err());
+    }
+
+    #[test]
+    fn test_firmware_error() {
+        assert_eq!(
+            Some((2, "Invalid arguments".to_owned())),
+            firmware_error("error=2 error_msg=\"Invalid arguments\"\n")
+        );
+        assert_eq!(Some((1, String::new())), firmware_error("error=1"));
+        assert_eq!(None, firmware_error("temp=47.2'C\n"));
     }
 
     #[test]
@@ -88,8 +275,101 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_config() {
+        assert_eq!(60isize, config("temp_soft_limit=60").unwrap())
+    }
+
+    #[test]
+    fn test_pmic_adc() {
+        let output = "   3V3_SYS_A current(1)=0.07320000A\n \
+                      VDD_CORE_A current(7)=2.13190000A\n \
+                      3V3_SYS_V volt(9)=3.31066200V\n \
+                      VDD_CORE_V volt(15)=0.72000000V\n \
+                      EXT5V_V volt(24)=5.13768000V\n";
+        let channels = pmic_adc(output).unwrap();
+
+        assert_eq!(5, channels.len());
+        assert_eq!(
+            AdcChannel {
+                rail: "VDD_CORE".to_owned(),
+                kind: AdcKind::Current,
+                value: 2.1319,
+            },
+            channels[1]
+        );
+        assert_eq!("EXT5V", channels[4].rail);
+        assert_eq!(AdcKind::Voltage, channels[4].kind);
+    }
+
     #[test]
     fn test_mem() {
-        assert_eq!(448isize, mem("arm=448M").unwrap())
+        assert_eq!(448isize, mem("arm=448M").unwrap());
+        assert_eq!(7isize, mem("reloc_total=7M").unwrap())
+    }
+
+    #[test]
+    fn test_reloc_stats() {
+        let output = "alloc failures:     2\n\
+                      compactions:        14\n\
+                      legacy block fails: 0\n";
+        assert_eq!(
+            RelocStats {
+                alloc_failures: 2,
+                compactions: 14,
+                legacy_block_fails: 0,
+            },
+            reloc_stats(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_display_power() {
+        assert_eq!(Some(true), display_power("display_power=1\n").unwrap());
+        assert_eq!(Some(false), display_power("display_power=0").unwrap());
+        assert_eq!(None, display_power("display_power=-1").unwrap());
+    }
+
+    #[test]
+    fn test_lcd_info() {
+        let lcd = lcd_info("1920 1080 24\n").unwrap();
+        assert_eq!((1920, 1080, 24), (lcd.width, lcd.height, lcd.depth));
+        assert_eq!(lcd, lcd_info("get_lcd_info=1920 1080 24").unwrap());
+        assert!(lcd_info("1920 1080").is_err());
+    }
+
+    #[test]
+    fn test_hdmi_timings() {
+        let timings =
+            hdmi_timings("hdmi_timings=800 0 40 48 88 480 0 13 3 32 0 0 0 60 0 32000000 6\n")
+                .unwrap()
+                .unwrap();
+        assert_eq!(800, timings.h_active_pixels);
+        assert_eq!(480, timings.v_active_lines);
+        assert!(!timings.interlaced);
+        assert_eq!(32_000_000, timings.pixel_freq);
+        assert_eq!(6, timings.aspect_ratio);
+
+        assert_eq!(None, hdmi_timings("hdmi_timings=\n").unwrap());
+        assert!(hdmi_timings("hdmi_timings=800 0 40").is_err());
+    }
+
+    #[test]
+    fn test_version() {
+        let output = "Mar 17 2023 10:50:39 \nCopyright (c) 2012 Broadcom\nversion 82f3750a65fadae9a38077e3c2e217ad158c8d54 (clean) (release) (start)\n";
+        let version = version(output).unwrap();
+        assert_eq!("Mar 17 2023 10:50:39", version.date);
+        assert_eq!("82f3750a65fadae9a38077e3c2e217ad158c8d54", version.hash);
+
+        assert_eq!("", super::version("").unwrap().hash);
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(
+            vec!["vcos", "measure_temp", "get_throttled"],
+            commands("commands=\"vcos, measure_temp, get_throttled\"\n").unwrap()
+        );
+        assert!(commands("").unwrap().is_empty());
     }
 }
@@ -0,0 +1,348 @@
+//! Consuming a monitor's samples as an iterator, and adapters to process them
+//!
+//! `Monitor::into_samples` hands a monitor's samples to blocking code, like the stream of
+//! an `AsyncMonitor` does for async code, see `stream`. The adapters of `SampleIterExt`
+//! work on any iterator of samples, so they apply just as well to samples replayed from a
+//! file.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use vcgencmd::monitor::{Metric, Monitor};
+//! use vcgencmd::pipeline::SampleIterExt;
+//!
+//! let minutes = Monitor::new(Duration::from_secs(1))
+//!     .metric(Metric::Temp)
+//!     .into_samples(16)?
+//!     .deduplicate()
+//!     .window(Duration::from_secs(60));
+//!
+//! for window in minutes {
+//!     println!("{} distinct samples this minute", window.len());
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use crate::monitor::{Metric, Monitor, MonitorHandle, Reading, Sample};
+
+struct State {
+    samples: VecDeque<Arc<Sample>>,
+    finished: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    available: Condvar,
+    capacity: usize,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The sink feeding the iterator, `None` ends it
+fn feed(shared: &Shared, sample: Option<Arc<Sample>>) {
+    let mut state = shared.lock();
+
+    match sample {
+        Some(sample) => {
+            if state.samples.len() >= shared.capacity {
+                state.samples.pop_front();
+            }
+            state.samples.push_back(sample);
+        }
+        None => state.finished = true,
+    }
+
+    shared.available.notify_one();
+}
+
+/// A blocking iterator over the samples taken by a running monitor.
+///
+/// Ends once the monitor stops, which happens when the iterator is dropped or stopped
+/// through its handle.
+pub struct Samples {
+    shared: Arc<Shared>,
+    handle: MonitorHandle,
+}
+
+impl Monitor {
+    /// Start the monitor and iterate over its samples.
+    ///
+    /// Up to `capacity` samples are buffered, if the consumer falls further behind the
+    /// oldest ones are discarded. Fails only if the monitor thread can't be spawned.
+    pub fn into_samples(mut self, capacity: usize) -> io::Result<Samples> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                samples: VecDeque::new(),
+                finished: false,
+            }),
+            available: Condvar::new(),
+            capacity: capacity.max(1),
+        });
+
+        let feeder = Arc::clone(&shared);
+        self.add_shared_sink(move |sample| feed(&feeder, sample));
+
+        Ok(Samples {
+            shared,
+            handle: self.start()?,
+        })
+    }
+}
+
+impl Samples {
+    /// Control the underlying monitor, e.g. to pause it or change its interval
+    pub fn handle(&self) -> &MonitorHandle {
+        &self.handle
+    }
+}
+
+impl Iterator for Samples {
+    type Item = Arc<Sample>;
+
+    fn next(&mut self) -> Option<Arc<Sample>> {
+        let mut state = self.shared.lock();
+
+        loop {
+            if let Some(sample) = state.samples.pop_front() {
+                return Some(sample);
+            } else if state.finished {
+                return None;
+            }
+            state = self
+                .shared
+                .available
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// Adapters for iterators of samples
+pub trait SampleIterExt: Iterator<Item = Arc<Sample>> + Sized {
+    /// Keep only every `n`th sample, starting with the first
+    fn sample_every(self, n: usize) -> SampleEvery<Self> {
+        SampleEvery {
+            samples: self,
+            n: n.max(1),
+            skip: 0,
+        }
+    }
+
+    /// Drop samples with the same readings and failing metrics as the sample before
+    fn deduplicate(self) -> Deduplicate<Self> {
+        Deduplicate {
+            samples: self,
+            previous: None,
+        }
+    }
+
+    /// The readings of every sample with the time they were taken at.
+    ///
+    /// Readings carry the units of the free functions, convert them with `map`, e.g. into
+    /// the types of `units`.
+    fn readings(self) -> Readings<Self> {
+        Readings {
+            samples: self,
+            current: None,
+        }
+    }
+
+    /// Group samples into consecutive windows of `duration`, by their timestamps.
+    ///
+    /// A window is handed out once a sample past its end arrives, or the samples end.
+    /// Windows without any samples are skipped.
+    fn window(self, duration: Duration) -> Window<Self> {
+        Window {
+            samples: self,
+            duration,
+            pending: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Arc<Sample>>> SampleIterExt for I {}
+
+/// Iterator returned by `SampleIterExt::sample_every`
+pub struct SampleEvery<I> {
+    samples: I,
+    n: usize,
+    skip: usize,
+}
+
+impl<I: Iterator<Item = Arc<Sample>>> Iterator for SampleEvery<I> {
+    type Item = Arc<Sample>;
+
+    fn next(&mut self) -> Option<Arc<Sample>> {
+        loop {
+            let sample = self.samples.next()?;
+            if self.skip == 0 {
+                self.skip = self.n - 1;
+                return Some(sample);
+            }
+            self.skip -= 1;
+        }
+    }
+}
+
+/// Iterator returned by `SampleIterExt::deduplicate`
+pub struct Deduplicate<I> {
+    samples: I,
+    previous: Option<(Vec<Reading>, Vec<Metric>)>,
+}
+
+impl<I: Iterator<Item = Arc<Sample>>> Iterator for Deduplicate<I> {
+    type Item = Arc<Sample>;
+
+    fn next(&mut self) -> Option<Arc<Sample>> {
+        loop {
+            let sample = self.samples.next()?;
+            let key = (
+                sample.readings.clone(),
+                sample.errors.iter().map(|(metric, _)| *metric).collect(),
+            );
+            if self.previous.as_ref() != Some(&key) {
+                self.previous = Some(key);
+                return Some(sample);
+            }
+        }
+    }
+}
+
+/// Iterator returned by `SampleIterExt::readings`
+pub struct Readings<I> {
+    samples: I,
+    current: Option<(Arc<Sample>, usize)>,
+}
+
+impl<I: Iterator<Item = Arc<Sample>>> Iterator for Readings<I> {
+    type Item = (SystemTime, Reading);
+
+    fn next(&mut self) -> Option<(SystemTime, Reading)> {
+        loop {
+            if let Some((sample, index)) = &mut self.current {
+                if let Some(&reading) = sample.readings.get(*index) {
+                    *index += 1;
+                    return Some((sample.timestamp, reading));
+                }
+            }
+            self.current = Some((self.samples.next()?, 0));
+        }
+    }
+}
+
+/// Iterator returned by `SampleIterExt::window`
+pub struct Window<I> {
+    samples: I,
+    duration: Duration,
+    /// The end of the window being filled and its samples
+    pending: Option<(SystemTime, Vec<Arc<Sample>>)>,
+}
+
+impl<I: Iterator<Item = Arc<Sample>>> Iterator for Window<I> {
+    type Item = Vec<Arc<Sample>>;
+
+    fn next(&mut self) -> Option<Vec<Arc<Sample>>> {
+        loop {
+            let sample = match self.samples.next() {
+                Some(sample) => sample,
+                None => return self.pending.take().map(|(_, window)| window),
+            };
+
+            match &mut self.pending {
+                Some((end, window)) if sample.timestamp < *end => window.push(sample),
+                pending => {
+                    let end = sample.timestamp + self.duration.max(Duration::from_nanos(1));
+                    let full = pending.replace((end, vec![sample]));
+                    if let Some((_, window)) = full {
+                        return Some(window);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(secs: u64, temp: f64) -> Arc<Sample> {
+        Arc::new(Sample {
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            readings: vec![Reading::Temp(temp)],
+            errors: Vec::new(),
+        })
+    }
+
+    fn temps<I: Iterator<Item = Arc<Sample>>>(samples: I) -> Vec<f64> {
+        samples
+            .readings()
+            .map(|(_, reading)| match reading {
+                Reading::Temp(temp) => temp,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sample_every_and_deduplicate() {
+        let samples = vec![
+            sample(0, 40.0),
+            sample(1, 40.0),
+            sample(2, 41.0),
+            sample(3, 41.0),
+            sample(4, 40.0),
+        ];
+
+        assert_eq!(
+            vec![40.0, 41.0, 40.0],
+            temps(samples.clone().into_iter().sample_every(2))
+        );
+        assert_eq!(
+            vec![40.0, 41.0, 40.0],
+            temps(samples.into_iter().deduplicate())
+        );
+    }
+
+    #[test]
+    fn test_window() {
+        let samples = vec![
+            sample(0, 40.0),
+            sample(5, 41.0),
+            sample(10, 42.0),
+            sample(31, 43.0),
+        ];
+        let windows: Vec<_> = samples
+            .into_iter()
+            .window(Duration::from_secs(10))
+            .map(|window| temps(window.into_iter()))
+            .collect();
+
+        assert_eq!(vec![vec![40.0, 41.0], vec![42.0], vec![43.0]], windows);
+    }
+
+    #[test]
+    fn test_into_samples() {
+        let mut samples = Monitor::new(Duration::from_millis(1))
+            .metric(Metric::Temp)
+            .sampler(|_| Ok(Reading::Temp(42.8)))
+            .into_samples(4)
+            .unwrap();
+
+        for sample in samples.by_ref().take(3) {
+            assert_eq!(vec![Reading::Temp(42.8)], sample.readings);
+        }
+
+        samples.handle().stop();
+        // whatever was buffered is still handed out before the iterator ends
+        for _ in samples {}
+    }
+}
@@ -0,0 +1,201 @@
+//! Power consumption estimated from the Raspberry Pi 5's PMIC readings
+//!
+//! `pmic_read_adc` reports currents and voltages of the supply rails on separate lines.
+//! Multiplying matching pairs gives the power drawn from each rail. Keep in mind that the
+//! sum is what the SoC and memory draw *behind* the PMIC: conversion losses and anything
+//! powered straight from the 5V input (USB devices, HATs, fans) aren't included, so it
+//! underestimates the power taken from the supply.
+
+use std::time::{Duration, Instant};
+
+use crate::{pmic_read_adc, AdcChannel, AdcKind, Result};
+
+/// Gaps between readings longer than this aren't integrated over, since the
+/// consumption in between is unknown (e.g. the monitor was paused or the system suspended)
+const DEFAULT_MAX_GAP: Duration = Duration::from_secs(60);
+
+/// Power drawn from a single rail
+#[derive(Debug, Clone, PartialEq)]
+pub struct RailPower {
+    pub rail: String,
+    pub volts: f64,
+    pub amps: f64,
+    pub watts: f64,
+}
+
+/// Per-rail power at one point in time
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PowerReading {
+    pub rails: Vec<RailPower>,
+}
+
+impl PowerReading {
+    /// Pair up current and voltage channels by rail.
+    ///
+    /// Rails reporting only one of the two (like `EXT5V`, which has no current sensor) are
+    /// left out. The current sensors have a small offset, so values slightly below zero
+    /// are read as zero rather than producing negative power.
+    pub fn from_channels(channels: &[AdcChannel]) -> PowerReading {
+        let rails = channels
+            .iter()
+            .filter(|c| c.kind == AdcKind::Current)
+            .filter_map(|current| {
+                let voltage = channels
+                    .iter()
+                    .find(|c| c.kind == AdcKind::Voltage && c.rail == current.rail)?;
+                let amps = current.value.max(0.0);
+
+                Some(RailPower {
+                    rail: current.rail.clone(),
+                    volts: voltage.value,
+                    amps,
+                    watts: voltage.value * amps,
+                })
+            })
+            .collect();
+
+        PowerReading { rails }
+    }
+
+    /// Combined power of all rails in W
+    pub fn total_watts(&self) -> f64 {
+        self.rails.iter().map(|r| r.watts).sum()
+    }
+
+    pub fn rail(&self, rail: &str) -> Option<&RailPower> {
+        self.rails.iter().find(|r| r.rail == rail)
+    }
+}
+
+/// Read the PMIC and compute the current power per rail
+pub fn measure_power() -> Result<PowerReading> {
+    let channels = pmic_read_adc()?;
+    Ok(PowerReading::from_channels(&channels))
+}
+
+/// Accumulates power readings taken over time into energy.
+///
+/// Consecutive readings are integrated with the trapezoidal rule, which is accurate
+/// for smoothly changing loads even at coarse sampling intervals.
+#[derive(Debug, Clone)]
+pub struct EnergyMeter {
+    last: Option<(Instant, f64)>,
+    watt_seconds: f64,
+    covered: Duration,
+    peak_watts: Option<f64>,
+    max_gap: Duration,
+}
+
+impl Default for EnergyMeter {
+    fn default() -> EnergyMeter {
+        EnergyMeter::new()
+    }
+}
+
+impl EnergyMeter {
+    pub fn new() -> EnergyMeter {
+        EnergyMeter {
+            last: None,
+            watt_seconds: 0.0,
+            covered: Duration::from_secs(0),
+            peak_watts: None,
+            max_gap: DEFAULT_MAX_GAP,
+        }
+    }
+
+    /// Don't integrate across gaps between readings longer than `max_gap`, one minute by default
+    pub fn max_gap(mut self, max_gap: Duration) -> EnergyMeter {
+        self.max_gap = max_gap;
+        self
+    }
+
+    /// Add a total power reading in W taken at `at`
+    pub fn add(&mut self, watts: f64, at: Instant) {
+        if let Some((last_at, last_watts)) = self.last {
+            let elapsed = at.saturating_duration_since(last_at);
+            if elapsed <= self.max_gap {
+                self.watt_seconds += (last_watts + watts) / 2.0 * elapsed.as_secs_f64();
+                self.covered += elapsed;
+            }
+        }
+
+        self.last = Some((at, watts));
+        self.peak_watts = Some(self.peak_watts.map_or(watts, |peak| peak.max(watts)));
+    }
+
+    /// Measure the power right now and add it
+    pub fn sample(&mut self) -> Result<PowerReading> {
+        let reading = measure_power()?;
+        self.add(reading.total_watts(), Instant::now());
+        Ok(reading)
+    }
+
+    /// Energy consumed so far in Wh
+    pub fn watt_hours(&self) -> f64 {
+        self.watt_seconds / 3600.0
+    }
+
+    /// The time span the energy was accumulated over, excluding skipped gaps
+    pub fn covered(&self) -> Duration {
+        self.covered
+    }
+
+    /// Mean power over the covered time span, `None` until two readings were added
+    pub fn average_watts(&self) -> Option<f64> {
+        match self.covered.as_secs_f64() {
+            secs if secs > 0.0 => Some(self.watt_seconds / secs),
+            _ => None,
+        }
+    }
+
+    pub fn peak_watts(&self) -> Option<f64> {
+        self.peak_watts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(rail: &str, kind: AdcKind, value: f64) -> AdcChannel {
+        AdcChannel {
+            rail: rail.to_owned(),
+            kind,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_from_channels() {
+        let reading = PowerReading::from_channels(&[
+            channel("VDD_CORE", AdcKind::Current, 2.0),
+            channel("3V3_SYS", AdcKind::Current, -0.001),
+            channel("VDD_CORE", AdcKind::Voltage, 0.75),
+            channel("3V3_SYS", AdcKind::Voltage, 3.3),
+            channel("EXT5V", AdcKind::Voltage, 5.1),
+        ]);
+
+        assert_eq!(2, reading.rails.len());
+        assert_eq!(1.5, reading.rail("VDD_CORE").unwrap().watts);
+        assert_eq!(0.0, reading.rail("3V3_SYS").unwrap().watts);
+        assert!(reading.rail("EXT5V").is_none());
+        assert_eq!(1.5, reading.total_watts());
+    }
+
+    #[test]
+    fn test_energy_meter() {
+        let start = Instant::now();
+        let mut meter = EnergyMeter::new().max_gap(Duration::from_secs(3600));
+        assert_eq!(None, meter.average_watts());
+
+        meter.add(2.0, start);
+        meter.add(4.0, start + Duration::from_secs(1800));
+        // a gap longer than an hour isn't integrated over
+        meter.add(100.0, start + Duration::from_secs(9000));
+
+        assert_eq!(1.5, meter.watt_hours());
+        assert_eq!(Some(3.0), meter.average_watts());
+        assert_eq!(Duration::from_secs(1800), meter.covered());
+        assert_eq!(Some(100.0), meter.peak_watts());
+    }
+}
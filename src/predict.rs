@@ -0,0 +1,350 @@
+//! Estimating how long until the temperature reaches the soft limit
+//!
+//! The firmware caps the clock once the soft limit is reached, by then it's too late for a
+//! workload to back off gracefully. Extrapolating the recent temperature trend gives an
+//! estimate of the time left, so load can be shed before the cap kicks in.
+//!
+//! A `Fit::Linear` trend is simple and errs on the early side, as a heating SoC slows down
+//! while it approaches the temperature its cooling can sustain. `Fit::Exponential` models
+//! that approach (Newton's law of cooling) and predicts no throttling at all if the
+//! temperature levels off below the limit, but needs a longer, less noisy history.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use crate::alert::{Alert, Severity};
+use crate::monitor::{Reading, Sample};
+use crate::thermal::{TempLimits, DEFAULT_SOFT_LIMIT};
+use crate::units::Celsius;
+
+/// Default span of readings the trend is fitted over
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+/// Default time to throttle below which an alert is raised
+const DEFAULT_HORIZON: Duration = Duration::from_secs(60);
+/// Readings needed before anything is predicted
+const MIN_READINGS: usize = 3;
+
+/// How the temperature trend is extrapolated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fit {
+    /// A straight line through the readings
+    Linear,
+    /// An exponential approach towards a steady-state temperature
+    Exponential,
+}
+
+/// The outcome of fitting the recent readings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prediction {
+    /// The latest temperature in °C
+    pub temp: f64,
+    /// Current rate of change in °C/s
+    pub rate: f64,
+    /// Estimated time until the soft limit is reached, `None` if it isn't expected to be
+    pub time_to_throttle: Option<Duration>,
+}
+
+/// Extrapolates the temperature trend to the soft limit.
+///
+/// Like the other detectors, an alert is raised once when the predicted time to throttle
+/// falls below the horizon, and the predictor re-arms once it is back above it.
+#[derive(Debug, Clone)]
+pub struct ThrottlePredictor {
+    limit: f64,
+    fit: Fit,
+    window: Duration,
+    horizon: Duration,
+    history: VecDeque<(SystemTime, f64)>,
+    alerted: bool,
+}
+
+impl Default for ThrottlePredictor {
+    fn default() -> ThrottlePredictor {
+        ThrottlePredictor::new(DEFAULT_SOFT_LIMIT)
+    }
+}
+
+impl ThrottlePredictor {
+    /// Predict the time until `limit` in °C is reached
+    pub fn new(limit: f64) -> ThrottlePredictor {
+        ThrottlePredictor {
+            limit,
+            fit: Fit::Linear,
+            window: DEFAULT_WINDOW,
+            horizon: DEFAULT_HORIZON,
+            history: VecDeque::new(),
+            alerted: false,
+        }
+    }
+
+    /// Predict the time until the soft limit of `limits` is reached
+    pub fn with_limits(limits: TempLimits) -> ThrottlePredictor {
+        ThrottlePredictor::new(limits.soft)
+    }
+
+    /// How to extrapolate, `Fit::Linear` by default
+    pub fn fit(mut self, fit: Fit) -> ThrottlePredictor {
+        self.fit = fit;
+        self
+    }
+
+    /// Span of recent readings to fit, 60 s by default
+    pub fn window(mut self, window: Duration) -> ThrottlePredictor {
+        self.window = window;
+        self
+    }
+
+    /// Raise an alert once the time to throttle falls below `horizon`, 60 s by default
+    pub fn horizon(mut self, horizon: Duration) -> ThrottlePredictor {
+        self.horizon = horizon;
+        self
+    }
+
+    /// Fit the readings observed so far, `None` until there are enough of them
+    pub fn predict(&self) -> Option<Prediction> {
+        let &(latest_at, temp) = self.history.back()?;
+        if self.history.len() < MIN_READINGS {
+            return None;
+        }
+
+        // seconds before the latest reading, which keeps the numbers small
+        let points: Vec<(f64, f64)> = self
+            .history
+            .iter()
+            .map(|&(at, temp)| (-secs_between(at, latest_at), temp))
+            .collect();
+        let (rate, time_to_throttle) = match self.fit {
+            Fit::Linear => linear(&points, temp, self.limit),
+            Fit::Exponential => exponential(&points, temp, self.limit)
+                .unwrap_or_else(|| linear(&points, temp, self.limit)),
+        };
+
+        Some(Prediction {
+            temp,
+            rate,
+            time_to_throttle,
+        })
+    }
+
+    /// Estimated time until the soft limit is reached, see `predict`
+    pub fn time_to_throttle(&self) -> Option<Duration> {
+        self.predict()?.time_to_throttle
+    }
+
+    /// Feed a temperature in °C, returns an alert if throttling is predicted within the
+    /// horizon
+    pub fn observe(&mut self, temp: f64, at: SystemTime) -> Option<Alert> {
+        self.history.push_back((at, temp));
+        while let Some(&(oldest, _)) = self.history.front() {
+            if secs_between(oldest, at) <= self.window.as_secs_f64() {
+                break;
+            }
+            self.history.pop_front();
+        }
+
+        let prediction = self.predict();
+        let imminent = prediction
+            .and_then(|prediction| prediction.time_to_throttle)
+            .filter(|&time| time < self.horizon);
+        let time = match imminent {
+            Some(time) => time,
+            None => {
+                self.alerted = false;
+                return None;
+            }
+        };
+        if self.alerted {
+            return None;
+        }
+        self.alerted = true;
+
+        let rate = prediction.map_or(0.0, |prediction| prediction.rate);
+        Some(Alert {
+            timestamp: at,
+            severity: Severity::Warning,
+            source: "throttle_predicted",
+            message: format!(
+                "soft limit of {} expected in {:.0} s, at {} rising {:.2} °C/s",
+                Celsius(self.limit),
+                time.as_secs_f64(),
+                Celsius(temp),
+                rate
+            ),
+        })
+    }
+
+    /// Look for a temperature in `sample`, taking the soft limit from a headroom reading
+    pub fn observe_sample(&mut self, sample: &Sample) -> Option<Alert> {
+        sample.readings.iter().find_map(|reading| match *reading {
+            Reading::Temp(temp) => self.observe(temp, sample.timestamp),
+            Reading::TempHeadroom(headroom) => {
+                self.limit = headroom.soft_limit;
+                self.observe(headroom.temp, sample.timestamp)
+            }
+            _ => None,
+        })
+    }
+
+    /// Turn the predictor into a monitor sink, passing alerts to `on_alert`
+    pub fn into_sink<F>(mut self, mut on_alert: F) -> impl FnMut(&Sample) + Send + 'static
+    where
+        F: FnMut(&Alert) + Send + 'static,
+    {
+        move |sample| {
+            if let Some(alert) = self.observe_sample(sample) {
+                on_alert(&alert);
+            }
+        }
+    }
+}
+
+/// Seconds from `earlier` to `later`, 0 if the clock went backwards in between
+fn secs_between(earlier: SystemTime, later: SystemTime) -> f64 {
+    later
+        .duration_since(earlier)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
+
+/// Slope and intercept of the least squares line through `points`, `None` if all x are equal
+fn least_squares(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for &(x, y) in points {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance += (x - mean_x) * (x - mean_x);
+    }
+    if variance <= f64::EPSILON {
+        return None;
+    }
+
+    let slope = covariance / variance;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+fn seconds(secs: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(secs.max(0.0)).ok()
+}
+
+/// Rate and time to `limit` of a straight line through `points`
+fn linear(points: &[(f64, f64)], temp: f64, limit: f64) -> (f64, Option<Duration>) {
+    let rate = least_squares(points).map_or(0.0, |(slope, _)| slope);
+    let time = if temp >= limit {
+        seconds(0.0)
+    } else if rate > 0.0 {
+        seconds((limit - temp) / rate)
+    } else {
+        None
+    };
+
+    (rate, time)
+}
+
+/// Rate and time to `limit` of an exponential approach to a steady state.
+///
+/// Under Newton's law of cooling the rate of change is linear in the temperature,
+/// `dT/dt = (T_steady - T) / tau`, so a line is fitted through the rates between
+/// consecutive readings. `None` if the readings don't show such an approach, e.g. while
+/// the load is still ramping up.
+fn exponential(points: &[(f64, f64)], temp: f64, limit: f64) -> Option<(f64, Option<Duration>)> {
+    let rates: Vec<(f64, f64)> = points
+        .windows(2)
+        .filter_map(|pair| match *pair {
+            [(t0, y0), (t1, y1)] if t1 > t0 => Some(((y0 + y1) / 2.0, (y1 - y0) / (t1 - t0))),
+            _ => None,
+        })
+        .collect();
+    // the slope is -1 / tau, it has to be negative for the temperature to level off
+    let (slope, intercept) = least_squares(&rates).filter(|&(slope, _)| slope < 0.0)?;
+
+    let steady = -intercept / slope;
+    let rate = intercept + slope * temp;
+    let time = if temp >= limit {
+        seconds(0.0)
+    } else if steady > limit && rate > 0.0 {
+        seconds(((steady - limit) / (steady - temp)).ln() / slope)
+    } else {
+        None
+    };
+
+    Some((rate, time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_linear_prediction() {
+        let mut predictor = ThrottlePredictor::new(60.0).horizon(Duration::from_secs(15));
+        assert!(predictor.observe(50.0, at(0)).is_none());
+        assert!(predictor.observe(51.0, at(2)).is_none());
+        assert_eq!(None, predictor.time_to_throttle());
+
+        // 0.5 °C/s, 8 °C to go
+        assert!(predictor.observe(52.0, at(4)).is_none());
+        let prediction = predictor.predict().unwrap();
+        assert!((prediction.rate - 0.5).abs() < 1e-9);
+        assert_eq!(Some(Duration::from_secs(16)), prediction.time_to_throttle);
+
+        let alert = predictor.observe(55.0, at(10)).unwrap();
+        assert_eq!("throttle_predicted", alert.source);
+        // no repeated alert while it stays imminent
+        assert!(predictor.observe(56.0, at(12)).is_none());
+    }
+
+    #[test]
+    fn test_rearms_after_cooling() {
+        let mut predictor = ThrottlePredictor::new(60.0)
+            .horizon(Duration::from_secs(10))
+            .window(Duration::from_secs(4));
+        predictor.observe(56.0, at(0));
+        predictor.observe(57.0, at(2));
+        assert!(predictor.observe(58.0, at(4)).is_some());
+
+        assert!(predictor.observe(57.0, at(6)).is_none());
+        assert!(predictor.observe(56.0, at(8)).is_none());
+        assert_eq!(None, predictor.time_to_throttle());
+
+        assert!(predictor.observe(57.0, at(10)).is_none());
+        assert!(predictor.observe(58.0, at(12)).is_some());
+    }
+
+    #[test]
+    fn test_window_drops_old_readings() {
+        let mut predictor = ThrottlePredictor::new(60.0).window(Duration::from_secs(5));
+        predictor.observe(20.0, at(0));
+        for (secs, temp) in [(10, 50.0), (11, 50.0), (12, 50.0)] {
+            predictor.observe(temp, at(secs));
+        }
+        // the early cold reading would make this look like heating
+        assert_eq!(0.0, predictor.predict().unwrap().rate);
+    }
+
+    #[test]
+    fn test_exponential_levels_off() {
+        // approaching 55 °C with tau = 10 s
+        let curve = |secs: u64| 55.0 - 15.0 * (-(secs as f64) / 10.0).exp();
+        let mut predictor = ThrottlePredictor::new(60.0).fit(Fit::Exponential);
+        for secs in 0..10 {
+            predictor.observe(curve(secs), at(secs));
+        }
+        assert_eq!(None, predictor.time_to_throttle());
+
+        // approaching 70 °C, the limit is crossed
+        let curve = |secs: u64| 70.0 - 30.0 * (-(secs as f64) / 10.0).exp();
+        let mut predictor = ThrottlePredictor::new(60.0).fit(Fit::Exponential);
+        for secs in 0..5 {
+            predictor.observe(curve(secs), at(secs));
+        }
+        // exactly 10 * ln(3) ≈ 11 s from the start, so about 7 s after the last reading
+        let time = predictor.time_to_throttle().unwrap().as_secs_f64();
+        assert!((time - 7.0).abs() < 0.5, "{}", time);
+    }
+}
@@ -0,0 +1,484 @@
+//! Thermal profiling: is the cooling adequate for a sustained load?
+//!
+//! A `ThermalProfile` records temperature, ARM clock and throttled state for a fixed
+//! duration, optionally while running a stress command such as `stress-ng --cpu 4`, and
+//! summarises the run as a `ProfileReport`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use vcgencmd::profile::ThermalProfile;
+//!
+//! let report = ThermalProfile::new(Duration::from_secs(600))
+//!     .stress_command(&["stress-ng", "--cpu", "4"])
+//!     .run()
+//!     .unwrap();
+//!
+//! println!("{}", report);
+//! std::fs::write("profile.json", report.to_json()).unwrap();
+//! ```
+
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use subprocess::{Exec, NullFile, Popen, PopenError};
+
+use crate::calibrate::Baseline;
+use crate::json;
+use crate::monitor::{next_slot, wait_until, Metric, Monitor, Reading, Sample};
+use crate::timefmt::rfc3339;
+use crate::{interpret_bit_pattern, ClockSrc, Result};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The readings taken at one point of a profiling run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfilePoint {
+    /// Time since the start of the run
+    pub elapsed: Duration,
+    /// Temperature in °C
+    pub temp: Option<f64>,
+    /// ARM clock in Hz
+    pub arm_clock: Option<isize>,
+    /// Bit pattern as returned by `get_throttled`
+    pub throttled: Option<isize>,
+}
+
+impl ProfilePoint {
+    /// The readings of `sample`, taken `elapsed` after the start of the run
+    pub(crate) fn from_sample(elapsed: Duration, sample: &Sample) -> ProfilePoint {
+        ProfilePoint {
+            elapsed,
+            temp: match sample.get(Metric::Temp) {
+                Some(&Reading::Temp(temp)) => Some(temp),
+                _ => None,
+            },
+            arm_clock: match sample.get(Metric::Clock(ClockSrc::Arm)) {
+                Some(&Reading::Clock(_, frequency)) => Some(frequency),
+                _ => None,
+            },
+            throttled: match sample.get(Metric::Throttled) {
+                Some(&Reading::Throttled(bit_pattern)) => Some(bit_pattern),
+                _ => None,
+            },
+        }
+    }
+
+    /// Whether the firmware was holding the clocks down at this point
+    pub fn is_throttled(&self) -> bool {
+        self.throttled.is_some_and(|bit_pattern| {
+            let status = interpret_bit_pattern(bit_pattern);
+            status.currently_throttled
+                || status.arm_frequency_capped
+                || status.soft_temp_limit_active
+        })
+    }
+}
+
+/// Records a thermal profile, see the module documentation
+pub struct ThermalProfile {
+    duration: Duration,
+    interval: Duration,
+    stress_command: Option<Vec<String>>,
+    monitor: Monitor,
+}
+
+impl ThermalProfile {
+    /// Profile for `duration`, sampling once per second
+    pub fn new(duration: Duration) -> ThermalProfile {
+        let monitor = Monitor::new(DEFAULT_INTERVAL)
+            .metric(Metric::Temp)
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .metric(Metric::Throttled);
+
+        ThermalProfile {
+            duration,
+            interval: DEFAULT_INTERVAL,
+            stress_command: None,
+            monitor,
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> ThermalProfile {
+        self.interval = interval;
+        self.monitor.set_interval(interval);
+        self
+    }
+
+    /// Run `argv` for the duration of the profile, it is killed if still running at the end
+    pub fn stress_command<S: AsRef<str>>(mut self, argv: &[S]) -> ThermalProfile {
+        self.stress_command = Some(argv.iter().map(|arg| arg.as_ref().to_owned()).collect());
+        self
+    }
+
+    /// Replace the function used to read a metric, see `Monitor::sampler`
+    pub fn sampler<F>(mut self, sampler: F) -> ThermalProfile
+    where
+        F: FnMut(Metric) -> Result<Reading> + Send + 'static,
+    {
+        self.monitor = self.monitor.sampler(sampler);
+        self
+    }
+
+    /// Record the profile, blocking for its duration
+    pub fn run(mut self) -> Result<ProfileReport, PopenError> {
+        let started_at = SystemTime::now();
+        let _stress = Stress::spawn(self.stress_command.as_deref())?;
+
+        let mut points = Vec::new();
+        sample_for(
+            &mut self.monitor,
+            self.duration,
+            self.interval,
+            |elapsed, sample| points.push(ProfilePoint::from_sample(elapsed, sample)),
+        );
+
+        Ok(ProfileReport {
+            started_at,
+            duration: self.duration,
+            stress_command: self.stress_command,
+            points,
+        })
+    }
+}
+
+/// A command generating load, killed when dropped if it is still running
+pub(crate) struct Stress(Option<Popen>);
+
+impl Stress {
+    /// Start `argv`, or nothing if it is `None` or empty
+    pub(crate) fn spawn(argv: Option<&[String]>) -> Result<Stress, PopenError> {
+        let popen = match argv.and_then(<[String]>::split_first) {
+            Some((program, args)) => Some(
+                Exec::cmd(program)
+                    .args(args)
+                    .stdout(NullFile)
+                    .stderr(NullFile)
+                    .popen()?,
+            ),
+            _ => None,
+        };
+
+        Ok(Stress(popen))
+    }
+}
+
+impl Drop for Stress {
+    fn drop(&mut self) {
+        if let Some(popen) = &mut self.0 {
+            if popen.poll().is_none() {
+                let _ = popen.kill();
+            }
+            let _ = popen.wait();
+        }
+    }
+}
+
+/// Sample `monitor` every `interval` until `duration` has passed, handing every sample and
+/// the time since the start to `on_sample`
+pub(crate) fn sample_for<F>(
+    monitor: &mut Monitor,
+    duration: Duration,
+    interval: Duration,
+    on_sample: F,
+) where
+    F: FnMut(Duration, &Sample),
+{
+    sample_on_clock(
+        monitor,
+        duration,
+        interval,
+        on_sample,
+        Instant::now,
+        |slot| wait_until(slot, || false),
+    );
+}
+
+/// `sample_for`, telling the time with `now` and waiting for the next slot with `wait`
+fn sample_on_clock<F, N, W>(
+    monitor: &mut Monitor,
+    duration: Duration,
+    interval: Duration,
+    mut on_sample: F,
+    now: N,
+    mut wait: W,
+) where
+    F: FnMut(Duration, &Sample),
+    N: Fn() -> Instant,
+    W: FnMut(Instant),
+{
+    let start = now();
+    let mut slot = start;
+
+    loop {
+        let sample = monitor.sample();
+        on_sample(now() - start, &sample);
+
+        slot = next_slot(slot, interval, now());
+        if slot > start + duration {
+            break;
+        }
+        wait(slot);
+    }
+}
+
+/// The history of a profiling run and the figures derived from it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileReport {
+    pub started_at: SystemTime,
+    pub duration: Duration,
+    pub stress_command: Option<Vec<String>>,
+    pub points: Vec<ProfilePoint>,
+}
+
+impl ProfileReport {
+    /// Highest temperature seen during the run
+    pub fn max_temp(&self) -> Option<f64> {
+        self.points
+            .iter()
+            .filter_map(|point| point.temp)
+            .fold(None, |max: Option<f64>, temp| match max {
+                Some(max) => Some(max.max(temp)),
+                None => Some(temp),
+            })
+    }
+
+    /// Time from the start of the run to the first throttled point
+    pub fn time_to_throttle(&self) -> Option<Duration> {
+        self.points
+            .iter()
+            .find(|point| point.is_throttled())
+            .map(|point| point.elapsed)
+    }
+
+    /// Median ARM clock over the second half of the run, once the temperature has had
+    /// time to settle
+    pub fn sustained_clock(&self) -> Option<isize> {
+        let half = self.duration / 2;
+        let mut clocks: Vec<_> = self
+            .points
+            .iter()
+            .filter(|point| point.elapsed >= half)
+            .filter_map(|point| point.arm_clock)
+            .collect();
+
+        clocks.sort_unstable();
+        clocks.get(clocks.len() / 2).copied()
+    }
+
+    /// How far the maximum temperature rose above the mean idle temperature of `baseline`
+    pub fn temp_rise(&self, baseline: &Baseline) -> Option<f64> {
+        Some(self.max_temp()? - baseline.temp?.mean)
+    }
+
+    /// Share of the points in which the firmware was throttling
+    pub fn throttled_fraction(&self) -> f64 {
+        if self.points.is_empty() {
+            return 0.0;
+        }
+
+        let throttled = self.points.iter().filter(|p| p.is_throttled()).count();
+        throttled as f64 / self.points.len() as f64
+    }
+
+    /// The report including the full history as a JSON object
+    pub fn to_json(&self) -> String {
+        let points = self.points.iter().map(|point| {
+            json::object(&[
+                ("elapsed", json::number(point.elapsed.as_secs_f64())),
+                ("temp", json::optional(point.temp, json::number)),
+                (
+                    "arm_clock",
+                    json::optional(point.arm_clock, |f| json::number(f as f64)),
+                ),
+                (
+                    "throttled",
+                    json::optional(point.throttled, |b| json::number(b as f64)),
+                ),
+            ])
+        });
+
+        json::object(&[
+            ("started_at", json::string(&rfc3339(self.started_at))),
+            ("duration", json::number(self.duration.as_secs_f64())),
+            (
+                "stress_command",
+                json::optional(self.stress_command.as_ref(), |argv| {
+                    json::array(argv.iter().map(|arg| json::string(arg)))
+                }),
+            ),
+            ("max_temp", json::optional(self.max_temp(), json::number)),
+            (
+                "time_to_throttle",
+                json::optional(self.time_to_throttle(), |t| json::number(t.as_secs_f64())),
+            ),
+            (
+                "sustained_clock",
+                json::optional(self.sustained_clock(), |f| json::number(f as f64)),
+            ),
+            (
+                "throttled_fraction",
+                json::number(self.throttled_fraction()),
+            ),
+            ("points", json::array(points)),
+        ])
+    }
+}
+
+/// A human readable summary of the run
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "duration:         {:.0} s", self.duration.as_secs_f64())?;
+        match self.max_temp() {
+            Some(temp) => writeln!(f, "max temp:         {:.1} °C", temp)?,
+            None => writeln!(f, "max temp:         n/a")?,
+        }
+        match self.time_to_throttle() {
+            Some(time) => writeln!(f, "time to throttle: {:.0} s", time.as_secs_f64())?,
+            None => writeln!(f, "time to throttle: never")?,
+        }
+        match self.sustained_clock() {
+            Some(clock) => writeln!(f, "sustained clock:  {} MHz", clock / 1_000_000)?,
+            None => writeln!(f, "sustained clock:  n/a")?,
+        }
+        write!(
+            f,
+            "throttled:        {:.0} % of the time",
+            self.throttled_fraction() * 100.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibrate::Stats;
+    use std::cell::Cell;
+    use std::time::UNIX_EPOCH;
+
+    const MHZ: isize = 1_000_000;
+
+    fn point(secs: u64, temp: f64, arm_clock: isize, throttled: isize) -> ProfilePoint {
+        ProfilePoint {
+            elapsed: Duration::from_secs(secs),
+            temp: Some(temp),
+            arm_clock: Some(arm_clock),
+            throttled: Some(throttled),
+        }
+    }
+
+    fn report() -> ProfileReport {
+        // all four "currently" bits, whichever of them mean throttled
+        let throttled = 0b1111;
+
+        ProfileReport {
+            started_at: UNIX_EPOCH,
+            duration: Duration::from_secs(40),
+            stress_command: Some(vec!["stress-ng".to_owned(), "--cpu".to_owned()]),
+            points: vec![
+                point(0, 45.0, 1500 * MHZ, 0),
+                point(10, 70.0, 1500 * MHZ, 0),
+                point(20, 82.0, 1200 * MHZ, throttled),
+                point(30, 80.5, 1000 * MHZ, throttled),
+                point(40, 80.0, 1100 * MHZ, throttled),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_report_figures() {
+        let report = report();
+        assert_eq!(Some(82.0), report.max_temp());
+        assert_eq!(Some(Duration::from_secs(20)), report.time_to_throttle());
+        assert_eq!(Some(1100 * MHZ), report.sustained_clock());
+        assert!((report.throttled_fraction() - 0.6).abs() < 1e-9);
+
+        let baseline = Baseline {
+            temp: Stats::from_values(vec![40.0, 42.0]),
+            ..Baseline::default()
+        };
+        assert_eq!(Some(41.0), report.temp_rise(&baseline));
+
+        let summary = report.to_string();
+        assert!(summary.contains("82.0 °C"));
+        assert!(summary.contains("1100 MHz"));
+    }
+
+    #[test]
+    fn test_report_json() {
+        let json = report().to_json();
+        assert!(json.starts_with(r#"{"started_at":"1970-01-01T00:00:00.000Z","duration":40,"#));
+        assert!(json.contains(r#""stress_command":["stress-ng","--cpu"]"#));
+        assert!(json.contains(r#""time_to_throttle":20,"#));
+        assert!(json.contains(r#"{"elapsed":0,"temp":45,"arm_clock":1500000000,"throttled":0}"#));
+    }
+
+    #[test]
+    fn test_run() {
+        let report = ThermalProfile::new(Duration::from_millis(50))
+            .interval(Duration::from_millis(10))
+            .sampler(|metric| match metric {
+                Metric::Temp => Ok(Reading::Temp(50.0)),
+                Metric::Clock(src) => Ok(Reading::Clock(src, 1500 * MHZ)),
+                _ => Ok(Reading::Throttled(0)),
+            })
+            .run()
+            .unwrap();
+
+        // how many samples fit depends on the scheduler, see `test_sample_on_clock`
+        assert!(!report.points.is_empty());
+        assert_eq!(Some(50.0), report.max_temp());
+        assert_eq!(None, report.time_to_throttle());
+    }
+
+    #[test]
+    fn test_sample_on_clock() {
+        let start = Instant::now();
+        let clock = Cell::new(start);
+        let mut monitor = Monitor::new(Duration::from_millis(10))
+            .metric(Metric::Temp)
+            .sampler(|_| Ok(Reading::Temp(50.0)));
+
+        let mut elapsed = Vec::new();
+        sample_on_clock(
+            &mut monitor,
+            Duration::from_millis(50),
+            Duration::from_millis(10),
+            |at, _| elapsed.push(at.as_millis()),
+            || clock.get(),
+            |slot| clock.set(slot),
+        );
+        assert_eq!(vec![0, 10, 20, 30, 40, 50], elapsed);
+
+        // a sample taking longer than the interval skips the slots it missed
+        let mut elapsed = Vec::new();
+        clock.set(start);
+        sample_on_clock(
+            &mut monitor,
+            Duration::from_millis(50),
+            Duration::from_millis(10),
+            |at, _| {
+                elapsed.push(at.as_millis());
+                clock.set(clock.get() + Duration::from_millis(15));
+            },
+            || clock.get(),
+            |slot| clock.set(slot),
+        );
+        assert_eq!(vec![0, 20, 40], elapsed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stress_command_is_stopped() {
+        let start = Instant::now();
+        ThermalProfile::new(Duration::from_millis(20))
+            .interval(Duration::from_millis(10))
+            .stress_command(&["sleep", "30"])
+            .sampler(|_| Ok(Reading::Throttled(0)))
+            .run()
+            .unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
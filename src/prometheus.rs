@@ -0,0 +1,600 @@
+//! Prometheus text exposition format, e.g. for node_exporter's textfile collector
+//!
+//! node_exporter reads every `*.prom` file in the directory passed as
+//! `--collector.textfile.directory`. `TextfileSink` writes one such file per sample, so the
+//! readings show up alongside node_exporter's own metrics without another HTTP server.
+//!
+//! Without node_exporter, `MetricsEndpoint` serves the latest sample at `/metrics` itself.
+//! Devices that can't be scraped, e.g. behind NAT, can push to a Pushgateway with
+//! `Pushgateway` instead.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write as _};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::events::Condition;
+use crate::monitor::{Reading, Sample};
+use crate::sink::{self, MetricSink};
+use crate::{interpret_bit_pattern, resolve_src, Src};
+
+const DEFAULT_FILE_NAME: &str = "vcgencmd.prom";
+const DEFAULT_JOB: &str = "vcgencmd";
+const DEFAULT_PUSHGATEWAY_PORT: u16 = 9091;
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<(String, f64)>,
+}
+
+impl Family {
+    fn new(name: &'static str, kind: &'static str, help: &'static str) -> Family {
+        Family {
+            name,
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, labels: String, value: f64) {
+        self.samples.push((labels, value));
+    }
+}
+
+fn label(name: &str, value: &str) -> String {
+    format!("{{{}=\"{}\"}}", name, value)
+}
+
+fn src_label(src: Src) -> String {
+    label("src", &resolve_src(Some(src)).unwrap_or_default())
+}
+
+/// Render the readings of `sample` in the text exposition format.
+///
+/// Metrics without a reading in `sample` are left out entirely, so a failed read shows up as
+/// a stale or absent series rather than a misleading value.
+pub fn exposition(sample: &Sample) -> String {
+    let mut temp = Family::new("vcgencmd_temperature_celsius", "gauge", "SoC temperature");
+    let mut headroom = Family::new(
+        "vcgencmd_temperature_headroom_celsius",
+        "gauge",
+        "Degrees left until the throttling limit is reached",
+    );
+    let mut throttled = Family::new(
+        "vcgencmd_throttled_bits",
+        "gauge",
+        "Bit pattern as returned by get_throttled",
+    );
+    let mut active = Family::new(
+        "vcgencmd_throttle_active",
+        "gauge",
+        "Whether a condition reported by get_throttled is active",
+    );
+    let mut occurred = Family::new(
+        "vcgencmd_throttle_occurred",
+        "gauge",
+        "Whether a condition reported by get_throttled occurred since boot",
+    );
+    let mut clock = Family::new("vcgencmd_clock_hz", "gauge", "Measured clock frequency");
+    let mut volts = Family::new("vcgencmd_volts", "gauge", "Measured voltage");
+    let mut mem = Family::new("vcgencmd_mem_bytes", "gauge", "Memory split");
+    let mut derived = Family::new(
+        "vcgencmd_derived",
+        "gauge",
+        "Metric derived from the others by the monitor",
+    );
+
+    for reading in &sample.readings {
+        match *reading {
+            Reading::Temp(value) => temp.push(String::new(), value),
+            Reading::TempHeadroom(value) => {
+                temp.push(String::new(), value.temp);
+                headroom.push(label("limit", "soft"), value.to_soft_limit());
+                headroom.push(label("limit", "hard"), value.to_hard_limit());
+            }
+            Reading::Throttled(bit_pattern) => {
+                throttled.push(String::new(), bit_pattern as f64);
+
+                let status = interpret_bit_pattern(bit_pattern);
+                for &condition in &Condition::ALL {
+                    let labels = label("condition", condition.name());
+                    let flag = |set: bool| if set { 1.0 } else { 0.0 };
+                    active.push(labels.clone(), flag(condition.is_active(&status)));
+                    occurred.push(labels, flag(condition.has_occurred(&status)));
+                }
+            }
+            Reading::Clock(src, frequency) => {
+                clock.push(src_label(Src::Clock(src)), frequency as f64)
+            }
+            Reading::Volts(src, value) => volts.push(src_label(Src::Volt(src)), value),
+            Reading::Mem(src, megabytes) => {
+                mem.push(src_label(Src::Mem(src)), megabytes as f64 * 1024.0 * 1024.0)
+            }
+            Reading::Derived(name, value) => derived.push(label("metric", name), value),
+        }
+    }
+
+    let mut output = String::new();
+    for family in &[
+        temp, headroom, throttled, active, occurred, clock, volts, mem, derived,
+    ] {
+        if family.samples.is_empty() {
+            continue;
+        }
+
+        let _ = writeln!(output, "# HELP {} {}", family.name, family.help);
+        let _ = writeln!(output, "# TYPE {} {}", family.name, family.kind);
+        for (labels, value) in &family.samples {
+            let _ = writeln!(output, "{}{} {}", family.name, labels, value);
+        }
+    }
+
+    output
+}
+
+/// Writes every sample to a `.prom` file for node_exporter's textfile collector.
+///
+/// The file is written to a temporary name in the same directory first and then renamed, so
+/// the collector never reads a half-written file.
+#[derive(Debug, Clone)]
+pub struct TextfileSink {
+    path: PathBuf,
+}
+
+impl TextfileSink {
+    /// Write to `vcgencmd.prom` in `directory`
+    pub fn new<P: AsRef<Path>>(directory: P) -> TextfileSink {
+        TextfileSink {
+            path: directory.as_ref().join(DEFAULT_FILE_NAME),
+        }
+    }
+
+    /// Use a different file name, it should end in `.prom` to be picked up
+    pub fn file_name(mut self, name: &str) -> TextfileSink {
+        self.path.set_file_name(name);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Atomically replace the file with the readings of `sample`
+    pub fn write(&self, sample: &Sample) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+
+        fs::write(&tmp, exposition(sample))?;
+        fs::rename(&tmp, &self.path).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+    }
+
+    /// Turn this into a plain monitor sink. After a failing write the file keeps the
+    /// previous sample until the next one, `Monitor::metric_sink` counts the failure.
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for TextfileSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        TextfileSink::write(self, sample)
+    }
+}
+
+/// A minimal HTTP server answering `GET /metrics` with the exposition of the latest sample.
+///
+/// Requests are handled one at a time on a background thread, which is plenty for a
+/// Prometheus server scraping every few seconds. The thread runs until the process exits.
+#[derive(Debug, Clone)]
+pub struct MetricsEndpoint {
+    latest: Arc<Mutex<String>>,
+    local_addr: SocketAddr,
+}
+
+impl MetricsEndpoint {
+    /// Listen on `addr`, until the first sample `/metrics` returns an empty page
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<MetricsEndpoint> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let latest = Arc::new(Mutex::new(String::new()));
+
+        let served = Arc::clone(&latest);
+        thread::Builder::new()
+            .name("vcgencmd-http".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    // a misbehaving client only costs its own request
+                    let _ = respond(stream, &served);
+                }
+            })?;
+
+        Ok(MetricsEndpoint { latest, local_addr })
+    }
+
+    /// The address actually bound, useful when binding to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Serve the readings of `sample` from now on
+    pub fn update(&self, sample: &Sample) {
+        let exposition = exposition(sample);
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = exposition;
+    }
+
+    /// Turn this into a plain monitor sink updating the served metrics
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for MetricsEndpoint {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        self.update(sample);
+        Ok(())
+    }
+}
+
+fn respond(stream: TcpStream, latest: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers aren't needed, but have to be read before answering
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = latest.lock().unwrap_or_else(|e| e.into_inner()).clone();
+            ("200 OK", body)
+        }
+        (Some("GET"), Some(_)) => ("404 Not Found", "Not Found\n".to_owned()),
+        _ => ("405 Method Not Allowed", "Method Not Allowed\n".to_owned()),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Pushes samples to a Prometheus Pushgateway.
+///
+/// Every push `POST`s the exposition of a sample to the group of the `job` label, `vcgencmd`
+/// by default, and the `instance` label, the hostname by default, plus any labels added.
+/// With an interval set, the samples in between pushes are skipped.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use vcgencmd::monitor::{Metric, Monitor};
+/// use vcgencmd::prometheus::Pushgateway;
+///
+/// let pushgateway = Pushgateway::new("http://metrics.example.com:9091")?
+///     .job("pi-fleet")
+///     .label("site", "garage")
+///     .interval(Duration::from_secs(30));
+/// let monitor = Monitor::new(Duration::from_secs(5))
+///     .metric(Metric::Temp)
+///     .metric_sink(pushgateway);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pushgateway {
+    /// `host:port`
+    address: String,
+    /// The path the Pushgateway is served under, without a trailing slash
+    prefix: String,
+    job: String,
+    labels: Vec<(String, String)>,
+    interval: Option<Duration>,
+    pushed_at: Option<Instant>,
+}
+
+impl Pushgateway {
+    /// Push to the Pushgateway at `url`, as `http://host[:port][/prefix]`, port 9091 by
+    /// default. Only plain HTTP is supported.
+    pub fn new(url: &str) -> io::Result<Pushgateway> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| invalid("the Pushgateway URL has to start with http://"))?;
+        let (authority, prefix) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if authority.is_empty() {
+            return Err(invalid("the Pushgateway URL has no host"));
+        }
+        let address = match authority.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => authority.to_owned(),
+            _ => format!("{}:{}", authority, DEFAULT_PUSHGATEWAY_PORT),
+        };
+
+        let mut labels = Vec::new();
+        if let Some(hostname) = crate::hostname() {
+            labels.push(("instance".to_owned(), hostname));
+        }
+        Ok(Pushgateway {
+            address,
+            prefix: prefix.trim_end_matches('/').to_owned(),
+            job: DEFAULT_JOB.to_owned(),
+            labels,
+            interval: None,
+            pushed_at: None,
+        })
+    }
+
+    pub fn job(mut self, job: &str) -> Pushgateway {
+        self.job = job.to_owned();
+        self
+    }
+
+    /// Push as `instance` instead of the hostname
+    pub fn instance(self, instance: &str) -> Pushgateway {
+        self.label("instance", instance)
+    }
+
+    /// Add a label to the grouping key, or change the value of one already added
+    pub fn label(mut self, name: &str, value: &str) -> Pushgateway {
+        match self.labels.iter_mut().find(|(known, _)| known == name) {
+            Some((_, known)) => *known = value.to_owned(),
+            None => self.labels.push((name.to_owned(), value.to_owned())),
+        }
+        self
+    }
+
+    /// Push at most once every `interval`, skipping the samples in between
+    pub fn interval(mut self, interval: Duration) -> Pushgateway {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// The path of the group pushed to, e.g. `/metrics/job/vcgencmd/instance/pi4`
+    pub fn path(&self) -> String {
+        let mut path = format!("{}/metrics/{}", self.prefix, path_label("job", &self.job));
+        for (name, value) in &self.labels {
+            path.push('/');
+            path.push_str(&path_label(name, value));
+        }
+        path
+    }
+
+    /// Push the readings of `sample` right away, regardless of the interval
+    pub fn push(&mut self, sample: &Sample) -> io::Result<()> {
+        let addr = self.address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "Pushgateway address not found")
+        })?;
+        let mut stream = TcpStream::connect_timeout(&addr, PUSH_TIMEOUT)?;
+        stream.set_read_timeout(Some(PUSH_TIMEOUT))?;
+        stream.set_write_timeout(Some(PUSH_TIMEOUT))?;
+        self.pushed_at = Some(Instant::now());
+
+        let body = exposition(sample);
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Content-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            self.path(),
+            self.address,
+            body.len(),
+            body
+        )?;
+        stream.flush()?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "the Pushgateway answered {}",
+                status_line.trim()
+            ))),
+        }
+    }
+
+    /// Turn this into a plain monitor sink. A failed push is dropped and the next due
+    /// sample pushed as usual, add the gateway with `Monitor::metric_sink` to count them.
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for Pushgateway {
+    /// Push `sample` if the interval passed since the last push
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let due = match (self.pushed_at, self.interval) {
+            (Some(pushed_at), Some(interval)) => pushed_at.elapsed() >= interval,
+            _ => true,
+        };
+        if !due {
+            return Ok(());
+        }
+        self.push(sample)
+    }
+}
+
+/// A label of a grouping key as a path segment, values that can't be put into a path as
+/// they are are encoded in base64
+fn path_label(name: &str, value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-._~".contains(c);
+    if !value.is_empty() && value.chars().all(plain) {
+        return format!("{}/{}", name, value);
+    }
+
+    match value {
+        "" => format!("{}@base64/=", name),
+        value => format!("{}@base64/{}", name, base64url(value.as_bytes())),
+    }
+}
+
+/// The URL-safe base64 encoding of `bytes`, with padding
+fn base64url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i > chunk.len() {
+                encoded.push('=');
+                continue;
+            }
+            let index = (bits >> (18 - 6 * i)) & 0x3f;
+            encoded.extend(ALPHABET.get(index as usize).map(|&c| char::from(c)));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thermal::TempLimits;
+    use crate::{ClockSrc, MemSrc};
+    use std::time::SystemTime;
+
+    fn sample() -> Sample {
+        Sample {
+            timestamp: SystemTime::now(),
+            readings: vec![
+                Reading::TempHeadroom(TempLimits::default().headroom(45.5)),
+                Reading::Throttled(0),
+                Reading::Clock(ClockSrc::Arm, 1_500_000_000),
+                Reading::Clock(ClockSrc::Core, 500_000_000),
+                Reading::Mem(MemSrc::Gpu, 76),
+            ],
+            errors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_exposition() {
+        let output = exposition(&sample());
+
+        assert!(output.starts_with(
+            "# HELP vcgencmd_temperature_celsius SoC temperature\n\
+             # TYPE vcgencmd_temperature_celsius gauge\n\
+             vcgencmd_temperature_celsius 45.5\n"
+        ));
+        assert!(output.contains("vcgencmd_temperature_headroom_celsius{limit=\"hard\"} 39.5\n"));
+        assert!(output.contains("vcgencmd_throttle_active{condition=\"under_voltage\"} 0\n"));
+        assert!(output.contains(
+            "vcgencmd_clock_hz{src=\"arm\"} 1500000000\nvcgencmd_clock_hz{src=\"core\"} 500000000\n"
+        ));
+        assert!(output.contains("vcgencmd_mem_bytes{src=\"gpu\"} 79691776\n"));
+        assert!(!output.contains("vcgencmd_volts"));
+    }
+
+    #[test]
+    fn test_textfile_sink() {
+        let dir = std::env::temp_dir().join(format!("vcgencmd-textfile-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let sink = TextfileSink::new(&dir).file_name("pi.prom");
+        assert_eq!(dir.join("pi.prom"), sink.path());
+
+        let mut sink = sink.into_sink();
+        sink(&sample());
+        let written = fs::read_to_string(dir.join("pi.prom")).unwrap();
+        assert_eq!(exposition(&sample()), written);
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        use std::io::Read;
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let endpoint = MetricsEndpoint::bind("127.0.0.1:0").unwrap();
+        let addr = endpoint.local_addr();
+        assert!(get(addr, "/metrics").ends_with("Content-Length: 0\r\nConnection: close\r\n\r\n"));
+
+        let mut sink = endpoint.into_sink();
+        sink(&sample());
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&exposition(&sample())));
+
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_pushgateway_path() {
+        let pushgateway = Pushgateway::new("http://localhost/gateway/")
+            .unwrap()
+            .instance("pi4")
+            .label("path", "/var/lib")
+            .label("empty", "");
+        assert_eq!("localhost:9091", pushgateway.address);
+        assert_eq!(
+            "/gateway/metrics/job/vcgencmd/instance/pi4/path@base64/L3Zhci9saWI=/empty@base64/=",
+            pushgateway.path()
+        );
+
+        assert_eq!("YS9i", base64url(b"a/b"));
+        assert!(Pushgateway::new("https://localhost").is_err());
+        assert!(Pushgateway::new("http://").is_err());
+    }
+
+    #[test]
+    fn test_pushgateway_push() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            while reader.read_line(&mut request).unwrap() > 2 {}
+            let mut stream = reader.into_inner();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            request
+        });
+
+        let mut pushgateway = Pushgateway::new(&url)
+            .unwrap()
+            .instance("pi4")
+            .interval(Duration::from_secs(3600));
+        MetricSink::write(&mut pushgateway, &sample()).unwrap();
+        // not due yet, nothing is sent
+        MetricSink::write(&mut pushgateway, &sample()).unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /metrics/job/vcgencmd/instance/pi4 HTTP/1.1\r\n"));
+        let length = exposition(&sample()).len();
+        assert!(request.contains(&format!("Content-Length: {}\r\n", length)));
+    }
+}
@@ -0,0 +1,64 @@
+//! One-liners for the readings most scripts want, in the units `vcgencmd` users know
+//!
+//! ```no_run
+//! use vcgencmd::quick::{cpu_freq_mhz, cpu_temp, gpu_mem_mb};
+//!
+//! println!("{} °C at {:.0} MHz", cpu_temp()?, cpu_freq_mhz()?);
+//! println!("{} MB for the GPU", gpu_mem_mb()?);
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+
+use crate::{get_mem, measure_clock, measure_temp, measure_volts};
+use crate::{ClockSrc, MemSrc, Result, Src, VoltSrc};
+
+/// SoC temperature in °C, `measure_temp`
+pub fn cpu_temp() -> Result<f64> {
+    measure_temp()
+}
+
+/// ARM clock in MHz
+pub fn cpu_freq_mhz() -> Result<f64> {
+    measure_clock(Src::Clock(ClockSrc::Arm)).map(mhz)
+}
+
+/// Core (GPU) clock in MHz
+pub fn gpu_freq_mhz() -> Result<f64> {
+    measure_clock(Src::Clock(ClockSrc::Core)).map(mhz)
+}
+
+/// Memory of the ARM in MB
+pub fn arm_mem_mb() -> Result<isize> {
+    get_mem(Src::Mem(MemSrc::Arm))
+}
+
+/// Memory of the GPU in MB
+pub fn gpu_mem_mb() -> Result<isize> {
+    get_mem(Src::Mem(MemSrc::Gpu))
+}
+
+/// Core voltage in V
+pub fn core_volts() -> Result<f64> {
+    measure_volts(Src::Volt(VoltSrc::Core))
+}
+
+fn mhz(hz: isize) -> f64 {
+    hz as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mhz() {
+        assert_eq!(1500.0, mhz(1_500_000_000));
+        assert_eq!(600.169, mhz(600_169_000));
+    }
+
+    #[cfg(target_arch = "arm")]
+    #[test]
+    fn test_quick() {
+        assert!(cpu_temp().is_ok());
+        assert!(cpu_freq_mhz().unwrap() > 0.0);
+    }
+}
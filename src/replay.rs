@@ -0,0 +1,320 @@
+//! Replaying samples logged by the CSV and JSON Lines sinks
+//!
+//! Telemetry captured in the field with `csv` or `JsonlSink` can be read back as samples
+//! and run through the same detectors, pipeline adapters and health rules as live ones,
+//! offline and as fast as the file can be read. The readings come back as they were
+//! logged, with two exceptions: errors are only kept as the absence of a reading, and CSV
+//! logs don't hold enough to restore `Reading::TempHeadroom`.
+//!
+//! ```no_run
+//! use vcgencmd::anomaly::ClockDropDetector;
+//! use vcgencmd::replay::Replay;
+//! use vcgencmd::ClockSrc;
+//!
+//! let mut detector = ClockDropDetector::new(ClockSrc::Arm);
+//! let mut replay = Replay::open("/var/log/vcgencmd.jsonl")?;
+//! for sample in replay.by_ref() {
+//!     if let Some(alert) = detector.observe_sample(&sample) {
+//!         println!("{}", alert);
+//!     }
+//! }
+//! println!("{} lines couldn't be read", replay.skipped());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::json::{self, Value};
+use crate::monitor::{Metric, Reading, Sample};
+use crate::thermal::TempHeadroom;
+use crate::timefmt::parse_rfc3339;
+
+/// How a log was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Comma separated values with a header row, as written by `csv::header` and `csv::row`
+    Csv,
+    /// Tab separated values with a header row
+    Tsv,
+    /// A `Sample::to_json` object per line, as written by `JsonlSink`
+    Jsonl,
+}
+
+impl Format {
+    /// The format of a file named like `path`, by its extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(Format::Csv),
+            "tsv" => Some(Format::Tsv),
+            "jsonl" | "ndjson" | "json" => Some(Format::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+/// The samples of a log, in the order they were logged.
+///
+/// Lines that can't be read as a sample are skipped and counted, as logs of a device that
+/// lost power often end in a partial line. Reading stops at the first I/O error, which is
+/// kept.
+pub struct Replay<R> {
+    lines: io::Lines<R>,
+    format: Format,
+    /// The metric of every CSV column after the timestamp, `None` for those that can't be
+    /// restored. Rows before the first header are skipped.
+    columns: Option<Vec<Option<Metric>>>,
+    skipped: usize,
+    error: Option<io::Error>,
+}
+
+impl Replay<BufReader<File>> {
+    /// Replay the log at `path`, in the format its extension names
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Replay<BufReader<File>>> {
+        let format = Format::from_path(&path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "unknown log format, expected a .csv, .tsv or .jsonl file",
+            )
+        })?;
+
+        Ok(Replay::new(BufReader::new(File::open(path)?), format))
+    }
+}
+
+impl<R: BufRead> Replay<R> {
+    pub fn new(reader: R, format: Format) -> Replay<R> {
+        Replay {
+            lines: reader.lines(),
+            format,
+            columns: None,
+            skipped: 0,
+            error: None,
+        }
+    }
+
+    /// Number of lines skipped so far
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// The I/O error that ended the replay early, if any
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Hand every sample to `sink`, returning how many there were, or the I/O error that
+    /// ended the replay early
+    pub fn feed<F: FnMut(&Sample)>(mut self, mut sink: F) -> io::Result<usize> {
+        let mut count = 0;
+        for sample in self.by_ref() {
+            sink(&sample);
+            count += 1;
+        }
+
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(count),
+        }
+    }
+
+    fn parse(&mut self, line: &str) -> Option<Sample> {
+        match self.format {
+            Format::Csv => self.parse_delimited(line, ','),
+            Format::Tsv => self.parse_delimited(line, '\t'),
+            Format::Jsonl => parse_json(line),
+        }
+    }
+
+    fn parse_delimited(&mut self, line: &str, delimiter: char) -> Option<Sample> {
+        let mut cells = line.split(delimiter).map(str::trim);
+        let first = cells.next()?;
+        if first == "timestamp" {
+            self.columns = Some(cells.map(Metric::from_name).collect());
+            return None;
+        }
+
+        let timestamp = parse_rfc3339(first)?;
+        let mut readings = Vec::new();
+        for (metric, cell) in self.columns.as_ref()?.iter().zip(cells) {
+            let metric = match metric {
+                Some(metric) if !cell.is_empty() => *metric,
+                _ => continue,
+            };
+            readings.extend(reading(metric, cell.parse().ok()?));
+        }
+
+        Some(sample(timestamp, readings))
+    }
+}
+
+impl<R: BufRead> Iterator for Replay<R> {
+    type Item = Arc<Sample>;
+
+    fn next(&mut self) -> Option<Arc<Sample>> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(error) => {
+                    self.error = Some(error);
+                    return None;
+                }
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let is_header = self.format != Format::Jsonl && line.starts_with("timestamp");
+            match self.parse(line) {
+                Some(sample) => return Some(Arc::new(sample)),
+                None if is_header => {}
+                None => self.skipped += 1,
+            }
+        }
+    }
+}
+
+fn sample(timestamp: std::time::SystemTime, readings: Vec<Reading>) -> Sample {
+    Sample {
+        timestamp,
+        readings,
+        errors: Vec::new(),
+    }
+}
+
+/// A `Sample::to_json` object
+fn parse_json(line: &str) -> Option<Sample> {
+    let value = json::parse(line).ok()?;
+    let timestamp = parse_rfc3339(value.get("timestamp")?.as_str()?)?;
+
+    let mut readings = Vec::new();
+    if let Value::Object(fields) = value.get("readings")? {
+        for (name, value) in fields {
+            let metric = match Metric::from_name(name) {
+                Some(metric) => metric,
+                None => continue,
+            };
+            let restored = match metric {
+                Metric::TempHeadroom => headroom(value),
+                metric => value.as_f64().and_then(|value| reading(metric, value)),
+            };
+            readings.extend(restored);
+        }
+    }
+
+    Some(sample(timestamp, readings))
+}
+
+/// A `Reading::TempHeadroom` logged as an object of its fields
+fn headroom(value: &Value) -> Option<Reading> {
+    let field = |name: &str| value.get(name).and_then(Value::as_f64);
+
+    Some(Reading::TempHeadroom(TempHeadroom {
+        temp: field("temp")?,
+        soft_limit: field("soft_limit")?,
+        hard_limit: field("hard_limit")?,
+    }))
+}
+
+/// The reading of `metric` logged as `value`, `None` for those that can't be restored
+/// from a single number
+fn reading(metric: Metric, value: f64) -> Option<Reading> {
+    let integer = value.round() as isize;
+    match metric {
+        Metric::Temp => Some(Reading::Temp(value)),
+        Metric::TempHeadroom => None,
+        Metric::Throttled => Some(Reading::Throttled(integer)),
+        Metric::Clock(src) => Some(Reading::Clock(src, integer)),
+        Metric::Volts(src) => Some(Reading::Volts(src, value)),
+        Metric::Mem(src) => Some(Reading::Mem(src, integer)),
+        Metric::Derived(name) => Some(Reading::Derived(name, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClockSrc, VoltSrc};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn replay(format: Format, log: &str) -> Replay<&[u8]> {
+        Replay::new(log.as_bytes(), format)
+    }
+
+    #[test]
+    fn test_csv() {
+        let log = "\
+            1970-01-01T00:00:00.000Z,47.2,,600000000\n\
+            timestamp,temp,volts.core,clock.arm,throttled\n\
+            1970-01-01T00:00:00.000Z,47.2,,600000000,327685\n\
+            \n\
+            1970-01-01T00:00:01.000Z,48,0.85,1500000000,0\n\
+            1970-01-01T00:00:0";
+
+        let mut replay = replay(Format::Csv, log);
+        let samples: Vec<_> = replay.by_ref().collect();
+        assert_eq!(2, samples.len());
+        assert_eq!(2, replay.skipped());
+        assert_eq!(
+            vec![
+                Reading::Temp(47.2),
+                Reading::Clock(ClockSrc::Arm, 600_000_000),
+                Reading::Throttled(0x50005),
+            ],
+            samples[0].readings
+        );
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(1), samples[1].timestamp);
+        assert_eq!(
+            Some(&Reading::Volts(VoltSrc::Core, 0.85)),
+            samples[1].get(Metric::Volts(VoltSrc::Core))
+        );
+
+        let tsv = "timestamp\ttemp\n1970-01-01T00:00:00.000Z\t51\n";
+        assert_eq!(1, self::replay(Format::Tsv, tsv).count());
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let log = concat!(
+            r#"{"timestamp":"1970-01-01T00:01:00.000Z","readings":{"temp":51,"throttled":327685,"#,
+            r#""temp_headroom":{"temp":51,"soft_limit":60,"hard_limit":85},"unknown":1},"#,
+            r#""errors":{"volts.core":"measure_volts core: failed to run vcgencmd"}}"#,
+            "\n",
+            r#"{"timestamp":"1970-01-01T00:01:01.000Z","readings":{"temp":5"#,
+        );
+
+        let mut replay = replay(Format::Jsonl, log);
+        let samples: Vec<_> = replay.by_ref().collect();
+        assert_eq!(1, samples.len());
+        assert_eq!(1, replay.skipped());
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(60), samples[0].timestamp);
+        assert_eq!(3, samples[0].readings.len());
+        assert_eq!(
+            Some(&Reading::Throttled(0x50005)),
+            samples[0].get(Metric::Throttled)
+        );
+        assert!(samples[0].errors.is_empty());
+    }
+
+    #[test]
+    fn test_feed_and_format() {
+        let log = "timestamp,temp\n1970-01-01T00:00:00.000Z,51\n1970-01-01T00:00:01.000Z,52\n";
+        let mut temps = Vec::new();
+        let count = replay(Format::Csv, log)
+            .feed(|sample| temps.extend(sample.readings.iter().copied()))
+            .unwrap();
+        assert_eq!(2, count);
+        assert_eq!(vec![Reading::Temp(51.0), Reading::Temp(52.0)], temps);
+
+        assert_eq!(Some(Format::Jsonl), Format::from_path("soc.JSONL"));
+        assert_eq!(None, Format::from_path("soc.txt"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Repeating calls that failed for a passing reason
+//!
+//! A hiccup of the VCHI connection or a `vcgencmd` stuck once shouldn't end a monitor that
+//! runs for weeks. A `RetryPolicy` repeats a call failing with a transient error, see
+//! `Error::is_transient`, after a backoff growing exponentially from attempt to attempt and
+//! randomized, so that processes failing together don't retry in lockstep. Errors that
+//! won't go away by themselves, like a missing binary or an unsupported command, are
+//! returned at once.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use vcgencmd::retry::RetryPolicy;
+//! use vcgencmd::Vcgencmd;
+//!
+//! let policy = RetryPolicy::new()
+//!     .max_attempts(5)
+//!     .initial_backoff(Duration::from_millis(200));
+//!
+//! let client = Vcgencmd::new().retry(policy.clone());
+//! println!("{} °C", client.measure_temp()?);
+//!
+//! // or around any call, e.g. of the free functions
+//! let throttled = policy.run(vcgencmd::get_throttled_status)?;
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::thread;
+use std::time::Duration;
+
+use crate::monitor::Jitter;
+use crate::Result;
+
+/// How often and how late to repeat a call failing with a transient error, see the module
+/// documentation
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+}
+
+/// Three attempts, 100 ms apart and then 200 ms, with jitter
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// How often to make the call at most, including the first time, at least once
+    pub fn max_attempts(mut self, attempts: u32) -> RetryPolicy {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// The backoff before the second attempt
+    pub fn initial_backoff(mut self, backoff: Duration) -> RetryPolicy {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// The backoff the growing ones are capped at
+    pub fn max_backoff(mut self, backoff: Duration) -> RetryPolicy {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// The factor each backoff is larger than the one before, at least 1
+    pub fn multiplier(mut self, multiplier: f64) -> RetryPolicy {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Whether to wait a random time between half the backoff and all of it instead
+    pub fn jitter(mut self, jitter: bool) -> RetryPolicy {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The backoff after the failed attempt number `attempt`, counting from 1, without
+    /// jitter
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+        let secs = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+
+        Duration::from_secs_f64(secs.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// Make `call`, and again as long as it fails with a transient error and attempts are
+    /// left, returning the first success or the last error
+    pub fn run<T, F: FnMut() -> Result<T>>(&self, mut call: F) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            match call() {
+                Err(error) if error.is_transient() && attempt < self.max_attempts => {
+                    thread::sleep(self.delay(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// The time to wait after the failed attempt number `attempt`
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.backoff(attempt);
+        if !self.jitter {
+            return backoff;
+        }
+
+        let half = backoff / 2;
+        half + Jitter::new(backoff - half).offset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, ErrorKind, Vcgencmd};
+    use std::cell::Cell;
+
+    fn vchi() -> Error {
+        Error::Vchi {
+            command: "measure_temp".to_owned(),
+            message: "VCHI initialization failed".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(500));
+        assert_eq!(Duration::from_millis(100), policy.backoff(1));
+        assert_eq!(Duration::from_millis(200), policy.backoff(2));
+        assert_eq!(Duration::from_millis(400), policy.backoff(3));
+        assert_eq!(Duration::from_millis(500), policy.backoff(4));
+        assert_eq!(Duration::from_millis(500), policy.backoff(u32::MAX));
+
+        for _ in 0..100 {
+            let delay = policy.delay(2);
+            assert!(delay >= Duration::from_millis(100) && delay < Duration::from_millis(200));
+        }
+        assert_eq!(
+            Duration::from_millis(200),
+            policy.clone().jitter(false).delay(2)
+        );
+        assert_eq!(
+            Duration::from_millis(100),
+            policy.multiplier(-3.0).backoff(3)
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let policy = RetryPolicy::new().initial_backoff(Duration::from_millis(1));
+
+        let attempts = Cell::new(0);
+        let flaky = || {
+            attempts.set(attempts.get() + 1);
+            match attempts.get() {
+                1 | 2 => Err(vchi()),
+                _ => Ok(48.3),
+            }
+        };
+        assert_eq!(48.3, policy.run(flaky).unwrap());
+        assert_eq!(3, attempts.get());
+
+        attempts.set(0);
+        let error = policy
+            .clone()
+            .max_attempts(2)
+            .run(|| -> Result<()> {
+                attempts.set(attempts.get() + 1);
+                Err(vchi())
+            })
+            .unwrap_err();
+        assert!(matches!(error, Error::Vchi { .. }));
+        assert_eq!(2, attempts.get());
+
+        attempts.set(0);
+        let error = policy
+            .run(|| -> Result<()> {
+                attempts.set(attempts.get() + 1);
+                Err(Error::firmware(
+                    "measure_volts sdram_c".to_owned(),
+                    2,
+                    "Invalid arguments".to_owned(),
+                ))
+            })
+            .unwrap_err();
+        assert_eq!(ErrorKind::Unsupported, error.kind());
+        assert_eq!(1, attempts.get());
+    }
+
+    #[test]
+    fn test_client_retry() {
+        let attempts = Cell::new(0);
+        let executor = |_, _| {
+            attempts.set(attempts.get() + 1);
+            match attempts.get() {
+                1 => Err(vchi()),
+                _ => Ok("temp=48.3'C\n".to_owned()),
+            }
+        };
+
+        let client = Vcgencmd::with_executor(executor)
+            .retry(RetryPolicy::new().initial_backoff(Duration::from_millis(1)));
+        assert_eq!(48.3, client.measure_temp().unwrap());
+        assert_eq!(2, attempts.get());
+    }
+}
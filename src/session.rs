@@ -0,0 +1,169 @@
+//! Firmware version, board model and supported commands, probed once and cached
+//!
+//! Probing takes a few invocations of vcgencmd, so it happens on first use only. After that
+//! every call of this crate consults the cached command list of the global session, and
+//! fails right away with `Error::Unsupported` for commands the firmware doesn't have,
+//! instead of spawning vcgencmd just to be told so. Changing the `Invocation` invalidates
+//! the cache, as does `Session::invalidate`, e.g. after a firmware update.
+//!
+//! ```no_run
+//! use vcgencmd::session;
+//!
+//! let capabilities = session::global().capabilities()?;
+//! println!("{:?} on {}", capabilities.model, capabilities.version.date);
+//! if capabilities.supports("pmic_read_adc") {
+//!     println!("{:?}", vcgencmd::pmic_read_adc()?);
+//! }
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+
+use std::fs;
+use std::sync::{Arc, RwLock};
+
+use crate::{call, invocation, parsers, resolve_command, Cmd, Result};
+
+const MODEL: &str = "/proc/device-tree/model";
+
+/// The firmware build as reported by `vcgencmd version`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareVersion {
+    /// Build date as printed, e.g. `Mar 17 2023 10:50:39`
+    pub date: String,
+    /// Commit hash of the build, empty if it isn't printed
+    pub hash: String,
+}
+
+/// What the firmware and the board can do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub version: FirmwareVersion,
+    /// e.g. `Raspberry Pi 4 Model B Rev 1.4`, `None` for remote hosts
+    pub model: Option<String>,
+    /// The commands listed by `vcgencmd commands`
+    pub commands: Vec<String>,
+}
+
+impl Capabilities {
+    /// Ask the firmware, and read the model from the device tree
+    pub fn probe() -> Result<Capabilities> {
+        let version = call(Cmd::Version, None, parsers::version)?;
+        let commands = call(Cmd::Commands, None, parsers::commands)?;
+        let model = match invocation().host {
+            Some(_) => None,
+            None => fs::read_to_string(MODEL)
+                .ok()
+                .map(|model| model.trim_end_matches('\0').trim().to_owned()),
+        };
+
+        Ok(Capabilities {
+            version,
+            model,
+            commands,
+        })
+    }
+
+    /// Whether `command`, e.g. `pmic_read_adc`, is known to the firmware
+    pub fn supports(&self, command: &str) -> bool {
+        self.commands.iter().any(|known| known == command)
+    }
+
+    pub(crate) fn supports_cmd(&self, command: Cmd) -> bool {
+        // an empty list means it couldn't be read, rather than that nothing is supported
+        self.commands.is_empty() || self.supports(&resolve_command(command))
+    }
+}
+
+/// Lazily probed `Capabilities`, see the module documentation
+#[derive(Debug, Default)]
+pub struct Session {
+    capabilities: RwLock<Option<Arc<Capabilities>>>,
+}
+
+impl Session {
+    /// A session that probes on first use
+    pub const fn new() -> Session {
+        Session {
+            capabilities: RwLock::new(None),
+        }
+    }
+
+    /// A session with known capabilities, e.g. for replaying captures from another Pi
+    pub fn with_capabilities(capabilities: Capabilities) -> Session {
+        Session {
+            capabilities: RwLock::new(Some(Arc::new(capabilities))),
+        }
+    }
+
+    /// The capabilities, probing them if they aren't cached yet
+    pub fn capabilities(&self) -> Result<Arc<Capabilities>> {
+        if let Some(capabilities) = self.cached() {
+            return Ok(capabilities);
+        }
+
+        // probed without holding the lock, a concurrent probe only costs a few calls
+        let capabilities = Arc::new(Capabilities::probe()?);
+        *self.capabilities.write().unwrap_or_else(|e| e.into_inner()) =
+            Some(Arc::clone(&capabilities));
+        Ok(capabilities)
+    }
+
+    /// The capabilities if they were probed already, never invokes vcgencmd
+    pub fn cached(&self) -> Option<Arc<Capabilities>> {
+        let capabilities = self.capabilities.read().unwrap_or_else(|e| e.into_inner());
+        capabilities.clone()
+    }
+
+    /// Drop the cached capabilities, they are probed again on next use
+    pub fn invalidate(&self) {
+        *self.capabilities.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Whether `command` is known to the firmware, probing if needed
+    pub fn supports(&self, command: &str) -> Result<bool> {
+        Ok(self.capabilities()?.supports(command))
+    }
+}
+
+static GLOBAL: Session = Session::new();
+
+/// The session of the current `Invocation`, consulted by every call of this crate
+pub fn global() -> &'static Session {
+    &GLOBAL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(commands: &[&str]) -> Capabilities {
+        Capabilities {
+            version: FirmwareVersion {
+                date: "Mar 17 2023 10:50:39".to_owned(),
+                hash: "82f3750a65fadae9a38077e3c2e217ad158c8d54".to_owned(),
+            },
+            model: None,
+            commands: commands.iter().map(|&command| command.to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_supports() {
+        let capabilities = capabilities(&["measure_temp", "get_throttled"]);
+        assert!(capabilities.supports("measure_temp"));
+        assert!(!capabilities.supports("pmic_read_adc"));
+        assert!(capabilities.supports_cmd(Cmd::GetThrottled));
+        assert!(!capabilities.supports_cmd(Cmd::PmicReadAdc));
+
+        assert!(self::capabilities(&[]).supports_cmd(Cmd::PmicReadAdc));
+    }
+
+    #[test]
+    fn test_cache_and_invalidate() {
+        let session = Session::with_capabilities(capabilities(&["measure_temp"]));
+        assert!(session.supports("measure_temp").unwrap());
+        assert!(session.cached().is_some());
+
+        session.invalidate();
+        assert!(session.cached().is_none());
+    }
+}
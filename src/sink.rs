@@ -0,0 +1,395 @@
+//! Delivery of samples to sinks that can't always keep up with the monitor
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::monitor::Sample;
+
+/// What to do with a new sample when a sink's queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for the sink to catch up, which stalls sampling for all sinks
+    Block,
+    /// Discard the oldest queued sample to make room for the new one
+    DropOldest,
+    /// Discard the new sample
+    DropNewest,
+}
+
+/// A destination for samples, implemented by all built-in exporters.
+///
+/// Every sample is the batch of readings taken at the same time. Unlike a plain sink
+/// closure, a `MetricSink` can fail, which the monitor counts in the `SinkStats` of the
+/// sink instead of each exporter ignoring its errors in its own way.
+///
+/// ```
+/// use std::io;
+/// use vcgencmd::monitor::Sample;
+/// use vcgencmd::sink::MetricSink;
+///
+/// struct Counter(usize);
+///
+/// impl MetricSink for Counter {
+///     fn write(&mut self, sample: &Sample) -> io::Result<()> {
+///         self.0 += sample.readings.len();
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait MetricSink: Send {
+    /// Hand over the readings of `sample`
+    fn write(&mut self, sample: &Sample) -> io::Result<()>;
+
+    /// Push out whatever was buffered, for sinks that don't write through
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: MetricSink + ?Sized> MetricSink for Box<S> {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        (**self).write(sample)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// A plain sink closure, which can't fail
+pub(crate) struct FnSink<F>(pub(crate) F);
+
+impl<F: FnMut(&Sample) + Send> MetricSink for FnSink<F> {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        (self.0)(sample);
+        Ok(())
+    }
+}
+
+/// `sink` as a plain sink closure, for the `into_sink` of the exporters. Its errors are
+/// dropped, where `Monitor::metric_sink` would count them.
+pub(crate) fn into_fn<S: MetricSink + 'static>(
+    mut sink: S,
+) -> impl FnMut(&Sample) + Send + 'static {
+    move |sample| {
+        let _ = sink.write(sample);
+    }
+}
+
+/// Counters describing how a sink keeps up
+#[derive(Debug, Default)]
+pub struct SinkStats {
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl SinkStats {
+    /// Number of samples the sink has processed
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Number of samples discarded because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of writes and flushes of a `MetricSink` that failed
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Count a write or flush of the sink
+    pub(crate) fn record(&self, result: io::Result<()>, delivery: bool) {
+        if result.is_err() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        if delivery {
+            self.delivered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct Queue {
+    samples: VecDeque<Arc<Sample>>,
+    closed: bool,
+    /// Whether the worker is delivering a sample it took off the queue
+    delivering: bool,
+}
+
+type BoxedSink = Box<dyn MetricSink>;
+
+struct Shared {
+    queue: Mutex<Queue>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    /// Notified whenever the worker finished delivering a sample
+    delivered: Condvar,
+    stats: Arc<SinkStats>,
+    sink: Mutex<BoxedSink>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        // a panicking sink poisons nothing the queue itself relies on
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn sink(&self) -> MutexGuard<'_, BoxedSink> {
+        self.sink.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn deliver(&self, sample: &Sample) {
+        let result = self.sink().write(sample);
+        self.stats.record(result, true);
+    }
+
+    fn flush(&self) {
+        let result = self.sink().flush();
+        self.stats.record(result, false);
+    }
+}
+
+/// Runs a sink on its own thread, fed through a bounded queue.
+///
+/// Dropping it closes the queue and waits until all samples queued so far were delivered.
+/// If no thread can be spawned, samples are delivered right away on the pushing thread.
+pub(crate) struct QueuedSink {
+    shared: Arc<Shared>,
+    capacity: usize,
+    policy: Backpressure,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl QueuedSink {
+    pub(crate) fn spawn<F>(sink: F, capacity: usize, policy: Backpressure) -> QueuedSink
+    where
+        F: FnMut(&Sample) + Send + 'static,
+    {
+        QueuedSink::spawn_metric(FnSink(sink), capacity, policy)
+    }
+
+    /// Like `spawn`, flushing `sink` whenever the queue ran empty
+    pub(crate) fn spawn_metric<S>(sink: S, capacity: usize, policy: Backpressure) -> QueuedSink
+    where
+        S: MetricSink + 'static,
+    {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                samples: VecDeque::with_capacity(capacity),
+                closed: false,
+                delivering: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            delivered: Condvar::new(),
+            stats: Arc::new(SinkStats::default()),
+            sink: Mutex::new(Box::new(sink)),
+        });
+
+        let worker = Arc::clone(&shared);
+        let thread = thread::Builder::new()
+            .name("vcgencmd-sink".to_owned())
+            .spawn(move || loop {
+                let sample = {
+                    let mut queue = worker.lock();
+                    loop {
+                        if let Some(sample) = queue.samples.pop_front() {
+                            queue.delivering = true;
+                            break sample;
+                        }
+                        if queue.closed {
+                            return;
+                        }
+                        queue = worker
+                            .not_empty
+                            .wait(queue)
+                            .unwrap_or_else(|e| e.into_inner());
+                    }
+                };
+                worker.not_full.notify_one();
+
+                worker.deliver(&sample);
+                if worker.lock().samples.is_empty() {
+                    worker.flush();
+                }
+                worker.lock().delivering = false;
+                worker.delivered.notify_all();
+            })
+            .ok();
+
+        QueuedSink {
+            shared,
+            capacity: capacity.max(1),
+            policy,
+            thread,
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Arc<SinkStats> {
+        Arc::clone(&self.shared.stats)
+    }
+
+    /// Queue a sample according to the backpressure policy
+    pub(crate) fn push(&self, sample: Arc<Sample>) {
+        if self.thread.is_none() {
+            self.shared.deliver(&sample);
+            self.shared.flush();
+            return;
+        }
+
+        let mut queue = self.shared.lock();
+
+        if queue.samples.len() >= self.capacity {
+            match self.policy {
+                Backpressure::Block => {
+                    while queue.samples.len() >= self.capacity {
+                        queue = self
+                            .shared
+                            .not_full
+                            .wait(queue)
+                            .unwrap_or_else(|e| e.into_inner());
+                    }
+                }
+                Backpressure::DropOldest => {
+                    queue.samples.pop_front();
+                    self.shared.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Backpressure::DropNewest => {
+                    self.shared.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+
+        queue.samples.push_back(sample);
+        self.shared.not_empty.notify_one();
+    }
+
+    /// Wait until every sample queued so far was delivered, for at most `timeout`.
+    ///
+    /// Returns whether the queue drained in time.
+    pub(crate) fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.shared.lock();
+
+        while !queue.samples.is_empty() || queue.delivering {
+            let now = Instant::now();
+            if now >= deadline || self.thread.is_none() {
+                return false;
+            }
+            queue = self
+                .shared
+                .delivered
+                .wait_timeout(queue, deadline - now)
+                .map_or_else(|e| e.into_inner().0, |(queue, _)| queue);
+        }
+
+        true
+    }
+}
+
+impl Drop for QueuedSink {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.not_empty.notify_one();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::SystemTime;
+
+    fn sample() -> Arc<Sample> {
+        Arc::new(Sample {
+            timestamp: SystemTime::now(),
+            readings: Vec::new(),
+            errors: Vec::new(),
+        })
+    }
+
+    /// A sink that blocks until it is released, with one sample in flight
+    fn stalled_sink(policy: Backpressure) -> (QueuedSink, mpsc::Sender<()>) {
+        let (release, released) = mpsc::channel();
+        let (started, has_started) = mpsc::channel();
+        let queued = QueuedSink::spawn(
+            move |_| {
+                let _ = started.send(());
+                let _ = released.recv();
+            },
+            2,
+            policy,
+        );
+
+        queued.push(sample());
+        has_started.recv().unwrap();
+        (queued, release)
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        let (queued, release) = stalled_sink(Backpressure::DropNewest);
+        for _ in 0..5 {
+            queued.push(sample());
+        }
+        let stats = queued.stats();
+        assert_eq!(3, stats.dropped());
+
+        drop(release);
+        drop(queued);
+        assert_eq!(3, stats.delivered());
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let (queued, release) = stalled_sink(Backpressure::DropOldest);
+        let newest = sample();
+        for _ in 0..4 {
+            queued.push(sample());
+        }
+        queued.push(Arc::clone(&newest));
+        assert_eq!(3, queued.stats().dropped());
+        assert!(Arc::ptr_eq(
+            &newest,
+            queued.shared.lock().samples.back().unwrap()
+        ));
+        drop(release);
+    }
+
+    #[test]
+    fn test_block_delivers_everything() {
+        let (tx, rx) = mpsc::channel();
+        let queued = QueuedSink::spawn(move |_| tx.send(()).unwrap(), 1, Backpressure::Block);
+        for _ in 0..20 {
+            queued.push(sample());
+        }
+        let stats = queued.stats();
+        drop(queued);
+
+        assert_eq!(20, rx.iter().count());
+        assert_eq!(0, stats.dropped());
+        assert_eq!(20, stats.delivered());
+    }
+
+    #[test]
+    fn test_flush() {
+        let (queued, release) = stalled_sink(Backpressure::Block);
+        queued.push(sample());
+        assert!(!queued.flush(Duration::from_millis(20)));
+
+        drop(release);
+        assert!(queued.flush(Duration::from_secs(10)));
+        assert_eq!(2, queued.stats().delivered());
+    }
+}
@@ -0,0 +1,725 @@
+//! The state of the system at one point in time
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::health::{HealthPolicy, HealthSummary};
+use crate::monitor::{Metric, Reading, Sample};
+use crate::sink::{self, MetricSink};
+use crate::thermal::{TempHeadroom, TempLimits};
+use crate::{interpret_bit_pattern, json, timefmt, ClockSrc, Error, Result, VoltSrc};
+
+/// The metrics a `Snapshot` is made of
+pub const SNAPSHOT_METRICS: [Metric; 5] = [
+    Metric::Temp,
+    Metric::Throttled,
+    Metric::Clock(ClockSrc::Arm),
+    Metric::Clock(ClockSrc::Core),
+    Metric::Volts(VoltSrc::Core),
+];
+
+/// How long after the start of a capture a metric may be read until it's reported as
+/// lagging, see `SnapshotSpec::max_lag`
+pub const DEFAULT_MAX_LAG: Duration = Duration::from_millis(250);
+
+/// A chosen set of metrics read together, e.g. from `temp,clock.arm,throttled`.
+///
+/// Where `Snapshot` has a fixed set of fields, a spec captures exactly the metrics asked
+/// for, in the order they were given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotSpec {
+    metrics: Vec<Metric>,
+    max_lag: Duration,
+}
+
+impl Default for SnapshotSpec {
+    /// The metrics of a `Snapshot`
+    fn default() -> SnapshotSpec {
+        SNAPSHOT_METRICS
+            .iter()
+            .fold(SnapshotSpec::new(), |spec, &metric| spec.metric(metric))
+    }
+}
+
+impl SnapshotSpec {
+    /// A spec without any metrics
+    pub fn new() -> SnapshotSpec {
+        SnapshotSpec {
+            metrics: Vec::new(),
+            max_lag: DEFAULT_MAX_LAG,
+        }
+    }
+
+    /// Report metrics read later than `max_lag` after the start of a capture as lagging,
+    /// instead of after `DEFAULT_MAX_LAG`
+    pub fn max_lag(mut self, max_lag: Duration) -> SnapshotSpec {
+        self.max_lag = max_lag;
+        self
+    }
+
+    /// Add `metric`, metrics already part of the spec are ignored
+    pub fn metric(mut self, metric: Metric) -> SnapshotSpec {
+        if !self.metrics.contains(&metric) {
+            self.metrics.push(metric);
+        }
+        self
+    }
+
+    /// Parse a comma separated list of metric names, see `Metric::name`
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use vcgencmd::monitor::Metric;
+    /// use vcgencmd::snapshot::SnapshotSpec;
+    /// use vcgencmd::ClockSrc;
+    ///
+    /// let spec = SnapshotSpec::parse("temp, clock.arm").unwrap();
+    /// assert_eq!(&[Metric::Temp, Metric::Clock(ClockSrc::Arm)], spec.metrics());
+    /// ```
+    pub fn parse(fields: &str) -> Result<SnapshotSpec, String> {
+        let mut spec = SnapshotSpec::new();
+        for field in fields.split(',').map(str::trim) {
+            match Metric::from_name(field) {
+                Some(metric) => spec = spec.metric(metric),
+                None if field.is_empty() => return Err("empty field name".to_owned()),
+                None => return Err(format!("unknown field '{}'", field)),
+            }
+        }
+
+        Ok(spec)
+    }
+
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    /// Read every metric of the spec by invoking vcgencmd, all at once, see `capture_timed`
+    pub fn capture(&self) -> Sample {
+        self.capture_timed().sample
+    }
+
+    /// Read every metric of the spec by invoking vcgencmd, each on a thread of its own so
+    /// the readings are as close together as possible.
+    ///
+    /// The sample is stamped with the time the capture started, and how long after it every
+    /// metric was read is kept along with it.
+    pub fn capture_timed(&self) -> TimedCapture {
+        self.capture_parallel_with(Metric::read)
+    }
+
+    /// Read every metric with `read` on a thread of its own, see `capture_timed`.
+    ///
+    /// Metrics whose thread the system refuses to spawn are read on the current thread, once
+    /// the others are under way.
+    pub fn capture_parallel_with<F>(&self, read: F) -> TimedCapture
+    where
+        F: Fn(Metric) -> Result<Reading> + Sync,
+    {
+        self.capture_parallel_on(read, thread::Builder::new)
+    }
+
+    /// `capture_parallel_with`, spawning the threads with the builders of `thread`
+    fn capture_parallel_on<F>(&self, read: F, thread: fn() -> thread::Builder) -> TimedCapture
+    where
+        F: Fn(Metric) -> Result<Reading> + Sync,
+    {
+        let timestamp = SystemTime::now();
+        let started = Instant::now();
+        let read = &read;
+
+        let results: Vec<_> = thread::scope(|scope| {
+            let threads: Vec<_> = self
+                .metrics
+                .iter()
+                .map(|&metric| {
+                    let thread =
+                        thread().spawn_scoped(scope, move || (read(metric), started.elapsed()));
+                    (metric, thread)
+                })
+                .collect();
+
+            threads
+                .into_iter()
+                .map(|(metric, thread)| {
+                    let (result, lag) = match thread {
+                        // a panicking `read` panics the capture, as if it was read right here
+                        Ok(thread) => thread
+                            .join()
+                            .unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+                        Err(_) => (read(metric), started.elapsed()),
+                    };
+                    (metric, result, lag)
+                })
+                .collect()
+        });
+
+        let mut capture = TimedCapture {
+            sample: Sample {
+                timestamp,
+                readings: Vec::new(),
+                errors: Vec::new(),
+            },
+            lags: Vec::new(),
+            max_lag: self.max_lag,
+        };
+        for (metric, result, lag) in results {
+            match result {
+                Ok(reading) => capture.sample.readings.push(reading),
+                Err(error) => capture.sample.errors.push((metric, error)),
+            }
+            capture.lags.push((metric, lag));
+        }
+
+        capture
+    }
+
+    /// Read every metric with `read`, one after the other, see `Monitor::sampler`
+    pub fn capture_with<F>(&self, mut read: F) -> Sample
+    where
+        F: FnMut(Metric) -> Result<Reading>,
+    {
+        let mut sample = Sample {
+            timestamp: SystemTime::now(),
+            readings: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for &metric in &self.metrics {
+            match read(metric) {
+                Ok(reading) => sample.readings.push(reading),
+                Err(error) => sample.errors.push((metric, error)),
+            }
+        }
+
+        sample
+    }
+}
+
+/// A sample read by `SnapshotSpec::capture_timed`, with how long after its timestamp every
+/// metric was read
+#[derive(Debug)]
+pub struct TimedCapture {
+    pub sample: Sample,
+    /// The metrics in the order of the spec, each with the time from the start of the
+    /// capture until it was read
+    pub lags: Vec<(Metric, Duration)>,
+    /// The lag beyond which a metric is lagging
+    pub max_lag: Duration,
+}
+
+impl TimedCapture {
+    /// The metrics read later than `max_lag` after the start of the capture, whose readings
+    /// are too far apart from the others to be correlated with them
+    pub fn lagging(&self) -> Vec<Metric> {
+        self.lags
+            .iter()
+            .filter(|&&(_, lag)| lag > self.max_lag)
+            .map(|&(metric, _)| metric)
+            .collect()
+    }
+
+    /// The time between the start of the capture and the last metric read
+    pub fn spread(&self) -> Duration {
+        self.lags
+            .iter()
+            .map(|&(_, lag)| lag)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// The commonly needed readings taken together.
+///
+/// Metrics that couldn't be read are `None`, the reason is kept in `errors`.
+#[derive(Debug)]
+pub struct Snapshot {
+    /// Wall-clock time the metrics were read at, in JSON as an RFC 3339 UTC timestamp.
+    ///
+    /// With the `chrono` or `time` feature it is also available as a calendar type, see
+    /// `date_time` and `offset_date_time`.
+    pub timestamp: SystemTime,
+    /// Temperature in °C
+    pub temp: Option<f64>,
+    /// The throttling limits `temp` is compared against
+    pub temp_limits: TempLimits,
+    /// Bit pattern as returned by `get_throttled`
+    pub throttled: Option<isize>,
+    /// ARM clock in Hz
+    pub arm_clock: Option<isize>,
+    /// Core clock in Hz
+    pub core_clock: Option<isize>,
+    /// Core voltage in V
+    pub core_volts: Option<f64>,
+    pub errors: Vec<(Metric, Error)>,
+    /// Metrics read too long after `timestamp` to be correlated with the others, see
+    /// `TimedCapture::lagging`
+    pub lagging: Vec<Metric>,
+}
+
+impl Snapshot {
+    /// Read all snapshot metrics by invoking vcgencmd, at once and stamped with the start of
+    /// the capture, see `SnapshotSpec::capture_timed`.
+    ///
+    /// The temperature limits fall back to the firmware defaults if they can't be read.
+    pub fn capture() -> Snapshot {
+        let capture = SnapshotSpec::default().capture_timed();
+        let lagging = capture.lagging();
+        let sample = capture.sample;
+
+        let limits = TempLimits::cached().unwrap_or_default();
+        Snapshot {
+            errors: sample.errors,
+            lagging,
+            ..Snapshot::from_readings(sample.timestamp, &sample.readings, limits)
+        }
+    }
+
+    /// Build a snapshot from whatever snapshot metrics `sample` contains
+    pub fn from_sample(sample: &Sample, temp_limits: TempLimits) -> Snapshot {
+        Snapshot::from_readings(sample.timestamp, &sample.readings, temp_limits)
+    }
+
+    pub(crate) fn from_readings(
+        timestamp: SystemTime,
+        readings: &[Reading],
+        limits: TempLimits,
+    ) -> Snapshot {
+        let mut snapshot = Snapshot {
+            timestamp,
+            temp: None,
+            temp_limits: limits,
+            throttled: None,
+            arm_clock: None,
+            core_clock: None,
+            core_volts: None,
+            errors: Vec::new(),
+            lagging: Vec::new(),
+        };
+
+        for reading in readings {
+            match *reading {
+                Reading::Temp(temp) => snapshot.temp = Some(temp),
+                Reading::TempHeadroom(headroom) => snapshot.temp = Some(headroom.temp),
+                Reading::Throttled(bit_pattern) => snapshot.throttled = Some(bit_pattern),
+                Reading::Clock(ClockSrc::Arm, frequency) => snapshot.arm_clock = Some(frequency),
+                Reading::Clock(ClockSrc::Core, frequency) => snapshot.core_clock = Some(frequency),
+                Reading::Volts(VoltSrc::Core, volts) => snapshot.core_volts = Some(volts),
+                _ => {}
+            }
+        }
+
+        snapshot
+    }
+
+    /// The temperature compared with `temp_limits`
+    pub fn temp_headroom(&self) -> Option<TempHeadroom> {
+        self.temp.map(|temp| self.temp_limits.headroom(temp))
+    }
+
+    /// The snapshot including its health as a JSON object
+    pub fn to_json(&self) -> String {
+        let health = self.health();
+        let integer = |value: Option<isize>| json::optional(value, |v| json::number(v as f64));
+        let errors = self.errors.iter().map(|(metric, error)| {
+            json::object(&[
+                ("metric", json::string(&format!("{:?}", metric))),
+                ("error", json::string(&error.to_string())),
+            ])
+        });
+
+        json::object(&[
+            ("timestamp", json::string(&timefmt::rfc3339(self.timestamp))),
+            ("temp", json::optional(self.temp, json::number)),
+            (
+                "temp_limits",
+                json::object(&[
+                    ("soft", json::number(self.temp_limits.soft)),
+                    ("hard", json::number(self.temp_limits.hard)),
+                ]),
+            ),
+            ("throttled", integer(self.throttled)),
+            (
+                "throttled_status",
+                json::optional(self.throttled, |b| interpret_bit_pattern(b).to_json()),
+            ),
+            ("arm_clock", integer(self.arm_clock)),
+            ("core_clock", integer(self.core_clock)),
+            ("core_volts", json::optional(self.core_volts, json::number)),
+            (
+                "health",
+                json::object(&[
+                    ("state", json::string(health.state())),
+                    (
+                        "reasons",
+                        json::array(
+                            health
+                                .reasons()
+                                .iter()
+                                .map(|r| json::string(&r.to_string())),
+                        ),
+                    ),
+                ]),
+            ),
+            ("errors", json::array(errors)),
+            (
+                "lagging",
+                json::array(
+                    self.lagging
+                        .iter()
+                        .map(|metric| json::string(&metric.name())),
+                ),
+            ),
+        ])
+    }
+
+    /// `timestamp` as a `chrono` date and time in UTC
+    #[cfg(feature = "chrono")]
+    pub fn date_time(&self) -> chrono::DateTime<chrono::Utc> {
+        self.timestamp.into()
+    }
+
+    /// `timestamp` as a `time` date and time in UTC
+    #[cfg(feature = "time")]
+    pub fn offset_date_time(&self) -> time::OffsetDateTime {
+        self.timestamp.into()
+    }
+
+    /// One traffic-light value for this snapshot, using the default policy
+    pub fn health(&self) -> HealthSummary {
+        self.health_with(&HealthPolicy::default())
+    }
+
+    pub fn health_with(&self, policy: &HealthPolicy) -> HealthSummary {
+        HealthSummary::assess(self, policy)
+    }
+}
+
+/// Serialized like `Snapshot::to_json`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Snapshot {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        #[derive(serde::Serialize)]
+        struct Health {
+            state: &'static str,
+            reasons: Vec<String>,
+        }
+
+        #[derive(serde::Serialize)]
+        struct MetricError {
+            metric: String,
+            error: String,
+        }
+
+        let health = self.health();
+        let health = Health {
+            state: health.state(),
+            reasons: health.reasons().iter().map(ToString::to_string).collect(),
+        };
+        let errors: Vec<_> = self
+            .errors
+            .iter()
+            .map(|(metric, error)| MetricError {
+                metric: format!("{:?}", metric),
+                error: error.to_string(),
+            })
+            .collect();
+        let lagging: Vec<_> = self.lagging.iter().map(Metric::name).collect();
+
+        let mut snapshot = serializer.serialize_struct("Snapshot", 11)?;
+        snapshot.serialize_field("timestamp", &timefmt::rfc3339(self.timestamp))?;
+        snapshot.serialize_field("temp", &self.temp)?;
+        snapshot.serialize_field("temp_limits", &self.temp_limits)?;
+        snapshot.serialize_field("throttled", &self.throttled)?;
+        snapshot.serialize_field(
+            "throttled_status",
+            &self.throttled.map(interpret_bit_pattern),
+        )?;
+        snapshot.serialize_field("arm_clock", &self.arm_clock)?;
+        snapshot.serialize_field("core_clock", &self.core_clock)?;
+        snapshot.serialize_field("core_volts", &self.core_volts)?;
+        snapshot.serialize_field("health", &health)?;
+        snapshot.serialize_field("errors", &errors)?;
+        snapshot.serialize_field("lagging", &lagging)?;
+        snapshot.end()
+    }
+}
+
+/// The limits of the `Metric::TempHeadroom` reading among `readings`, if there is one
+pub(crate) fn temp_limits_of(readings: &[Reading]) -> Option<TempLimits> {
+    readings.iter().find_map(|reading| match reading {
+        Reading::TempHeadroom(headroom) => Some(TempLimits {
+            soft: headroom.soft_limit,
+            hard: headroom.hard_limit,
+        }),
+        _ => None,
+    })
+}
+
+/// Keeps the latest snapshot as JSON in a file at a fixed path, for processes that can't
+/// talk to the monitor, like shell scripts, conky or status bars.
+///
+/// The file is written to a temporary name in the same directory first and then renamed, so
+/// readers never see a half-written file. Every sample updates the readings it contains,
+/// the others keep their last value, except that a metric failing to read is `null` until
+/// it is read again rather than showing a stale value.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use vcgencmd::monitor::{Metric, Monitor};
+/// use vcgencmd::snapshot::SnapshotFile;
+///
+/// let monitor = Monitor::new(Duration::from_secs(5))
+///     .metric(Metric::Temp)
+///     .metric(Metric::Throttled)
+///     .metric_sink(SnapshotFile::new("/run/vcgencmd/snapshot.json"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SnapshotFile {
+    path: PathBuf,
+    temp_limits: TempLimits,
+    latest: Vec<Reading>,
+}
+
+impl SnapshotFile {
+    /// Write to `path`, its directory has to exist
+    pub fn new<P: AsRef<Path>>(path: P) -> SnapshotFile {
+        SnapshotFile {
+            path: path.as_ref().to_owned(),
+            temp_limits: TempLimits::default(),
+            latest: Vec::new(),
+        }
+    }
+
+    /// Compare the temperature with `temp_limits` instead of the firmware defaults, unless
+    /// the monitor samples `Metric::TempHeadroom`, which brings its own
+    pub fn temp_limits(mut self, temp_limits: TempLimits) -> SnapshotFile {
+        self.temp_limits = temp_limits;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The snapshot of the latest readings, stamped with the time of the last sample
+    pub fn snapshot(&self, timestamp: SystemTime) -> Snapshot {
+        let limits = temp_limits_of(&self.latest).unwrap_or(self.temp_limits);
+        Snapshot::from_readings(timestamp, &self.latest, limits)
+    }
+
+    /// Take over the readings of `sample` and atomically replace the file
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        self.latest.retain(|latest| {
+            sample
+                .errors
+                .iter()
+                .all(|(metric, _)| latest.metric() != *metric)
+        });
+        for reading in &sample.readings {
+            match self
+                .latest
+                .iter_mut()
+                .find(|r| r.metric() == reading.metric())
+            {
+                Some(latest) => *latest = *reading,
+                None => self.latest.push(*reading),
+            }
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+
+        fs::write(&tmp, self.snapshot(sample.timestamp).to_json() + "\n")?;
+        fs::rename(&tmp, &self.path).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+    }
+
+    /// Turn this into a plain monitor sink. The file is replaced again with the next
+    /// sample after a failing write, `Monitor::metric_sink` also counts the failure.
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for SnapshotFile {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        SnapshotFile::write(self, sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemSrc;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_spec() {
+        let spec = SnapshotSpec::parse("volts.core,temp,volts.core,mem.gpu").unwrap();
+        assert_eq!(
+            &[
+                Metric::Volts(VoltSrc::Core),
+                Metric::Temp,
+                Metric::Mem(MemSrc::Gpu)
+            ],
+            spec.metrics()
+        );
+        assert_eq!(&SNAPSHOT_METRICS, SnapshotSpec::default().metrics());
+
+        assert_eq!(
+            Err("unknown field 'clock.gpu'".to_owned()),
+            SnapshotSpec::parse("temp,clock.gpu")
+        );
+        assert!(SnapshotSpec::parse("temp,").is_err());
+    }
+
+    #[test]
+    fn test_spec_capture() {
+        let spec = SnapshotSpec::parse("throttled,temp").unwrap();
+        let sample = spec.capture_with(|metric| match metric {
+            Metric::Temp => Ok(Reading::Temp(51.0)),
+            _ => Err(Error::ParseInt {
+                command: "get_throttled".to_owned(),
+                source: "x".parse::<isize>().unwrap_err(),
+            }),
+        });
+
+        assert_eq!(vec![Reading::Temp(51.0)], sample.readings);
+        assert_eq!(Metric::Throttled, sample.errors[0].0);
+    }
+
+    #[test]
+    fn test_capture_parallel() {
+        let spec = SnapshotSpec::parse("throttled,temp")
+            .unwrap()
+            .max_lag(Duration::from_millis(50));
+        let before = SystemTime::now();
+        let capture = spec.capture_parallel_with(|metric| match metric {
+            Metric::Throttled => {
+                thread::sleep(Duration::from_millis(100));
+                Ok(Reading::Throttled(0))
+            }
+            _ => Ok(Reading::Temp(51.0)),
+        });
+
+        assert!(capture.sample.timestamp >= before);
+        assert!(capture.sample.timestamp < before + Duration::from_millis(50));
+        assert_eq!(
+            vec![Reading::Throttled(0), Reading::Temp(51.0)],
+            capture.sample.readings
+        );
+        assert_eq!(vec![Metric::Throttled], capture.lagging());
+        assert!(capture.spread() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_capture_parallel_without_threads() {
+        let spec = SnapshotSpec::parse("throttled,temp").unwrap();
+        // no system has the address space for a stack this large
+        let refused = || thread::Builder::new().stack_size(usize::MAX / 2);
+        assert!(refused().spawn(|| ()).is_err());
+        let capture = spec.capture_parallel_on(
+            |metric| match metric {
+                Metric::Throttled => Ok(Reading::Throttled(0)),
+                _ => Ok(Reading::Temp(51.0)),
+            },
+            refused,
+        );
+
+        assert_eq!(
+            vec![Reading::Throttled(0), Reading::Temp(51.0)],
+            capture.sample.readings
+        );
+        assert_eq!(2, capture.lags.len());
+    }
+
+    #[test]
+    fn test_from_sample() {
+        let sample = Sample {
+            timestamp: UNIX_EPOCH,
+            readings: vec![
+                Reading::Temp(48.0),
+                Reading::Throttled(0),
+                Reading::Clock(ClockSrc::Arm, 1_500_000_000),
+                Reading::Clock(ClockSrc::H264, 0),
+            ],
+            errors: Vec::new(),
+        };
+        let snapshot = Snapshot::from_sample(&sample, TempLimits::default());
+
+        assert_eq!(Some(48.0), snapshot.temp);
+        assert_eq!(Some(1_500_000_000), snapshot.arm_clock);
+        assert_eq!(None, snapshot.core_clock);
+        assert_eq!(
+            Some(12.0),
+            snapshot.temp_headroom().map(|h| h.to_soft_limit())
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let sample = Sample {
+            timestamp: UNIX_EPOCH,
+            readings: vec![Reading::Temp(48.5), Reading::Throttled(0)],
+            errors: Vec::new(),
+        };
+        let json = Snapshot::from_sample(&sample, TempLimits::default()).to_json();
+
+        assert!(json.starts_with(
+            r#"{"timestamp":"1970-01-01T00:00:00.000Z","temp":48.5,"temp_limits":{"soft":60,"hard":85},"throttled":0,"throttled_status":{"arm_frequency_cap_occurred":false,"#
+        ));
+        assert!(json.ends_with(
+            r#""arm_clock":null,"core_clock":null,"core_volts":null,"health":{"state":"healthy","reasons":[]},"errors":[],"lagging":[]}"#
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_file() {
+        let path = std::env::temp_dir().join(format!("vcgencmd-snapshot-{}", std::process::id()));
+        let mut file = SnapshotFile::new(&path);
+
+        let sample = Sample {
+            timestamp: UNIX_EPOCH,
+            readings: vec![Reading::Temp(48.5), Reading::Throttled(0)],
+            errors: Vec::new(),
+        };
+        file.write(&sample).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+        assert!(json.starts_with(r#"{"timestamp":"1970-01-01T00:00:00.000Z","temp":48.5,"#));
+        assert!(json.ends_with("}\n"));
+
+        // the temperature keeps its value, the failed throttled read clears it
+        let sample = Sample {
+            timestamp: UNIX_EPOCH + Duration::from_secs(1),
+            readings: vec![Reading::Clock(ClockSrc::Arm, 1_500_000_000)],
+            errors: vec![(
+                Metric::Throttled,
+                Error::ParseInt {
+                    command: "get_throttled".to_owned(),
+                    source: "x".parse::<isize>().unwrap_err(),
+                },
+            )],
+        };
+        file.write(&sample).unwrap();
+        let snapshot = file.snapshot(sample.timestamp);
+        assert_eq!(Some(48.5), snapshot.temp);
+        assert_eq!(None, snapshot.throttled);
+        assert_eq!(Some(1_500_000_000), snapshot.arm_clock);
+        assert_eq!(
+            snapshot.to_json() + "\n",
+            fs::read_to_string(&path).unwrap()
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
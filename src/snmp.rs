@@ -0,0 +1,683 @@
+//! A minimal SNMP agent serving the latest readings to network management systems
+//!
+//! `SnmpAgent` answers SNMPv1 and SNMPv2c `get`, `get-next` and `get-bulk` requests for a
+//! single read-only community, which is enough for `snmpget`, `snmpwalk` and the pollers
+//! of most NMS. There are no traps, no SNMPv3 and nothing is writable. The values are those
+//! of the last sample handed to the agent, objects without a reading in it don't exist.
+//!
+//! The objects live under a base OID, by default the NET-SNMP playpen
+//! `1.3.6.1.4.1.8072.9999.9999`, which is meant for local use and can be changed to an
+//! arc of your own with `SnmpAgent::base`:
+//!
+//! | OID under the base | Type      | Value                                                |
+//! |--------------------|-----------|------------------------------------------------------|
+//! | `.1.0`             | INTEGER   | Temperature in m°C                                   |
+//! | `.2.0`             | Gauge32   | Bit pattern as returned by `get_throttled`           |
+//! | `.3.0`             | Gauge32   | ARM clock in Hz                                      |
+//! | `.4.0`             | Gauge32   | Core clock in Hz                                     |
+//! | `.5.0`             | Gauge32   | Core voltage in mV                                   |
+//! | `.6.0`             | INTEGER   | Health: healthy(1), degraded(2), critical(3)         |
+//! | `.7.N`             | INTEGER   | 1 if condition N is active, 0 otherwise              |
+//! | `.8.N`             | INTEGER   | 1 if condition N occurred since boot, 0 otherwise    |
+//!
+//! The conditions are numbered from 1 in the order of `Condition::ALL`: under-voltage,
+//! ARM frequency capped, throttled and soft temperature limit. The health is only served
+//! when the sample has the throttled bit pattern.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use vcgencmd::monitor::{Metric, Monitor};
+//! use vcgencmd::snmp::SnmpAgent;
+//!
+//! // snmpwalk -v2c -c public localhost:1161 1.3.6.1.4.1.8072.9999.9999
+//! let agent = SnmpAgent::bind("0.0.0.0:1161", "public")?;
+//! let monitor = Monitor::new(Duration::from_secs(5))
+//!     .metric(Metric::Temp)
+//!     .metric(Metric::Throttled)
+//!     .metric_sink(agent);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use crate::events::Condition;
+use crate::health::HealthSummary;
+use crate::interpret_bit_pattern;
+use crate::monitor::{Metric, Reading, Sample};
+use crate::sink::{self, MetricSink};
+use crate::snapshot::Snapshot;
+use crate::thermal::TempLimits;
+use crate::{ClockSrc, VoltSrc};
+
+/// `NET-SNMP-MIB::netSnmpPlaypen`
+pub const DEFAULT_BASE: [u32; 9] = [1, 3, 6, 1, 4, 1, 8072, 9999, 9999];
+
+/// The largest payload of a UDP datagram
+const MAX_MESSAGE: usize = 65_507;
+/// Upper bound for the repetitions of a `get-bulk` request, which has few objects to walk
+const MAX_REPETITIONS: i64 = 64;
+
+const VERSION_1: i64 = 0;
+const VERSION_2C: i64 = 1;
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OBJECT_ID: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const GAUGE32: u8 = 0x42;
+const NO_SUCH_OBJECT: u8 = 0x80;
+const END_OF_MIB_VIEW: u8 = 0x82;
+
+const GET: u8 = 0xa0;
+const GET_NEXT: u8 = 0xa1;
+const RESPONSE: u8 = 0xa2;
+const SET: u8 = 0xa3;
+const GET_BULK: u8 = 0xa5;
+
+const NO_SUCH_NAME: i64 = 2;
+const READ_ONLY: i64 = 4;
+const NOT_WRITABLE: i64 = 17;
+
+/// The value of an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    Integer(i64),
+    Gauge(u32),
+}
+
+impl Value {
+    fn encode(self) -> Vec<u8> {
+        match self {
+            Value::Integer(value) => tlv(INTEGER, &integer(value)),
+            Value::Gauge(value) => tlv(GAUGE32, &unsigned(value)),
+        }
+    }
+}
+
+/// The objects served, ordered by OID
+type Objects = Vec<(Vec<u32>, Value)>;
+
+/// Answers SNMP requests on a background thread, see the module documentation.
+///
+/// The thread runs until the process exits.
+#[derive(Debug, Clone)]
+pub struct SnmpAgent {
+    objects: Arc<Mutex<Objects>>,
+    base: Vec<u32>,
+    local_addr: SocketAddr,
+}
+
+impl SnmpAgent {
+    /// Listen on the UDP address `addr`, answering requests for `community`. Until the
+    /// first sample there are no objects.
+    ///
+    /// The standard port 161 needs root or `CAP_NET_BIND_SERVICE`.
+    pub fn bind<A: ToSocketAddrs>(addr: A, community: &str) -> io::Result<SnmpAgent> {
+        let socket = UdpSocket::bind(addr)?;
+        let local_addr = socket.local_addr()?;
+        let objects = Arc::new(Mutex::new(Objects::new()));
+
+        let served = Arc::clone(&objects);
+        let community = community.to_owned();
+        thread::Builder::new()
+            .name("vcgencmd-snmp".to_owned())
+            .spawn(move || {
+                let mut buffer = vec![0; MAX_MESSAGE];
+                loop {
+                    let (len, peer) = match socket.recv_from(&mut buffer) {
+                        Ok(received) => received,
+                        Err(_) => continue,
+                    };
+                    let message = buffer.get(..len).unwrap_or_default();
+                    if let Some(response) = respond(message, &community, &lock(&served)) {
+                        // a peer that went away only costs its own request
+                        let _ = socket.send_to(&response, peer);
+                    }
+                }
+            })?;
+
+        Ok(SnmpAgent {
+            objects,
+            base: DEFAULT_BASE.to_vec(),
+            local_addr,
+        })
+    }
+
+    /// Serve the objects under `base` instead of `DEFAULT_BASE`, from the next sample on
+    pub fn base(mut self, base: &[u32]) -> SnmpAgent {
+        self.base = base.to_vec();
+        self
+    }
+
+    /// The address actually bound, useful when binding to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Serve the readings of `sample` from now on
+    pub fn update(&self, sample: &Sample) {
+        let objects = objects(sample, &self.base);
+        *lock(&self.objects) = objects;
+    }
+
+    /// Turn this into a plain monitor sink updating the served objects
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for SnmpAgent {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        self.update(sample);
+        Ok(())
+    }
+}
+
+fn lock(objects: &Mutex<Objects>) -> MutexGuard<'_, Objects> {
+    objects.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The objects for the readings of `sample`, see the module documentation for the layout
+fn objects(sample: &Sample, base: &[u32]) -> Objects {
+    let mut objects = Objects::new();
+    let mut add = |arc: &[u32], value: Value| {
+        let mut oid = base.to_vec();
+        oid.extend_from_slice(arc);
+        objects.push((oid, value));
+    };
+    let gauge = |value: f64| Value::Gauge(value.round().clamp(0.0, f64::from(u32::MAX)) as u32);
+    let flag = |set: bool| Value::Integer(i64::from(set));
+
+    let read = |metric: Metric| sample.get(metric).and_then(Reading::value);
+    let temp = read(Metric::Temp).or_else(|| read(Metric::TempHeadroom));
+    if let Some(temp) = temp {
+        add(&[1, 0], Value::Integer((temp * 1000.0).round() as i64));
+    }
+    if let Some(&Reading::Throttled(bit_pattern)) = sample.get(Metric::Throttled) {
+        add(&[2, 0], Value::Gauge(bit_pattern as u32));
+    }
+    if let Some(hz) = read(Metric::Clock(ClockSrc::Arm)) {
+        add(&[3, 0], gauge(hz));
+    }
+    if let Some(hz) = read(Metric::Clock(ClockSrc::Core)) {
+        add(&[4, 0], gauge(hz));
+    }
+    if let Some(volts) = read(Metric::Volts(VoltSrc::Core)) {
+        add(&[5, 0], gauge(volts * 1000.0));
+    }
+
+    if let Some(&Reading::Throttled(bit_pattern)) = sample.get(Metric::Throttled) {
+        let limits = match sample.get(Metric::TempHeadroom) {
+            Some(Reading::TempHeadroom(headroom)) => TempLimits {
+                soft: headroom.soft_limit,
+                hard: headroom.hard_limit,
+            },
+            _ => TempLimits::default(),
+        };
+        let health = match Snapshot::from_sample(sample, limits).health() {
+            HealthSummary::Healthy => 1,
+            HealthSummary::Degraded(_) => 2,
+            HealthSummary::Critical(_) => 3,
+        };
+        add(&[6, 0], Value::Integer(health));
+
+        let status = interpret_bit_pattern(bit_pattern);
+        for (n, condition) in (1..).zip(Condition::ALL.iter()) {
+            add(&[7, n], flag(condition.is_active(&status)));
+        }
+        for (n, condition) in (1..).zip(Condition::ALL.iter()) {
+            add(&[8, n], flag(condition.has_occurred(&status)));
+        }
+    }
+
+    objects
+}
+
+/// The response to the request `message`, `None` for requests that aren't answered, e.g.
+/// those of another community
+fn respond(message: &[u8], community: &str, objects: &Objects) -> Option<Vec<u8>> {
+    let mut message = Reader::new(Reader::new(message).expect(SEQUENCE)?);
+    let version = message.integer()?;
+    if version != VERSION_1 && version != VERSION_2C {
+        return None;
+    }
+    if message.expect(OCTET_STRING)? != community.as_bytes() {
+        return None;
+    }
+
+    let (kind, pdu) = message.tlv()?;
+    let mut pdu = Reader::new(pdu);
+    let request_id = pdu.integer()?;
+    // the error status and index, or the non-repeaters and max-repetitions of get-bulk
+    let (first, second) = (pdu.integer()?, pdu.integer()?);
+    let mut list = Reader::new(pdu.expect(SEQUENCE)?);
+    let mut names = Vec::new();
+    while !list.is_empty() {
+        names.push(Reader::new(list.expect(SEQUENCE)?).oid()?);
+    }
+
+    let find = |name: &[u32]| objects.iter().find(|(oid, _)| oid.as_slice() == name);
+    let next = |name: &[u32]| objects.iter().find(|(oid, _)| oid.as_slice() > name);
+    let found = |(oid, value): &(Vec<u32>, Value)| (oid.clone(), value.encode());
+    let missing = |name: &[u32], exception: u8| (name.to_vec(), tlv(exception, &[]));
+
+    let (mut status, mut index) = (0, 0);
+    let mut bindings = Vec::new();
+    match kind {
+        GET | GET_NEXT => {
+            for (i, name) in names.iter().enumerate() {
+                let (object, exception) = match kind {
+                    GET => (find(name), NO_SUCH_OBJECT),
+                    _ => (next(name), END_OF_MIB_VIEW),
+                };
+                match object {
+                    Some(object) => bindings.push(found(object)),
+                    None if version == VERSION_2C => bindings.push(missing(name, exception)),
+                    None => {
+                        // SNMPv1 has no exceptions, the request fails as a whole
+                        status = NO_SUCH_NAME;
+                        index = i as i64 + 1;
+                        bindings = names.iter().map(|name| missing(name, NULL)).collect();
+                        break;
+                    }
+                }
+            }
+        }
+        GET_BULK if version == VERSION_2C => {
+            let non_repeaters = first.clamp(0, names.len() as i64) as usize;
+            let (single, repeated) = names.split_at(non_repeaters);
+            for name in single {
+                bindings.push(next(name).map_or_else(|| missing(name, END_OF_MIB_VIEW), found));
+            }
+
+            let mut cursors: Vec<Vec<u32>> = repeated.to_vec();
+            for _ in 0..second.clamp(0, MAX_REPETITIONS) {
+                let mut walked = false;
+                for cursor in &mut cursors {
+                    match next(cursor) {
+                        Some(object) => {
+                            bindings.push(found(object));
+                            cursor.clone_from(&object.0);
+                            walked = true;
+                        }
+                        None => bindings.push(missing(cursor, END_OF_MIB_VIEW)),
+                    }
+                }
+                if !walked {
+                    break;
+                }
+            }
+        }
+        SET => {
+            status = if version == VERSION_2C {
+                NOT_WRITABLE
+            } else {
+                READ_ONLY
+            };
+            index = 1;
+            bindings = names.iter().map(|name| missing(name, NULL)).collect();
+        }
+        _ => return None,
+    }
+
+    let bindings: Vec<u8> = bindings
+        .iter()
+        .flat_map(|(oid, value)| {
+            tlv(
+                SEQUENCE,
+                &[tlv(OBJECT_ID, &encode_oid(oid)), value.clone()].concat(),
+            )
+        })
+        .collect();
+    let pdu = [
+        tlv(INTEGER, &integer(request_id)),
+        tlv(INTEGER, &integer(status)),
+        tlv(INTEGER, &integer(index)),
+        tlv(SEQUENCE, &bindings),
+    ]
+    .concat();
+    let message = [
+        tlv(INTEGER, &integer(version)),
+        tlv(OCTET_STRING, community.as_bytes()),
+        tlv(RESPONSE, &pdu),
+    ]
+    .concat();
+
+    Some(tlv(SEQUENCE, &message))
+}
+
+/// Reads the BER encoded values of a message one after the other
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The tag and contents of the next value
+    fn tlv(&mut self) -> Option<(u8, &'a [u8])> {
+        let (&tag, rest) = self.data.split_first()?;
+        let (&first, rest) = rest.split_first()?;
+        let (length, rest) = match first {
+            short if short < 0x80 => (usize::from(short), rest),
+            long => {
+                let count = usize::from(long & 0x7f);
+                if count == 0 || count > 4 {
+                    return None;
+                }
+                let (bytes, rest) = rest.split_at_checked(count)?;
+                let length = bytes
+                    .iter()
+                    .fold(0, |length, &byte| length << 8 | usize::from(byte));
+                (length, rest)
+            }
+        };
+
+        let (contents, rest) = rest.split_at_checked(length)?;
+        self.data = rest;
+        Some((tag, contents))
+    }
+
+    /// The contents of the next value, if it has the tag `expected`
+    fn expect(&mut self, expected: u8) -> Option<&'a [u8]> {
+        let (tag, contents) = self.tlv()?;
+        (tag == expected).then_some(contents)
+    }
+
+    fn integer(&mut self) -> Option<i64> {
+        let contents = self.expect(INTEGER)?;
+        let (&first, _) = contents.split_first()?;
+        if contents.len() > 8 {
+            return None;
+        }
+
+        let sign = if first & 0x80 != 0 { -1 } else { 0 };
+        Some(
+            contents
+                .iter()
+                .fold(sign, |value: i64, &byte| value << 8 | i64::from(byte)),
+        )
+    }
+
+    fn oid(&mut self) -> Option<Vec<u32>> {
+        let contents = self.expect(OBJECT_ID)?;
+
+        let mut subidentifiers = Vec::new();
+        let mut value: u32 = 0;
+        for &byte in contents {
+            value = value.checked_mul(128)? | u32::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                subidentifiers.push(value);
+                value = 0;
+            }
+        }
+
+        // the first two arcs are encoded together
+        let (&first, rest) = subidentifiers.split_first()?;
+        let top = (first / 40).min(2);
+        let mut oid = vec![top, first - top * 40];
+        oid.extend_from_slice(rest);
+        Some(oid)
+    }
+}
+
+/// A value with tag `tag` and `contents`
+fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match contents.len() {
+        short if short < 0x80 => encoded.push(short as u8),
+        long => {
+            let bytes = long.to_be_bytes();
+            let skip = bytes.iter().take_while(|&&byte| byte == 0).count();
+            let bytes = bytes.get(skip..).unwrap_or_default();
+            encoded.push(0x80 | bytes.len() as u8);
+            encoded.extend_from_slice(bytes);
+        }
+    }
+    encoded.extend_from_slice(contents);
+    encoded
+}
+
+/// The shortest two's complement encoding of `value`
+fn integer(value: i64) -> Vec<u8> {
+    let mut bytes = value.to_be_bytes().to_vec();
+    while let [first, second, ..] = *bytes.as_slice() {
+        let redundant = (first == 0 && second & 0x80 == 0) || (first == 0xff && second & 0x80 != 0);
+        if !redundant {
+            break;
+        }
+        bytes.remove(0);
+    }
+    bytes
+}
+
+/// `value` as a non-negative integer
+fn unsigned(value: u32) -> Vec<u8> {
+    integer(i64::from(value))
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut arcs = oid.iter().copied();
+    let first = arcs.next().unwrap_or_default() * 40 + arcs.next().unwrap_or_default();
+
+    let mut encoded = Vec::new();
+    for arc in std::iter::once(first).chain(arcs) {
+        let mut groups = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        encoded.extend(groups.iter().rev());
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn sample() -> Sample {
+        Sample {
+            timestamp: SystemTime::now(),
+            readings: vec![
+                Reading::Temp(48.312),
+                Reading::Throttled(0x50005),
+                Reading::Clock(ClockSrc::Arm, 600_000_000),
+                Reading::Volts(VoltSrc::Core, 0.8563),
+            ],
+            errors: Vec::new(),
+        }
+    }
+
+    fn oid(arc: &[u32]) -> Vec<u32> {
+        [&DEFAULT_BASE[..], arc].concat()
+    }
+
+    fn request(
+        version: i64,
+        community: &str,
+        kind: u8,
+        fields: (i64, i64),
+        names: &[Vec<u32>],
+    ) -> Vec<u8> {
+        let bindings: Vec<u8> = names
+            .iter()
+            .flat_map(|name| {
+                tlv(
+                    SEQUENCE,
+                    &[tlv(OBJECT_ID, &encode_oid(name)), tlv(NULL, &[])].concat(),
+                )
+            })
+            .collect();
+        let pdu = [
+            tlv(INTEGER, &integer(7)),
+            tlv(INTEGER, &integer(fields.0)),
+            tlv(INTEGER, &integer(fields.1)),
+            tlv(SEQUENCE, &bindings),
+        ]
+        .concat();
+        let message = [
+            tlv(INTEGER, &integer(version)),
+            tlv(OCTET_STRING, community.as_bytes()),
+            tlv(kind, &pdu),
+        ]
+        .concat();
+        tlv(SEQUENCE, &message)
+    }
+
+    /// A variable binding with the value still encoded
+    type Binding = (Vec<u32>, Vec<u8>);
+
+    /// The error status and the bindings of a response
+    fn parse(response: &[u8]) -> (i64, Vec<Binding>) {
+        let mut message = Reader::new(Reader::new(response).expect(SEQUENCE).unwrap());
+        message.integer().unwrap();
+        message.expect(OCTET_STRING).unwrap();
+        let mut pdu = Reader::new(message.expect(RESPONSE).unwrap());
+        assert_eq!(Some(7), pdu.integer());
+        let status = pdu.integer().unwrap();
+        pdu.integer().unwrap();
+
+        let mut list = Reader::new(pdu.expect(SEQUENCE).unwrap());
+        let mut bindings = Vec::new();
+        while !list.is_empty() {
+            let mut binding = Reader::new(list.expect(SEQUENCE).unwrap());
+            let name = binding.oid().unwrap();
+            bindings.push((name, binding.data.to_vec()));
+        }
+        (status, bindings)
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(vec![0x00], integer(0));
+        assert_eq!(vec![0x00, 0x80], integer(128));
+        assert_eq!(vec![0xff, 0x7f], integer(-129));
+        assert_eq!(vec![0x00, 0xff, 0xff, 0xff, 0xff], unsigned(u32::MAX));
+        assert_eq!(
+            vec![0x2b, 0x06, 0x01, 0x04, 0x01, 0xbf, 0x08, 0xce, 0x0f, 0xce, 0x0f],
+            encode_oid(&DEFAULT_BASE)
+        );
+        assert_eq!(
+            vec![0x04, 0x81, 0x80],
+            tlv(OCTET_STRING, &[0; 128])[..3].to_vec()
+        );
+
+        let mut reader = Reader::new(&[0x06, 0x03, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x80]);
+        assert_eq!(Some(vec![1, 3, 6, 1]), reader.oid());
+        assert_eq!(Some(-128), reader.integer());
+    }
+
+    #[test]
+    fn test_get() {
+        let objects = objects(&sample(), &DEFAULT_BASE);
+        let names = [oid(&[1, 0]), oid(&[4, 0])];
+
+        let (status, bindings) = parse(
+            &respond(
+                &request(1, "public", GET, (0, 0), &names),
+                "public",
+                &objects,
+            )
+            .unwrap(),
+        );
+        assert_eq!(0, status);
+        assert_eq!((oid(&[1, 0]), Value::Integer(48_312).encode()), bindings[0]);
+        assert_eq!((oid(&[4, 0]), vec![NO_SUCH_OBJECT, 0]), bindings[1]);
+
+        let (status, _) = parse(
+            &respond(
+                &request(0, "public", GET, (0, 0), &names),
+                "public",
+                &objects,
+            )
+            .unwrap(),
+        );
+        assert_eq!(NO_SUCH_NAME, status);
+
+        assert!(respond(
+            &request(1, "private", GET, (0, 0), &names),
+            "public",
+            &objects
+        )
+        .is_none());
+        assert!(respond(b"\x30\x03\x02\x01", "public", &objects).is_none());
+    }
+
+    #[test]
+    fn test_walk() {
+        let objects = objects(&sample(), &DEFAULT_BASE);
+        // temperature, throttled, ARM clock, voltage, health and 2 * 4 conditions
+        assert_eq!(13, objects.len());
+        assert_eq!((oid(&[5, 0]), Value::Gauge(856)), objects[3]);
+        assert_eq!((oid(&[6, 0]), Value::Integer(3)), objects[4]);
+        assert_eq!((oid(&[7, 1]), Value::Integer(1)), objects[5]);
+
+        let mut walked = Vec::new();
+        let mut name = DEFAULT_BASE.to_vec();
+        loop {
+            let response = respond(
+                &request(1, "public", GET_NEXT, (0, 0), &[name]),
+                "public",
+                &objects,
+            )
+            .unwrap();
+            let (_, bindings) = parse(&response);
+            let (next, value) = bindings.into_iter().next().unwrap();
+            if value == vec![END_OF_MIB_VIEW, 0] {
+                break;
+            }
+            walked.push(next.clone());
+            name = next;
+        }
+        let all: Vec<_> = objects.iter().map(|(oid, _)| oid.clone()).collect();
+        assert_eq!(all, walked);
+
+        let response = respond(
+            &request(1, "public", GET_BULK, (1, 100), &[oid(&[1, 0]), oid(&[7])]),
+            "public",
+            &objects,
+        )
+        .unwrap();
+        let (_, bindings) = parse(&response);
+        // the throttled bit pattern, 8 conditions and the end of the MIB view
+        assert_eq!(10, bindings.len());
+        assert_eq!(oid(&[2, 0]), bindings[0].0);
+        assert_eq!(vec![END_OF_MIB_VIEW, 0], bindings[9].1);
+    }
+
+    #[test]
+    fn test_agent() {
+        let agent = SnmpAgent::bind("127.0.0.1:0", "public").unwrap();
+        agent.update(&sample());
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+            .send_to(
+                &request(1, "public", SET, (0, 0), &[oid(&[2, 0])]),
+                agent.local_addr(),
+            )
+            .unwrap();
+        let mut buffer = [0; 512];
+        let len = socket.recv(&mut buffer).unwrap();
+
+        let (status, bindings) = parse(&buffer[..len]);
+        assert_eq!(NOT_WRITABLE, status);
+        assert_eq!(vec![(oid(&[2, 0]), vec![NULL, 0])], bindings);
+    }
+}
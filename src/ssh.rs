@@ -0,0 +1,311 @@
+//! Reading a remote Pi over one ssh connection, with the `ssh` feature
+//!
+//! `Invocation::host` already runs vcgencmd over `ssh`, but opens a new connection for every
+//! reading, which costs a key exchange each time. An `SshExecutor` can keep a master
+//! connection open instead, which the readings are multiplexed over, and takes the port,
+//! key and options of the host, so a central box can watch headless Pis through `Vcgencmd`
+//! clients without an ssh config for each of them:
+//!
+//! ```rust,no_run
+//! use vcgencmd::ssh::SshExecutor;
+//! use vcgencmd::Vcgencmd;
+//!
+//! let executor = SshExecutor::new("pi@10.0.0.3")
+//!     .port(2222)
+//!     .identity_file("/etc/monitoring/id_ed25519")
+//!     .connect()?;
+//! let client = Vcgencmd::with_executor(executor);
+//! println!("{} °C", client.measure_temp()?);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! `ssh` runs in batch mode, so the host needs key based login, and `sudo` on it must not
+//! ask for a password. Should the master connection drop, every reading connects on its
+//! own again.
+
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use subprocess::PopenError;
+
+use crate::executor::Executor;
+use crate::{
+    child, resolve_command, resolve_program, resolve_src, stdout_of, Cmd, Escalation,
+    ExecutionError, Invocation, PrivilegeMode, Src, DEFAULT_TIMEOUT,
+};
+
+/// Tells the control sockets of the executors of this process apart
+static SOCKETS: AtomicUsize = AtomicUsize::new(0);
+
+/// The master connection, closed when dropped
+#[derive(Debug)]
+struct Master {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl Drop for Master {
+    fn drop(&mut self) {
+        // SIGTERM, so the master removes its socket
+        if let Ok(pid) = libc::pid_t::try_from(self.child.id()) {
+            // SAFETY: the child isn't reaped yet, so its pid can't have been reused
+            unsafe { libc::kill(pid, libc::SIGTERM) };
+        }
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+/// Runs vcgencmd on a remote host over ssh, see the module documentation
+#[derive(Debug)]
+pub struct SshExecutor {
+    invocation: Invocation,
+    port: Option<u16>,
+    identity_file: Option<PathBuf>,
+    options: Vec<String>,
+    master: Option<Master>,
+}
+
+impl SshExecutor {
+    /// Run vcgencmd on `host`, e.g. `pi@pi4.local`, through `sudo` by default
+    pub fn new(host: &str) -> SshExecutor {
+        SshExecutor {
+            invocation: Invocation {
+                host: Some(host.to_owned()),
+                ..Invocation::default()
+            },
+            port: None,
+            identity_file: None,
+            options: Vec::new(),
+            master: None,
+        }
+    }
+
+    pub fn port(mut self, port: u16) -> SshExecutor {
+        self.port = Some(port);
+        self
+    }
+
+    /// Log in with the private key at `path`
+    pub fn identity_file<P: Into<PathBuf>>(mut self, path: P) -> SshExecutor {
+        self.identity_file = Some(path.into());
+        self
+    }
+
+    /// Pass `option` to `ssh -o`, e.g. `StrictHostKeyChecking=accept-new`
+    pub fn option(mut self, option: &str) -> SshExecutor {
+        self.options.push(option.to_owned());
+        self
+    }
+
+    /// Run the binary at `path` on the host, a plain name is looked up in its `PATH`
+    pub fn binary_path<P: Into<PathBuf>>(mut self, path: P) -> SshExecutor {
+        self.invocation.binary = path.into();
+        self
+    }
+
+    /// Whether to run it through `sudo` on the host
+    pub fn privilege(mut self, privilege: PrivilegeMode) -> SshExecutor {
+        self.invocation.privilege = privilege;
+        self
+    }
+
+    /// `privilege(PrivilegeMode::Sudo)` or `privilege(PrivilegeMode::None)`
+    pub fn use_sudo(self, sudo: bool) -> SshExecutor {
+        self.privilege(if sudo {
+            PrivilegeMode::Sudo
+        } else {
+            PrivilegeMode::None
+        })
+    }
+
+    /// Run it through `escalation` on the host instead of `sudo`
+    pub fn escalation(mut self, escalation: Escalation) -> SshExecutor {
+        self.invocation.escalation = escalation;
+        self
+    }
+
+    /// Terminate a reading taking longer than `timeout`, including connecting for it
+    pub fn timeout(mut self, timeout: Duration) -> SshExecutor {
+        self.invocation.timeout = Some(timeout);
+        self
+    }
+
+    pub fn host(&self) -> &str {
+        self.invocation.host.as_deref().unwrap_or_default()
+    }
+
+    /// Open the master connection the readings are sent over from now on, and which is
+    /// closed when the executor is dropped.
+    ///
+    /// Fails if `ssh` can't log in within the timeout.
+    pub fn connect(mut self) -> io::Result<SshExecutor> {
+        let id = SOCKETS.fetch_add(1, Ordering::Relaxed);
+        let socket = std::env::temp_dir().join(format!("vcgencmd-ssh-{}-{}", process::id(), id));
+
+        let mut exec = self.ssh();
+        exec.args(["-N", "-o", "ControlMaster=yes", "-o"])
+            .arg(control_path(&socket))
+            .arg("--")
+            .arg(self.host())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let mut master = Master {
+            child: exec.spawn()?,
+            socket,
+        };
+
+        let timeout = self.invocation.timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let deadline = Instant::now() + timeout;
+        while !master.socket.exists() {
+            if let Some(status) = master.child.try_wait()? {
+                return Err(io::Error::other(format!(
+                    "ssh to {} exited with {}",
+                    self.host(),
+                    status
+                )));
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("ssh to {} didn't connect within {:?}", self.host(), timeout),
+                ));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        self.master = Some(master);
+        Ok(self)
+    }
+
+    /// Whether the readings are sent over a master connection
+    pub fn is_connected(&self) -> bool {
+        self.master.is_some()
+    }
+
+    /// `ssh` with the options of the host
+    fn ssh(&self) -> process::Command {
+        let mut exec = process::Command::new(resolve_program(Path::new("ssh")));
+        exec.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            exec.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity_file) = &self.identity_file {
+            exec.arg("-i").arg(identity_file);
+        }
+        for option in &self.options {
+            exec.arg("-o").arg(option);
+        }
+        exec
+    }
+
+    /// The process running `cmd` with `src` on the host
+    fn command(&self, cmd: Cmd, src: Option<Src>) -> process::Command {
+        let mut exec = self.ssh();
+        if let Some(master) = &self.master {
+            exec.args(["-o", "ControlMaster=no", "-o"])
+                .arg(control_path(&master.socket));
+        }
+
+        exec.arg("--").arg(self.host());
+        if self.invocation.privilege == PrivilegeMode::Sudo {
+            exec.arg(self.invocation.escalation.program());
+        }
+        exec.arg(&self.invocation.binary).arg(resolve_command(cmd));
+        if let Some(src) = resolve_src(src) {
+            exec.arg(src);
+        }
+        exec.stdin(Stdio::null());
+        exec
+    }
+}
+
+fn control_path(socket: &Path) -> OsString {
+    let mut option = OsString::from("ControlPath=");
+    option.push(socket);
+    option
+}
+
+impl Executor for SshExecutor {
+    fn run(&self, cmd: Cmd, src: Option<Src>) -> Result<String, ExecutionError> {
+        let output = child::output(&mut self.command(cmd, src), self.invocation.timeout);
+        stdout_of(
+            &self.invocation,
+            cmd,
+            src,
+            output.map_err(PopenError::IoError),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VoltSrc;
+
+    fn args(exec: &process::Command) -> Vec<String> {
+        exec.get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_command() {
+        let executor = SshExecutor::new("pi@10.0.0.3")
+            .port(2222)
+            .identity_file("/keys/id_ed25519")
+            .option("StrictHostKeyChecking=accept-new");
+
+        assert_eq!(
+            vec![
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                "2222",
+                "-i",
+                "/keys/id_ed25519",
+                "-o",
+                "StrictHostKeyChecking=accept-new",
+                "--",
+                "pi@10.0.0.3",
+                "sudo",
+                "vcgencmd",
+                "measure_volts",
+                "sdram_c",
+            ],
+            args(&executor.command(Cmd::MeasureVolts, Some(Src::Volt(VoltSrc::SdramC))))
+        );
+
+        let executor = SshExecutor::new("pi4")
+            .use_sudo(false)
+            .escalation(Escalation::Doas)
+            .binary_path("/usr/bin/vcgencmd");
+        assert_eq!(
+            vec![
+                "-o",
+                "BatchMode=yes",
+                "--",
+                "pi4",
+                "/usr/bin/vcgencmd",
+                "measure_temp"
+            ],
+            args(&executor.command(Cmd::MeasureTemp, None))
+        );
+        assert!(!executor.is_connected());
+
+        let executor = executor.use_sudo(true);
+        assert_eq!(
+            Some("doas"),
+            args(&executor.command(Cmd::MeasureTemp, None))
+                .get(4)
+                .map(String::as_str)
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Sampling metrics periodically from async code, on Tokio or async-std
+//!
+//! An `AsyncMonitor` is the async counterpart of a `Monitor`: instead of sampling on a
+//! thread of its own, its `SampleStream` waits on the runtime's timers and reads the
+//! metrics concurrently through an `AsyncVcgencmd`, so an async service polls the firmware
+//! without a blocking thread. Samples are taken on the same drift-free grid of intervals as
+//! a `Monitor`'s, the first one right away.
+//!
+//! ```rust,no_run
+//! # async fn example() {
+//! use std::time::Duration;
+//! use futures_util::StreamExt;
+//! use vcgencmd::monitor::Metric;
+//! use vcgencmd::stream::AsyncMonitor;
+//!
+//! let mut samples = AsyncMonitor::new(Duration::from_secs(1))
+//!     .metric(Metric::Temp)
+//!     .into_stream();
+//!
+//! while let Some(sample) = samples.next().await {
+//!     println!("{:?}", sample.readings);
+//! }
+//! # }
+//! ```
+//!
+//! The stream never ends on its own, sampling stops once it's dropped.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+
+use crate::asynchronous::{sleep_until, AsyncVcgencmd};
+use crate::monitor::{next_slot, Metric, Sample};
+
+/// Samples a set of metrics periodically through an `AsyncVcgencmd`, see `into_stream`
+#[derive(Debug, Clone)]
+pub struct AsyncMonitor {
+    client: AsyncVcgencmd,
+    interval: Duration,
+    metrics: Vec<Metric>,
+}
+
+impl AsyncMonitor {
+    /// A monitor without any metrics sampling every `interval`, with the global `Invocation`
+    pub fn new(interval: Duration) -> AsyncMonitor {
+        AsyncMonitor {
+            client: AsyncVcgencmd::from_global(),
+            interval,
+            metrics: Vec::new(),
+        }
+    }
+
+    /// Read the metrics through `client` instead, e.g. one talking to a remote Pi
+    pub fn client(mut self, client: AsyncVcgencmd) -> AsyncMonitor {
+        self.client = client;
+        self
+    }
+
+    /// Add `metric`, metrics already sampled are ignored
+    pub fn metric(mut self, metric: Metric) -> AsyncMonitor {
+        if !self.metrics.contains(&metric) {
+            self.metrics.push(metric);
+        }
+        self
+    }
+
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Start sampling as the stream is polled
+    pub fn into_stream(self) -> SampleStream {
+        SampleStream {
+            monitor: Arc::new(self),
+            due: Instant::now(),
+            pending: None,
+        }
+    }
+}
+
+/// The samples of an `AsyncMonitor`, one per interval.
+///
+/// Only the sample due next is being taken at any time, if the consumer falls behind, the
+/// slots it missed are skipped rather than sampled in a burst.
+pub struct SampleStream {
+    monitor: Arc<AsyncMonitor>,
+    /// The slot the next sample is due at
+    due: Instant,
+    /// Waiting for the slot and reading the metrics
+    pending: Option<Pin<Box<dyn Future<Output = Sample> + Send>>>,
+}
+
+impl SampleStream {
+    pub fn monitor(&self) -> &AsyncMonitor {
+        &self.monitor
+    }
+}
+
+impl Stream for SampleStream {
+    type Item = Sample;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Sample>> {
+        let stream = self.get_mut();
+
+        let (monitor, due) = (&stream.monitor, stream.due);
+        let pending = stream.pending.get_or_insert_with(|| {
+            let monitor = Arc::clone(monitor);
+            Box::pin(async move {
+                sleep_until(due).await;
+                monitor.client.sample(&monitor.metrics).await
+            })
+        });
+
+        match pending.as_mut().poll(cx) {
+            Poll::Ready(sample) => {
+                stream.pending = None;
+                stream.due = next_slot(stream.due, stream.monitor.interval, Instant::now());
+                Poll::Ready(Some(sample))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// async-std spawns processes on its own reactor, so any executor can drive the stream here
+#[cfg(all(test, feature = "async-std"))]
+mod tests {
+    use super::*;
+    use crate::{Invocation, PrivilegeMode};
+    use futures_util::StreamExt;
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::thread::{self, Thread};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_samples_on_the_interval() {
+        // `true` answers nothing, so every metric ends up among the errors
+        let client = AsyncVcgencmd::new(Invocation {
+            binary: "true".into(),
+            privilege: PrivilegeMode::None,
+            ..Invocation::default()
+        });
+        let interval = Duration::from_millis(20);
+        let mut samples = AsyncMonitor::new(interval)
+            .client(client)
+            .metric(Metric::Temp)
+            .metric(Metric::Temp)
+            .into_stream();
+
+        let started = Instant::now();
+        for _ in 0..3 {
+            let sample = block_on(samples.next()).unwrap();
+            assert_eq!(1, sample.readings.len() + sample.errors.len());
+        }
+        // the first sample is taken right away, the others a slot each after it
+        assert!(started.elapsed() >= 2 * interval);
+    }
+}
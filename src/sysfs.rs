@@ -0,0 +1,201 @@
+//! The temperature and the ARM clock from sysfs, for systems without vcgencmd
+//!
+//! Minimal and 64 bit images often lack the userland tools, but the kernel still exposes
+//! the SoC temperature as thermal zone 0 and the ARM clock through cpufreq. When spawning
+//! vcgencmd fails because it isn't installed, `measure_temp` and `measure_clock(Arm)` are
+//! answered from there instead, unless `Invocation::sysfs_fallback` is turned off or
+//! vcgencmd is run on another host. Everything else still fails as before.
+//!
+//! A `SysfsExecutor` answers a `Vcgencmd` client from sysfs only:
+//!
+//! ```rust,no_run
+//! use vcgencmd::sysfs::SysfsExecutor;
+//! use vcgencmd::{ClockSrc, Src, Vcgencmd};
+//!
+//! let client = Vcgencmd::with_executor(SysfsExecutor::new());
+//! println!("{} °C", client.measure_temp()?);
+//! println!("{} Hz", client.measure_clock(Src::Clock(ClockSrc::Arm))?);
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+//!
+//! The clock is the one cpufreq last set, which the firmware may have capped below.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use subprocess::PopenError;
+
+use crate::executor::{Executor, INVALID_ARGUMENTS, NOT_REGISTERED};
+use crate::{describe, ClockSrc, Cmd, Error, ExecutionError, Invocation, Src};
+
+const TEMP: &str = "class/thermal/thermal_zone0/temp";
+const ARM_CLOCK: &str = "devices/system/cpu/cpu0/cpufreq/scaling_cur_freq";
+
+/// Reads from sysfs what vcgencmd would print, see the module documentation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysfsExecutor {
+    root: PathBuf,
+}
+
+impl Default for SysfsExecutor {
+    fn default() -> SysfsExecutor {
+        SysfsExecutor::with_root("/sys")
+    }
+}
+
+impl SysfsExecutor {
+    pub fn new() -> SysfsExecutor {
+        SysfsExecutor::default()
+    }
+
+    /// Read sysfs mounted at `root` instead of `/sys`, e.g. the host's inside a container
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> SysfsExecutor {
+        SysfsExecutor { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The integer in the file at `path` below the root
+    fn read(&self, path: &str) -> io::Result<u64> {
+        let value = fs::read_to_string(self.root.join(path))?;
+        value.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} holds no number: {:?}", path, value.trim()),
+            )
+        })
+    }
+
+    /// What vcgencmd would print for `cmd` with `src`, `None` for the commands sysfs can't
+    /// answer
+    fn answer(&self, cmd: Cmd, src: Option<Src>) -> Option<io::Result<String>> {
+        match (cmd, src) {
+            (Cmd::MeasureTemp, None) => Some(
+                self.read(TEMP)
+                    .map(|millis| format!("temp={}'C\n", millis as f64 / 1000.0)),
+            ),
+            (Cmd::MeasureClock, Some(Src::Clock(ClockSrc::Arm))) => Some(
+                self.read(ARM_CLOCK)
+                    .map(|khz| format!("frequency(48)={}\n", khz * 1000)),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl Executor for SysfsExecutor {
+    fn run(&self, cmd: Cmd, src: Option<Src>) -> Result<String, ExecutionError> {
+        match self.answer(cmd, src) {
+            Some(answer) => {
+                answer.map_err(|error| Error::spawn(describe(cmd, src), PopenError::IoError(error)))
+            }
+            None if cmd == Cmd::MeasureClock => Ok(INVALID_ARGUMENTS.to_owned()),
+            None => Ok(NOT_REGISTERED.to_owned()),
+        }
+    }
+}
+
+/// `error` of running `cmd` as configured by `invocation`, or the answer from sysfs if it
+/// is about the binary missing and sysfs has one
+pub(crate) fn fallback(
+    invocation: &Invocation,
+    cmd: Cmd,
+    src: Option<Src>,
+    error: Error,
+) -> Result<String, Error> {
+    let missing = matches!(
+        error,
+        Error::MissingBinary { .. }
+            | Error::Container {
+                binary: Some(_),
+                ..
+            }
+    );
+    if !missing || !invocation.sysfs_fallback || invocation.host.is_some() {
+        return Err(error);
+    }
+
+    match SysfsExecutor::new().answer(cmd, src) {
+        Some(Ok(answer)) => Ok(answer),
+        _ => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorKind, Vcgencmd};
+    use std::process;
+
+    #[test]
+    fn test_sysfs_executor() {
+        let root = std::env::temp_dir().join(format!("vcgencmd-sysfs-{}", process::id()));
+        let zone = root.join("class/thermal/thermal_zone0");
+        let cpufreq = root.join("devices/system/cpu/cpu0/cpufreq");
+        fs::create_dir_all(&zone).unwrap();
+        fs::create_dir_all(&cpufreq).unwrap();
+        fs::write(zone.join("temp"), "48312\n").unwrap();
+        fs::write(cpufreq.join("scaling_cur_freq"), "1500000\n").unwrap();
+
+        let client = Vcgencmd::with_executor(SysfsExecutor::with_root(&root));
+        assert_eq!(48.312, client.measure_temp().unwrap());
+        assert_eq!(
+            1_500_000_000,
+            client.measure_clock(Src::Clock(ClockSrc::Arm)).unwrap()
+        );
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client
+                .measure_clock(Src::Clock(ClockSrc::Core))
+                .unwrap_err()
+                .kind()
+        );
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client.get_throttled().unwrap_err().kind()
+        );
+
+        fs::write(zone.join("temp"), "hot").unwrap();
+        assert_eq!(ErrorKind::Io, client.measure_temp().unwrap_err().kind());
+
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            ErrorKind::Unsupported,
+            client.measure_temp().unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_fallback_only_for_missing_binary() {
+        let missing = || Error::MissingBinary {
+            command: "get_throttled".to_owned(),
+            message: "No such file or directory".to_owned(),
+        };
+        let invocation = Invocation::default();
+        // sysfs has no answer to it
+        assert!(fallback(&invocation, Cmd::GetThrottled, None, missing()).is_err());
+
+        let remote = Invocation {
+            host: Some("pi4".to_owned()),
+            ..Invocation::default()
+        };
+        let disabled = Invocation {
+            sysfs_fallback: false,
+            ..Invocation::default()
+        };
+        for invocation in &[remote, disabled] {
+            let error = fallback(invocation, Cmd::MeasureTemp, None, missing()).unwrap_err();
+            assert!(matches!(error, Error::MissingBinary { .. }));
+        }
+
+        let denied = Error::Permission {
+            command: "measure_temp".to_owned(),
+            message: "Permission denied".to_owned(),
+        };
+        let error = fallback(&invocation, Cmd::MeasureTemp, None, denied).unwrap_err();
+        assert!(matches!(error, Error::Permission { .. }));
+    }
+}
@@ -0,0 +1,431 @@
+//! Integration with systemd: the journal and the service notification protocol
+
+use std::env;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::process;
+use std::time::{Duration, Instant};
+
+use crate::{interpret_bit_pattern, ThrottledStatus};
+
+/// Path of the socket journald listens on for its native protocol
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Identifier used for entries, so they can be found with `journalctl -t vcgencmd`
+const DEFAULT_IDENTIFIER: &str = "vcgencmd";
+
+/// Syslog priority levels, as understood by journald
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Emergency = 0,
+    Alert = 1,
+    Critical = 2,
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+    Debug = 7,
+}
+
+/// A sink writing structured entries to the systemd journal.
+///
+/// Every entry carries a `MESSAGE`, a `PRIORITY` and a `SYSLOG_IDENTIFIER`, plus
+/// `VCGENCMD_*` fields holding the measured values, so they can be queried with e.g.
+/// `journalctl -t vcgencmd VCGENCMD_UNDER_VOLTAGE=1`.
+pub struct Journal {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+impl Journal {
+    /// Connect to the journal socket at its default location
+    pub fn new() -> io::Result<Journal> {
+        Journal::with_socket_path(JOURNAL_SOCKET)
+    }
+
+    /// Connect to a journal socket at a custom location
+    pub fn with_socket_path(path: &str) -> io::Result<Journal> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+
+        Ok(Journal {
+            socket,
+            identifier: DEFAULT_IDENTIFIER.to_owned(),
+        })
+    }
+
+    /// Use a different `SYSLOG_IDENTIFIER` than `vcgencmd` for all entries
+    pub fn identifier(mut self, identifier: &str) -> Journal {
+        self.identifier = identifier.to_owned();
+        self
+    }
+
+    /// Send a single entry with arbitrary additional fields.
+    ///
+    /// Field names must consist of uppercase letters, digits and underscores,
+    /// and must not start with an underscore.
+    pub fn send(
+        &self,
+        priority: Priority,
+        message: &str,
+        fields: &[(&str, String)],
+    ) -> io::Result<()> {
+        let mut datagram = Vec::new();
+        append_field(&mut datagram, "MESSAGE", message);
+        append_field(&mut datagram, "PRIORITY", &(priority as u8).to_string());
+        append_field(&mut datagram, "SYSLOG_IDENTIFIER", &self.identifier);
+
+        for (name, value) in fields {
+            if !is_valid_field_name(name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid journal field name: {}", name),
+                ));
+            }
+            append_field(&mut datagram, name, value);
+        }
+
+        self.socket.send(&datagram)?;
+        Ok(())
+    }
+
+    /// Log a temperature reading in °C
+    pub fn log_temp(&self, temp: f64) -> io::Result<()> {
+        self.send(
+            Priority::Info,
+            &format!("temperature {:.1}'C", temp),
+            &[("VCGENCMD_TEMP", temp.to_string())],
+        )
+    }
+
+    /// Log a bit pattern obtained from `get_throttled`, together with its decoded flags.
+    ///
+    /// Entries are logged as warnings while under-voltage or throttling is active, so
+    /// `journalctl -t vcgencmd -p warning` lists exactly the throttle events.
+    pub fn log_throttled(&self, bit_pattern: isize) -> io::Result<()> {
+        let status = interpret_bit_pattern(bit_pattern);
+        let priority = throttled_priority(&status);
+        let message = format!("throttled=0x{:x}", bit_pattern);
+
+        self.send(priority, &message, &throttled_fields(bit_pattern, &status))
+    }
+}
+
+/// Sends state changes to the service manager via the `sd_notify` protocol.
+///
+/// For a unit with `Type=notify`, systemd considers the service started only once `READY=1`
+/// was sent, so units ordered `After=` it only start when readings are actually available.
+pub struct Notifier {
+    socket: UnixDatagram,
+}
+
+impl Notifier {
+    /// Connect to the socket given in `NOTIFY_SOCKET`.
+    ///
+    /// Returns `None` if the variable isn't set, i.e. the process wasn't started by systemd
+    /// as a `Type=notify` service.
+    pub fn from_env() -> io::Result<Option<Notifier>> {
+        match env::var("NOTIFY_SOCKET") {
+            Ok(path) => Notifier::with_socket_path(&path).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Connect to a notification socket, paths starting with `@` refer to the abstract namespace
+    pub fn with_socket_path(path: &str) -> io::Result<Notifier> {
+        let socket = UnixDatagram::unbound()?;
+
+        match path.strip_prefix('@') {
+            Some(name) => socket.connect_addr(&SocketAddr::from_abstract_name(name)?)?,
+            None => socket.connect(path)?,
+        }
+
+        Ok(Notifier { socket })
+    }
+
+    /// Send a raw, newline separated list of `KEY=value` assignments
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send(state.as_bytes())?;
+        Ok(())
+    }
+
+    /// Tell the service manager that startup has finished
+    pub fn ready(&self) -> io::Result<()> {
+        self.notify("READY=1")
+    }
+
+    /// Tell the service manager that the service is shutting down
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+
+    /// Set the free-form status shown by `systemctl status`
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("STATUS={}", status.replace('\n', " ")))
+    }
+
+    /// Reset the service's watchdog timer
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+}
+
+/// The watchdog timeout configured via `WatchdogSec=`, if it applies to this process
+pub fn watchdog_interval() -> Option<Duration> {
+    parse_watchdog_env(
+        env::var("WATCHDOG_USEC").ok().as_deref(),
+        env::var("WATCHDOG_PID").ok().as_deref(),
+        process::id(),
+    )
+}
+
+fn parse_watchdog_env(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    // WATCHDOG_PID is optional, but if set it has to match us and not e.g. a parent shell
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+
+    match usec?.parse::<u64>().ok()? {
+        0 => None,
+        usec => Some(Duration::from_micros(usec)),
+    }
+}
+
+/// Ties systemd's readiness and watchdog notifications to successful sampling.
+///
+/// Instead of pinging from a timer, which would keep a wedged process alive, call
+/// `sample_succeeded` whenever a reading came back from the firmware. The first call sends
+/// `READY=1`, later ones send `WATCHDOG=1` at most every half watchdog interval. If the
+/// firmware interface hangs, the pings stop and systemd restarts the service.
+///
+/// ```ini
+/// [Service]
+/// Type=notify
+/// WatchdogSec=30
+/// Restart=on-watchdog
+/// ```
+pub struct Watchdog {
+    notifier: Notifier,
+    interval: Option<Duration>,
+    last_ping: Option<Instant>,
+    ready: bool,
+}
+
+impl Watchdog {
+    /// Create a watchdog pinging at least every half `interval`, or only signalling
+    /// readiness if `interval` is `None`
+    pub fn new(notifier: Notifier, interval: Option<Duration>) -> Watchdog {
+        Watchdog {
+            notifier,
+            interval,
+            last_ping: None,
+            ready: false,
+        }
+    }
+
+    /// Set up from `NOTIFY_SOCKET` and `WATCHDOG_USEC`, returns `None` outside of systemd
+    pub fn from_env() -> io::Result<Option<Watchdog>> {
+        let notifier = Notifier::from_env()?;
+        Ok(notifier.map(|notifier| Watchdog::new(notifier, watchdog_interval())))
+    }
+
+    /// Report a successful sample to the service manager
+    pub fn sample_succeeded(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+
+        if !self.ready {
+            self.notifier.ready()?;
+            self.ready = true;
+            self.last_ping = Some(now);
+            return Ok(());
+        }
+
+        let due = match (self.interval, self.last_ping) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(interval), Some(last_ping)) => now.duration_since(last_ping) >= interval / 2,
+        };
+
+        if due {
+            self.notifier.watchdog()?;
+            self.last_ping = Some(now);
+        }
+
+        Ok(())
+    }
+
+    /// Access the underlying notifier, e.g. to update the status line
+    pub fn notifier(&self) -> &Notifier {
+        &self.notifier
+    }
+}
+
+fn throttled_priority(status: &ThrottledStatus) -> Priority {
+    if status.under_voltage || status.currently_throttled {
+        Priority::Warning
+    } else if status.arm_frequency_capped || status.soft_temp_limit_active {
+        Priority::Notice
+    } else {
+        Priority::Info
+    }
+}
+
+fn throttled_fields(bit_pattern: isize, status: &ThrottledStatus) -> Vec<(&'static str, String)> {
+    let flag = |active: bool| if active { "1" } else { "0" }.to_owned();
+
+    vec![
+        ("VCGENCMD_THROTTLED", format!("0x{:x}", bit_pattern)),
+        ("VCGENCMD_UNDER_VOLTAGE", flag(status.under_voltage)),
+        (
+            "VCGENCMD_ARM_FREQUENCY_CAPPED",
+            flag(status.arm_frequency_capped),
+        ),
+        (
+            "VCGENCMD_CURRENTLY_THROTTLED",
+            flag(status.currently_throttled),
+        ),
+        (
+            "VCGENCMD_SOFT_TEMP_LIMIT_ACTIVE",
+            flag(status.soft_temp_limit_active),
+        ),
+        (
+            "VCGENCMD_UNDER_VOLTAGE_OCCURRED",
+            flag(status.under_voltage_occurred),
+        ),
+        (
+            "VCGENCMD_ARM_FREQUENCY_CAP_OCCURRED",
+            flag(status.arm_frequency_cap_occurred),
+        ),
+        (
+            "VCGENCMD_THROTTLING_OCCURRED",
+            flag(status.throttling_occurred),
+        ),
+        (
+            "VCGENCMD_SOFT_TEMP_LIMIT_OCCURRED",
+            flag(status.soft_temp_limit_occurred),
+        ),
+    ]
+}
+
+fn is_valid_field_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('_')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+}
+
+/// Serialize a field using journald's native protocol.
+///
+/// Values containing a newline can't use the simple `KEY=value` form and are
+/// written as the key, a newline, the value's length as little-endian u64 and the raw value.
+fn append_field(datagram: &mut Vec<u8>, name: &str, value: &str) {
+    datagram.extend_from_slice(name.as_bytes());
+
+    if value.contains('\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+
+    datagram.extend_from_slice(value.as_bytes());
+    datagram.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_field() {
+        let mut datagram = Vec::new();
+        append_field(&mut datagram, "MESSAGE", "temperature 42.8'C");
+        assert_eq!(datagram, b"MESSAGE=temperature 42.8'C\n");
+
+        let mut datagram = Vec::new();
+        append_field(&mut datagram, "MESSAGE", "a\nb");
+        assert_eq!(datagram, b"MESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n");
+    }
+
+    #[test]
+    fn test_is_valid_field_name() {
+        assert!(is_valid_field_name("VCGENCMD_TEMP"));
+        assert!(!is_valid_field_name("_PID"));
+        assert!(!is_valid_field_name("temp"));
+        assert!(!is_valid_field_name(""));
+    }
+
+    #[test]
+    fn test_throttled_priority() {
+        assert_eq!(
+            Priority::Warning,
+            throttled_priority(&ThrottledStatus::new(0x50005))
+        );
+        assert_eq!(
+            Priority::Info,
+            throttled_priority(&ThrottledStatus::new(0x50000))
+        );
+        assert_eq!(
+            Priority::Notice,
+            throttled_priority(&ThrottledStatus::new(0x8))
+        );
+    }
+
+    #[test]
+    fn test_parse_watchdog_env() {
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            parse_watchdog_env(Some("30000000"), None, 42)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            parse_watchdog_env(Some("30000000"), Some("42"), 42)
+        );
+        assert_eq!(None, parse_watchdog_env(Some("30000000"), Some("1"), 42));
+        assert_eq!(None, parse_watchdog_env(Some("0"), None, 42));
+        assert_eq!(None, parse_watchdog_env(None, None, 42));
+    }
+
+    #[test]
+    fn test_watchdog_pings() {
+        let path = std::env::temp_dir().join(format!("vcgencmd-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+        server.set_nonblocking(true).unwrap();
+
+        let notifier = Notifier::with_socket_path(path.to_str().unwrap()).unwrap();
+        let mut watchdog = Watchdog::new(notifier, Some(Duration::from_secs(3600)));
+        watchdog.sample_succeeded().unwrap();
+        watchdog.sample_succeeded().unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(b"READY=1", &buf[..len]);
+        // the second sample came well within half the interval, so no ping was sent
+        assert!(server.recv(&mut buf).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_send_to_socket() {
+        let dir = std::env::temp_dir().join(format!("vcgencmd-journal-{}", std::process::id()));
+        let _ = std::fs::remove_file(&dir);
+        let server = UnixDatagram::bind(&dir).unwrap();
+
+        let journal = Journal::with_socket_path(dir.to_str().unwrap()).unwrap();
+        journal.log_temp(42.8).unwrap();
+
+        let mut buf = [0u8; 512];
+        let len = server.recv(&mut buf).unwrap();
+        let received = String::from_utf8_lossy(&buf[..len]).into_owned();
+        std::fs::remove_file(&dir).unwrap();
+
+        assert!(received.contains("PRIORITY=6\n"));
+        assert!(received.contains("SYSLOG_IDENTIFIER=vcgencmd\n"));
+        assert!(received.contains("VCGENCMD_TEMP=42.8\n"));
+    }
+}
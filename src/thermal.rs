@@ -0,0 +1,178 @@
+//! Temperature relative to the configured throttling limits
+//!
+//! The firmware starts reducing clocks at `temp_soft_limit` (60 °C unless configured
+//! otherwise) on some models and throttles hard at `temp_limit` (85 °C by default). As both
+//! can be changed in `config.txt`, alerting on the distance to the limit is more meaningful
+//! than alerting on an absolute temperature.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::units::Celsius;
+use crate::{get_config, measure_temp, ConfigSrc, Result, Src};
+
+/// Soft limit used by the firmware when `temp_soft_limit` isn't set
+pub const DEFAULT_SOFT_LIMIT: f64 = 60.0;
+/// Hard limit used by the firmware when `temp_limit` isn't set
+pub const DEFAULT_HARD_LIMIT: f64 = 85.0;
+
+/// The limits once read, see `TempLimits::cached`
+static LIMITS: OnceLock<TempLimits> = OnceLock::new();
+
+/// The throttling thresholds in °C
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct TempLimits {
+    pub soft: f64,
+    pub hard: f64,
+}
+
+impl Default for TempLimits {
+    fn default() -> TempLimits {
+        TempLimits {
+            soft: DEFAULT_SOFT_LIMIT,
+            hard: DEFAULT_HARD_LIMIT,
+        }
+    }
+}
+
+impl TempLimits {
+    /// Read both limits via `get_config`, falling back to the defaults for unset ones
+    pub fn query() -> Result<TempLimits> {
+        let soft = get_config(Src::Config(ConfigSrc::TempSoftLimit))?;
+        let hard = get_config(Src::Config(ConfigSrc::TempLimit))?;
+
+        Ok(TempLimits::from_config(soft, hard))
+    }
+
+    /// Like `query`, but only asks the firmware once per process, since the limits can't
+    /// change without a reboot
+    pub fn cached() -> Result<TempLimits> {
+        match TempLimits::cached_if_read() {
+            Some(limits) => Ok(limits),
+            None => Ok(TempLimits::cache(TempLimits::query()?)),
+        }
+    }
+
+    /// The cached limits, `None` if they weren't read yet
+    pub(crate) fn cached_if_read() -> Option<TempLimits> {
+        LIMITS.get().copied()
+    }
+
+    /// Cache `limits` read from the firmware, returns the ones cached first
+    pub(crate) fn cache(limits: TempLimits) -> TempLimits {
+        *LIMITS.get_or_init(|| limits)
+    }
+
+    /// Build limits from raw `get_config` values, where 0 means unset
+    pub fn from_config(soft: isize, hard: isize) -> TempLimits {
+        let or_default = |value: isize, default: f64| match value {
+            value if value > 0 => value as f64,
+            _ => default,
+        };
+
+        TempLimits {
+            soft: or_default(soft, DEFAULT_SOFT_LIMIT),
+            hard: or_default(hard, DEFAULT_HARD_LIMIT),
+        }
+    }
+
+    /// How far `temp` is from these limits
+    pub fn headroom(&self, temp: f64) -> TempHeadroom {
+        TempHeadroom {
+            temp,
+            soft_limit: self.soft,
+            hard_limit: self.hard,
+        }
+    }
+}
+
+/// A temperature together with the limits it is compared against
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct TempHeadroom {
+    pub temp: f64,
+    pub soft_limit: f64,
+    pub hard_limit: f64,
+}
+
+impl TempHeadroom {
+    /// Degrees left until the soft limit is reached, negative once it is exceeded
+    pub fn to_soft_limit(&self) -> f64 {
+        self.soft_limit - self.temp
+    }
+
+    /// Degrees left until the hard limit is reached, negative once it is exceeded
+    pub fn to_hard_limit(&self) -> f64 {
+        self.hard_limit - self.temp
+    }
+
+    /// The temperature as a percentage of the soft limit, 100 meaning the limit is reached
+    pub fn soft_limit_percent(&self) -> f64 {
+        self.temp / self.soft_limit * 100.0
+    }
+
+    /// The temperature as a percentage of the hard limit, 100 meaning the limit is reached
+    pub fn hard_limit_percent(&self) -> f64 {
+        self.temp / self.hard_limit * 100.0
+    }
+}
+
+/// e.g. `45.0 °C, 15.0 °C below the soft limit`
+impl fmt::Display for TempHeadroom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let headroom = self.to_soft_limit();
+        let relation = if headroom < 0.0 { "above" } else { "below" };
+        write!(
+            f,
+            "{}, {} {} the soft limit",
+            Celsius(self.temp),
+            Celsius(headroom.abs()),
+            relation
+        )
+    }
+}
+
+/// Measure the temperature and compare it with the configured limits
+pub fn measure_temp_headroom() -> Result<TempHeadroom> {
+    let limits = TempLimits::cached()?;
+    Ok(limits.headroom(measure_temp()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_config() {
+        assert_eq!(
+            TempLimits {
+                soft: 70.0,
+                hard: 80.0
+            },
+            TempLimits::from_config(70, 80)
+        );
+        assert_eq!(TempLimits::default(), TempLimits::from_config(0, 0));
+    }
+
+    #[test]
+    fn test_headroom() {
+        let headroom = TempLimits::default().headroom(45.0);
+        assert_eq!(15.0, headroom.to_soft_limit());
+        assert_eq!(40.0, headroom.to_hard_limit());
+        assert_eq!(75.0, headroom.soft_limit_percent());
+
+        let exceeded = TempLimits::default().headroom(66.0);
+        assert_eq!(-6.0, exceeded.to_soft_limit());
+        assert!((exceeded.soft_limit_percent() - 110.0).abs() < 1e-9);
+
+        assert_eq!(
+            "45.0 °C, 15.0 °C below the soft limit",
+            headroom.to_string()
+        );
+        assert_eq!("66.0 °C, 6.0 °C above the soft limit", exceeded.to_string());
+    }
+}
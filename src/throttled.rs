@@ -0,0 +1,305 @@
+//! What every bit of `get_throttled` means
+//!
+//! `explain` turns a bit pattern like `0x50005` into a list of the flags it contains, split
+//! into the conditions active right now and the ones that only occurred since boot.
+
+use std::fmt;
+
+use crate::alert::Severity;
+use crate::events::Condition;
+use crate::health::{HealthPolicy, HealthReason};
+use crate::json;
+
+/// A single bit of the `get_throttled` bit pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThrottledFlag {
+    pub bit: u32,
+    /// Name of the matching `ThrottledStatus` field
+    pub name: &'static str,
+    pub meaning: &'static str,
+    pub condition: Condition,
+    /// Whether the bit is sticky, i.e. says the condition occurred at some point since boot
+    pub historic: bool,
+}
+
+impl ThrottledFlag {
+    pub fn is_set(&self, bit_pattern: isize) -> bool {
+        bit_pattern & (1 << self.bit) != 0
+    }
+
+    /// How bad it is that this flag is set, consistent with `HealthSummary`
+    pub fn severity(&self) -> Severity {
+        let reason = if self.historic {
+            HealthReason::Occurred(self.condition)
+        } else {
+            HealthReason::Active(self.condition)
+        };
+
+        reason.severity(&HealthPolicy::default())
+    }
+}
+
+/// All documented flags, in the order of their bits
+pub const FLAGS: [ThrottledFlag; 8] = [
+    ThrottledFlag {
+        bit: 0,
+        name: "under_voltage",
+        meaning: "Under-voltage detected",
+        condition: Condition::UnderVoltage,
+        historic: false,
+    },
+    ThrottledFlag {
+        bit: 1,
+        name: "arm_frequency_capped",
+        meaning: "Arm frequency capped",
+        condition: Condition::ArmFrequencyCapped,
+        historic: false,
+    },
+    ThrottledFlag {
+        bit: 2,
+        name: "currently_throttled",
+        meaning: "Currently throttled",
+        condition: Condition::Throttled,
+        historic: false,
+    },
+    ThrottledFlag {
+        bit: 3,
+        name: "soft_temp_limit_active",
+        meaning: "Soft temperature limit active",
+        condition: Condition::SoftTempLimit,
+        historic: false,
+    },
+    ThrottledFlag {
+        bit: 16,
+        name: "under_voltage_occurred",
+        meaning: "Under-voltage has occurred",
+        condition: Condition::UnderVoltage,
+        historic: true,
+    },
+    ThrottledFlag {
+        bit: 17,
+        name: "arm_frequency_cap_occurred",
+        meaning: "Arm frequency capping has occurred",
+        condition: Condition::ArmFrequencyCapped,
+        historic: true,
+    },
+    ThrottledFlag {
+        bit: 18,
+        name: "throttling_occurred",
+        meaning: "Throttling has occurred",
+        condition: Condition::Throttled,
+        historic: true,
+    },
+    ThrottledFlag {
+        bit: 19,
+        name: "soft_temp_limit_occurred",
+        meaning: "Soft temperature limit has occurred",
+        condition: Condition::SoftTempLimit,
+        historic: true,
+    },
+];
+
+/// A bit pattern broken down into its flags
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub bit_pattern: isize,
+    /// Set flags describing the current state
+    pub active: Vec<ThrottledFlag>,
+    /// Set sticky flags, cleared only by a reboot
+    pub historic: Vec<ThrottledFlag>,
+    /// Set bits without a documented meaning
+    pub unknown_bits: Vec<u32>,
+}
+
+impl Explanation {
+    /// The worst severity of all set flags, `None` if no flag is set
+    pub fn severity(&self) -> Option<Severity> {
+        self.active
+            .iter()
+            .chain(&self.historic)
+            .map(ThrottledFlag::severity)
+            .max()
+    }
+
+    pub fn to_json(&self) -> String {
+        let flags = |flags: &[ThrottledFlag]| {
+            json::array(flags.iter().map(|flag| {
+                json::object(&[
+                    ("bit", flag.bit.to_string()),
+                    ("name", json::string(flag.name)),
+                    ("meaning", json::string(flag.meaning)),
+                    ("severity", json::string(&flag.severity().to_string())),
+                ])
+            }))
+        };
+
+        json::object(&[
+            ("bit_pattern", self.bit_pattern.to_string()),
+            ("hex", json::string(&format!("0x{:x}", self.bit_pattern))),
+            ("active", flags(&self.active)),
+            ("historic", flags(&self.historic)),
+            (
+                "unknown_bits",
+                json::array(self.unknown_bits.iter().map(u32::to_string)),
+            ),
+        ])
+    }
+}
+
+/// Serialized with its severity, like in `Explanation::to_json`
+#[cfg(feature = "serde")]
+impl serde::Serialize for ThrottledFlag {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut flag = serializer.serialize_struct("ThrottledFlag", 4)?;
+        flag.serialize_field("bit", &self.bit)?;
+        flag.serialize_field("name", self.name)?;
+        flag.serialize_field("meaning", self.meaning)?;
+        flag.serialize_field("severity", &self.severity().to_string())?;
+        flag.end()
+    }
+}
+
+/// Serialized like `Explanation::to_json`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Explanation {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut explanation = serializer.serialize_struct("Explanation", 5)?;
+        explanation.serialize_field("bit_pattern", &self.bit_pattern)?;
+        explanation.serialize_field("hex", &format!("0x{:x}", self.bit_pattern))?;
+        explanation.serialize_field("active", &self.active)?;
+        explanation.serialize_field("historic", &self.historic)?;
+        explanation.serialize_field("unknown_bits", &self.unknown_bits)?;
+        explanation.end()
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:x}", self.bit_pattern)?;
+
+        if self.active.is_empty() && self.historic.is_empty() && self.unknown_bits.is_empty() {
+            return write!(f, ": no flags set");
+        }
+
+        let groups = [
+            ("active now", &self.active),
+            ("occurred since boot", &self.historic),
+        ];
+        for (title, flags) in &groups {
+            if flags.is_empty() {
+                continue;
+            }
+
+            write!(f, "\n{}:", title)?;
+            for flag in flags.iter() {
+                write!(
+                    f,
+                    "\n  bit {:<2}  {:<8}  {}",
+                    flag.bit,
+                    flag.severity(),
+                    flag.meaning
+                )?;
+            }
+        }
+
+        if !self.unknown_bits.is_empty() {
+            write!(f, "\nundocumented:")?;
+            for bit in &self.unknown_bits {
+                write!(f, "\n  bit {}", bit)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Break `bit_pattern` down into its flags
+///
+/// # Examples
+///
+/// ```rust
+/// use vcgencmd::throttled::explain;
+///
+/// let explanation = explain(0x50005);
+/// assert_eq!(2, explanation.active.len());
+/// println!("{}", explanation);
+/// ```
+pub fn explain(bit_pattern: isize) -> Explanation {
+    let set = |historic: bool| {
+        FLAGS
+            .iter()
+            .filter(|flag| flag.historic == historic && flag.is_set(bit_pattern))
+            .copied()
+            .collect()
+    };
+
+    let unknown_bits = (0..isize::BITS)
+        .filter(|&bit| bit_pattern & (1 << bit) != 0)
+        .filter(|&bit| FLAGS.iter().all(|flag| flag.bit != bit))
+        .collect();
+
+    Explanation {
+        bit_pattern,
+        active: set(false),
+        historic: set(true),
+        unknown_bits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpret_bit_pattern;
+
+    #[test]
+    fn test_flags_match_interpretation() {
+        for flag in &FLAGS {
+            let status = interpret_bit_pattern(1 << flag.bit);
+            let set = if flag.historic {
+                flag.condition.has_occurred(&status)
+            } else {
+                flag.condition.is_active(&status)
+            };
+            assert!(set, "bit {} isn't decoded as {}", flag.bit, flag.name);
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let explanation = explain(0x50005);
+        let names = |flags: &[ThrottledFlag]| flags.iter().map(|f| f.name).collect::<Vec<_>>();
+
+        assert_eq!(
+            vec!["under_voltage", "currently_throttled"],
+            names(&explanation.active)
+        );
+        assert_eq!(
+            vec!["under_voltage_occurred", "throttling_occurred"],
+            names(&explanation.historic)
+        );
+        assert_eq!(Some(Severity::Critical), explanation.severity());
+
+        let text = explanation.to_string();
+        assert!(
+            text.starts_with("0x50005\nactive now:\n  bit 0   critical  Under-voltage detected")
+        );
+        assert!(
+            text.contains("occurred since boot:\n  bit 16  warning   Under-voltage has occurred")
+        );
+    }
+
+    #[test]
+    fn test_explain_edge_cases() {
+        assert_eq!("0x0: no flags set", explain(0).to_string());
+        assert_eq!(None, explain(0).severity());
+
+        let explanation = explain(0x20);
+        assert_eq!(vec![5], explanation.unknown_bits);
+        assert!(explanation.to_string().contains("undocumented:\n  bit 5"));
+        assert!(explanation.to_json().ends_with(r#""unknown_bits":[5]}"#));
+    }
+}
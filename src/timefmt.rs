@@ -0,0 +1,148 @@
+//! Formatting and parsing wall-clock time without pulling in a date/time crate
+
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Format a point in time as an RFC 3339 UTC timestamp with millisecond precision,
+/// e.g. `2019-08-25T14:03:09.120Z`
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Milliseconds since the unix epoch, saturating at zero for earlier times
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+pub(crate) fn from_unix_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+/// Parse an RFC 3339 timestamp as written by `rfc3339`, also accepting other fractions
+/// of a second and offsets from UTC, e.g. `2019-08-25T16:03:09+02:00`
+pub(crate) fn parse_rfc3339(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(at) => (time.get(..at)?, time.get(at..)?),
+        None => return None,
+    };
+    let offset_secs = match offset {
+        "Z" | "z" => 0,
+        offset => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = offset.get(1..)?.split_once(':')?;
+            sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60)
+        }
+    };
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hours, minutes, secs) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    let nanos = match fraction {
+        "" => 0,
+        fraction if fraction.bytes().all(|b| b.is_ascii_digit()) => {
+            let digits = fraction.get(..fraction.len().min(9))?;
+            digits.parse::<u32>().ok()? * 10u32.pow(9 - digits.len() as u32)
+        }
+        _ => return None,
+    };
+
+    let secs = days_from_civil(year, month as u32, day as u32) * 86_400
+        + hours * 3600
+        + minutes * 60
+        + secs
+        - offset_secs;
+    let secs = u64::try_from(secs).ok()?;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// Convert a date in the proleptic Gregorian calendar to days since 1970-01-01, the
+/// inverse of `civil_from_days`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date in the proleptic
+/// Gregorian calendar, following Howard Hinnant's `civil_from_days`
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!("1970-01-01T00:00:00.000Z", rfc3339(UNIX_EPOCH));
+        assert_eq!(
+            "2019-08-25T14:03:09.120Z",
+            rfc3339(from_unix_millis(1_566_741_789_120))
+        );
+        assert_eq!(
+            "2024-02-29T23:59:59.999Z",
+            rfc3339(from_unix_millis(1_709_251_199_999))
+        );
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        for millis in [0, 1_566_741_789_120, 1_709_251_199_999] {
+            let time = from_unix_millis(millis);
+            assert_eq!(Some(time), parse_rfc3339(&rfc3339(time)));
+        }
+        assert_eq!(
+            Some(from_unix_millis(1_566_741_789_000)),
+            parse_rfc3339("2019-08-25T16:03:09+02:00")
+        );
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::new(1, 5)),
+            parse_rfc3339("1970-01-01T00:00:01.000000005Z")
+        );
+
+        assert_eq!(None, parse_rfc3339("2019-08-25"));
+        assert_eq!(None, parse_rfc3339("2019-13-25T14:03:09Z"));
+        assert_eq!(None, parse_rfc3339("2019-08-25T14:03:09"));
+    }
+}
@@ -0,0 +1,419 @@
+//! Diagnosing the cause of under-voltage: the power supply or the workload?
+//!
+//! An `UnderVoltageProbe` samples core voltage, ARM clock and throttled state at a high
+//! rate for a short burst, ideally started as soon as under-voltage is detected, e.g. from
+//! an `events::EventLog` or `is_undervolted`. The `UnderVoltageReport` then relates the
+//! under-voltage flag to the load, using the ARM clock as its proxy: with a dynamic cpufreq
+//! governor the clock rises with the load.
+//!
+//! Under-voltage that comes and goes with the load means the supply can't keep up with
+//! peaks, which a better PSU fixes as well as spreading out the load does. Under-voltage
+//! regardless of the load points at the supply or its cable.
+//!
+//! ```rust,no_run
+//! use vcgencmd::undervolt::UnderVoltageProbe;
+//!
+//! if vcgencmd::is_undervolted()? {
+//!     let report = UnderVoltageProbe::new().run();
+//!     println!("{}", report);
+//! }
+//! # Ok::<(), vcgencmd::Error>(())
+//! ```
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::json;
+use crate::monitor::{Metric, Monitor, Reading};
+use crate::profile::sample_for;
+use crate::timefmt::rfc3339;
+use crate::{interpret_bit_pattern, ClockSrc, Result, VoltSrc};
+
+const DEFAULT_DURATION: Duration = Duration::from_secs(5);
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(50);
+/// Points with both a clock and a throttled reading needed for a verdict
+const MIN_POINTS: usize = 10;
+/// Share of under-voltage points above which the load can't explain it
+const PERSISTENT_FRACTION: f64 = 0.9;
+/// Correlation between clock and under-voltage above which the load is blamed
+const LOAD_CORRELATION: f64 = 0.5;
+
+/// The readings taken at one point of a burst
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnderVoltagePoint {
+    /// Time since the start of the burst
+    pub elapsed: Duration,
+    /// Core voltage in V
+    pub core_volts: Option<f64>,
+    /// ARM clock in Hz
+    pub arm_clock: Option<isize>,
+    /// Bit pattern as returned by `get_throttled`
+    pub throttled: Option<isize>,
+}
+
+impl UnderVoltagePoint {
+    /// Whether the supply voltage was too low at this point, `None` if it wasn't read
+    pub fn is_undervolted(&self) -> Option<bool> {
+        self.throttled
+            .map(|bit_pattern| interpret_bit_pattern(bit_pattern).under_voltage)
+    }
+}
+
+/// What the under-voltage most likely comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    /// There was no under-voltage during the burst
+    NoUnderVoltage,
+    /// Under-voltage regardless of the load, i.e. a weak power supply or a bad cable
+    PowerSupply,
+    /// Under-voltage during load spikes, the supply can't keep up with the peaks
+    LoadSpikes,
+    /// Too few readings, or a clock that doesn't follow the load, e.g. with the
+    /// `performance` governor
+    Inconclusive,
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Cause::NoUnderVoltage => "no under-voltage",
+            Cause::PowerSupply => "power supply",
+            Cause::LoadSpikes => "load spikes",
+            Cause::Inconclusive => "inconclusive",
+        })
+    }
+}
+
+/// Samples a short burst for an `UnderVoltageReport`, see the module documentation
+pub struct UnderVoltageProbe {
+    duration: Duration,
+    interval: Duration,
+    monitor: Monitor,
+}
+
+impl Default for UnderVoltageProbe {
+    fn default() -> UnderVoltageProbe {
+        UnderVoltageProbe::new()
+    }
+}
+
+impl UnderVoltageProbe {
+    /// Sample for 5 s, every 50 ms
+    pub fn new() -> UnderVoltageProbe {
+        let monitor = Monitor::new(DEFAULT_INTERVAL)
+            .metric(Metric::Volts(VoltSrc::Core))
+            .metric(Metric::Clock(ClockSrc::Arm))
+            .metric(Metric::Throttled);
+
+        UnderVoltageProbe {
+            duration: DEFAULT_DURATION,
+            interval: DEFAULT_INTERVAL,
+            monitor,
+        }
+    }
+
+    pub fn duration(mut self, duration: Duration) -> UnderVoltageProbe {
+        self.duration = duration;
+        self
+    }
+
+    /// Time between samples, each sample invokes vcgencmd three times
+    pub fn interval(mut self, interval: Duration) -> UnderVoltageProbe {
+        self.interval = interval;
+        self.monitor.set_interval(interval);
+        self
+    }
+
+    /// Replace the function used to read a metric, see `Monitor::sampler`
+    pub fn sampler<F>(mut self, sampler: F) -> UnderVoltageProbe
+    where
+        F: FnMut(Metric) -> Result<Reading> + Send + 'static,
+    {
+        self.monitor = self.monitor.sampler(sampler);
+        self
+    }
+
+    /// Sample the burst, blocking for its duration
+    pub fn run(mut self) -> UnderVoltageReport {
+        let started_at = SystemTime::now();
+        let mut points = Vec::new();
+        sample_for(
+            &mut self.monitor,
+            self.duration,
+            self.interval,
+            |elapsed, sample| {
+                points.push(UnderVoltagePoint {
+                    elapsed,
+                    core_volts: match sample.get(Metric::Volts(VoltSrc::Core)) {
+                        Some(&Reading::Volts(_, volts)) => Some(volts),
+                        _ => None,
+                    },
+                    arm_clock: match sample.get(Metric::Clock(ClockSrc::Arm)) {
+                        Some(&Reading::Clock(_, frequency)) => Some(frequency),
+                        _ => None,
+                    },
+                    throttled: match sample.get(Metric::Throttled) {
+                        Some(&Reading::Throttled(bit_pattern)) => Some(bit_pattern),
+                        _ => None,
+                    },
+                })
+            },
+        );
+
+        UnderVoltageReport { started_at, points }
+    }
+}
+
+/// The readings of a burst and the verdict derived from them
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnderVoltageReport {
+    pub started_at: SystemTime,
+    pub points: Vec<UnderVoltagePoint>,
+}
+
+impl UnderVoltageReport {
+    /// Share of the points with under-voltage, among those the flags were read for
+    pub fn undervolted_fraction(&self) -> f64 {
+        let flags: Vec<_> = self
+            .points
+            .iter()
+            .filter_map(UnderVoltagePoint::is_undervolted)
+            .collect();
+        if flags.is_empty() {
+            return 0.0;
+        }
+
+        flags.iter().filter(|&&flag| flag).count() as f64 / flags.len() as f64
+    }
+
+    /// Pearson correlation between the ARM clock and the under-voltage flag, `None` if
+    /// either of them didn't change during the burst
+    pub fn load_correlation(&self) -> Option<f64> {
+        let pairs: Vec<(f64, f64)> = self
+            .points
+            .iter()
+            .filter_map(|point| {
+                let flag = if point.is_undervolted()? { 1.0 } else { 0.0 };
+                Some((point.arm_clock? as f64, flag))
+            })
+            .collect();
+
+        correlation(&pairs)
+    }
+
+    /// Lowest core voltage seen during the burst
+    pub fn min_core_volts(&self) -> Option<f64> {
+        self.points
+            .iter()
+            .filter_map(|point| point.core_volts)
+            .fold(None, |min: Option<f64>, volts| match min {
+                Some(min) => Some(min.min(volts)),
+                None => Some(volts),
+            })
+    }
+
+    /// The most likely cause of the under-voltage
+    pub fn cause(&self) -> Cause {
+        let fraction = self.undervolted_fraction();
+        if fraction == 0.0 {
+            return Cause::NoUnderVoltage;
+        }
+        if fraction >= PERSISTENT_FRACTION {
+            return Cause::PowerSupply;
+        }
+
+        let points = self
+            .points
+            .iter()
+            .filter(|point| point.arm_clock.is_some() && point.throttled.is_some())
+            .count();
+        match self.load_correlation() {
+            _ if points < MIN_POINTS => Cause::Inconclusive,
+            Some(correlation) if correlation >= LOAD_CORRELATION => Cause::LoadSpikes,
+            Some(_) => Cause::PowerSupply,
+            None => Cause::Inconclusive,
+        }
+    }
+
+    /// The report including all points as a JSON object
+    pub fn to_json(&self) -> String {
+        let points = self.points.iter().map(|point| {
+            json::object(&[
+                ("elapsed", json::number(point.elapsed.as_secs_f64())),
+                ("core_volts", json::optional(point.core_volts, json::number)),
+                (
+                    "arm_clock",
+                    json::optional(point.arm_clock, |f| json::number(f as f64)),
+                ),
+                (
+                    "throttled",
+                    json::optional(point.throttled, |b| json::number(b as f64)),
+                ),
+            ])
+        });
+
+        json::object(&[
+            ("started_at", json::string(&rfc3339(self.started_at))),
+            ("cause", json::string(&self.cause().to_string())),
+            (
+                "undervolted_fraction",
+                json::number(self.undervolted_fraction()),
+            ),
+            (
+                "load_correlation",
+                json::optional(self.load_correlation(), json::number),
+            ),
+            (
+                "min_core_volts",
+                json::optional(self.min_core_volts(), json::number),
+            ),
+            ("points", json::array(points)),
+        ])
+    }
+}
+
+/// A human readable summary of the burst
+impl fmt::Display for UnderVoltageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "cause:            {}", self.cause())?;
+        writeln!(
+            f,
+            "under-voltage:    {:.0} % of the time",
+            self.undervolted_fraction() * 100.0
+        )?;
+        match self.load_correlation() {
+            Some(correlation) => writeln!(f, "load correlation: {:.2}", correlation)?,
+            None => writeln!(f, "load correlation: n/a")?,
+        }
+        match self.min_core_volts() {
+            Some(volts) => write!(f, "min core voltage: {:.3} V", volts),
+            None => write!(f, "min core voltage: n/a"),
+        }
+    }
+}
+
+/// Pearson correlation coefficient of `pairs`, `None` if either side is constant
+fn correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|&(_, y)| y).sum::<f64>() / n;
+
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for &(x, y) in pairs {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x) * (x - mean_x);
+        variance_y += (y - mean_y) * (y - mean_y);
+    }
+    if variance_x <= f64::EPSILON || variance_y <= f64::EPSILON {
+        return None;
+    }
+
+    Some(covariance / (variance_x * variance_y).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    const MHZ: isize = 1_000_000;
+
+    fn report(points: &[(isize, isize)]) -> UnderVoltageReport {
+        let points = points
+            .iter()
+            .enumerate()
+            .map(|(index, &(arm_clock, throttled))| UnderVoltagePoint {
+                elapsed: Duration::from_millis(50 * index as u64),
+                core_volts: Some(if throttled & 1 == 1 { 0.80 } else { 0.85 }),
+                arm_clock: Some(arm_clock),
+                throttled: Some(throttled),
+            })
+            .collect();
+
+        UnderVoltageReport {
+            started_at: UNIX_EPOCH,
+            points,
+        }
+    }
+
+    #[test]
+    fn test_load_spikes() {
+        let points: Vec<_> = (0..20)
+            .map(|index| match index % 4 {
+                0 => (1800 * MHZ, 0x50005),
+                _ => (600 * MHZ, 0x50000),
+            })
+            .collect();
+        let report = report(&points);
+
+        assert_eq!(Cause::LoadSpikes, report.cause());
+        assert!((report.undervolted_fraction() - 0.25).abs() < 1e-9);
+        assert!(report.load_correlation().unwrap() > 0.99);
+        assert_eq!(Some(0.80), report.min_core_volts());
+        assert!(report.to_string().contains("load spikes"));
+    }
+
+    #[test]
+    fn test_power_supply() {
+        // under-voltage while idle as often as under load
+        let points: Vec<_> = (0..20)
+            .map(|index| match index % 4 {
+                0 => (1800 * MHZ, 0x50005),
+                1 => (1800 * MHZ, 0x50000),
+                2 => (600 * MHZ, 0x50005),
+                _ => (600 * MHZ, 0x50000),
+            })
+            .collect();
+        assert_eq!(Cause::PowerSupply, report(&points).cause());
+
+        let persistent = vec![(1800 * MHZ, 0x50005); 20];
+        assert_eq!(Cause::PowerSupply, report(&persistent).cause());
+    }
+
+    #[test]
+    fn test_no_or_too_little_data() {
+        assert_eq!(
+            Cause::NoUnderVoltage,
+            report(&[(600 * MHZ, 0x50000); 20]).cause()
+        );
+        assert_eq!(
+            Cause::Inconclusive,
+            report(&[(1800 * MHZ, 0x50005), (600 * MHZ, 0)]).cause()
+        );
+
+        // a fixed clock says nothing about the load
+        let fixed: Vec<_> = (0..20)
+            .map(|index| (1500 * MHZ, if index % 2 == 0 { 0x1 } else { 0x0 }))
+            .collect();
+        assert_eq!(Cause::Inconclusive, report(&fixed).cause());
+    }
+
+    #[test]
+    fn test_report_json() {
+        let json = report(&[(600 * MHZ, 0x0)]).to_json();
+        assert!(json.starts_with(
+            r#"{"started_at":"1970-01-01T00:00:00.000Z","cause":"no under-voltage","#
+        ));
+        assert!(json.contains(r#""load_correlation":null,"#));
+        assert!(
+            json.contains(r#"{"elapsed":0,"core_volts":0.85,"arm_clock":600000000,"throttled":0}"#)
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let report = UnderVoltageProbe::new()
+            .duration(Duration::from_millis(50))
+            .interval(Duration::from_millis(10))
+            .sampler(|metric| match metric {
+                Metric::Volts(src) => Ok(Reading::Volts(src, 0.85)),
+                Metric::Clock(src) => Ok(Reading::Clock(src, 1500 * MHZ)),
+                _ => Ok(Reading::Throttled(0x1)),
+            })
+            .run();
+
+        assert!(report.points.len() >= 5);
+        assert_eq!(1.0, report.undervolted_fraction());
+        assert_eq!(Cause::PowerSupply, report.cause());
+    }
+}
@@ -0,0 +1,76 @@
+//! Readings wrapped with their unit, for compact human readable output
+//!
+//! `Display` scales them to a fitting unit, e.g. `Hz(1_500_000_000)` as `1.50 GHz` and
+//! `MiB(448)` as `448 MiB`. The output doesn't depend on the locale, and width and alignment
+//! are honoured, so `{:>9}` lines up columns in a status bar or TUI.
+
+use std::fmt;
+
+/// A frequency in Hz, as returned by `measure_clock`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Hz(pub isize);
+
+impl fmt::Display for Hz {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hz = self.0 as f64;
+        let text = match self.0.abs() {
+            1_000_000_000.. => format!("{:.2} GHz", hz / 1e9),
+            1_000_000.. => format!("{:.0} MHz", hz / 1e6),
+            1_000.. => format!("{:.0} kHz", hz / 1e3),
+            _ => format!("{} Hz", self.0),
+        };
+        f.pad(&text)
+    }
+}
+
+/// A temperature in °C, as returned by `measure_temp`
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Celsius(pub f64);
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&format!("{:.1} °C", self.0))
+    }
+}
+
+/// A voltage in V, as returned by `measure_volts`
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Volts(pub f64);
+
+impl fmt::Display for Volts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(&format!("{:.3} V", self.0))
+    }
+}
+
+/// An amount of memory in MiB, as returned by `get_mem`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MiB(pub isize);
+
+impl fmt::Display for MiB {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self.0.abs() {
+            1024.. => format!("{:.1} GiB", self.0 as f64 / 1024.0),
+            _ => format!("{} MiB", self.0),
+        };
+        f.pad(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!("1.50 GHz", Hz(1_500_000_000).to_string());
+        assert_eq!("600 MHz", Hz(600_169_000).to_string());
+        assert_eq!("32 kHz", Hz(32_000).to_string());
+        assert_eq!("0 Hz", Hz(0).to_string());
+        assert_eq!("61.2 °C", Celsius(61.23).to_string());
+        assert_eq!("0.850 V", Volts(0.85).to_string());
+        assert_eq!("448 MiB", MiB(448).to_string());
+        assert_eq!("7.8 GiB", MiB(7_988).to_string());
+        assert_eq!("  1.50 GHz", format!("{:>10}", Hz(1_500_000_000)));
+    }
+}
@@ -0,0 +1,222 @@
+//! Cross-checking vcgencmd readings against the kernel's view in sysfs
+//!
+//! The firmware and the kernel measure temperature and ARM clock independently. They should
+//! agree within a small margin; when they don't, one of them is misconfigured or buggy and
+//! any alerting based on either is suspect.
+//!
+//! Both sides are read one after another, not at the same instant, so the tolerances have to
+//! allow for the quantities changing in between.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{measure_clock, measure_temp, ClockSrc, Error, Src};
+
+const THERMAL_ZONE_TEMP: &str = "/sys/class/thermal/thermal_zone0/temp";
+const CPUFREQ_CUR_FREQ: &str = "/sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq";
+
+/// A quantity that can be read from both vcgencmd and sysfs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantity {
+    /// SoC temperature in °C, from `thermal_zone0`
+    Temp,
+    /// ARM clock in Hz, from cpufreq of `cpu0`
+    ArmClock,
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantity::Temp => f.write_str("temperature"),
+            Quantity::ArmClock => f.write_str("arm clock"),
+        }
+    }
+}
+
+/// How far the two sources may disagree before it counts as a discrepancy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Absolute difference in °C, 2 °C by default
+    pub temp: f64,
+    /// Difference relative to the vcgencmd reading, 5 % by default
+    pub clock_ratio: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance {
+            temp: 2.0,
+            clock_ratio: 0.05,
+        }
+    }
+}
+
+/// Both readings of one quantity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub quantity: Quantity,
+    pub vcgencmd: f64,
+    pub sysfs: f64,
+    /// The largest absolute difference that is still accepted
+    pub allowed: f64,
+}
+
+impl Comparison {
+    /// Compare two readings of `quantity` using `tolerance`
+    pub fn new(quantity: Quantity, vcgencmd: f64, sysfs: f64, tolerance: Tolerance) -> Comparison {
+        let allowed = match quantity {
+            Quantity::Temp => tolerance.temp,
+            Quantity::ArmClock => vcgencmd.abs() * tolerance.clock_ratio,
+        };
+
+        Comparison {
+            quantity,
+            vcgencmd,
+            sysfs,
+            allowed,
+        }
+    }
+
+    /// Absolute difference between the two readings
+    pub fn difference(&self) -> f64 {
+        (self.vcgencmd - self.sysfs).abs()
+    }
+
+    pub fn is_discrepancy(&self) -> bool {
+        self.difference() > self.allowed
+    }
+}
+
+/// Why one side of a comparison couldn't be read
+#[derive(Debug)]
+pub enum VerifyError {
+    Vcgencmd(Error),
+    Sysfs(io::Error),
+}
+
+/// Result of a `verify` run
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub comparisons: Vec<Comparison>,
+    /// Quantities that couldn't be compared, e.g. because cpufreq isn't enabled
+    pub errors: Vec<(Quantity, VerifyError)>,
+}
+
+impl VerifyReport {
+    /// The comparisons in which both sources disagree beyond the tolerance
+    pub fn discrepancies(&self) -> impl Iterator<Item = &Comparison> {
+        self.comparisons.iter().filter(|c| c.is_discrepancy())
+    }
+
+    /// True if every quantity could be read and none of them disagree
+    pub fn is_consistent(&self) -> bool {
+        self.errors.is_empty() && self.discrepancies().next().is_none()
+    }
+}
+
+/// Compare temperature and ARM clock of vcgencmd and sysfs using the default tolerance
+pub fn verify() -> VerifyReport {
+    verify_with(Tolerance::default())
+}
+
+/// Compare temperature and ARM clock of vcgencmd and sysfs
+pub fn verify_with(tolerance: Tolerance) -> VerifyReport {
+    let mut report = VerifyReport::default();
+
+    for &quantity in &[Quantity::Temp, Quantity::ArmClock] {
+        match read_both(quantity) {
+            Ok((vcgencmd, sysfs)) => {
+                report
+                    .comparisons
+                    .push(Comparison::new(quantity, vcgencmd, sysfs, tolerance));
+            }
+            Err(error) => report.errors.push((quantity, error)),
+        }
+    }
+
+    report
+}
+
+fn read_both(quantity: Quantity) -> Result<(f64, f64), VerifyError> {
+    match quantity {
+        Quantity::Temp => {
+            let vcgencmd = measure_temp().map_err(VerifyError::Vcgencmd)?;
+            let sysfs = read_sysfs_temp(THERMAL_ZONE_TEMP).map_err(VerifyError::Sysfs)?;
+            Ok((vcgencmd, sysfs))
+        }
+        Quantity::ArmClock => {
+            let vcgencmd =
+                measure_clock(Src::Clock(ClockSrc::Arm)).map_err(VerifyError::Vcgencmd)?;
+            let sysfs = read_sysfs_freq(CPUFREQ_CUR_FREQ).map_err(VerifyError::Sysfs)?;
+            Ok((vcgencmd as f64, sysfs))
+        }
+    }
+}
+
+/// Thermal zones report millidegrees Celsius
+fn read_sysfs_temp<P: AsRef<Path>>(path: P) -> io::Result<f64> {
+    read_sysfs_number(path).map(|millidegrees| millidegrees / 1000.0)
+}
+
+/// cpufreq reports kHz
+fn read_sysfs_freq<P: AsRef<Path>>(path: P) -> io::Result<f64> {
+    read_sysfs_number(path).map(|khz| khz * 1000.0)
+}
+
+fn read_sysfs_number<P: AsRef<Path>>(path: P) -> io::Result<f64> {
+    fs::read_to_string(path)?
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparison() {
+        let tolerance = Tolerance::default();
+
+        let temp = Comparison::new(Quantity::Temp, 48.3, 47.2, tolerance);
+        assert!(!temp.is_discrepancy());
+        let temp = Comparison::new(Quantity::Temp, 48.3, 55.0, tolerance);
+        assert!(temp.is_discrepancy());
+
+        let clock = Comparison::new(
+            Quantity::ArmClock,
+            1_500_000_000.0,
+            1_450_000_000.0,
+            tolerance,
+        );
+        assert!(!clock.is_discrepancy());
+        let clock = Comparison::new(
+            Quantity::ArmClock,
+            1_500_000_000.0,
+            600_000_000.0,
+            tolerance,
+        );
+        assert!(clock.is_discrepancy());
+    }
+
+    #[test]
+    fn test_read_sysfs() {
+        let dir = std::env::temp_dir().join(format!("vcgencmd-verify-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("temp"), "47236\n").unwrap();
+        fs::write(dir.join("freq"), "1500000\n").unwrap();
+        fs::write(dir.join("garbage"), "n/a\n").unwrap();
+
+        assert!((read_sysfs_temp(dir.join("temp")).unwrap() - 47.236).abs() < 1e-9);
+        assert_eq!(1_500_000_000.0, read_sysfs_freq(dir.join("freq")).unwrap());
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            read_sysfs_temp(dir.join("garbage")).unwrap_err().kind()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+//! Sending readings to a Zabbix server or proxy with the sender (trapper) protocol
+//!
+//! Every reading is sent as the value of an item of the host the sender is configured
+//! with, the hostname by default. The items have to exist in Zabbix with the type "Zabbix
+//! trapper", values of unknown items are rejected by the server. Their keys are
+//! `vcgencmd.` followed by `Metric::name`, e.g. `vcgencmd.temp` or `vcgencmd.clock.arm`,
+//! unless mapped to the keys of an existing template:
+//!
+//! ```no_run
+//! use vcgencmd::monitor::Metric;
+//! use vcgencmd::zabbix::ZabbixSender;
+//! use vcgencmd::ClockSrc;
+//!
+//! let sender = ZabbixSender::new("zabbix.example.com")
+//!     .host("line-3-pi")
+//!     .key(Metric::Temp, "rpi.temperature")
+//!     .key(Metric::Clock(ClockSrc::Arm), "rpi.clock[arm]");
+//! ```
+//!
+//! The throttled bit pattern is sent as an integer, everything else in the unit of its
+//! `Reading`.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::json::{self, Value};
+use crate::monitor::{Metric, Reading, Sample};
+use crate::sink::{self, MetricSink};
+
+const DEFAULT_PORT: u16 = 10051;
+const DEFAULT_KEY_PREFIX: &str = "vcgencmd.";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const HEADER: &[u8; 5] = b"ZBXD\x01";
+/// The header, the length of the data and 4 reserved bytes
+const HEADER_LEN: usize = 13;
+/// Responses are a short JSON object, anything longer isn't from a Zabbix server
+const MAX_RESPONSE: usize = 64 * 1024;
+
+/// Sends every sample to a Zabbix server, see the module documentation
+#[derive(Debug, Clone)]
+pub struct ZabbixSender {
+    /// `host:port`
+    server: String,
+    host: String,
+    keys: Vec<(Metric, String)>,
+}
+
+impl ZabbixSender {
+    /// Send to `server`, given as `host` or `host:port`, port 10051 by default
+    pub fn new(server: &str) -> ZabbixSender {
+        let server = match server.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => server.to_owned(),
+            _ => format!("{}:{}", server, DEFAULT_PORT),
+        };
+
+        ZabbixSender {
+            server,
+            host: crate::hostname().unwrap_or_default(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Send as `host`, the technical name of the host in Zabbix, instead of the hostname
+    pub fn host(mut self, host: &str) -> ZabbixSender {
+        self.host = host.to_owned();
+        self
+    }
+
+    /// Send the readings of `metric` as the item `key`
+    pub fn key(mut self, metric: Metric, key: &str) -> ZabbixSender {
+        match self.keys.iter_mut().find(|(known, _)| *known == metric) {
+            Some((_, known)) => *known = key.to_owned(),
+            None => self.keys.push((metric, key.to_owned())),
+        }
+        self
+    }
+
+    /// The key of the item the readings of `metric` are sent as
+    pub fn item_key(&self, metric: Metric) -> String {
+        match self.keys.iter().find(|(known, _)| *known == metric) {
+            Some((_, key)) => key.clone(),
+            None => format!("{}{}", DEFAULT_KEY_PREFIX, metric.name()),
+        }
+    }
+
+    /// Send the readings of `sample`, returning how the server processed them.
+    ///
+    /// A sample without readings isn't sent.
+    pub fn send(&self, sample: &Sample) -> io::Result<Processed> {
+        if sample.readings.is_empty() {
+            return Ok(Processed::default());
+        }
+
+        let addr = self.server.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "Zabbix server address not found")
+        })?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        stream.write_all(&packet(&self.request(sample)))?;
+        let response = read_packet(&mut stream)?;
+        parse_response(&response)
+    }
+
+    /// The `sender data` request for the readings of `sample`
+    fn request(&self, sample: &Sample) -> String {
+        let since_epoch = sample
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let clock = json::number(since_epoch.as_secs() as f64);
+        let ns = json::number(f64::from(since_epoch.subsec_nanos()));
+
+        let data = sample.readings.iter().map(|reading| {
+            json::object(&[
+                ("host", json::string(&self.host)),
+                ("key", json::string(&self.item_key(reading.metric()))),
+                ("value", json::string(&value(reading))),
+                ("clock", clock.clone()),
+                ("ns", ns.clone()),
+            ])
+        });
+
+        json::object(&[
+            ("request", json::string("sender data")),
+            ("data", json::array(data)),
+        ])
+    }
+
+    /// Turn this into a plain monitor sink, dropping failed sends and values the server
+    /// rejected. `Monitor::metric_sink` counts both as failures.
+    pub fn into_sink(self) -> impl FnMut(&Sample) + Send + 'static {
+        sink::into_fn(self)
+    }
+}
+
+impl MetricSink for ZabbixSender {
+    /// Send `sample`, failing as well if the server rejected some of its values
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let processed = self.send(sample)?;
+        match processed.failed {
+            0 => Ok(()),
+            failed => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Zabbix rejected {} of {} values, are the trapper items of host '{}' set up?",
+                    failed, processed.total, self.host
+                ),
+            )),
+        }
+    }
+}
+
+/// How the server processed the values sent to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Processed {
+    pub processed: usize,
+    /// Values rejected, e.g. because there is no trapper item with their key
+    pub failed: usize,
+    pub total: usize,
+}
+
+/// A reading as the value of an item
+fn value(reading: &Reading) -> String {
+    match *reading {
+        Reading::Throttled(bit_pattern) => bit_pattern.to_string(),
+        Reading::Clock(_, value) | Reading::Mem(_, value) => value.to_string(),
+        Reading::Temp(value) | Reading::Volts(_, value) | Reading::Derived(_, value) => {
+            value.to_string()
+        }
+        Reading::TempHeadroom(headroom) => headroom.temp.to_string(),
+    }
+}
+
+/// `data` with the header of the protocol and its length
+fn packet(data: &str) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + data.len());
+    packet.extend_from_slice(HEADER);
+    packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    packet.extend_from_slice(&[0; 4]);
+    packet.extend_from_slice(data.as_bytes());
+    packet
+}
+
+fn read_packet<R: Read>(reader: &mut R) -> io::Result<String> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+
+    let mut header = [0; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    let (protocol, length) = header.split_at(HEADER.len());
+    if protocol != HEADER {
+        return Err(invalid("not a response of a Zabbix server"));
+    }
+    let mut length_bytes = [0; 4];
+    length_bytes.copy_from_slice(length.get(..4).unwrap_or(&[0; 4]));
+    let length = u32::from_le_bytes(length_bytes) as usize;
+    if length > MAX_RESPONSE {
+        return Err(invalid("response of the Zabbix server too long"));
+    }
+
+    let mut data = vec![0; length];
+    reader.read_exact(&mut data)?;
+    String::from_utf8(data).map_err(|_| invalid("response of the Zabbix server isn't UTF-8"))
+}
+
+/// The counts in a response like
+/// `{"response":"success","info":"processed: 1; failed: 0; total: 1; seconds spent: 0.0001"}`
+fn parse_response(response: &str) -> io::Result<Processed> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let value = json::parse(response).map_err(invalid)?;
+    let info = value
+        .get("info")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+    match value.get("response").and_then(Value::as_str) {
+        Some("success") => {}
+        _ => {
+            return Err(invalid(format!(
+                "the Zabbix server failed the request: {}",
+                info
+            )))
+        }
+    }
+
+    let count = |name: &str| {
+        info.split(';')
+            .filter_map(|field| field.trim().split_once(':'))
+            .find(|(field, _)| *field == name)
+            .and_then(|(_, count)| count.trim().parse().ok())
+            .unwrap_or_default()
+    };
+    Ok(Processed {
+        processed: count("processed"),
+        failed: count("failed"),
+        total: count("total"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClockSrc;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn sample() -> Sample {
+        Sample {
+            timestamp: UNIX_EPOCH + Duration::new(60, 500),
+            readings: vec![
+                Reading::Temp(51.5),
+                Reading::Throttled(0x50005),
+                Reading::Clock(ClockSrc::Arm, 1_500_000_000),
+            ],
+            errors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_request() {
+        let sender = ZabbixSender::new("zabbix")
+            .host("pi4")
+            .key(Metric::Temp, "rpi.temp");
+        assert_eq!("zabbix:10051", sender.server);
+
+        let request = sender.request(&sample());
+        assert!(request.starts_with(
+            r#"{"request":"sender data","data":[{"host":"pi4","key":"rpi.temp","value":"51.5","clock":60,"ns":500},"#
+        ));
+        assert!(request.contains(r#""key":"vcgencmd.throttled","value":"327685""#));
+        assert!(request.contains(r#""key":"vcgencmd.clock.arm","value":"1500000000""#));
+        assert_eq!(
+            b"ZBXD\x01\x02\x00\x00\x00\x00\x00\x00\x00{}".to_vec(),
+            packet("{}")
+        );
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = r#"{"response":"success","info":"processed: 2; failed: 1; total: 3; seconds spent: 0.000055"}"#;
+        assert_eq!(
+            Processed {
+                processed: 2,
+                failed: 1,
+                total: 3
+            },
+            parse_response(response).unwrap()
+        );
+        assert!(parse_response(r#"{"response":"failed","info":"invalid"}"#).is_err());
+    }
+
+    #[test]
+    fn test_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+
+        let received = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = read_packet(&mut stream).unwrap();
+            let response = r#"{"response":"success","info":"processed: 2; failed: 1; total: 3; seconds spent: 0.000055"}"#;
+            stream.write_all(&packet(response)).unwrap();
+            request
+        });
+
+        let mut sender = ZabbixSender::new(&server).host("pi4");
+        let error = MetricSink::write(&mut sender, &sample()).unwrap_err();
+        assert!(error.to_string().contains("rejected 1 of 3 values"));
+        assert_eq!(sender.request(&sample()), received.join().unwrap());
+    }
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "derive")]
+
+use vcgencmd::{Cmd, ErrorKind, Result, Src, ThrottledStatus, VcSnapshot, Vcgencmd};
+
+#[derive(VcSnapshot)]
+struct Custom {
+    #[vc(temp)]
+    temp: f64,
+    #[vc(clock = "arm")]
+    arm: isize,
+    #[vc(volts = "sdram_c")]
+    sdram_c: Option<f64>,
+    #[vc(mem = "gpu")]
+    gpu: isize,
+    #[vc(config = "temp_soft_limit")]
+    soft_limit: Option<isize>,
+    #[vc(throttled_status)]
+    throttled: ThrottledStatus,
+}
+
+#[test]
+fn test_derive_capture() {
+    let client = Vcgencmd::with_executor(|cmd, src| {
+        Ok(match (cmd, src) {
+            (Cmd::MeasureTemp, _) => "temp=48.3'C\n",
+            (Cmd::MeasureClock, _) => "frequency(48)=1500000000\n",
+            (Cmd::GetMem, _) => "gpu=76M\n",
+            (Cmd::GetConfig, _) => "temp_soft_limit=60\n",
+            (Cmd::GetThrottled, _) => "throttled=0x50005\n",
+            (_, Some(Src::Volt(_))) => "error=2 error_msg=\"Invalid arguments\"\n",
+            _ => "",
+        }
+        .to_owned())
+    });
+
+    let custom = Custom::capture(&client).unwrap();
+    assert_eq!(48.3, custom.temp);
+    assert_eq!(1_500_000_000, custom.arm);
+    assert_eq!(None, custom.sdram_c);
+    assert_eq!(76, custom.gpu);
+    assert_eq!(Some(60), custom.soft_limit);
+    assert!(custom.throttled.under_voltage);
+
+    let failing =
+        Vcgencmd::with_executor(|_, _| Ok("error=2 error_msg=\"Invalid arguments\"\n".to_owned()));
+    let error = Custom::capture(&failing).err().unwrap();
+    assert_eq!(ErrorKind::Unsupported, error.kind());
+
+    let capture_global: fn() -> Result<Custom> = Custom::capture_global;
+    if cfg!(target_arch = "arm") {
+        let custom = capture_global().unwrap();
+        assert!(custom.temp > 0.0 && custom.arm > 0 && custom.gpu > 0);
+        assert!(custom.sdram_c.is_none_or(|volts| volts > 0.0));
+        assert!(custom.soft_limit.is_some());
+        dbg!(custom.throttled);
+    }
+}
@@ -0,0 +1,244 @@
+//! `#[derive(VcSnapshot)]`, re-exported by `vcgencmd` with the `derive` feature
+//!
+//! Every field names the metric it holds with a `#[vc(...)]` attribute, the derive
+//! generates a `capture(&client)` constructor reading all of them through a `Vcgencmd`
+//! client, and `capture_global()`, which reads them like the free functions do:
+//!
+//! ```ignore
+//! use vcgencmd::{VcSnapshot, Vcgencmd};
+//!
+//! #[derive(VcSnapshot)]
+//! struct Thermals {
+//!     #[vc(temp)]
+//!     temp: f64,
+//!     #[vc(clock = "arm")]
+//!     arm: isize,
+//!     // `None` if the rail can't be measured, instead of failing the capture
+//!     #[vc(volts = "sdram_c")]
+//!     sdram_c: Option<f64>,
+//! }
+//!
+//! let thermals = Thermals::capture(&Vcgencmd::new())?;
+//! ```
+//!
+//! The metrics are
+//!
+//! | attribute                | reading                          | type               |
+//! |--------------------------|----------------------------------|--------------------|
+//! | `temp`                   | `measure_temp`                   | `f64`              |
+//! | `throttled`              | `get_throttled`                  | `isize`            |
+//! | `throttled_status`       | `get_throttled_status`           | `ThrottledStatus`  |
+//! | `clock = "<ClockSrc>"`   | `measure_clock`                  | `isize`            |
+//! | `volts = "<VoltSrc>"`    | `measure_volts`                  | `f64`              |
+//! | `mem = "<MemSrc>"`       | `get_mem`                        | `isize`            |
+//! | `config = "<ConfigSrc>"` | `get_config`                     | `isize`            |
+//!
+//! with sources named as vcgencmd names them. Wrapping the type in an `Option` turns a
+//! failed reading into `None`.
+
+extern crate proc_macro;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Field, Fields, LitStr, Type};
+
+const CLOCK_SRCS: &[(&str, &str)] = &[
+    ("arm", "Arm"),
+    ("core", "Core"),
+    ("dpi", "Dpi"),
+    ("emmc", "Emmc"),
+    ("h264", "H264"),
+    ("hdmi", "Hdmi"),
+    ("isp", "Isp"),
+    ("pixel", "Pixel"),
+    ("pwm", "Pwm"),
+    ("uart", "Uart"),
+    ("v3d", "V3d"),
+    ("vec", "Vec"),
+];
+const VOLT_SRCS: &[(&str, &str)] = &[
+    ("core", "Core"),
+    ("sdram_c", "SdramC"),
+    ("sdram_i", "SdramI"),
+    ("sdram_p", "SdramP"),
+];
+const MEM_SRCS: &[(&str, &str)] = &[
+    ("arm", "Arm"),
+    ("gpu", "Gpu"),
+    ("malloc", "Malloc"),
+    ("malloc_total", "MallocTotal"),
+    ("reloc", "Reloc"),
+    ("reloc_total", "RelocTotal"),
+];
+const CONFIG_SRCS: &[(&str, &str)] = &[
+    ("arm_freq", "ArmFreq"),
+    ("core_freq", "CoreFreq"),
+    ("gpu_freq", "GpuFreq"),
+    ("gpu_mem", "GpuMem"),
+    ("over_voltage", "OverVoltage"),
+    ("sdram_freq", "SdramFreq"),
+    ("temp_limit", "TempLimit"),
+    ("temp_soft_limit", "TempSoftLimit"),
+    ("total_mem", "TotalMem"),
+];
+
+#[proc_macro_derive(VcSnapshot, attributes(vc))]
+pub fn derive_vc_snapshot(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "VcSnapshot needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "VcSnapshot can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut initializers = Vec::new();
+    let mut errors: Option<Error> = None;
+    for field in fields {
+        match initializer(field) {
+            Ok(initializer) => initializers.push(initializer),
+            Err(error) => match &mut errors {
+                Some(errors) => errors.combine(error),
+                None => errors = Some(error),
+            },
+        }
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Read every metric of the struct through `client`
+            pub fn capture<X: ::vcgencmd::executor::Executor>(
+                client: &::vcgencmd::Vcgencmd<X>,
+            ) -> ::vcgencmd::Result<Self> {
+                ::std::result::Result::Ok(#name {
+                    #(#initializers,)*
+                })
+            }
+
+            /// Read every metric of the struct like the free functions do, with the
+            /// global `Invocation`
+            pub fn capture_global() -> ::vcgencmd::Result<Self> {
+                Self::capture(&::vcgencmd::Vcgencmd::from_global())
+            }
+        }
+    })
+}
+
+/// `field: <reading>`, with the reading as the attribute of the field asks for
+fn initializer(field: &Field) -> syn::Result<TokenStream> {
+    let ident = &field.ident;
+    let reading = reading(field)?;
+    if is_option(&field.ty) {
+        Ok(quote!(#ident: #reading.ok()))
+    } else {
+        Ok(quote!(#ident: #reading?))
+    }
+}
+
+fn reading(field: &Field) -> syn::Result<TokenStream> {
+    let attrs: Vec<&Attribute> = field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("vc"))
+        .collect();
+    let attr = match attrs.as_slice() {
+        [attr] => attr,
+        [] => {
+            return Err(Error::new_spanned(
+                field,
+                "missing #[vc(...)] attribute naming the metric",
+            ))
+        }
+        [_, second, ..] => {
+            return Err(Error::new_spanned(
+                second,
+                "only one #[vc(...)] attribute per field",
+            ))
+        }
+    };
+
+    let mut reading = None;
+    attr.parse_nested_meta(|meta| {
+        if reading.is_some() {
+            return Err(meta.error("only one metric per field"));
+        }
+
+        reading = Some(if meta.path.is_ident("temp") {
+            quote!(client.measure_temp())
+        } else if meta.path.is_ident("throttled") {
+            quote!(client.get_throttled())
+        } else if meta.path.is_ident("throttled_status") {
+            quote!(client.get_throttled_status())
+        } else if meta.path.is_ident("clock") {
+            let src = source(meta.value()?.parse()?, "ClockSrc", CLOCK_SRCS)?;
+            quote!(client.measure_clock(::vcgencmd::Src::Clock(#src)))
+        } else if meta.path.is_ident("volts") {
+            let src = source(meta.value()?.parse()?, "VoltSrc", VOLT_SRCS)?;
+            quote!(client.measure_volts(::vcgencmd::Src::Volt(#src)))
+        } else if meta.path.is_ident("mem") {
+            let src = source(meta.value()?.parse()?, "MemSrc", MEM_SRCS)?;
+            quote!(client.get_mem(::vcgencmd::Src::Mem(#src)))
+        } else if meta.path.is_ident("config") {
+            let src = source(meta.value()?.parse()?, "ConfigSrc", CONFIG_SRCS)?;
+            quote!(client.get_config(::vcgencmd::Src::Config(#src)))
+        } else {
+            return Err(meta.error(
+                "unknown metric, expected temp, throttled, throttled_status, clock, volts, mem or config",
+            ));
+        });
+        Ok(())
+    })?;
+
+    reading.ok_or_else(|| Error::new_spanned(attr, "#[vc(...)] needs a metric"))
+}
+
+/// The path of the variant of `ty` that vcgencmd calls `name`
+fn source(name: LitStr, ty: &str, sources: &[(&str, &str)]) -> syn::Result<TokenStream> {
+    match sources.iter().find(|(src, _)| *src == name.value()) {
+        Some((_, variant)) => {
+            let ty = syn::Ident::new(ty, name.span());
+            let variant = syn::Ident::new(variant, name.span());
+            Ok(quote!(::vcgencmd::#ty::#variant))
+        }
+        None => {
+            let names: Vec<_> = sources.iter().map(|(src, _)| *src).collect();
+            Err(Error::new(
+                name.span(),
+                format!("unknown source, expected one of {}", names.join(", ")),
+            ))
+        }
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(ty) => ty
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
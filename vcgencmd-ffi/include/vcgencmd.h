@@ -0,0 +1,122 @@
+/*
+ * C interface of the vcgencmd crate, built with `cargo build --release -p vcgencmd-ffi`
+ * into libvcgencmd_ffi.so and libvcgencmd_ffi.a.
+ *
+ * Every function returns VCGENCMD_OK or a negative VCGENCMD_ERR_* code and writes the
+ * reading through its out pointer. Generated from src/lib.rs by cbindgen, don't edit.
+ */
+
+#ifndef VCGENCMD_H
+#define VCGENCMD_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define VCGENCMD_OK 0
+
+#define VCGENCMD_ERR_IO -1
+
+#define VCGENCMD_ERR_PERMISSION -2
+
+#define VCGENCMD_ERR_FIRMWARE -3
+
+#define VCGENCMD_ERR_PARSE -4
+
+#define VCGENCMD_ERR_TIMEOUT -5
+
+#define VCGENCMD_ERR_UNSUPPORTED -6
+
+/**
+ * A null pointer or a source out of range was passed
+ */
+#define VCGENCMD_ERR_INVALID_ARGUMENT -7
+
+/**
+ * The sources of `vcgencmd_measure_clock`, in the order of `ClockSrc::ALL`
+ */
+enum vcgencmd_clock {
+  VCGENCMD_CLOCK_ARM = 0,
+  VCGENCMD_CLOCK_CORE = 1,
+  VCGENCMD_CLOCK_DPI = 2,
+  VCGENCMD_CLOCK_EMMC = 3,
+  VCGENCMD_CLOCK_H264 = 4,
+  VCGENCMD_CLOCK_HDMI = 5,
+  VCGENCMD_CLOCK_ISP = 6,
+  VCGENCMD_CLOCK_PIXEL = 7,
+  VCGENCMD_CLOCK_PWM = 8,
+  VCGENCMD_CLOCK_UART = 9,
+  VCGENCMD_CLOCK_V3D = 10,
+  VCGENCMD_CLOCK_VEC = 11,
+};
+
+/**
+ * The sources of `vcgencmd_measure_volts`, in the order of `VoltSrc::ALL`
+ */
+enum vcgencmd_volt {
+  VCGENCMD_VOLT_CORE = 0,
+  VCGENCMD_VOLT_SDRAM_C = 1,
+  VCGENCMD_VOLT_SDRAM_I = 2,
+  VCGENCMD_VOLT_SDRAM_P = 3,
+};
+
+/**
+ * The decoded `get_throttled` bit pattern
+ */
+struct vcgencmd_throttled_status {
+  bool arm_frequency_cap_occurred;
+  bool arm_frequency_capped;
+  bool currently_throttled;
+  bool soft_temp_limit_active;
+  bool soft_temp_limit_occurred;
+  bool throttling_occurred;
+  bool under_voltage;
+  bool under_voltage_occurred;
+};
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Measure the SoC temperature in °C.
+ */
+int vcgencmd_measure_temp(double *out);
+
+/**
+ * Measure the clock of `src`, a `vcgencmd_clock`, in Hz.
+ */
+int vcgencmd_measure_clock(int src, int64_t *out);
+
+/**
+ * Measure the voltage of `src`, a `vcgencmd_volt`, in V.
+ */
+int vcgencmd_measure_volts(int src, double *out);
+
+/**
+ * Read the `get_throttled` bit pattern and decode it into `status` unless it is null.
+ */
+int vcgencmd_get_throttled(int64_t *bit_pattern, struct vcgencmd_throttled_status *status);
+
+/**
+ * Decode a `get_throttled` bit pattern, see `interpret_bit_pattern`
+ */
+struct vcgencmd_throttled_status vcgencmd_decode_throttled(int64_t bit_pattern);
+
+/**
+ * Temperature, throttling, clocks and voltage as JSON, free it with `vcgencmd_string_free`.
+ */
+char *vcgencmd_snapshot_json(void);
+
+/**
+ * Free a string returned by this library.
+ */
+void vcgencmd_string_free(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VCGENCMD_H */
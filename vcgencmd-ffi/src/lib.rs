@@ -0,0 +1,271 @@
+//! A C ABI for the vcgencmd crate, declared in `include/vcgencmd.h`
+//!
+//! Every function returns `VCGENCMD_OK` or one of the negative `VCGENCMD_ERR_*` codes,
+//! the reading itself is written through an out pointer. Strings returned by the library
+//! are owned by the caller and released with `vcgencmd_string_free`.
+//!
+//! The header is generated by cbindgen from this file, see `cbindgen.toml`, and the tests
+//! check that the committed one is up to date.
+//!
+//! A panic mustn't unwind into C, so like the library this crate denies the panicking
+//! clippy lints outside of tests.
+
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::panic,
+        clippy::indexing_slicing,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::unreachable
+    )
+)]
+
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use vcgencmd::snapshot::Snapshot;
+use vcgencmd::{
+    get_throttled, interpret_bit_pattern, measure_clock, measure_temp, measure_volts, ClockSrc,
+    Error, ErrorKind, Src, ThrottledStatus, VoltSrc,
+};
+
+pub const VCGENCMD_OK: c_int = 0;
+pub const VCGENCMD_ERR_IO: c_int = -1;
+pub const VCGENCMD_ERR_PERMISSION: c_int = -2;
+pub const VCGENCMD_ERR_FIRMWARE: c_int = -3;
+pub const VCGENCMD_ERR_PARSE: c_int = -4;
+pub const VCGENCMD_ERR_TIMEOUT: c_int = -5;
+pub const VCGENCMD_ERR_UNSUPPORTED: c_int = -6;
+/// A null pointer or a source out of range was passed
+pub const VCGENCMD_ERR_INVALID_ARGUMENT: c_int = -7;
+
+/// The sources of `vcgencmd_measure_clock`, in the order of `ClockSrc::ALL`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VcgencmdClock {
+    Arm = 0,
+    Core = 1,
+    Dpi = 2,
+    Emmc = 3,
+    H264 = 4,
+    Hdmi = 5,
+    Isp = 6,
+    Pixel = 7,
+    Pwm = 8,
+    Uart = 9,
+    V3d = 10,
+    Vec = 11,
+}
+
+/// The sources of `vcgencmd_measure_volts`, in the order of `VoltSrc::ALL`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VcgencmdVolt {
+    Core = 0,
+    SdramC = 1,
+    SdramI = 2,
+    SdramP = 3,
+}
+
+/// The decoded `get_throttled` bit pattern
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VcgencmdThrottledStatus {
+    pub arm_frequency_cap_occurred: bool,
+    pub arm_frequency_capped: bool,
+    pub currently_throttled: bool,
+    pub soft_temp_limit_active: bool,
+    pub soft_temp_limit_occurred: bool,
+    pub throttling_occurred: bool,
+    pub under_voltage: bool,
+    pub under_voltage_occurred: bool,
+}
+
+impl From<ThrottledStatus> for VcgencmdThrottledStatus {
+    fn from(status: ThrottledStatus) -> VcgencmdThrottledStatus {
+        VcgencmdThrottledStatus {
+            arm_frequency_cap_occurred: status.arm_frequency_cap_occurred,
+            arm_frequency_capped: status.arm_frequency_capped,
+            currently_throttled: status.currently_throttled,
+            soft_temp_limit_active: status.soft_temp_limit_active,
+            soft_temp_limit_occurred: status.soft_temp_limit_occurred,
+            throttling_occurred: status.throttling_occurred,
+            under_voltage: status.under_voltage,
+            under_voltage_occurred: status.under_voltage_occurred,
+        }
+    }
+}
+
+fn error_code(error: &Error) -> c_int {
+    match error.kind() {
+        ErrorKind::Permission => VCGENCMD_ERR_PERMISSION,
+        ErrorKind::Firmware => VCGENCMD_ERR_FIRMWARE,
+        ErrorKind::Parse => VCGENCMD_ERR_PARSE,
+        ErrorKind::Timeout => VCGENCMD_ERR_TIMEOUT,
+        ErrorKind::Unsupported => VCGENCMD_ERR_UNSUPPORTED,
+        // `ErrorKind::Io`, and kinds added later until they get a code of their own
+        _ => VCGENCMD_ERR_IO,
+    }
+}
+
+/// Write the outcome of `read` to `out`
+unsafe fn write<T>(out: *mut T, read: impl FnOnce() -> vcgencmd::Result<T>) -> c_int {
+    if out.is_null() {
+        return VCGENCMD_ERR_INVALID_ARGUMENT;
+    }
+    match read() {
+        Ok(value) => {
+            *out = value;
+            VCGENCMD_OK
+        }
+        Err(error) => error_code(&error),
+    }
+}
+
+/// Measure the SoC temperature in °C.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vcgencmd_measure_temp(out: *mut f64) -> c_int {
+    write(out, measure_temp)
+}
+
+/// Measure the clock of `src`, a `vcgencmd_clock`, in Hz.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vcgencmd_measure_clock(src: c_int, out: *mut i64) -> c_int {
+    match usize::try_from(src).ok().and_then(|i| ClockSrc::ALL.get(i)) {
+        Some(&src) => write(out, || {
+            measure_clock(Src::Clock(src)).map(|frequency| frequency as i64)
+        }),
+        None => VCGENCMD_ERR_INVALID_ARGUMENT,
+    }
+}
+
+/// Measure the voltage of `src`, a `vcgencmd_volt`, in V.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vcgencmd_measure_volts(src: c_int, out: *mut f64) -> c_int {
+    match usize::try_from(src).ok().and_then(|i| VoltSrc::ALL.get(i)) {
+        Some(&src) => write(out, || measure_volts(Src::Volt(src))),
+        None => VCGENCMD_ERR_INVALID_ARGUMENT,
+    }
+}
+
+/// Read the `get_throttled` bit pattern and decode it into `status` unless it is null.
+///
+/// # Safety
+///
+/// `bit_pattern` must be null or valid for writes, and so must `status` unless it is null.
+#[no_mangle]
+pub unsafe extern "C" fn vcgencmd_get_throttled(
+    bit_pattern: *mut i64,
+    status: *mut VcgencmdThrottledStatus,
+) -> c_int {
+    let code = write(bit_pattern, || get_throttled().map(|b| b as i64));
+    if code == VCGENCMD_OK && !status.is_null() {
+        *status = vcgencmd_decode_throttled(*bit_pattern);
+    }
+    code
+}
+
+/// Decode a `get_throttled` bit pattern, see `interpret_bit_pattern`
+#[no_mangle]
+pub extern "C" fn vcgencmd_decode_throttled(bit_pattern: i64) -> VcgencmdThrottledStatus {
+    interpret_bit_pattern(bit_pattern as isize).into()
+}
+
+/// Temperature, throttling, clocks and voltage as JSON, free it with `vcgencmd_string_free`.
+///
+/// Null if the JSON can't be represented as a C string, see `Snapshot::to_json`.
+#[no_mangle]
+pub extern "C" fn vcgencmd_snapshot_json() -> *mut c_char {
+    match CString::new(Snapshot::capture().to_json()) {
+        Ok(json) => json.into_raw(),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Free a string returned by this library.
+///
+/// # Safety
+///
+/// `string` must be null or a string returned by this library that wasn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn vcgencmd_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_decode_throttled() {
+        let status = vcgencmd_decode_throttled(0x50005);
+        assert!(status.under_voltage && status.currently_throttled);
+        assert!(!status.soft_temp_limit_occurred);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        unsafe {
+            assert_eq!(
+                VCGENCMD_ERR_INVALID_ARGUMENT,
+                vcgencmd_measure_temp(ptr::null_mut())
+            );
+            let mut frequency = 0;
+            assert_eq!(
+                VCGENCMD_ERR_INVALID_ARGUMENT,
+                vcgencmd_measure_clock(12, &mut frequency)
+            );
+            assert_eq!(
+                VCGENCMD_ERR_INVALID_ARGUMENT,
+                vcgencmd_measure_volts(-1, ptr::null_mut())
+            );
+            vcgencmd_string_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_sources() {
+        assert_eq!(ClockSrc::V3d, ClockSrc::ALL[VcgencmdClock::V3d as usize]);
+        assert_eq!(ClockSrc::ALL.len(), VcgencmdClock::Vec as usize + 1);
+        assert_eq!(VoltSrc::SdramI, VoltSrc::ALL[VcgencmdVolt::SdramI as usize]);
+        assert_eq!(VoltSrc::ALL.len(), VcgencmdVolt::SdramP as usize + 1);
+    }
+
+    #[test]
+    fn test_header() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+        let mut generated = Vec::new();
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(dir.join("src/lib.rs"))
+            .generate()
+            .unwrap()
+            .write(&mut generated);
+
+        let committed = fs::read(dir.join("include/vcgencmd.h")).unwrap();
+        assert!(
+            generated == committed,
+            "include/vcgencmd.h is out of date, regenerate it as described in cbindgen.toml"
+        );
+    }
+}
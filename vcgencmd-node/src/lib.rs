@@ -0,0 +1,224 @@
+//! Node.js bindings of the vcgencmd crate, an N-API addon built with `napi build` of
+//! `@napi-rs/cli`, see `package.json`.
+//!
+//! The functions return promises, vcgencmd runs on the Tokio runtime of napi through the
+//! crate's `asynchronous` module, so a dashboard can await several readings at once:
+//!
+//! ```js
+//! const vcgencmd = require('vcgencmd-rs')
+//!
+//! vcgencmd.configure({ sudo: false })
+//! const [temp, status] = await Promise.all([
+//!   vcgencmd.measureTemp(),
+//!   vcgencmd.getThrottledStatus(),
+//! ])
+//! const snapshot = await vcgencmd.snapshot()
+//! console.log(temp, status.underVoltage, snapshot.health)
+//! ```
+//!
+//! Failed calls reject with an `Error` whose message says why, and how to fix it where
+//! that's known.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use napi::{Error, Result};
+use napi_derive::napi;
+use vcgencmd::asynchronous;
+use vcgencmd::monitor::Metric;
+use vcgencmd::{PrivilegeMode, Src};
+
+fn js_error(error: vcgencmd::Error) -> Error {
+    match error.hint() {
+        Some(hint) => Error::from_reason(format!("{} ({})", error, hint)),
+        None => Error::from_reason(error.to_string()),
+    }
+}
+
+/// The source of `kind`, `clock`, `volts` or `mem`, called `name` by vcgencmd
+fn src(kind: &str, name: &str) -> Result<Src> {
+    match Metric::from_name(&format!("{}.{}", kind, name)) {
+        Some(Metric::Clock(src)) => Ok(Src::Clock(src)),
+        Some(Metric::Volts(src)) => Ok(Src::Volt(src)),
+        Some(Metric::Mem(src)) => Ok(Src::Mem(src)),
+        _ => Err(Error::from_reason(format!(
+            "unknown {} source '{}'",
+            kind, name
+        ))),
+    }
+}
+
+/// How vcgencmd is run by all functions, settings left out keep their defaults
+#[napi(object)]
+pub struct Options {
+    /// Path to vcgencmd, defaults to looking it up in PATH
+    pub binary: Option<String>,
+    /// Whether to run it through sudo
+    pub sudo: Option<bool>,
+    /// Run it on this host over ssh, e.g. `pi@pi4.local`
+    pub host: Option<String>,
+    /// Milliseconds a call may take
+    pub timeout: Option<u32>,
+}
+
+#[napi]
+pub fn configure(options: Options) {
+    let mut invocation = vcgencmd::Config::cached().invocation();
+    if let Some(binary) = options.binary {
+        invocation.binary = PathBuf::from(binary);
+    }
+    if let Some(sudo) = options.sudo {
+        invocation.privilege = match sudo {
+            true => PrivilegeMode::Sudo,
+            false => PrivilegeMode::None,
+        };
+    }
+    invocation.host = options.host;
+    if let Some(timeout) = options.timeout {
+        invocation.timeout = Some(Duration::from_millis(u64::from(timeout)));
+    }
+    vcgencmd::set_invocation(invocation);
+}
+
+/// SoC temperature in °C
+#[napi]
+pub async fn measure_temp() -> Result<f64> {
+    asynchronous::measure_temp().await.map_err(js_error)
+}
+
+/// Clock frequency in Hz of `src`, e.g. `arm`, the default, `core` or `v3d`
+#[napi]
+pub async fn measure_clock(src: Option<String>) -> Result<i64> {
+    let src = self::src("clock", src.as_deref().unwrap_or("arm"))?;
+    let frequency = asynchronous::measure_clock(src).await;
+    frequency
+        .map(|frequency| frequency as i64)
+        .map_err(js_error)
+}
+
+/// Voltage in V of `src`, `core`, the default, `sdram_c`, `sdram_i` or `sdram_p`
+#[napi]
+pub async fn measure_volts(src: Option<String>) -> Result<f64> {
+    let src = self::src("volts", src.as_deref().unwrap_or("core"))?;
+    asynchronous::measure_volts(src).await.map_err(js_error)
+}
+
+/// Memory in MB of `src`, `arm` or `gpu`, or the bytes of the relocatable heap ones
+#[napi]
+pub async fn get_mem(src: String) -> Result<i64> {
+    let src = self::src("mem", &src)?;
+    let mem = asynchronous::get_mem(src).await;
+    mem.map(|mem| mem as i64).map_err(js_error)
+}
+
+/// The bit pattern of `get_throttled`, decode it with `interpretBitPattern`
+#[napi]
+pub async fn get_throttled() -> Result<i64> {
+    let bit_pattern = asynchronous::get_throttled().await;
+    bit_pattern.map(|b| b as i64).map_err(js_error)
+}
+
+/// The decoded flags of `get_throttled`
+#[napi(object)]
+pub struct ThrottledStatus {
+    pub under_voltage: bool,
+    pub arm_frequency_capped: bool,
+    pub currently_throttled: bool,
+    pub soft_temp_limit_active: bool,
+    pub under_voltage_occurred: bool,
+    pub arm_frequency_cap_occurred: bool,
+    pub throttling_occurred: bool,
+    pub soft_temp_limit_occurred: bool,
+}
+
+impl From<vcgencmd::ThrottledStatus> for ThrottledStatus {
+    fn from(status: vcgencmd::ThrottledStatus) -> ThrottledStatus {
+        ThrottledStatus {
+            under_voltage: status.under_voltage,
+            arm_frequency_capped: status.arm_frequency_capped,
+            currently_throttled: status.currently_throttled,
+            soft_temp_limit_active: status.soft_temp_limit_active,
+            under_voltage_occurred: status.under_voltage_occurred,
+            arm_frequency_cap_occurred: status.arm_frequency_cap_occurred,
+            throttling_occurred: status.throttling_occurred,
+            soft_temp_limit_occurred: status.soft_temp_limit_occurred,
+        }
+    }
+}
+
+#[napi]
+pub async fn get_throttled_status() -> Result<ThrottledStatus> {
+    let status = asynchronous::get_throttled_status().await;
+    status.map(ThrottledStatus::from).map_err(js_error)
+}
+
+/// Decode a bit pattern as returned by `getThrottled`
+#[napi]
+pub fn interpret_bit_pattern(bit_pattern: i64) -> ThrottledStatus {
+    ThrottledStatus::from(vcgencmd::interpret_bit_pattern(bit_pattern as isize))
+}
+
+/// Temperature, throttling, clocks and core voltage. Readings that failed are missing,
+/// with the reason in `errors`.
+#[napi(object)]
+pub struct Snapshot {
+    /// Milliseconds since the Unix epoch the metrics were read at, for `new Date()`
+    pub timestamp: f64,
+    /// Temperature in °C
+    pub temp: Option<f64>,
+    /// Bit pattern as returned by `getThrottled`
+    pub throttled: Option<i64>,
+    /// ARM clock in Hz
+    pub arm_clock: Option<i64>,
+    /// Core clock in Hz
+    pub core_clock: Option<i64>,
+    /// Core voltage in V
+    pub core_volts: Option<f64>,
+    /// `healthy`, `degraded` or `critical`
+    pub health: String,
+    /// Why metrics couldn't be read, keyed by their names like `temp` or `clock.arm`
+    pub errors: HashMap<String, String>,
+}
+
+#[napi]
+pub async fn snapshot() -> Snapshot {
+    let snapshot = asynchronous::snapshot().await;
+    let since_epoch = snapshot.timestamp.duration_since(UNIX_EPOCH);
+
+    Snapshot {
+        timestamp: since_epoch.unwrap_or_default().as_secs_f64() * 1000.0,
+        temp: snapshot.temp,
+        throttled: snapshot.throttled.map(|b| b as i64),
+        arm_clock: snapshot.arm_clock.map(|f| f as i64),
+        core_clock: snapshot.core_clock.map(|f| f as i64),
+        core_volts: snapshot.core_volts,
+        health: snapshot.health().state().to_owned(),
+        errors: snapshot
+            .errors
+            .iter()
+            .map(|(metric, error)| (metric.name(), error.to_string()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vcgencmd::{ClockSrc, VoltSrc};
+
+    #[test]
+    fn test_arguments() {
+        assert!(matches!(src("clock", "v3d"), Ok(Src::Clock(ClockSrc::V3d))));
+        assert!(matches!(
+            src("volts", "sdram_c"),
+            Ok(Src::Volt(VoltSrc::SdramC))
+        ));
+        assert!(src("clock", "gpu").is_err());
+        assert!(src("mem", "core").is_err());
+
+        let status = interpret_bit_pattern(0x50005);
+        assert!(status.under_voltage && status.currently_throttled);
+        assert!(!status.soft_temp_limit_occurred);
+    }
+}
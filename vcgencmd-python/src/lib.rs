@@ -0,0 +1,318 @@
+//! Python bindings of the vcgencmd crate, the `vcgencmd_rs` module.
+//!
+//! Built with maturin, `maturin build --release` in this directory, or `maturin develop` into
+//! the active virtualenv. Calls release the GIL while vcgencmd runs.
+//!
+//! ```python
+//! import vcgencmd_rs
+//!
+//! client = vcgencmd_rs.Vcgencmd(sudo=False)
+//! print(client.measure_temp(), client.measure_clock("arm"))
+//!
+//! status = client.get_throttled_status()
+//! if status.under_voltage:
+//!     print("under-voltage")
+//!
+//! snapshot = client.snapshot()
+//! print(snapshot.health, snapshot.to_json())
+//!
+//! try:
+//!     client.measure_volts("sdram_c")
+//! except vcgencmd_rs.VcgencmdError as error:
+//!     print(error)
+//! ```
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use vcgencmd::monitor::Metric;
+use vcgencmd::snapshot::SnapshotSpec;
+use vcgencmd::{PrivilegeMode, Src};
+
+create_exception!(
+    vcgencmd_rs,
+    VcgencmdError,
+    PyException,
+    "A vcgencmd call that failed, the message says why and how to fix it where that's known"
+);
+
+fn py_error(error: vcgencmd::Error) -> PyErr {
+    let message = match error.hint() {
+        Some(hint) => format!("{} ({})", error, hint),
+        None => error.to_string(),
+    };
+    VcgencmdError::new_err(message)
+}
+
+/// The source of `kind`, `clock`, `volts` or `mem`, called `name` by vcgencmd
+fn src(kind: &str, name: &str) -> PyResult<Src> {
+    match Metric::from_name(&format!("{}.{}", kind, name)) {
+        Some(Metric::Clock(src)) => Ok(Src::Clock(src)),
+        Some(Metric::Volts(src)) => Ok(Src::Volt(src)),
+        Some(Metric::Mem(src)) => Ok(Src::Mem(src)),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown {} source '{}'",
+            kind, name
+        ))),
+    }
+}
+
+/// A client running vcgencmd, locally or on `host` over ssh
+#[pyclass(name = "Vcgencmd", module = "vcgencmd_rs", frozen)]
+struct Client {
+    client: vcgencmd::Vcgencmd,
+}
+
+#[pymethods]
+impl Client {
+    /// `sudo` defaults to the crate's `PrivilegeMode::default`, `timeout` is in seconds
+    #[new]
+    #[pyo3(signature = (binary = None, sudo = None, host = None, timeout = None))]
+    fn new(
+        binary: Option<PathBuf>,
+        sudo: Option<bool>,
+        host: Option<&str>,
+        timeout: Option<f64>,
+    ) -> PyResult<Client> {
+        let mut builder = vcgencmd::Vcgencmd::builder();
+        if let Some(binary) = binary {
+            builder = builder.binary_path(binary);
+        }
+        if let Some(sudo) = sudo {
+            builder = builder.privilege(match sudo {
+                true => PrivilegeMode::Sudo,
+                false => PrivilegeMode::None,
+            });
+        }
+        if let Some(host) = host {
+            builder = builder.host(host);
+        }
+        if let Some(timeout) = timeout {
+            let timeout = Duration::try_from_secs_f64(timeout)
+                .map_err(|_| PyValueError::new_err(format!("invalid timeout {}", timeout)))?;
+            builder = builder.timeout(timeout);
+        }
+
+        Ok(Client {
+            client: builder.build(),
+        })
+    }
+
+    /// SoC temperature in °C
+    fn measure_temp(&self, py: Python<'_>) -> PyResult<f64> {
+        py.allow_threads(|| self.client.measure_temp())
+            .map_err(py_error)
+    }
+
+    /// Clock frequency in Hz of `src`, e.g. `arm`, `core` or `v3d`
+    #[pyo3(signature = (src = "arm"))]
+    fn measure_clock(&self, py: Python<'_>, src: &str) -> PyResult<isize> {
+        let src = self::src("clock", src)?;
+        py.allow_threads(|| self.client.measure_clock(src))
+            .map_err(py_error)
+    }
+
+    /// Voltage in V of `src`, `core`, `sdram_c`, `sdram_i` or `sdram_p`
+    #[pyo3(signature = (src = "core"))]
+    fn measure_volts(&self, py: Python<'_>, src: &str) -> PyResult<f64> {
+        let src = self::src("volts", src)?;
+        py.allow_threads(|| self.client.measure_volts(src))
+            .map_err(py_error)
+    }
+
+    /// Memory in MB of `src`, `arm` or `gpu`, or the bytes of the relocatable heap ones
+    fn get_mem(&self, py: Python<'_>, src: &str) -> PyResult<isize> {
+        let src = self::src("mem", src)?;
+        py.allow_threads(|| self.client.get_mem(src))
+            .map_err(py_error)
+    }
+
+    /// The bit pattern of `get_throttled`, decode it with `interpret_bit_pattern`
+    fn get_throttled(&self, py: Python<'_>) -> PyResult<isize> {
+        py.allow_threads(|| self.client.get_throttled())
+            .map_err(py_error)
+    }
+
+    fn get_throttled_status(&self, py: Python<'_>) -> PyResult<ThrottledStatus> {
+        py.allow_threads(|| self.client.get_throttled_status())
+            .map(ThrottledStatus::from)
+            .map_err(py_error)
+    }
+
+    /// Temperature, throttling, clocks and core voltage, read at once. Readings that fail
+    /// are `None`, with the reason in `errors`.
+    fn snapshot(&self, py: Python<'_>) -> Snapshot {
+        py.allow_threads(|| {
+            let capture =
+                SnapshotSpec::default().capture_parallel_with(|metric| self.client.read(metric));
+            let lagging = capture.lagging();
+            let sample = capture.sample;
+
+            let limits = self.client.temp_limits().unwrap_or_default();
+            let mut snapshot = vcgencmd::snapshot::Snapshot::from_sample(&sample, limits);
+            snapshot.errors = sample.errors;
+            snapshot.lagging = lagging;
+            Snapshot(snapshot)
+        })
+    }
+}
+
+/// The decoded flags of `get_throttled`
+#[pyclass(module = "vcgencmd_rs", frozen, get_all, eq)]
+#[derive(Debug, Clone, PartialEq)]
+struct ThrottledStatus {
+    under_voltage: bool,
+    arm_frequency_capped: bool,
+    currently_throttled: bool,
+    soft_temp_limit_active: bool,
+    under_voltage_occurred: bool,
+    arm_frequency_cap_occurred: bool,
+    throttling_occurred: bool,
+    soft_temp_limit_occurred: bool,
+}
+
+impl From<vcgencmd::ThrottledStatus> for ThrottledStatus {
+    fn from(status: vcgencmd::ThrottledStatus) -> ThrottledStatus {
+        ThrottledStatus {
+            under_voltage: status.under_voltage,
+            arm_frequency_capped: status.arm_frequency_capped,
+            currently_throttled: status.currently_throttled,
+            soft_temp_limit_active: status.soft_temp_limit_active,
+            under_voltage_occurred: status.under_voltage_occurred,
+            arm_frequency_cap_occurred: status.arm_frequency_cap_occurred,
+            throttling_occurred: status.throttling_occurred,
+            soft_temp_limit_occurred: status.soft_temp_limit_occurred,
+        }
+    }
+}
+
+#[pymethods]
+impl ThrottledStatus {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// Decode a bit pattern as returned by `get_throttled`
+#[pyfunction]
+fn interpret_bit_pattern(bit_pattern: isize) -> ThrottledStatus {
+    ThrottledStatus::from(vcgencmd::interpret_bit_pattern(bit_pattern))
+}
+
+/// The snapshot metrics read at once, see `Vcgencmd.snapshot`
+#[pyclass(module = "vcgencmd_rs", frozen)]
+struct Snapshot(vcgencmd::snapshot::Snapshot);
+
+#[pymethods]
+impl Snapshot {
+    /// Seconds since the Unix epoch the metrics were read at
+    #[getter]
+    fn timestamp(&self) -> f64 {
+        let since_epoch = self.0.timestamp.duration_since(UNIX_EPOCH);
+        since_epoch.unwrap_or_default().as_secs_f64()
+    }
+
+    /// Temperature in °C
+    #[getter]
+    fn temp(&self) -> Option<f64> {
+        self.0.temp
+    }
+
+    /// Bit pattern as returned by `get_throttled`
+    #[getter]
+    fn throttled(&self) -> Option<isize> {
+        self.0.throttled
+    }
+
+    /// The decoded `throttled`
+    #[getter]
+    fn throttled_status(&self) -> Option<ThrottledStatus> {
+        self.0.throttled.map(interpret_bit_pattern)
+    }
+
+    /// ARM clock in Hz
+    #[getter]
+    fn arm_clock(&self) -> Option<isize> {
+        self.0.arm_clock
+    }
+
+    /// Core clock in Hz
+    #[getter]
+    fn core_clock(&self) -> Option<isize> {
+        self.0.core_clock
+    }
+
+    /// Core voltage in V
+    #[getter]
+    fn core_volts(&self) -> Option<f64> {
+        self.0.core_volts
+    }
+
+    /// `healthy`, `degraded` or `critical`
+    #[getter]
+    fn health(&self) -> &'static str {
+        self.0.health().state()
+    }
+
+    /// Why metrics couldn't be read, keyed by their names like `temp` or `clock.arm`
+    #[getter]
+    fn errors(&self) -> HashMap<String, String> {
+        let errors = self.0.errors.iter();
+        errors
+            .map(|(metric, error)| (metric.name(), error.to_string()))
+            .collect()
+    }
+
+    /// The snapshot as a JSON object, the same as the crate's `Snapshot::to_json`
+    fn to_json(&self) -> String {
+        self.0.to_json()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Snapshot({})", self.0.to_json())
+    }
+}
+
+#[pymodule]
+fn vcgencmd_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Client>()?;
+    m.add_class::<ThrottledStatus>()?;
+    m.add_class::<Snapshot>()?;
+    m.add_function(wrap_pyfunction!(interpret_bit_pattern, m)?)?;
+    m.add("VcgencmdError", m.py().get_type::<VcgencmdError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module() {
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "vcgencmd_rs").unwrap();
+            vcgencmd_rs(&module).unwrap();
+
+            let status = module
+                .getattr("interpret_bit_pattern")
+                .and_then(|f| f.call1((0x50005,)))
+                .and_then(|status| status.extract::<ThrottledStatus>())
+                .unwrap();
+            assert!(status.under_voltage && status.currently_throttled);
+            assert!(!status.soft_temp_limit_occurred);
+
+            let client = module
+                .getattr("Vcgencmd")
+                .and_then(|class| class.call1(("/nonexistent/vcgencmd", false)))
+                .unwrap();
+            let error = client.call_method0("get_throttled").unwrap_err();
+            assert!(error.is_instance_of::<VcgencmdError>(py));
+            let error = client.call_method1("measure_clock", ("gpu",)).unwrap_err();
+            assert!(error.is_instance_of::<PyValueError>(py));
+        });
+    }
+}